@@ -0,0 +1,15 @@
+fn main() {
+    println!("cargo:rerun-if-env-changed=RUSTFLAGS");
+    println!("cargo:rerun-if-env-changed=CARGO_ENCODED_RUSTFLAGS");
+
+    // tokio only records the task diagnostics tokio-console shows when built with this cfg, which
+    // only the final binary's build can set
+    let console = std::env::var_os("CARGO_FEATURE_CONSOLE").is_some();
+    let unstable = std::env::var_os("CARGO_CFG_TOKIO_UNSTABLE").is_some();
+    if console && !unstable {
+        println!(
+            "cargo:warning=the `console` feature needs tokio built with \
+             `RUSTFLAGS=\"--cfg tokio_unstable\"`, or tokio-console shows no tasks"
+        );
+    }
+}
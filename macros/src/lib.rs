@@ -0,0 +1,154 @@
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{quote, ToTokens};
+use syn::{parse_macro_input, FnArg, Ident, ItemFn, LitStr, Pat, ReturnType, Type};
+
+/// Runs the function in a span carrying the OpenTelemetry `code.function` and `code.namespace`
+/// attributes and, as `Debug`, its arguments; `Err` returns mark the span as an error.
+///
+/// - `name = "..."` names the span, instead of the function's name.
+/// - `skip(a, b)` leaves out arguments, e.g. ones that aren't `Debug` or hold secrets.
+/// - `skip_all` leaves out every argument.
+///
+/// Errors are only detected on functions whose return type is spelled `Result` or `...::Result`,
+/// and recorded with their `Display` output.
+///
+/// ```ignore
+/// #[traced(skip(pool))]
+/// async fn load_cart(pool: &PgPool, cart_id: u64) -> Result<Cart, sqlx::Error> { ... }
+/// ```
+#[proc_macro_attribute]
+pub fn traced(args: TokenStream, item: TokenStream) -> TokenStream {
+    let mut options = Options::default();
+    let parser = syn::meta::parser(|meta| {
+        if meta.path.is_ident("name") {
+            options.name = Some(meta.value()?.parse::<LitStr>()?.value());
+            Ok(())
+        } else if meta.path.is_ident("skip_all") {
+            options.skip_all = true;
+            Ok(())
+        } else if meta.path.is_ident("skip") {
+            meta.parse_nested_meta(|nested| {
+                let ident = nested.path.get_ident().cloned();
+                options
+                    .skip
+                    .push(ident.ok_or_else(|| nested.error("expected an argument name"))?);
+                Ok(())
+            })
+        } else {
+            Err(meta.error("expected `name`, `skip` or `skip_all`"))
+        }
+    });
+    parse_macro_input!(args with parser);
+
+    let function = parse_macro_input!(item as ItemFn);
+    expand(function, &options).into()
+}
+
+#[derive(Default)]
+struct Options {
+    name: Option<String>,
+    skip: Vec<Ident>,
+    skip_all: bool,
+}
+
+fn expand(function: ItemFn, options: &Options) -> TokenStream2 {
+    let ItemFn {
+        attrs,
+        vis,
+        sig,
+        block,
+    } = function;
+    let function_name = sig.ident.to_string();
+    let span_name = options
+        .name
+        .clone()
+        .unwrap_or_else(|| function_name.clone());
+
+    // Destructured arguments have no single name to record them under, so they are left out
+    let arguments = sig
+        .inputs
+        .iter()
+        .filter_map(|input| match input {
+            FnArg::Typed(argument) => match &*argument.pat {
+                Pat::Ident(pat) => Some(pat.ident.clone()),
+                _ => None,
+            },
+            FnArg::Receiver(_) => None,
+        })
+        .filter(|ident| !options.skip_all && !options.skip.contains(ident))
+        .map(|ident| quote!(#ident = ?#ident));
+
+    let tracing = quote!(::axum_picklist::__private::tracing);
+    let span = quote! {
+        #tracing::info_span!(
+            #span_name,
+            otel.name = #span_name,
+            code.function = #function_name,
+            code.namespace = ::core::module_path!(),
+            otel.status_code = #tracing::field::Empty,
+            otel.status_message = #tracing::field::Empty,
+            #(#arguments,)*
+        )
+    };
+
+    let record_error = returns_result(&sig.output).then(|| {
+        quote! {
+            if let ::core::result::Result::Err(error) = &result {
+                let span = #tracing::Span::current();
+                span.record("otel.status_code", "ERROR");
+                span.record("otel.status_message", #tracing::field::display(error));
+                #tracing::error!(exception.message = %error, "exception");
+            }
+        }
+    });
+
+    let body = if sig.asyncness.is_some() {
+        quote! {
+            let span = #span;
+            #tracing::Instrument::instrument(
+                async move {
+                    let result = async move #block.await;
+                    #record_error
+                    result
+                },
+                span,
+            )
+            .await
+        }
+    } else {
+        let output = match &sig.output {
+            ReturnType::Default => quote!(()),
+            ReturnType::Type(_, ty) => ty.to_token_stream(),
+        };
+        quote! {
+            let span = #span;
+            let _guard = span.enter();
+            #[allow(clippy::redundant_closure_call)]
+            let result: #output = (move || #block)();
+            #record_error
+            result
+        }
+    };
+
+    quote! {
+        #(#attrs)*
+        #vis #sig {
+            #body
+        }
+    }
+}
+
+fn returns_result(output: &ReturnType) -> bool {
+    let ReturnType::Type(_, ty) = output else {
+        return false;
+    };
+    match &**ty {
+        Type::Path(path) => path
+            .path
+            .segments
+            .last()
+            .is_some_and(|segment| segment.ident == "Result"),
+        _ => false,
+    }
+}
@@ -0,0 +1,56 @@
+use crate::filter::FilterError;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use serde_json::json;
+
+pub const FLUSH_PATH: &str = "/internal/telemetry/flush";
+pub const LOG_LEVEL_PATH: &str = "/internal/log-level";
+
+/// Internal endpoints for operating the telemetry pipeline, best merged into a router that isn't
+/// reachable from outside the cluster.
+///
+/// - `POST /internal/telemetry/flush` exports the spans waiting in the batch processors, e.g. before
+///   scaling down or while looking for missing spans, answering 200 once they are exported and 500
+///   with the error otherwise.
+/// - `GET /internal/log-level` answers the current filter directive, and `PUT` replaces it with the
+///   directive in the request body, e.g. `info,axum_picklist=debug`, without a restart.
+pub fn admin_router() -> Router {
+    Router::new()
+        .route(FLUSH_PATH, post(flush))
+        .route(LOG_LEVEL_PATH, get(log_level).put(set_log_level))
+}
+
+async fn flush() -> Response {
+    match crate::force_flush_spans().await {
+        Ok(()) => Json(json!({ "flushed": true })).into_response(),
+        Err(err) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({ "flushed": false, "error": err.to_string() })),
+        )
+            .into_response(),
+    }
+}
+
+async fn log_level() -> Response {
+    match crate::log_filter() {
+        Some(filter) => Json(json!({ "filter": filter })).into_response(),
+        None => filter_error(FilterError::NotInstalled),
+    }
+}
+
+async fn set_log_level(directive: String) -> Response {
+    match crate::set_log_filter(directive.trim()) {
+        Ok(()) => log_level().await,
+        Err(err) => filter_error(err),
+    }
+}
+
+fn filter_error(err: FilterError) -> Response {
+    let status = match err {
+        FilterError::Parse(_) => StatusCode::BAD_REQUEST,
+        FilterError::NotInstalled | FilterError::Reload(_) => StatusCode::INTERNAL_SERVER_ERROR,
+    };
+    (status, Json(json!({ "error": err.to_string() }))).into_response()
+}
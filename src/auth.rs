@@ -0,0 +1,204 @@
+use crate::SpanExt;
+use async_trait::async_trait;
+use axum::body::Body;
+use axum::http::header::{AUTHORIZATION, WWW_AUTHENTICATE};
+use axum::http::{HeaderValue, Request, Response, StatusCode};
+use axum::response::IntoResponse;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::fmt::Write as _;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tower::{Layer, Service};
+use tracing::Instrument;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+/// Who made a request, as established by a [`TokenValidator`]; handlers can read it with
+/// `Extension<Principal>`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Principal {
+    pub id: String,
+    pub roles: Vec<String>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum AuthError {
+    #[error("the request has no bearer token")]
+    MissingToken,
+    #[error("invalid token: {0}")]
+    InvalidToken(String),
+}
+
+/// Checks the bearer tokens of requests, e.g. by verifying a JWT's signature and expiry with the
+/// issuer's keys, or by asking an introspection endpoint.
+#[async_trait]
+pub trait TokenValidator: Send + Sync + 'static {
+    async fn validate(&self, token: &str) -> Result<Principal, AuthError>;
+}
+
+/// Layer authenticating requests by their `Authorization: Bearer` token with a [`TokenValidator`],
+/// answering the ones without a valid token with 401 Unauthorized, and adding the [`Principal`] of
+/// the others to their extensions.
+///
+/// The principal is recorded on the current span as `enduser.id` and `enduser.role`, its roles
+/// separated by commas, and each validation gets a child `auth.validate_token` span for its
+/// latency, when added as in `telemetry.instrument(router.layer(AuthLayer::new(validator)))`.
+pub struct AuthLayer<V> {
+    validator: Arc<V>,
+    optional: bool,
+    pseudonymize: Option<Arc<str>>,
+}
+
+impl<V> Clone for AuthLayer<V> {
+    fn clone(&self) -> Self {
+        Self {
+            validator: self.validator.clone(),
+            optional: self.optional,
+            pseudonymize: self.pseudonymize.clone(),
+        }
+    }
+}
+
+impl<V: TokenValidator> AuthLayer<V> {
+    pub fn new(validator: V) -> Self {
+        Self {
+            validator: Arc::new(validator),
+            optional: false,
+            pseudonymize: None,
+        }
+    }
+
+    /// Lets requests without a token through unauthenticated; invalid tokens are still rejected.
+    pub fn optional(mut self) -> Self {
+        self.optional = true;
+        self
+    }
+
+    /// Records the HMAC-SHA256 of the user ID keyed with `salt`, in hex, as `enduser.id`, so the
+    /// requests of a user can be told apart without the traces saying who they are.
+    pub fn pseudonymize(mut self, salt: impl Into<String>) -> Self {
+        self.pseudonymize = Some(salt.into().into());
+        self
+    }
+}
+
+impl<S, V> Layer<S> for AuthLayer<V> {
+    type Service = Auth<S, V>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Auth {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+/// Service created by [`AuthLayer`].
+pub struct Auth<S, V> {
+    inner: S,
+    layer: AuthLayer<V>,
+}
+
+impl<S: Clone, V> Clone for Auth<S, V> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            layer: self.layer.clone(),
+        }
+    }
+}
+
+fn bearer_token<B>(request: &Request<B>) -> Option<String> {
+    let header = request.headers().get(AUTHORIZATION)?.to_str().ok()?;
+    let (scheme, token) = header.split_once(' ')?;
+    scheme
+        .eq_ignore_ascii_case("bearer")
+        .then(|| token.trim().to_string())
+}
+
+// Keyed, so the IDs can't be recovered by hashing candidates without the salt, and stable across
+// processes and releases, so the pseudonyms of a user keep matching
+pub(crate) fn pseudonym(salt: &str, id: impl AsRef<[u8]>) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(salt.as_bytes()).expect("HMAC takes keys of any length");
+    mac.update(id.as_ref());
+    mac.finalize()
+        .into_bytes()
+        .iter()
+        .fold(String::with_capacity(64), |mut hex, byte| {
+            let _ = write!(hex, "{byte:02x}");
+            hex
+        })
+}
+
+fn unauthorized(error: &AuthError) -> Response<Body> {
+    let challenge = match error {
+        AuthError::MissingToken => "Bearer",
+        AuthError::InvalidToken(_) => "Bearer error=\"invalid_token\"",
+    };
+    let mut response = StatusCode::UNAUTHORIZED.into_response();
+    response
+        .headers_mut()
+        .insert(WWW_AUTHENTICATE, HeaderValue::from_static(challenge));
+    response
+}
+
+impl<S, V, B> Service<Request<B>> for Auth<S, V>
+where
+    S: Service<Request<B>, Response = Response<Body>> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+    V: TokenValidator,
+    B: Send + 'static,
+{
+    type Response = Response<Body>;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut request: Request<B>) -> Self::Future {
+        // The ready service is the one that must be called, so the clone is kept for next time
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let layer = self.layer.clone();
+        let span = tracing::Span::current();
+
+        Box::pin(async move {
+            let Some(token) = bearer_token(&request) else {
+                if layer.optional {
+                    return inner.call(request).await;
+                }
+                return Ok(unauthorized(&AuthError::MissingToken));
+            };
+
+            let validation = tracing::info_span!("auth.validate_token");
+            let principal = match layer
+                .validator
+                .validate(&token)
+                .instrument(validation.clone())
+                .await
+            {
+                Ok(principal) => principal,
+                Err(err) => {
+                    validation.record_error(&err);
+                    return Ok(unauthorized(&err));
+                }
+            };
+
+            let id = match &layer.pseudonymize {
+                Some(salt) => pseudonym(salt, &principal.id),
+                None => principal.id.clone(),
+            };
+            span.set_attribute("enduser.id", id);
+            if !principal.roles.is_empty() {
+                span.set_attribute("enduser.role", principal.roles.join(","));
+            }
+            request.extensions_mut().insert(principal);
+            inner.call(request).await
+        })
+    }
+}
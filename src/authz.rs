@@ -0,0 +1,199 @@
+use crate::Principal;
+use axum::http::{Request, Response, StatusCode};
+use opentelemetry::trace::TraceContextExt;
+use opentelemetry::KeyValue;
+use std::future::Future;
+use std::io::Write;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::task::{Context, Poll};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tower::{Layer, Service};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+// Set by `Telemetry::init` when an audit sink is configured
+static AUDIT_SINK: OnceLock<Arc<dyn AuditSink>> = OnceLock::new();
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Decision {
+    Allow,
+    Deny,
+}
+
+impl Decision {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Allow => "allow",
+            Self::Deny => "deny",
+        }
+    }
+}
+
+/// An authorization decision, as sent to the [`AuditSink`].
+#[derive(Clone, Debug, PartialEq, serde::Serialize)]
+pub struct AuditEvent {
+    pub timestamp_ms: u64,
+    pub policy: String,
+    pub decision: Decision,
+    /// Why the policy decided so, when it says.
+    pub reason: Option<String>,
+    /// The unhashed [`Principal`] ID, as audit trails have to say who did what.
+    pub enduser_id: Option<String>,
+    /// Trace of the request the decision was made for, to find it from the audit log.
+    pub trace_id: Option<String>,
+}
+
+/// Where authorization decisions are kept for auditing, apart from the traces, which are sampled
+/// and expire.
+pub trait AuditSink: Send + Sync + 'static {
+    fn record(&self, event: &AuditEvent);
+}
+
+/// [`AuditSink`] writing each event as a line of JSON, e.g. to a file rotated by
+/// `tracing_appender::rolling::daily`.
+pub struct JsonLinesAuditSink<W> {
+    writer: Mutex<W>,
+}
+
+impl<W: Write + Send + 'static> JsonLinesAuditSink<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer: Mutex::new(writer),
+        }
+    }
+}
+
+impl<W: Write + Send + 'static> AuditSink for JsonLinesAuditSink<W> {
+    fn record(&self, event: &AuditEvent) {
+        let mut line = serde_json::to_vec(event).expect("audit events serialize to JSON");
+        line.push(b'\n');
+        if let Err(err) = self.writer.lock().unwrap().write_all(&line) {
+            tracing::error!(error = %err, "failed to write an audit event");
+        }
+    }
+}
+
+pub(crate) fn set_audit_sink(sink: Arc<dyn AuditSink>) {
+    let _ = AUDIT_SINK.set(sink);
+}
+
+/// Records an authorization decision made by `policy` as an `authz.decision` event on the current
+/// span, with `authz.policy`, `authz.decision` and `authz.reason` attributes, and sends it to the
+/// [`AuditSink`] given to [`crate::Telemetry::audit_sink`], if any.
+///
+/// [`AuthorizeLayer`] calls it for its policy's decisions, and handlers making their own can too.
+pub fn record_decision(
+    policy: &str,
+    decision: Decision,
+    reason: Option<&str>,
+    principal: Option<&Principal>,
+) {
+    let span = tracing::Span::current();
+    let mut attributes = vec![
+        KeyValue::new("authz.policy", policy.to_string()),
+        KeyValue::new("authz.decision", decision.as_str()),
+    ];
+    if let Some(reason) = reason {
+        attributes.push(KeyValue::new("authz.reason", reason.to_string()));
+    }
+    span.add_event("authz.decision", attributes);
+
+    let Some(sink) = AUDIT_SINK.get() else {
+        return;
+    };
+    let span_context = span.context().span().span_context().clone();
+    let timestamp_ms = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64;
+    sink.record(&AuditEvent {
+        timestamp_ms,
+        policy: policy.to_string(),
+        decision,
+        reason: reason.map(str::to_string),
+        enduser_id: principal.map(|principal| principal.id.clone()),
+        trace_id: span_context
+            .is_valid()
+            .then(|| span_context.trace_id().to_string()),
+    });
+}
+
+/// Decides whether requests may go ahead, from the [`Principal`] established by
+/// [`crate::AuthLayer`], if any, and the request itself.
+pub trait Policy: Clone + Send + Sync + 'static {
+    /// Name the decisions are recorded under, e.g. `orders.write`.
+    fn name(&self) -> &str;
+
+    /// The decision, and optionally why.
+    fn decide<B>(
+        &self,
+        principal: Option<&Principal>,
+        request: &Request<B>,
+    ) -> (Decision, Option<String>);
+}
+
+/// Layer applying a [`Policy`] to each request, answering the denied ones with 403 Forbidden, and
+/// recording every decision with [`record_decision`].
+///
+/// It must run inside the [`crate::AuthLayer`] to see the principal, e.g. `telemetry.instrument(
+/// router.layer(AuthorizeLayer::new(policy)).layer(AuthLayer::new(validator)))`.
+#[derive(Clone, Debug)]
+pub struct AuthorizeLayer<P> {
+    policy: P,
+}
+
+impl<P: Policy> AuthorizeLayer<P> {
+    pub fn new(policy: P) -> Self {
+        Self { policy }
+    }
+}
+
+impl<S, P: Clone> Layer<S> for AuthorizeLayer<P> {
+    type Service = Authorize<S, P>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Authorize {
+            inner,
+            policy: self.policy.clone(),
+        }
+    }
+}
+
+/// Service created by [`AuthorizeLayer`].
+#[derive(Clone, Debug)]
+pub struct Authorize<S, P> {
+    inner: S,
+    policy: P,
+}
+
+impl<S, P, B, ResBody> Service<Request<B>> for Authorize<S, P>
+where
+    S: Service<Request<B>, Response = Response<ResBody>>,
+    S::Future: Send + 'static,
+    P: Policy,
+    ResBody: Default,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<B>) -> Self::Future {
+        let principal = request.extensions().get::<Principal>();
+        let (decision, reason) = self.policy.decide(principal, &request);
+        record_decision(self.policy.name(), decision, reason.as_deref(), principal);
+
+        if decision == Decision::Allow {
+            return Box::pin(self.inner.call(request));
+        }
+        Box::pin(async {
+            let mut response = Response::new(ResBody::default());
+            *response.status_mut() = StatusCode::FORBIDDEN;
+            Ok(response)
+        })
+    }
+}
@@ -0,0 +1,46 @@
+use opentelemetry::baggage::BaggageExt;
+use opentelemetry::trace::Span as _;
+use opentelemetry::{Context, Key, KeyValue};
+use opentelemetry_sdk::error::OTelSdkResult;
+use opentelemetry_sdk::trace::{Span, SpanData, SpanProcessor};
+use std::time::Duration;
+
+/// [`SpanProcessor`] copying the selected W3C baggage entries onto every span as it starts, e.g. a
+/// `tenant.id` set by the edge service, so spans can be queried by it without each handler recording it.
+///
+/// Baggage arrives with the trace context of the request, so the entries are copied onto the request
+/// span and every span started within it. Only requests whose headers are extracted with the
+/// [`PropagationFormat::Baggage`](crate::PropagationFormat::Baggage) propagator carry baggage.
+#[derive(Debug)]
+pub struct BaggageSpanProcessor {
+    keys: Vec<Key>,
+}
+
+impl BaggageSpanProcessor {
+    pub fn new(keys: impl IntoIterator<Item = impl Into<Key>>) -> Self {
+        Self {
+            keys: keys.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+impl SpanProcessor for BaggageSpanProcessor {
+    fn on_start(&self, span: &mut Span, cx: &Context) {
+        let baggage = cx.baggage();
+        for key in &self.keys {
+            if let Some(value) = baggage.get(key.clone()) {
+                span.set_attribute(KeyValue::new(key.clone(), value.clone()));
+            }
+        }
+    }
+
+    fn on_end(&self, _span: SpanData) {}
+
+    fn force_flush(&self) -> OTelSdkResult {
+        Ok(())
+    }
+
+    fn shutdown_with_timeout(&self, _timeout: Duration) -> OTelSdkResult {
+        Ok(())
+    }
+}
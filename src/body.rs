@@ -0,0 +1,457 @@
+use axum::body::{Body, Bytes, HttpBody};
+use axum::http::header::CONTENT_TYPE;
+use axum::http::{HeaderMap, Request, Response};
+use axum::BoxError;
+use http_body::Frame;
+use regex::Regex;
+use serde_json::Value;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Instant;
+use tower::{Layer, Service};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+/// What [`BodyCaptureLayer`] records.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BodyCaptureConfig {
+    /// Only the first this many bytes of the bodies are kept, and recorded once redacted.
+    pub max_bytes: usize,
+    /// Content types whose bodies are recorded, matched as prefixes so `text/` covers every kind of
+    /// text; other bodies are streamed through untouched.
+    pub content_types: Vec<String>,
+    /// JSON object keys, at any depth and in any case, whose values are replaced with `[REDACTED]`.
+    pub redact_fields: Vec<String>,
+}
+
+impl Default for BodyCaptureConfig {
+    fn default() -> Self {
+        Self {
+            max_bytes: 4096,
+            content_types: vec!["application/json".to_string(), "text/plain".to_string()],
+            redact_fields: ["password", "secret", "token", "api_key"]
+                .map(String::from)
+                .to_vec(),
+        }
+    }
+}
+
+/// Layer recording request and response bodies on the request span as `http.request.body` and
+/// `http.response.body`, for debugging.
+///
+/// The bodies are streamed through, copying only their first [`BodyCaptureConfig::max_bytes`], and
+/// recorded once read to their end, or dropped before that. They can still be large on every span,
+/// so apply it only to the routes being debugged, e.g.
+/// `post(handler).layer(BodyCaptureLayer::new(BodyCaptureConfig::default()))`.
+#[derive(Clone, Debug)]
+pub struct BodyCaptureLayer {
+    capture: Arc<Capture>,
+}
+
+impl BodyCaptureLayer {
+    pub fn new(config: BodyCaptureConfig) -> Self {
+        Self {
+            capture: Arc::new(Capture::new(config)),
+        }
+    }
+}
+
+impl<S> Layer<S> for BodyCaptureLayer {
+    type Service = BodyCapture<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        BodyCapture {
+            inner,
+            capture: self.capture.clone(),
+        }
+    }
+}
+
+/// Service created by [`BodyCaptureLayer`].
+#[derive(Clone, Debug)]
+pub struct BodyCapture<S> {
+    inner: S,
+    capture: Arc<Capture>,
+}
+
+impl<S, B, ResBody> Service<Request<B>> for BodyCapture<S>
+where
+    S: Service<Request<Body>, Response = Response<ResBody>>,
+    S::Future: Send + 'static,
+    B: HttpBody<Data = Bytes> + Send + 'static,
+    B::Error: Into<BoxError>,
+    ResBody: HttpBody<Data = Bytes> + Send + 'static,
+    ResBody::Error: Into<BoxError>,
+{
+    type Response = Response<Body>;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<B>) -> Self::Future {
+        let capture = self.capture.clone();
+        let span = tracing::Span::current();
+        let request = if capture.config.content_type_matches(request.headers()) {
+            let (capture, span) = (capture.clone(), span.clone());
+            request.map(|body| {
+                Body::new(CapturedBody::new(body, capture.clone(), move |body| {
+                    span.set_attribute("http.request.body", body);
+                }))
+            })
+        } else {
+            request.map(Body::new)
+        };
+        let future = self.inner.call(request);
+
+        Box::pin(async move {
+            let response = future.await?;
+            if !capture.config.content_type_matches(response.headers()) {
+                return Ok(response.map(Body::new));
+            }
+            Ok(response.map(|body| {
+                Body::new(CapturedBody::new(body, capture.clone(), move |body| {
+                    span.set_attribute("http.response.body", body);
+                }))
+            }))
+        })
+    }
+}
+
+// The configuration, with the pattern redacting the bodies that were cut short
+#[derive(Debug)]
+struct Capture {
+    config: BodyCaptureConfig,
+    fields: Option<Regex>,
+}
+
+impl Capture {
+    fn new(config: BodyCaptureConfig) -> Self {
+        let fields = (!config.redact_fields.is_empty()).then(|| {
+            let names: Vec<_> = config
+                .redact_fields
+                .iter()
+                .map(|field| regex::escape(field))
+                .collect();
+            // A string, possibly left unterminated by the cut, or anything else up to the end of
+            // the value, or to the end of the text for objects and arrays
+            Regex::new(&format!(
+                r#"(?is)"({})"\s*:\s*(?:"(?:[^"\\]|\\.)*(?:"|$)|[\[{{].*|[^,}}\]]*)"#,
+                names.join("|")
+            ))
+            .unwrap()
+        });
+        Self { config, fields }
+    }
+
+    // Whole JSON bodies are redacted once parsed, and the others, such as JSON cut short, which no
+    // longer parses, where the pattern matches
+    fn render(&self, body: &[u8], cut: bool) -> String {
+        let body = match serde_json::from_slice::<Value>(body) {
+            Ok(mut json) if !cut => {
+                self.config.redact(&mut json);
+                json.to_string()
+            }
+            _ => {
+                let text = String::from_utf8_lossy(body);
+                match &self.fields {
+                    Some(fields) => fields
+                        .replace_all(&text, r#""$1":"[REDACTED]""#)
+                        .into_owned(),
+                    None => text.into_owned(),
+                }
+            }
+        };
+        truncate(body, self.config.max_bytes)
+    }
+}
+
+impl BodyCaptureConfig {
+    fn content_type_matches(&self, headers: &HeaderMap) -> bool {
+        let Some(content_type) = headers
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+        else {
+            return false;
+        };
+        self.content_types
+            .iter()
+            .any(|allowed| content_type.starts_with(allowed.as_str()))
+    }
+
+    fn redact(&self, json: &mut Value) {
+        match json {
+            Value::Object(object) => {
+                for (key, value) in object {
+                    if self
+                        .redact_fields
+                        .iter()
+                        .any(|field| field.eq_ignore_ascii_case(key))
+                    {
+                        *value = Value::String("[REDACTED]".to_string());
+                    } else {
+                        self.redact(value);
+                    }
+                }
+            }
+            Value::Array(values) => values.iter_mut().for_each(|value| self.redact(value)),
+            _ => {}
+        }
+    }
+}
+
+// Body passing `inner` through while copying its first bytes, handing them to `record`, rendered,
+// once it ends, or is dropped before that, e.g. when the client goes away. Bodies dropped unread
+// aren't recorded
+struct CapturedBody<F: FnOnce(String)> {
+    inner: Body,
+    capture: Arc<Capture>,
+    captured: Vec<u8>,
+    cut: bool,
+    record: Option<F>,
+}
+
+impl<F: FnOnce(String)> CapturedBody<F> {
+    fn new<B>(inner: B, capture: Arc<Capture>, record: F) -> Self
+    where
+        B: HttpBody<Data = Bytes> + Send + 'static,
+        B::Error: Into<BoxError>,
+    {
+        Self {
+            inner: Body::new(inner),
+            capture,
+            captured: Vec::new(),
+            cut: false,
+            record: Some(record),
+        }
+    }
+
+    fn end(&mut self) {
+        if let Some(record) = self.record.take() {
+            record(self.capture.render(&self.captured, self.cut));
+        }
+    }
+}
+
+impl<F: FnOnce(String) + Unpin> HttpBody for CapturedBody<F> {
+    type Data = Bytes;
+    type Error = axum::Error;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let this = self.get_mut();
+        let poll = Pin::new(&mut this.inner).poll_frame(cx);
+        match &poll {
+            // Trailers aren't recorded
+            Poll::Ready(Some(Ok(frame))) => {
+                if let Some(chunk) = frame.data_ref() {
+                    let room = this.capture.config.max_bytes - this.captured.len();
+                    this.captured
+                        .extend_from_slice(&chunk[..chunk.len().min(room)]);
+                    this.cut |= chunk.len() > room;
+                }
+            }
+            Poll::Ready(None) => this.end(),
+            _ => {}
+        }
+        poll
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> http_body::SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+impl<F: FnOnce(String)> Drop for CapturedBody<F> {
+    fn drop(&mut self) {
+        if !self.captured.is_empty() {
+            self.end();
+        }
+    }
+}
+
+// Cuts on a character boundary so the attribute stays valid UTF-8
+fn truncate(mut text: String, max_bytes: usize) -> String {
+    if text.len() > max_bytes {
+        let mut end = max_bytes;
+        while !text.is_char_boundary(end) {
+            end -= 1;
+        }
+        text.truncate(end);
+    }
+    text
+}
+
+/// Layer recording the size of the response body as `http.response.body.size` and the time from
+/// the request to its last byte as `http.response.time_to_last_byte_ms`, and holding the request
+/// span open until then, so streamed responses are timed to their end rather than to their headers.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct BodyTimingLayer;
+
+impl BodyTimingLayer {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl<S> Layer<S> for BodyTimingLayer {
+    type Service = BodyTiming<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        BodyTiming { inner }
+    }
+}
+
+/// Service created by [`BodyTimingLayer`].
+#[derive(Clone, Debug)]
+pub struct BodyTiming<S> {
+    inner: S,
+}
+
+impl<S, B, ResBody> Service<Request<B>> for BodyTiming<S>
+where
+    S: Service<Request<B>, Response = Response<ResBody>>,
+    S::Future: Send + 'static,
+    ResBody: HttpBody<Data = Bytes> + Send + 'static,
+    ResBody::Error: Into<BoxError>,
+{
+    type Response = Response<Body>;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<B>) -> Self::Future {
+        let start = Instant::now();
+        let span = tracing::Span::current();
+        let future = self.inner.call(request);
+
+        Box::pin(async move {
+            let response = future.await?;
+            Ok(response.map(|body| {
+                Body::new(MeteredBody::new(Body::new(body), move |bytes| {
+                    span.set_attribute("http.response.body.size", bytes as i64);
+                    span.set_attribute(
+                        "http.response.time_to_last_byte_ms",
+                        start.elapsed().as_secs_f64() * 1000.0,
+                    );
+                }))
+            }))
+        })
+    }
+}
+
+/// Body passing `inner` through while counting its bytes, calling `on_end` with their total once it
+/// ends, or is dropped before that, e.g. when the client goes away.
+pub(crate) struct MeteredBody<B, F: FnOnce(u64)> {
+    inner: B,
+    bytes: u64,
+    on_end: Option<F>,
+}
+
+impl<B, F: FnOnce(u64)> MeteredBody<B, F> {
+    pub(crate) fn new(inner: B, on_end: F) -> Self {
+        Self {
+            inner,
+            bytes: 0,
+            on_end: Some(on_end),
+        }
+    }
+
+    fn end(&mut self) {
+        if let Some(on_end) = self.on_end.take() {
+            on_end(self.bytes);
+        }
+    }
+}
+
+impl<B, F> HttpBody for MeteredBody<B, F>
+where
+    B: HttpBody<Data = Bytes> + Unpin,
+    F: FnOnce(u64) + Unpin,
+{
+    type Data = Bytes;
+    type Error = B::Error;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let this = self.get_mut();
+        let poll = Pin::new(&mut this.inner).poll_frame(cx);
+        match &poll {
+            Poll::Ready(Some(Ok(frame))) => {
+                if let Some(chunk) = frame.data_ref() {
+                    this.bytes += chunk.len() as u64;
+                }
+            }
+            Poll::Ready(None) => this.end(),
+            _ => {}
+        }
+        poll
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> http_body::SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+impl<B, F: FnOnce(u64)> Drop for MeteredBody<B, F> {
+    fn drop(&mut self) {
+        self.end();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn redacts_bodies_whether_or_not_they_were_cut() {
+        let capture = Capture::new(BodyCaptureConfig {
+            max_bytes: 128,
+            ..BodyCaptureConfig::default()
+        });
+
+        let whole = br#"{"user":"jane","Password":"hunter2","nested":[{"token":1}]}"#;
+        assert_eq!(
+            capture.render(whole, false),
+            r#"{"Password":"[REDACTED]","nested":[{"token":"[REDACTED]"}],"user":"jane"}"#
+        );
+
+        // Cut inside the string, inside an object, and inside a number
+        assert_eq!(
+            capture.render(br#"{"user":"jane","password":"hunt"#, true),
+            r#"{"user":"jane","password":"[REDACTED]""#
+        );
+        assert_eq!(
+            capture.render(br#"{"user":"jane","secret":{"pin":12"#, true),
+            r#"{"user":"jane","secret":"[REDACTED]""#
+        );
+        assert_eq!(
+            capture.render(br#"{"token":1234,"user":"ja"#, true),
+            r#"{"token":"[REDACTED]","user":"ja"#
+        );
+    }
+
+    #[test]
+    fn cuts_on_a_character_boundary() {
+        assert_eq!(truncate("héllo".to_string(), 2), "h");
+        assert_eq!(truncate("héllo".to_string(), 3), "hé");
+    }
+}
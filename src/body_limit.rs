@@ -0,0 +1,163 @@
+use crate::body::MeteredBody;
+use axum::body::{Body, Bytes, HttpBody};
+use axum::extract::MatchedPath;
+use axum::http::header::CONTENT_LENGTH;
+use axum::http::{Request, Response, StatusCode};
+use axum::BoxError;
+use http_body_util::Limited;
+use opentelemetry::metrics::Counter;
+use opentelemetry::KeyValue;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tower::{Layer, Service};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+/// Body size limit for the requests whose path starts with `prefix`, taking precedence over the
+/// global one.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RouteBodyLimit {
+    pub prefix: String,
+    pub max_bytes: usize,
+}
+
+/// Layer answering requests whose body is larger than their limit with an empty 413 Payload Too
+/// Large response; without a limit bodies can be of any size.
+///
+/// Requests declaring a larger `content-length` are rejected before the handler runs, and the
+/// bodies of the others are cut off at the limit, the extractors reading them answering 413 too.
+/// Limited requests get their body size recorded on the current span as `http.request.body.size`,
+/// and the rejected ones are counted in the `request.rejected.too_large` metric.
+#[derive(Clone)]
+pub struct BodyLimitLayer {
+    limit: Option<usize>,
+    routes: Arc<Vec<RouteBodyLimit>>,
+    rejected: Counter<u64>,
+}
+
+impl BodyLimitLayer {
+    pub fn new(limit: Option<usize>) -> Self {
+        let rejected = opentelemetry::global::meter(env!("CARGO_PKG_NAME"))
+            .u64_counter("request.rejected.too_large")
+            .with_description("HTTP server requests rejected for the size of their body")
+            .build();
+        Self {
+            limit,
+            routes: Arc::new(Vec::new()),
+            rejected,
+        }
+    }
+
+    /// Gives the requests whose path starts with `prefix` a limit of `max_bytes` instead, e.g. for
+    /// an upload route, the longest matching prefix winning, even when there is no global limit.
+    pub fn with_route(mut self, prefix: impl Into<String>, max_bytes: usize) -> Self {
+        Arc::make_mut(&mut self.routes).push(RouteBodyLimit {
+            prefix: prefix.into(),
+            max_bytes,
+        });
+        self
+    }
+
+    pub(crate) fn from_limits(limit: Option<usize>, routes: Vec<RouteBodyLimit>) -> Self {
+        Self {
+            routes: Arc::new(routes),
+            ..Self::new(limit)
+        }
+    }
+}
+
+impl<S> Layer<S> for BodyLimitLayer {
+    type Service = BodyLimit<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        BodyLimit {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+/// Service created by [`BodyLimitLayer`].
+#[derive(Clone)]
+pub struct BodyLimit<S> {
+    inner: S,
+    layer: BodyLimitLayer,
+}
+
+fn rejection_attributes<B>(request: &Request<B>) -> Vec<KeyValue> {
+    let mut attributes = vec![KeyValue::new(
+        "http.request.method",
+        request.method().to_string(),
+    )];
+    if let Some(route) = request.extensions().get::<MatchedPath>() {
+        attributes.push(KeyValue::new("http.route", route.as_str().to_string()));
+    }
+    attributes
+}
+
+impl<S, B, ResBody> Service<Request<B>> for BodyLimit<S>
+where
+    S: Service<Request<Body>, Response = Response<ResBody>>,
+    S::Future: Send + 'static,
+    B: HttpBody<Data = Bytes> + Send + 'static,
+    B::Error: Into<BoxError>,
+    ResBody: Default,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<B>) -> Self::Future {
+        let path = request.uri().path();
+        let limit = self
+            .layer
+            .routes
+            .iter()
+            .filter(|route| path.starts_with(route.prefix.as_str()))
+            .max_by_key(|route| route.prefix.len())
+            .map(|route| route.max_bytes)
+            .or(self.layer.limit);
+        let Some(limit) = limit else {
+            return Box::pin(self.inner.call(request.map(Body::new)));
+        };
+
+        let span = tracing::Span::current();
+        let attributes = rejection_attributes(&request);
+        let rejected = self.layer.rejected.clone();
+        let declared = request
+            .headers()
+            .get(CONTENT_LENGTH)
+            .and_then(|value| value.to_str().ok()?.parse::<u64>().ok());
+        if let Some(declared) = declared.filter(|declared| *declared > limit as u64) {
+            span.set_attribute("http.request.body.size", declared as i64);
+            rejected.add(1, &attributes);
+            return Box::pin(async {
+                let mut response = Response::new(ResBody::default());
+                *response.status_mut() = StatusCode::PAYLOAD_TOO_LARGE;
+                Ok(response)
+            });
+        }
+
+        let request = request.map(|body| {
+            let span = span.clone();
+            let metered = MeteredBody::new(Body::new(body), move |bytes| {
+                span.set_attribute("http.request.body.size", bytes as i64);
+            });
+            Body::new(Limited::new(metered, limit))
+        });
+        let future = self.inner.call(request);
+        Box::pin(async move {
+            let response = future.await?;
+            // Bodies without a `content-length` are only found too large once the handler reads them
+            if response.status() == StatusCode::PAYLOAD_TOO_LARGE {
+                rejected.add(1, &attributes);
+            }
+            Ok(response)
+        })
+    }
+}
@@ -0,0 +1,186 @@
+use opentelemetry::{Context, Key, KeyValue, Value};
+use opentelemetry_sdk::error::OTelSdkResult;
+use opentelemetry_sdk::trace::{Span, SpanData, SpanProcessor, TracerProviderBuilder};
+use opentelemetry_sdk::Resource;
+use std::time::Duration;
+
+/// Bounds on the attributes of exported spans and span events, so a handler recording too much
+/// can't push the dataset past the backend's column limits, e.g. Honeycomb's, or blow up the export
+/// payloads.
+#[derive(Clone, Debug, PartialEq, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AttributeLimits {
+    /// Attributes kept per span and per span event, the first ones recorded; defaults to 128. These
+    /// include the code location and thread ones `tracing_opentelemetry` records before any other.
+    pub max_attributes: usize,
+    /// Longest string value kept, in bytes, longer ones being cut on a character boundary; defaults
+    /// to 4096.
+    pub max_value_length: usize,
+    /// Keys known to carry unbounded values, e.g. `session.id`, whose string values are replaced by
+    /// a hash of them, so equal values can still be grouped by.
+    pub hashed_keys: Vec<String>,
+}
+
+impl Default for AttributeLimits {
+    fn default() -> Self {
+        Self {
+            max_attributes: 128,
+            max_value_length: 4096,
+            hashed_keys: Vec::new(),
+        }
+    }
+}
+
+/// [`SpanProcessor`] enforcing [`AttributeLimits`] on finished spans and their events before
+/// passing them on to `inner`.
+///
+/// Attributes beyond the limit are dropped and counted in the span's `dropped_attributes_count`, as
+/// the SDK does for its own limits. Each attribute dropped, cut or hashed is counted by the
+/// `otel.processor.attributes.limited` metric, by `reason`: `count`, `length` or `hashed`.
+#[derive(Debug)]
+pub struct AttributeLimitProcessor<P> {
+    inner: P,
+    limits: AttributeLimits,
+    hashed_keys: Vec<Key>,
+}
+
+impl<P: SpanProcessor> AttributeLimitProcessor<P> {
+    pub fn new(inner: P, limits: AttributeLimits) -> Self {
+        let hashed_keys = limits.hashed_keys.iter().cloned().map(Key::from).collect();
+        Self {
+            inner,
+            limits,
+            hashed_keys,
+        }
+    }
+
+    // Returns the number of attributes dropped
+    fn limit(&self, attributes: &mut Vec<KeyValue>) -> u32 {
+        let mut hashed = 0;
+        let mut cut = 0;
+        for attribute in attributes.iter_mut() {
+            let Value::String(value) = &attribute.value else {
+                continue;
+            };
+            if self.hashed_keys.contains(&attribute.key) {
+                attribute.value = Value::from(format!("{:016x}", fnv1a(value.as_str().as_bytes())));
+                hashed += 1;
+            } else if value.as_str().len() > self.limits.max_value_length {
+                let value = value.as_str();
+                let mut end = self.limits.max_value_length;
+                while !value.is_char_boundary(end) {
+                    end -= 1;
+                }
+                attribute.value = Value::from(value[..end].to_string());
+                cut += 1;
+            }
+        }
+
+        let dropped = attributes.len().saturating_sub(self.limits.max_attributes);
+        attributes.truncate(self.limits.max_attributes);
+
+        crate::self_metrics::attributes_limited("hashed", hashed);
+        crate::self_metrics::attributes_limited("length", cut);
+        crate::self_metrics::attributes_limited("count", dropped as u64);
+        dropped as u32
+    }
+}
+
+// Stable across processes and releases, unlike std's hasher, so hashes can be compared over time
+pub(crate) fn fnv1a(value: &[u8]) -> u64 {
+    value.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+impl<P: SpanProcessor> SpanProcessor for AttributeLimitProcessor<P> {
+    fn on_start(&self, span: &mut Span, cx: &Context) {
+        self.inner.on_start(span, cx);
+    }
+
+    fn on_end(&self, mut span: SpanData) {
+        let dropped = self.limit(&mut span.attributes);
+        span.dropped_attributes_count = span.dropped_attributes_count.saturating_add(dropped);
+        for event in &mut span.events.events {
+            let dropped = self.limit(&mut event.attributes);
+            event.dropped_attributes_count = event.dropped_attributes_count.saturating_add(dropped);
+        }
+        self.inner.on_end(span);
+    }
+
+    fn force_flush(&self) -> OTelSdkResult {
+        self.inner.force_flush()
+    }
+
+    fn shutdown_with_timeout(&self, timeout: Duration) -> OTelSdkResult {
+        self.inner.shutdown_with_timeout(timeout)
+    }
+
+    fn set_resource(&mut self, resource: &Resource) {
+        self.inner.set_resource(resource);
+    }
+}
+
+/// Limits the SDK applies to every span as it is built, attributes, events and links beyond them
+/// being dropped, the later ones first, and counted in the span's dropped counts. Unset fields keep
+/// the SDK defaults of 128, which honour the `OTEL_SPAN_*_COUNT_LIMIT` variables.
+///
+/// The SDK has no limit on the length of values, so [`max_attribute_value_length`] is enforced by
+/// an [`AttributeLimitProcessor`] when the spans end, along with any [`AttributeLimits`].
+///
+/// [`max_attribute_value_length`]: Self::max_attribute_value_length
+#[derive(Clone, Debug, Default, PartialEq, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SpanLimitSettings {
+    pub max_attributes_per_span: Option<u32>,
+    /// Events kept per span, e.g. the logs recorded while it is entered.
+    pub max_events_per_span: Option<u32>,
+    pub max_links_per_span: Option<u32>,
+    pub max_attributes_per_event: Option<u32>,
+    pub max_attributes_per_link: Option<u32>,
+    /// Longest string value kept on spans and their events, in bytes, longer ones being cut on a
+    /// character boundary.
+    pub max_attribute_value_length: Option<usize>,
+}
+
+impl SpanLimitSettings {
+    pub(crate) fn apply(&self, mut builder: TracerProviderBuilder) -> TracerProviderBuilder {
+        if let Some(max) = self.max_attributes_per_span {
+            builder = builder.with_max_attributes_per_span(max);
+        }
+        if let Some(max) = self.max_events_per_span {
+            builder = builder.with_max_events_per_span(max);
+        }
+        if let Some(max) = self.max_links_per_span {
+            builder = builder.with_max_links_per_span(max);
+        }
+        if let Some(max) = self.max_attributes_per_event {
+            builder = builder.with_max_attributes_per_event(max);
+        }
+        if let Some(max) = self.max_attributes_per_link {
+            builder = builder.with_max_attributes_per_link(max);
+        }
+        builder
+    }
+
+    // The attribute limits to enforce when the spans end, the shorter value length winning when
+    // both set one
+    pub(crate) fn attribute_limits(
+        &self,
+        limits: Option<&AttributeLimits>,
+    ) -> Option<AttributeLimits> {
+        match (limits, self.max_attribute_value_length) {
+            (Some(limits), Some(length)) => Some(AttributeLimits {
+                max_value_length: limits.max_value_length.min(length),
+                ..limits.clone()
+            }),
+            (Some(limits), None) => Some(limits.clone()),
+            (None, Some(length)) => Some(AttributeLimits {
+                max_attributes: usize::MAX,
+                max_value_length: length,
+                hashed_keys: Vec::new(),
+            }),
+            (None, None) => None,
+        }
+    }
+}
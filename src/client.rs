@@ -0,0 +1,93 @@
+use opentelemetry_http::HeaderInjector;
+use reqwest::{Request, RequestBuilder, Response};
+use tracing::field::Empty;
+use tracing::Instrument;
+
+/// [`reqwest::Client`] wrapper that runs every call in a client span and propagates its context
+/// downstream, so the receiving service's spans become children of the current trace.
+#[derive(Clone, Debug, Default)]
+pub struct TracedClient {
+    client: reqwest::Client,
+}
+
+impl TracedClient {
+    pub fn new(client: reqwest::Client) -> Self {
+        Self { client }
+    }
+
+    /// Starts a request; finish it with [`TracedClient::send`] rather than `RequestBuilder::send`.
+    pub fn request(&self, method: reqwest::Method, url: impl reqwest::IntoUrl) -> RequestBuilder {
+        self.client.request(method, url)
+    }
+
+    pub fn get(&self, url: impl reqwest::IntoUrl) -> RequestBuilder {
+        self.request(reqwest::Method::GET, url)
+    }
+
+    pub fn post(&self, url: impl reqwest::IntoUrl) -> RequestBuilder {
+        self.request(reqwest::Method::POST, url)
+    }
+
+    pub async fn send(&self, request: RequestBuilder) -> reqwest::Result<Response> {
+        self.execute(request.build()?).await
+    }
+
+    pub async fn execute(&self, mut request: Request) -> reqwest::Result<Response> {
+        // Credentials embedded in the URL must never end up on a span
+        let mut url = request.url().clone();
+        let _ = url.set_username("");
+        let _ = url.set_password(None);
+
+        let span = tracing::info_span!(
+            "HTTP client request",
+            otel.name = %request.method(),
+            otel.kind = "client",
+            otel.status_code = Empty,
+            http.method = %request.method(),
+            http.url = %url,
+            net.peer.name = url.host_str(),
+            http.status_code = Empty,
+            error.type = Empty,
+        );
+
+        let context = crate::span::otel_context(&span);
+        opentelemetry::global::get_text_map_propagator(|propagator| {
+            propagator.inject_context(&context, &mut HeaderInjector(request.headers_mut()))
+        });
+
+        let result = self.client.execute(request).instrument(span.clone()).await;
+        match &result {
+            Ok(response) => {
+                span.record("http.status_code", i64::from(response.status().as_u16()));
+                if response.status().is_client_error() || response.status().is_server_error() {
+                    span.record("otel.status_code", "ERROR");
+                }
+            }
+            Err(error) => {
+                span.record("otel.status_code", "ERROR");
+                span.record("error.type", error_type(error));
+                tracing::error!(parent: &span, exception.message = %error, "exception");
+            }
+        }
+        result
+    }
+}
+
+// What went wrong in a few words, as OpenTelemetry's `error.type` asks for
+fn error_type(error: &reqwest::Error) -> &'static str {
+    if error.is_timeout() {
+        "timeout"
+    } else if error.is_connect() {
+        "connect"
+    } else if error.is_redirect() {
+        "redirect"
+    } else if error.is_body() {
+        "body"
+    } else if error.is_decode() {
+        "decode"
+    } else if error.is_builder() {
+        "builder"
+    } else {
+        "_OTHER"
+    }
+}
@@ -0,0 +1,195 @@
+use axum::extract::ConnectInfo;
+use axum::http::{HeaderMap, Request};
+use ipnet::IpNet;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, OnceLock};
+use std::task::{Context, Poll};
+use tower::{Layer, Service};
+
+// Set by `Telemetry::init` for `ClientIpLayer::from_telemetry` to pick up
+static CONFIG: OnceLock<ClientIpConfig> = OnceLock::new();
+
+/// Headers a proxy can pass the client address on in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ForwardedHeader {
+    /// `Forwarded: for=192.0.2.60;proto=https`, as in RFC 7239.
+    Forwarded,
+    /// `X-Forwarded-For: 192.0.2.60, 10.0.0.2`.
+    XForwardedFor,
+    /// `X-Real-IP: 192.0.2.60`, as set by nginx.
+    XRealIp,
+}
+
+impl ForwardedHeader {
+    // The addresses the header lists, client first, or `None` when it's missing
+    fn addresses(self, headers: &HeaderMap) -> Option<Vec<Option<IpAddr>>> {
+        let name = match self {
+            Self::Forwarded => "forwarded",
+            Self::XForwardedFor => "x-forwarded-for",
+            Self::XRealIp => "x-real-ip",
+        };
+        let mut values = headers.get_all(name).iter().peekable();
+        values.peek()?;
+
+        let elements = values
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','));
+        let addresses = match self {
+            Self::Forwarded => elements
+                .map(|element| {
+                    element
+                        .split(';')
+                        .filter_map(|pair| pair.split_once('='))
+                        .find(|(key, _)| key.trim().eq_ignore_ascii_case("for"))
+                        .and_then(|(_, value)| parse_address(value.trim().trim_matches('"')))
+                })
+                .collect(),
+            Self::XForwardedFor | Self::XRealIp => elements.map(parse_address).collect(),
+        };
+        Some(addresses)
+    }
+}
+
+// Addresses can come with a port, and IPv6 ones in brackets then
+fn parse_address(value: &str) -> Option<IpAddr> {
+    let value = value.trim();
+    value
+        .parse()
+        .ok()
+        .or_else(|| value.parse::<SocketAddr>().ok().map(|addr| addr.ip()))
+        .or_else(|| value.strip_prefix('[')?.strip_suffix(']')?.parse().ok())
+}
+
+/// How [`ClientIpLayer`] tells the client address from the proxies' in front of the service.
+#[derive(Clone, Debug, PartialEq, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ClientIpConfig {
+    /// Proxies whose forwarding headers are believed, e.g. `10.0.0.0/8` for a load balancer in
+    /// the same network; no peer is trusted by default, so the headers are ignored.
+    pub trusted_proxies: Vec<IpNet>,
+    /// Headers read from trusted proxies, the first one present winning; defaults to `Forwarded`,
+    /// then `X-Forwarded-For`.
+    pub headers: Vec<ForwardedHeader>,
+}
+
+impl Default for ClientIpConfig {
+    fn default() -> Self {
+        Self {
+            trusted_proxies: Vec::new(),
+            headers: vec![ForwardedHeader::Forwarded, ForwardedHeader::XForwardedFor],
+        }
+    }
+}
+
+impl ClientIpConfig {
+    fn is_trusted(&self, ip: IpAddr) -> bool {
+        self.trusted_proxies.iter().any(|proxy| proxy.contains(&ip))
+    }
+
+    /// The client address of a request from `peer`: the peer itself unless it's a trusted proxy,
+    /// otherwise the last address before the trusted proxies in the first forwarding header present.
+    pub fn resolve(&self, peer: IpAddr, headers: &HeaderMap) -> IpAddr {
+        if !self.is_trusted(peer) {
+            return peer;
+        }
+        let Some(addresses) = self
+            .headers
+            .iter()
+            .find_map(|header| header.addresses(headers))
+        else {
+            return peer;
+        };
+
+        // Walked from the nearest hop, as only trusted proxies can be believed about the one before
+        let mut client = peer;
+        for address in addresses.into_iter().rev() {
+            match address {
+                Some(address) => {
+                    client = address;
+                    if !self.is_trusted(address) {
+                        break;
+                    }
+                }
+                // Obfuscated or malformed, so whatever came before it can't be told either
+                None => break,
+            }
+        }
+        client
+    }
+}
+
+pub(crate) fn set_config(config: ClientIpConfig) {
+    let _ = CONFIG.set(config);
+}
+
+/// Address of the client a request came from, as resolved by [`ClientIpLayer`] from the peer and
+/// the trusted proxies' forwarding headers. Read it with `Extension<ClientIp>`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ClientIp(pub IpAddr);
+
+/// Layer resolving the [`ClientIp`] of each request and adding it to the request extensions, where
+/// [`crate::OtelOnRequest`] records it as `client.address`.
+///
+/// The peer address is only known when the server is started with
+/// `into_make_service_with_connect_info::<SocketAddr>()`; without it requests get no [`ClientIp`].
+#[derive(Clone, Debug, Default)]
+pub struct ClientIpLayer {
+    config: Arc<ClientIpConfig>,
+}
+
+impl ClientIpLayer {
+    pub fn new(config: ClientIpConfig) -> Self {
+        Self {
+            config: Arc::new(config),
+        }
+    }
+
+    /// Layer resolving client addresses as configured with [`crate::Telemetry::client_ip`].
+    pub fn from_telemetry() -> Self {
+        Self::new(CONFIG.get().cloned().unwrap_or_default())
+    }
+}
+
+impl<S> Layer<S> for ClientIpLayer {
+    type Service = ResolveClientIp<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ResolveClientIp {
+            inner,
+            config: self.config.clone(),
+        }
+    }
+}
+
+/// Service created by [`ClientIpLayer`].
+#[derive(Clone, Debug)]
+pub struct ResolveClientIp<S> {
+    inner: S,
+    config: Arc<ClientIpConfig>,
+}
+
+impl<S, B> Service<Request<B>> for ResolveClientIp<S>
+where
+    S: Service<Request<B>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut request: Request<B>) -> Self::Future {
+        let peer = request
+            .extensions()
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| addr.ip());
+        if let Some(peer) = peer {
+            let client = self.config.resolve(peer, request.headers());
+            request.extensions_mut().insert(ClientIp(client));
+        }
+        self.inner.call(request)
+    }
+}
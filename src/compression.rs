@@ -0,0 +1,123 @@
+use crate::body::MeteredBody;
+use axum::body::{Body, Bytes, HttpBody};
+use axum::http::header::CONTENT_ENCODING;
+use axum::http::{Request, Response};
+use axum::BoxError;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tower::{Layer, Service};
+use tower_http::compression::{Compression, CompressionLayer};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+/// Layer compressing response bodies with brotli or gzip, whichever the client prefers in its
+/// `accept-encoding` header, leaving alone the small ones, images and event streams; when disabled
+/// every response is sent as is.
+///
+/// The current span gets the chosen encoding as `http.response.content_encoding` and the size of
+/// the body before compression as `http.response.body.uncompressed_size`, to compare with the
+/// `http.response.body.size` sent that [`crate::BodyTimingLayer`] records from outside it.
+#[derive(Clone, Debug)]
+pub struct ResponseCompressionLayer {
+    enabled: bool,
+}
+
+impl ResponseCompressionLayer {
+    pub fn new(enabled: bool) -> Self {
+        Self { enabled }
+    }
+}
+
+impl<S> Layer<S> for ResponseCompressionLayer {
+    type Service = ResponseCompression<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        let compression = CompressionLayer::new().br(self.enabled).gzip(self.enabled);
+        ResponseCompression {
+            inner: compression.layer(UncompressedSize {
+                inner,
+                enabled: self.enabled,
+            }),
+        }
+    }
+}
+
+/// Service created by [`ResponseCompressionLayer`].
+#[derive(Clone)]
+pub struct ResponseCompression<S> {
+    inner: Compression<UncompressedSize<S>>,
+}
+
+impl<S, B, ResBody> Service<Request<B>> for ResponseCompression<S>
+where
+    S: Service<Request<B>, Response = Response<ResBody>>,
+    S::Future: Send + 'static,
+    S::Error: 'static,
+    ResBody: HttpBody<Data = Bytes> + Send + 'static,
+    ResBody::Error: Into<BoxError>,
+{
+    type Response = Response<Body>;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<B>) -> Self::Future {
+        let span = tracing::Span::current();
+        let future = self.inner.call(request);
+
+        Box::pin(async move {
+            let response = future.await?;
+            if let Some(encoding) = response
+                .headers()
+                .get(CONTENT_ENCODING)
+                .and_then(|value| value.to_str().ok())
+            {
+                span.set_attribute("http.response.content_encoding", encoding.to_string());
+            }
+            Ok(response.map(Body::new))
+        })
+    }
+}
+
+// Counts the bytes of the body handed to the compression
+#[derive(Clone, Debug)]
+struct UncompressedSize<S> {
+    inner: S,
+    enabled: bool,
+}
+
+impl<S, B, ResBody> Service<Request<B>> for UncompressedSize<S>
+where
+    S: Service<Request<B>, Response = Response<ResBody>>,
+    S::Future: Send + 'static,
+    ResBody: HttpBody<Data = Bytes> + Send + 'static,
+    ResBody::Error: Into<BoxError>,
+{
+    type Response = Response<Body>;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<B>) -> Self::Future {
+        let span = self.enabled.then(tracing::Span::current);
+        let future = self.inner.call(request);
+
+        Box::pin(async move {
+            let response = future.await?;
+            let Some(span) = span else {
+                return Ok(response.map(Body::new));
+            };
+            Ok(response.map(|body| {
+                Body::new(MeteredBody::new(Body::new(body), move |bytes| {
+                    span.set_attribute("http.response.body.uncompressed_size", bytes as i64);
+                }))
+            }))
+        })
+    }
+}
@@ -0,0 +1,690 @@
+use crate::authz::AuditSink;
+use crate::body_limit::RouteBodyLimit;
+use crate::cardinality::{AttributeLimits, SpanLimitSettings};
+use crate::client_ip::ClientIpConfig;
+use crate::config_file::FileConfig;
+use crate::connectivity::ConnectivityCheck;
+use crate::cors::PreflightSpans;
+use crate::headers::HeaderCaptureConfig;
+use crate::ids::IdGenerator;
+use crate::rate_limit::RateLimit;
+use crate::redact::RedactionRule;
+use crate::retry::RetryPolicy;
+use crate::sampling::{RouteRule, TailSampling};
+use crate::spill::SpillConfig;
+use crate::timeout::RouteTimeout;
+use crate::{
+    BatchSettings, CompressionSettings, ExporterBackend, PropagationFormat, ProxySettings,
+    SamplingStrategy, TlsSettings,
+};
+use axum::http::StatusCode;
+use opentelemetry_otlp::Protocol;
+use regex::Regex;
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::LazyLock;
+use std::time::Duration;
+use tower_http::cors::CorsLayer;
+
+/// Environment variable holding the Honeycomb API key.
+pub const HONEYCOMB_API_KEY: &str = "HONEYCOMB_API_KEY";
+/// Environment variable naming the Honeycomb dataset, which only classic keys need.
+pub const HONEYCOMB_DATASET: &str = "HONEYCOMB_DATASET";
+
+#[derive(Debug, thiserror::Error)]
+pub enum ConfigError {
+    #[error("environment variable {0} is not set")]
+    MissingEnv(String),
+    #[error("failed to read {}: {source}", path.display())]
+    ReadFile {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("API key is empty")]
+    EmptyApiKey,
+    #[error(
+        "API key isn't a Honeycomb key: expected 32 hex or 22 alphanumeric characters, or 64 for an \
+         ingest key, but got {length} characters"
+    )]
+    InvalidApiKey { length: usize },
+    #[error("failed to parse {}: {message}", path.display())]
+    InvalidFile { path: PathBuf, message: String },
+    #[error("invalid setting: {0}")]
+    InvalidSetting(String),
+    #[error("invalid certificate or key in {}: {message}", path.display())]
+    InvalidCertificate { path: PathBuf, message: String },
+    #[error(transparent)]
+    Unreachable(#[from] crate::connectivity::Unreachable),
+    #[error("a global tracing subscriber is already installed")]
+    SubscriberInstalled,
+}
+
+/// Where to load an API key from at startup, so secrets never have to be compiled into the binary.
+pub enum ApiKeySource {
+    /// The value of the named environment variable.
+    Env(String),
+    /// The contents of a file holding only the key.
+    File(PathBuf),
+    /// The key itself.
+    Literal(String),
+}
+
+impl ApiKeySource {
+    /// Loads the key, trimming the surrounding whitespace (such as a trailing newline in a key file).
+    pub fn load(&self) -> Result<String, ConfigError> {
+        let key = match self {
+            ApiKeySource::Env(name) => {
+                std::env::var(name).map_err(|_| ConfigError::MissingEnv(name.clone()))?
+            }
+            ApiKeySource::File(path) => {
+                std::fs::read_to_string(path).map_err(|source| ConfigError::ReadFile {
+                    path: path.clone(),
+                    source,
+                })?
+            }
+            ApiKeySource::Literal(key) => key.clone(),
+        };
+
+        let key = key.trim();
+        if key.is_empty() {
+            return Err(ConfigError::EmptyApiKey);
+        }
+        Ok(key.to_string())
+    }
+}
+
+// Classic configuration and ingest keys
+static CLASSIC_KEY: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new("^(?:[0-9a-f]{32}|hc[a-z]ic_[0-9a-z]{58})$").unwrap());
+// Environment configuration and ingest keys
+static ENVIRONMENT_KEY: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new("^(?:[0-9A-Za-z]{22}|hc[a-z]ik_[0-9a-z]{58})$").unwrap());
+
+/// Kind of Honeycomb API key, deciding which signals need the dataset sent along.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HoneycombKeyKind {
+    /// Key of a classic team, whose data goes to the dataset named in `x-honeycomb-dataset`.
+    Classic,
+    /// Key of an environment, whose spans and logs go to a dataset named after their service.
+    Environment,
+}
+
+impl HoneycombKeyKind {
+    /// Tells the kind of `key` from its format: 32 hex characters for classic configuration keys and 22
+    /// alphanumeric ones for environment ones, while ingest keys start with `hcxic_` or `hcxik_`
+    /// respectively. Keys in neither format, such as truncated ones, are rejected.
+    pub fn detect(key: &str) -> Result<Self, ConfigError> {
+        if CLASSIC_KEY.is_match(key) {
+            Ok(HoneycombKeyKind::Classic)
+        } else if ENVIRONMENT_KEY.is_match(key) {
+            Ok(HoneycombKeyKind::Environment)
+        } else {
+            Err(ConfigError::InvalidApiKey { length: key.len() })
+        }
+    }
+}
+
+/// Settings for the telemetry pipeline, either built in code or read from the standard `OTEL_*` variables.
+pub struct TelemetryConfig {
+    pub service_name: String,
+    /// Deployment environment, such as `dev`, `staging` or `prod`.
+    pub environment: Option<String>,
+    /// Further resource attributes, overriding the detected ones.
+    pub resource_attributes: HashMap<String, String>,
+    pub exporter: ExporterBackend,
+    /// Further backends receiving the spans, but not the metrics or logs.
+    pub extra_exporters: Vec<ExporterBackend>,
+    /// Transport used by the Honeycomb exporter, which is HTTP unless the `grpc` feature is on; the
+    /// OTLP exporters pick theirs explicitly.
+    pub protocol: Protocol,
+    /// Certificates the OTLP exporters use over `https://`.
+    pub tls: TlsSettings,
+    /// Proxy for the OTLP/HTTP exporters, overriding the `HTTPS_PROXY` variables.
+    pub proxy: Option<ProxySettings>,
+    /// How the OTLP export payloads are compressed; they are sent uncompressed when unset.
+    pub compression: Option<CompressionSettings>,
+    /// Whether the OTLP backends are probed at startup, and what happens when they can't be reached.
+    pub connectivity_check: Option<ConnectivityCheck>,
+    /// How spans are batched before export.
+    pub batch: BatchSettings,
+    /// How failed OTLP span exports are retried.
+    pub retry: RetryPolicy,
+    /// Where OTLP span batches that can't be exported are kept for replay; they are dropped when unset.
+    pub spill: Option<SpillConfig>,
+    pub sampler: SamplingStrategy,
+    /// Per-route sample rates overriding `sampler` for matching requests.
+    pub route_sampling: Vec<RouteRule>,
+    /// When set, spans are held per trace and only errored, slow or sampled traces are exported.
+    pub tail_sampling: Option<TailSampling>,
+    /// Where trace and span IDs come from, random ones when unset.
+    pub id_generator: Option<Arc<dyn IdGenerator>>,
+    pub propagation: Vec<PropagationFormat>,
+    /// Baggage entries copied onto every span as attributes.
+    pub baggage_attributes: Vec<String>,
+    /// Rules scrubbing attribute values from spans before they are exported.
+    pub redaction: Vec<RedactionRule>,
+    /// Bounds on the attributes of exported spans, which are left as recorded when unset.
+    pub attribute_limits: Option<AttributeLimits>,
+    /// Limits on the attributes, events and links kept per span; the SDK defaults when unset.
+    pub span_limits: SpanLimitSettings,
+    /// Headers recorded on the request spans.
+    pub headers: HeaderCaptureConfig,
+    /// Proxies trusted to report the client address, recorded as `client.address`.
+    pub client_ip: ClientIpConfig,
+    /// How long requests may run before they are answered with
+    /// [`timeout_status`](Self::timeout_status).
+    pub request_timeout: Option<Duration>,
+    /// Timeouts for the routes that need a different one than
+    /// [`request_timeout`](Self::request_timeout).
+    pub route_timeouts: Vec<RouteTimeout>,
+    /// Status answering timed out requests; defaults to 408 Request Timeout.
+    pub timeout_status: StatusCode,
+    /// Requests handled at once, beyond which new ones are answered with 503 Service Unavailable.
+    pub max_concurrent_requests: Option<usize>,
+    /// Request body size, in bytes, beyond which requests are answered with 413 Payload Too Large.
+    pub max_request_body: Option<usize>,
+    /// Body size limits for the routes that need a different one than
+    /// [`max_request_body`](Self::max_request_body).
+    pub route_body_limits: Vec<RouteBodyLimit>,
+    /// Requests allowed per client, beyond which they are answered with 429 Too Many Requests.
+    pub rate_limit: Option<RateLimit>,
+    /// Whether response bodies are compressed for the clients accepting it.
+    pub response_compression: bool,
+    /// CORS policy answering preflights and adding the CORS headers to responses.
+    pub cors: Option<CorsLayer>,
+    /// What becomes of the spans of CORS preflights.
+    pub preflight_spans: PreflightSpans,
+    /// Where authorization decisions are sent for auditing, besides the request spans.
+    pub audit_sink: Option<Arc<dyn AuditSink>>,
+    /// `RUST_LOG`-style directive selecting the spans and events that are recorded.
+    pub log_filter: String,
+    /// Directive narrowing what is exported over OpenTelemetry.
+    pub otel_filter: String,
+    /// Directive narrowing what the JSON logs print.
+    #[cfg(feature = "fmt")]
+    pub console_filter: String,
+    /// Address the application is served on, `0.0.0.0:3000` by default. Port 0 binds any free port,
+    /// e.g. for tests running several instances at once.
+    pub bind_address: SocketAddr,
+    /// How long shutdown waits for pending telemetry to be flushed.
+    pub shutdown_timeout: Duration,
+    /// How long requests in flight get to finish once the shutdown started.
+    pub drain_timeout: Duration,
+    /// Whether `SIGUSR1`, `SIGUSR2` and `SIGQUIT` dump the telemetry stats, flush and dump the
+    /// runtime stats.
+    pub signal_handlers: bool,
+    /// Whether to export request metrics alongside the traces.
+    #[cfg(feature = "metrics")]
+    pub metrics: bool,
+    /// Whether to record metrics for the Prometheus endpoint served by [`crate::metrics::prometheus_router`].
+    #[cfg(feature = "metrics")]
+    pub prometheus: bool,
+    /// How often to sample the tokio runtime metrics, or `None` not to record them.
+    #[cfg(feature = "metrics")]
+    pub runtime_metrics: Option<Duration>,
+    /// Whether to record the CPU, memory and file descriptor use of the process.
+    #[cfg(feature = "metrics")]
+    pub process_metrics: bool,
+    /// Whether to serve task diagnostics to `tokio-console`.
+    #[cfg(feature = "console")]
+    pub tokio_console: bool,
+    /// Whether to ship `tracing` events as OpenTelemetry log records.
+    #[cfg(feature = "logs")]
+    pub logs: bool,
+    /// Whether to print events to stdout as JSON lines.
+    #[cfg(feature = "fmt")]
+    pub json_logs: bool,
+}
+
+impl TelemetryConfig {
+    pub fn new(exporter: ExporterBackend) -> Self {
+        Self {
+            service_name: DEFAULT_SERVICE_NAME.to_string(),
+            environment: None,
+            resource_attributes: HashMap::new(),
+            exporter,
+            extra_exporters: Vec::new(),
+            protocol: Protocol::HttpBinary,
+            tls: TlsSettings::default(),
+            proxy: None,
+            compression: None,
+            connectivity_check: None,
+            batch: BatchSettings::default(),
+            retry: RetryPolicy::default(),
+            spill: None,
+            sampler: SamplingStrategy::default(),
+            route_sampling: Vec::new(),
+            tail_sampling: None,
+            id_generator: None,
+            propagation: vec![PropagationFormat::W3C, PropagationFormat::Baggage],
+            baggage_attributes: Vec::new(),
+            redaction: Vec::new(),
+            attribute_limits: None,
+            span_limits: SpanLimitSettings::default(),
+            headers: HeaderCaptureConfig::default(),
+            client_ip: ClientIpConfig::default(),
+            request_timeout: None,
+            route_timeouts: Vec::new(),
+            timeout_status: StatusCode::REQUEST_TIMEOUT,
+            max_concurrent_requests: None,
+            max_request_body: None,
+            route_body_limits: Vec::new(),
+            rate_limit: None,
+            response_compression: true,
+            cors: None,
+            preflight_spans: PreflightSpans::default(),
+            audit_sink: None,
+            log_filter: "trace".to_string(),
+            otel_filter: "info".to_string(),
+            #[cfg(feature = "fmt")]
+            console_filter: "debug".to_string(),
+            bind_address: DEFAULT_BIND_ADDRESS,
+            shutdown_timeout: crate::shutdown::DEFAULT_TIMEOUT,
+            drain_timeout: crate::shutdown::DEFAULT_DRAIN_TIMEOUT,
+            signal_handlers: false,
+            #[cfg(feature = "metrics")]
+            metrics: false,
+            #[cfg(feature = "metrics")]
+            prometheus: false,
+            #[cfg(feature = "metrics")]
+            runtime_metrics: None,
+            #[cfg(feature = "metrics")]
+            process_metrics: false,
+            #[cfg(feature = "console")]
+            tokio_console: false,
+            #[cfg(feature = "logs")]
+            logs: false,
+            #[cfg(feature = "fmt")]
+            json_logs: false,
+        }
+    }
+
+    /// Reads the configuration from the environment.
+    ///
+    /// Exports to Honeycomb when `HONEYCOMB_API_KEY` is set, into the `HONEYCOMB_DATASET` dataset for
+    /// classic keys, otherwise over OTLP/HTTP to a local collector.
+    pub fn from_env() -> Self {
+        Self::new(default_exporter()).with_env()
+    }
+
+    /// Reads the configuration from a TOML or YAML file, picked by its extension, then applies
+    /// [`with_env`](Self::with_env) so the environment variables still override it, e.g.
+    ///
+    /// ```toml
+    /// service_name = "pick-list"
+    /// environment = "prod"
+    /// propagation = ["tracecontext", "b3"]
+    /// route_sampling = [{ prefix = "/healthz", ratio = 0.01 }]
+    ///
+    /// [exporter]
+    /// type = "otlp_grpc"
+    /// endpoint = "https://collector:4317"
+    ///
+    /// [tls]
+    /// ca_certificate = "/etc/ssl/internal-ca.pem"
+    ///
+    /// [sampler]
+    /// name = "parentbased_traceidratio"
+    /// arg = "0.1"
+    ///
+    /// [resource]
+    /// team = "checkout"
+    ///
+    /// [[redaction]]
+    /// preset = "defaults"
+    ///
+    /// [[redaction]]
+    /// pattern = "sk_live_\\w+"
+    /// action = "drop"
+    ///
+    /// [attribute_limits]
+    /// max_value_length = 1024
+    /// hashed_keys = ["session.id"]
+    ///
+    /// [span_limits]
+    /// max_events_per_span = 32
+    /// ```
+    ///
+    /// Every setting is optional. The exporter `type` is the snake case name of an
+    /// [`ExporterBackend`] variant, with its fields alongside; `honeycomb` reads its key from
+    /// `api_key_file` or `HONEYCOMB_API_KEY` and rejects keys in neither Honeycomb format. `tls`,
+    /// `proxy`, `compression`, `attribute_limits` and `span_limits` take the [`TlsSettings`],
+    /// [`ProxySettings`], [`CompressionSettings`], [`AttributeLimits`] and [`SpanLimitSettings`]
+    /// fields. The filters and the `metrics`, `prometheus`, `logs` and `json_logs` switches can be
+    /// set too. Without an exporter, the one [`from_env`](Self::from_env) would pick is used.
+    /// Settings for backends or signals whose Cargo feature is off are rejected.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        let file = FileConfig::read(path.as_ref())?;
+        let mut config = Self::new(file.exporter()?.unwrap_or_else(default_exporter));
+        file.apply(&mut config)?;
+        Ok(config.with_env())
+    }
+
+    /// Checks the settings the exporters would otherwise only trip over once they are built or
+    /// exporting: the Honeycomb API keys being in a [`HoneycombKeyKind`] format, the Jaeger
+    /// endpoints having a host and a port receiving OTLP, the Zipkin endpoints being URLs, the
+    /// headers of the OTLP/gRPC exporters being valid gRPC metadata, the [`TlsSettings`] files
+    /// being readable PEM certificates and keys, the [`ProxySettings`] URL parsing and the
+    /// [`CompressionSettings`] level being in range, so [`Telemetry::init`] fails instead of
+    /// panicking, including for the key [`from_env`](Self::from_env) reads.
+    ///
+    /// [`Telemetry::init`]: crate::Telemetry::init
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        for exporter in std::iter::once(&self.exporter).chain(&self.extra_exporters) {
+            exporter.validate()?;
+            // The headers are the same for every signal but the Honeycomb metrics dataset
+            #[cfg(feature = "grpc")]
+            for signal in ["traces", "metrics"] {
+                match exporter.otlp_target(self, signal) {
+                    Some(target) if target.protocol == Protocol::Grpc => drop(target.metadata()?),
+                    _ => {}
+                }
+            }
+        }
+        self.tls.validate()?;
+        if let Some(proxy) = &self.proxy {
+            proxy.validate()?;
+        }
+        if let Some(compression) = &self.compression {
+            compression.validate()?;
+        }
+        Ok(())
+    }
+
+    /// Overrides any settings whose `OTEL_*` variable is set, leaving the rest untouched.
+    ///
+    /// `DEPLOYMENT_ENVIRONMENT` sets the environment, and `BIND_ADDR` the bind address. `RUST_LOG`
+    /// sets the filter for everything that is recorded, and `RUST_LOG_OTEL` and `RUST_LOG_CONSOLE`
+    /// narrow it further for the OpenTelemetry export and the JSON logs.
+    ///
+    /// Setting `OTEL_EXPORTER_OTLP_ENDPOINT` switches the exporter to OTLP against that collector, over
+    /// gRPC when `OTEL_EXPORTER_OTLP_PROTOCOL` is `grpc` and the `grpc` feature is on, and otherwise
+    /// over HTTP with `/v1/traces` appended. `OTEL_EXPORTER_OTLP_CERTIFICATE`,
+    /// `OTEL_EXPORTER_OTLP_CLIENT_CERTIFICATE` and `OTEL_EXPORTER_OTLP_CLIENT_KEY` set the
+    /// [`TlsSettings`] paths.
+    pub fn with_env(mut self) -> Self {
+        if let Some(service_name) = env_var(OTEL_SERVICE_NAME) {
+            self.service_name = service_name;
+        }
+        if let Some(environment) = env_var(DEPLOYMENT_ENVIRONMENT) {
+            self.environment = Some(environment);
+        }
+
+        // Invalid addresses are ignored like unknown sampler names, so the configured one is used
+        if let Some(address) = env_var(BIND_ADDR).and_then(|address| address.parse().ok()) {
+            self.bind_address = address;
+        }
+
+        match env_var(OTEL_EXPORTER_OTLP_PROTOCOL).as_deref() {
+            #[cfg(feature = "grpc")]
+            Some("grpc") => self.protocol = Protocol::Grpc,
+            Some("http/protobuf") => self.protocol = Protocol::HttpBinary,
+            _ => {}
+        }
+
+        let env_headers = env_var(OTEL_EXPORTER_OTLP_HEADERS)
+            .map(|headers| parse_headers(&headers))
+            .unwrap_or_default();
+
+        if let (Some(name), ExporterBackend::Honeycomb { dataset, .. }) =
+            (env_var(HONEYCOMB_DATASET), &mut self.exporter)
+        {
+            *dataset = Some(name);
+        }
+
+        if let Some(endpoint) = env_var(OTEL_EXPORTER_OTLP_ENDPOINT) {
+            // Keep the Honeycomb key and dataset so pointing at another Honeycomb region only needs the
+            // endpoint
+            let mut headers = match &self.exporter {
+                ExporterBackend::Honeycomb { api_key, dataset } => {
+                    let mut headers =
+                        HashMap::from([(HONEYCOMB_TEAM_HEADER.to_string(), api_key.clone())]);
+                    if let Some(dataset) = dataset {
+                        headers.insert(HONEYCOMB_DATASET_HEADER.to_string(), dataset.clone());
+                    }
+                    headers
+                }
+                #[cfg(feature = "grpc")]
+                ExporterBackend::OtlpGrpc { headers, .. } => headers.clone(),
+                ExporterBackend::OtlpHttp { headers, .. } => headers.clone(),
+                _ => HashMap::new(),
+            };
+            headers.extend(env_headers);
+
+            self.exporter = match self.protocol {
+                #[cfg(feature = "grpc")]
+                Protocol::Grpc => ExporterBackend::OtlpGrpc { endpoint, headers },
+                _ => ExporterBackend::OtlpHttp {
+                    endpoint: format!("{}{OTLP_HTTP_TRACES_PATH}", endpoint.trim_end_matches('/')),
+                    headers,
+                },
+            };
+        } else {
+            match &mut self.exporter {
+                #[cfg(feature = "grpc")]
+                ExporterBackend::OtlpGrpc { headers, .. } => headers.extend(env_headers),
+                ExporterBackend::OtlpHttp { headers, .. } => headers.extend(env_headers),
+                _ => {}
+            }
+        }
+
+        if let Some(path) = env_var(OTEL_EXPORTER_OTLP_CERTIFICATE) {
+            self.tls.ca_certificate = Some(path.into());
+        }
+        if let Some(path) = env_var(OTEL_EXPORTER_OTLP_CLIENT_CERTIFICATE) {
+            self.tls.client_certificate = Some(path.into());
+        }
+        if let Some(path) = env_var(OTEL_EXPORTER_OTLP_CLIENT_KEY) {
+            self.tls.client_key = Some(path.into());
+        }
+
+        // `console` is the name other SDKs use for printing spans, for local development
+        match env_var(OTEL_TRACES_EXPORTER).as_deref() {
+            Some("console") => self.exporter = ExporterBackend::Stdout,
+            #[cfg(feature = "zipkin")]
+            Some("zipkin") => {
+                self.exporter = ExporterBackend::Zipkin {
+                    endpoint: env_var(OTEL_EXPORTER_ZIPKIN_ENDPOINT)
+                        .unwrap_or_else(|| DEFAULT_ZIPKIN_ENDPOINT.to_string()),
+                }
+            }
+            #[cfg(feature = "jaeger")]
+            Some("jaeger") => self.exporter = jaeger_from_env(),
+            Some("none") => self.exporter = ExporterBackend::None,
+            _ => {}
+        }
+
+        // Unknown sampler names are ignored so a typo falls back to the configured strategy instead of
+        // failing startup
+        if let Some(sampler) = env_var(OTEL_TRACES_SAMPLER) {
+            let arg = env_var(OTEL_TRACES_SAMPLER_ARG);
+            if let Some(sampler) = SamplingStrategy::from_otel_names(&sampler, arg.as_deref()) {
+                self.sampler = sampler;
+            }
+        }
+
+        #[cfg(feature = "metrics")]
+        if let Some(exporters) = env_var(OTEL_METRICS_EXPORTER) {
+            let exporters: Vec<&str> = exporters.split(',').map(str::trim).collect();
+            self.metrics = exporters.contains(&"otlp");
+            self.prometheus = exporters.contains(&"prometheus");
+        }
+
+        #[cfg(feature = "logs")]
+        match env_var(OTEL_LOGS_EXPORTER).as_deref() {
+            Some("otlp") => self.logs = true,
+            Some("none") => self.logs = false,
+            _ => {}
+        }
+
+        #[cfg(feature = "fmt")]
+        match env_var(LOG_FORMAT).as_deref() {
+            Some("json") => self.json_logs = true,
+            Some("none") => self.json_logs = false,
+            _ => {}
+        }
+
+        if let Some(directive) = env_var(RUST_LOG) {
+            self.log_filter = directive;
+        }
+        if let Some(directive) = env_var(RUST_LOG_OTEL) {
+            self.otel_filter = directive;
+        }
+        #[cfg(feature = "fmt")]
+        if let Some(directive) = env_var(RUST_LOG_CONSOLE) {
+            self.console_filter = directive;
+        }
+
+        // Names we don't support, such as `xray`, are skipped rather than failing startup
+        if let Some(propagators) = env_var(OTEL_PROPAGATORS) {
+            self.propagation = propagators
+                .split(',')
+                .filter_map(|name| PropagationFormat::from_otel_name(name.trim()))
+                .collect();
+        }
+
+        self
+    }
+}
+
+pub(crate) const HONEYCOMB_TEAM_HEADER: &str = "x-honeycomb-team";
+pub(crate) const HONEYCOMB_DATASET_HEADER: &str = "x-honeycomb-dataset";
+const DEFAULT_SERVICE_NAME: &str = "Pick List";
+const DEFAULT_BIND_ADDRESS: SocketAddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), 3000);
+const DEFAULT_OTLP_HTTP_ENDPOINT: &str = "http://localhost:4318";
+const OTLP_HTTP_TRACES_PATH: &str = "/v1/traces";
+#[cfg(feature = "zipkin")]
+const DEFAULT_ZIPKIN_ENDPOINT: &str = "http://localhost:9411/api/v2/spans";
+
+const BIND_ADDR: &str = "BIND_ADDR";
+const DEPLOYMENT_ENVIRONMENT: &str = "DEPLOYMENT_ENVIRONMENT";
+#[cfg(feature = "fmt")]
+const LOG_FORMAT: &str = "LOG_FORMAT";
+const RUST_LOG: &str = "RUST_LOG";
+#[cfg(feature = "fmt")]
+const RUST_LOG_CONSOLE: &str = "RUST_LOG_CONSOLE";
+const RUST_LOG_OTEL: &str = "RUST_LOG_OTEL";
+const OTEL_SERVICE_NAME: &str = "OTEL_SERVICE_NAME";
+const OTEL_EXPORTER_OTLP_ENDPOINT: &str = "OTEL_EXPORTER_OTLP_ENDPOINT";
+const OTEL_EXPORTER_OTLP_PROTOCOL: &str = "OTEL_EXPORTER_OTLP_PROTOCOL";
+const OTEL_EXPORTER_OTLP_HEADERS: &str = "OTEL_EXPORTER_OTLP_HEADERS";
+const OTEL_EXPORTER_OTLP_CERTIFICATE: &str = "OTEL_EXPORTER_OTLP_CERTIFICATE";
+const OTEL_EXPORTER_OTLP_CLIENT_CERTIFICATE: &str = "OTEL_EXPORTER_OTLP_CLIENT_CERTIFICATE";
+const OTEL_EXPORTER_OTLP_CLIENT_KEY: &str = "OTEL_EXPORTER_OTLP_CLIENT_KEY";
+#[cfg(feature = "jaeger")]
+const OTEL_EXPORTER_JAEGER_AGENT_HOST: &str = "OTEL_EXPORTER_JAEGER_AGENT_HOST";
+#[cfg(feature = "jaeger")]
+const OTEL_EXPORTER_JAEGER_AGENT_PORT: &str = "OTEL_EXPORTER_JAEGER_AGENT_PORT";
+#[cfg(feature = "jaeger")]
+const OTEL_EXPORTER_JAEGER_ENDPOINT: &str = "OTEL_EXPORTER_JAEGER_ENDPOINT";
+#[cfg(feature = "zipkin")]
+const OTEL_EXPORTER_ZIPKIN_ENDPOINT: &str = "OTEL_EXPORTER_ZIPKIN_ENDPOINT";
+#[cfg(feature = "logs")]
+const OTEL_LOGS_EXPORTER: &str = "OTEL_LOGS_EXPORTER";
+#[cfg(feature = "metrics")]
+const OTEL_METRICS_EXPORTER: &str = "OTEL_METRICS_EXPORTER";
+const OTEL_PROPAGATORS: &str = "OTEL_PROPAGATORS";
+const OTEL_TRACES_EXPORTER: &str = "OTEL_TRACES_EXPORTER";
+const OTEL_TRACES_SAMPLER: &str = "OTEL_TRACES_SAMPLER";
+const OTEL_TRACES_SAMPLER_ARG: &str = "OTEL_TRACES_SAMPLER_ARG";
+
+// Honeycomb when its key is set, otherwise a local collector
+fn default_exporter() -> ExporterBackend {
+    match ApiKeySource::Env(HONEYCOMB_API_KEY.to_string()).load() {
+        Ok(api_key) => ExporterBackend::Honeycomb {
+            api_key,
+            dataset: None,
+        },
+        Err(_) => ExporterBackend::OtlpHttp {
+            endpoint: format!("{DEFAULT_OTLP_HTTP_ENDPOINT}{OTLP_HTTP_TRACES_PATH}"),
+            headers: HashMap::new(),
+        },
+    }
+}
+
+// Prefers the collector, as the spec does, when its endpoint is set
+#[cfg(feature = "jaeger")]
+fn jaeger_from_env() -> ExporterBackend {
+    if let Some(endpoint) = env_var(OTEL_EXPORTER_JAEGER_ENDPOINT) {
+        return ExporterBackend::JaegerCollector { endpoint };
+    }
+    // Without a port, Jaeger's OTLP one is used rather than the agent's, which takes no OTLP
+    let host = env_var(OTEL_EXPORTER_JAEGER_AGENT_HOST).unwrap_or_else(|| "localhost".to_string());
+    let endpoint = match env_var(OTEL_EXPORTER_JAEGER_AGENT_PORT) {
+        Some(port) => format!("{host}:{port}"),
+        None => host,
+    };
+    ExporterBackend::JaegerAgent { endpoint }
+}
+
+// Unset and empty variables are treated the same, as the OpenTelemetry spec asks
+fn env_var(name: &str) -> Option<String> {
+    std::env::var(name).ok().filter(|value| !value.is_empty())
+}
+
+// `OTEL_EXPORTER_OTLP_HEADERS` is a comma-separated list of `key=value` pairs, percent-encoded so
+// that values can hold commas and equal signs
+fn parse_headers(headers: &str) -> HashMap<String, String> {
+    let decode = |part: &str| {
+        percent_encoding::percent_decode_str(part.trim())
+            .decode_utf8_lossy()
+            .into_owned()
+    };
+    headers
+        .split(',')
+        .filter_map(|pair| pair.split_once('='))
+        .map(|(key, value)| (decode(key), decode(value)))
+        .filter(|(key, _)| !key.is_empty())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tells_classic_keys_from_environment_ones() {
+        let ingest = |prefix: &str| format!("{prefix}_{}", "0a".repeat(29));
+        assert_eq!(
+            HoneycombKeyKind::detect(&"0123456789abcdef".repeat(2)).unwrap(),
+            HoneycombKeyKind::Classic
+        );
+        assert_eq!(
+            HoneycombKeyKind::detect(&ingest("hcaic")).unwrap(),
+            HoneycombKeyKind::Classic
+        );
+        assert_eq!(
+            HoneycombKeyKind::detect("AbCdEfGhIjKlMnOpQrStUv").unwrap(),
+            HoneycombKeyKind::Environment
+        );
+        assert_eq!(
+            HoneycombKeyKind::detect(&ingest("hcaik")).unwrap(),
+            HoneycombKeyKind::Environment
+        );
+
+        // Truncated, padded or upper case classic keys are neither
+        for key in [
+            "0123456789abcdef",
+            " AbCdEfGhIjKlMnOpQrStUv",
+            &"0123456789ABCDEF".repeat(2),
+        ] {
+            assert!(matches!(
+                HoneycombKeyKind::detect(key),
+                Err(ConfigError::InvalidApiKey { length }) if length == key.len()
+            ));
+        }
+    }
+    #[test]
+    fn decodes_the_otlp_headers() {
+        let headers = parse_headers(" api-key = abc%3D%3D , x-tags=a%2Cb%20c,=orphan,malformed");
+        assert_eq!(
+            headers,
+            HashMap::from([
+                ("api-key".to_string(), "abc==".to_string()),
+                ("x-tags".to_string(), "a,b c".to_string()),
+            ])
+        );
+    }
+}
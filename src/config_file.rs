@@ -0,0 +1,403 @@
+use crate::cardinality::{AttributeLimits, SpanLimitSettings};
+use crate::client_ip::ClientIpConfig;
+use crate::config::{ApiKeySource, ConfigError, HoneycombKeyKind, HONEYCOMB_API_KEY};
+use crate::connectivity::ConnectivityCheck;
+use crate::cors::PreflightSpans;
+use crate::redact::{RedactionAction, RedactionRule};
+use crate::sampling::RouteRule;
+use crate::{
+    CompressionSettings, ExporterBackend, PropagationFormat, ProxySettings, SamplingStrategy,
+    TelemetryConfig, TlsSettings,
+};
+use opentelemetry_otlp::Protocol;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+
+// Every setting is optional, leaving the configuration it is applied to untouched when missing
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct FileConfig {
+    service_name: Option<String>,
+    environment: Option<String>,
+    exporter: Option<FileExporter>,
+    /// `grpc` or `http/protobuf`, as in `OTEL_EXPORTER_OTLP_PROTOCOL`.
+    protocol: Option<String>,
+    tls: Option<TlsSettings>,
+    proxy: Option<ProxySettings>,
+    compression: Option<CompressionSettings>,
+    /// `fail_fast` or `fail_open`.
+    connectivity_check: Option<ConnectivityCheck>,
+    sampler: Option<FileSampler>,
+    route_sampling: Option<Vec<RouteRule>>,
+    /// Names as in `OTEL_PROPAGATORS`.
+    propagation: Option<Vec<String>>,
+    baggage_attributes: Option<Vec<String>>,
+    client_ip: Option<ClientIpConfig>,
+    response_compression: Option<bool>,
+    /// `record`, `skip` or `collapse`.
+    preflight_spans: Option<PreflightSpans>,
+    /// E.g. `127.0.0.1:8080`.
+    bind_address: Option<SocketAddr>,
+    resource: HashMap<String, String>,
+    redaction: Option<Vec<FileRedaction>>,
+    attribute_limits: Option<AttributeLimits>,
+    span_limits: Option<SpanLimitSettings>,
+    log_filter: Option<String>,
+    otel_filter: Option<String>,
+    console_filter: Option<String>,
+    metrics: Option<bool>,
+    prometheus: Option<bool>,
+    logs: Option<bool>,
+    json_logs: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum FileExporter {
+    /// The key is read from `api_key_file` when given, and from `HONEYCOMB_API_KEY` otherwise, so it
+    /// never has to be written in the configuration.
+    Honeycomb {
+        api_key_file: Option<PathBuf>,
+        dataset: Option<String>,
+    },
+    #[cfg(feature = "grpc")]
+    OtlpGrpc {
+        endpoint: String,
+        #[serde(default)]
+        headers: HashMap<String, String>,
+    },
+    OtlpHttp {
+        endpoint: String,
+        #[serde(default)]
+        headers: HashMap<String, String>,
+    },
+    #[cfg(feature = "jaeger")]
+    JaegerAgent {
+        endpoint: String,
+    },
+    #[cfg(feature = "jaeger")]
+    JaegerCollector {
+        endpoint: String,
+    },
+    #[cfg(feature = "zipkin")]
+    Zipkin {
+        endpoint: String,
+    },
+    Stdout,
+    StdoutPretty,
+    File {
+        directory: PathBuf,
+        prefix: String,
+    },
+    None,
+}
+
+/// Either one of the built-in rules, e.g. `{ preset = "emails" }`, or a pattern, e.g.
+/// `{ pattern = "secret-\\w+", action = "drop" }`.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum FileRedaction {
+    Preset {
+        /// `emails`, `bearer_tokens`, `credit_cards` or `defaults` for all three.
+        preset: String,
+    },
+    Pattern {
+        pattern: String,
+        /// `mask`, the default, or `drop`.
+        action: Option<String>,
+    },
+}
+
+/// Names as in `OTEL_TRACES_SAMPLER` and `OTEL_TRACES_SAMPLER_ARG`.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FileSampler {
+    name: String,
+    arg: Option<String>,
+}
+
+impl FileConfig {
+    /// Parses `path` as TOML or YAML, picked by its extension.
+    pub(crate) fn read(path: &Path) -> Result<Self, ConfigError> {
+        let contents = std::fs::read_to_string(path).map_err(|source| ConfigError::ReadFile {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        let invalid = |message: String| ConfigError::InvalidFile {
+            path: path.to_path_buf(),
+            message,
+        };
+
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("toml") => toml::from_str(&contents).map_err(|err| invalid(err.to_string())),
+            Some("yaml" | "yml") => {
+                serde_yaml::from_str(&contents).map_err(|err| invalid(err.to_string()))
+            }
+            _ => Err(invalid(
+                "expected a .toml, .yaml or .yml extension".to_string(),
+            )),
+        }
+    }
+
+    /// The exporter given in the file, if any, with its API key loaded.
+    pub(crate) fn exporter(&self) -> Result<Option<ExporterBackend>, ConfigError> {
+        let Some(exporter) = &self.exporter else {
+            return Ok(None);
+        };
+        let exporter = match exporter {
+            FileExporter::Honeycomb {
+                api_key_file,
+                dataset,
+            } => {
+                let source = match api_key_file {
+                    Some(path) => ApiKeySource::File(path.clone()),
+                    None => ApiKeySource::Env(HONEYCOMB_API_KEY.to_string()),
+                };
+                let api_key = source.load()?;
+                HoneycombKeyKind::detect(&api_key)?;
+                ExporterBackend::Honeycomb {
+                    api_key,
+                    dataset: dataset.clone(),
+                }
+            }
+            #[cfg(feature = "grpc")]
+            FileExporter::OtlpGrpc { endpoint, headers } => ExporterBackend::OtlpGrpc {
+                endpoint: endpoint.clone(),
+                headers: headers.clone(),
+            },
+            FileExporter::OtlpHttp { endpoint, headers } => ExporterBackend::OtlpHttp {
+                endpoint: endpoint.clone(),
+                headers: headers.clone(),
+            },
+            #[cfg(feature = "jaeger")]
+            FileExporter::JaegerAgent { endpoint } => ExporterBackend::JaegerAgent {
+                endpoint: endpoint.clone(),
+            },
+            #[cfg(feature = "jaeger")]
+            FileExporter::JaegerCollector { endpoint } => ExporterBackend::JaegerCollector {
+                endpoint: endpoint.clone(),
+            },
+            #[cfg(feature = "zipkin")]
+            FileExporter::Zipkin { endpoint } => ExporterBackend::Zipkin {
+                endpoint: endpoint.clone(),
+            },
+            FileExporter::Stdout => ExporterBackend::Stdout,
+            FileExporter::StdoutPretty => ExporterBackend::StdoutPretty,
+            FileExporter::File { directory, prefix } => ExporterBackend::File {
+                directory: directory.clone(),
+                prefix: prefix.clone(),
+            },
+            FileExporter::None => ExporterBackend::None,
+        };
+        Ok(Some(exporter))
+    }
+
+    /// Overrides the settings of `config` given in the file. Unlike the environment variables,
+    /// unknown names are rejected, as a typo in a checked-in file should fail loudly.
+    pub(crate) fn apply(self, config: &mut TelemetryConfig) -> Result<(), ConfigError> {
+        let invalid = |message: String| ConfigError::InvalidSetting(message);
+
+        if let Some(service_name) = self.service_name {
+            config.service_name = service_name;
+        }
+        if let Some(environment) = self.environment {
+            config.environment = Some(environment);
+        }
+        match self.protocol.as_deref() {
+            #[cfg(feature = "grpc")]
+            Some("grpc") => config.protocol = Protocol::Grpc,
+            Some("http/protobuf") => config.protocol = Protocol::HttpBinary,
+            Some(protocol) => return Err(invalid(format!("unknown protocol {protocol}"))),
+            None => {}
+        }
+        if let Some(tls) = self.tls {
+            config.tls = tls;
+        }
+        if let Some(proxy) = self.proxy {
+            proxy.validate()?;
+            config.proxy = Some(proxy);
+        }
+        if let Some(compression) = self.compression {
+            compression.validate()?;
+            config.compression = Some(compression);
+        }
+        if let Some(check) = self.connectivity_check {
+            config.connectivity_check = Some(check);
+        }
+        if let Some(sampler) = self.sampler {
+            config.sampler =
+                SamplingStrategy::from_otel_names(&sampler.name, sampler.arg.as_deref())
+                    .ok_or_else(|| invalid(format!("unknown sampler {}", sampler.name)))?;
+        }
+        if let Some(rules) = self.route_sampling {
+            config.route_sampling = rules;
+        }
+        if let Some(propagation) = self.propagation {
+            config.propagation = propagation
+                .iter()
+                .map(|name| {
+                    PropagationFormat::from_otel_name(name)
+                        .ok_or_else(|| invalid(format!("unknown propagator {name}")))
+                })
+                .collect::<Result<_, _>>()?;
+        }
+        if let Some(keys) = self.baggage_attributes {
+            config.baggage_attributes = keys;
+        }
+        if let Some(client_ip) = self.client_ip {
+            config.client_ip = client_ip;
+        }
+        if let Some(enabled) = self.response_compression {
+            config.response_compression = enabled;
+        }
+        if let Some(preflights) = self.preflight_spans {
+            config.preflight_spans = preflights;
+        }
+        if let Some(address) = self.bind_address {
+            config.bind_address = address;
+        }
+        config.resource_attributes.extend(self.resource);
+        if let Some(redaction) = self.redaction {
+            config.redaction = redaction
+                .into_iter()
+                .map(FileRedaction::into_rules)
+                .collect::<Result<Vec<_>, _>>()?
+                .into_iter()
+                .flatten()
+                .collect();
+        }
+        if let Some(limits) = self.attribute_limits {
+            config.attribute_limits = Some(limits);
+        }
+        if let Some(limits) = self.span_limits {
+            config.span_limits = limits;
+        }
+
+        if let Some(directive) = self.log_filter {
+            config.log_filter = directive;
+        }
+        if let Some(directive) = self.otel_filter {
+            config.otel_filter = directive;
+        }
+        // Settings for what isn't built in are rejected rather than silently doing nothing
+        let missing_feature = [
+            (
+                "console_filter",
+                "fmt",
+                cfg!(feature = "fmt"),
+                self.console_filter.is_some(),
+            ),
+            (
+                "metrics",
+                "metrics",
+                cfg!(feature = "metrics"),
+                self.metrics.is_some(),
+            ),
+            (
+                "prometheus",
+                "metrics",
+                cfg!(feature = "metrics"),
+                self.prometheus.is_some(),
+            ),
+            ("logs", "logs", cfg!(feature = "logs"), self.logs.is_some()),
+            (
+                "json_logs",
+                "fmt",
+                cfg!(feature = "fmt"),
+                self.json_logs.is_some(),
+            ),
+        ]
+        .into_iter()
+        .find(|(_, _, built, set)| *set && !built);
+        if let Some((setting, feature, ..)) = missing_feature {
+            return Err(invalid(format!(
+                "{setting} needs the {feature} feature of {}",
+                env!("CARGO_PKG_NAME")
+            )));
+        }
+
+        #[cfg(feature = "fmt")]
+        if let Some(directive) = self.console_filter {
+            config.console_filter = directive;
+        }
+        #[cfg(feature = "metrics")]
+        if let Some(enabled) = self.metrics {
+            config.metrics = enabled;
+        }
+        #[cfg(feature = "metrics")]
+        if let Some(enabled) = self.prometheus {
+            config.prometheus = enabled;
+        }
+        #[cfg(feature = "logs")]
+        if let Some(enabled) = self.logs {
+            config.logs = enabled;
+        }
+        #[cfg(feature = "fmt")]
+        if let Some(enabled) = self.json_logs {
+            config.json_logs = enabled;
+        }
+        Ok(())
+    }
+
+    /// The settings only read at startup, which a reload leaves as they were, by name and with
+    /// their values written out to tell when they change.
+    pub(crate) fn startup_settings(&self) -> Vec<(&'static str, String)> {
+        let settings: [(&'static str, &dyn fmt::Debug); 21] = [
+            ("service_name", &self.service_name),
+            ("environment", &self.environment),
+            ("exporter", &self.exporter),
+            ("protocol", &self.protocol),
+            ("tls", &self.tls),
+            ("proxy", &self.proxy),
+            ("compression", &self.compression),
+            ("connectivity_check", &self.connectivity_check),
+            ("propagation", &self.propagation),
+            ("baggage_attributes", &self.baggage_attributes),
+            ("client_ip", &self.client_ip),
+            ("response_compression", &self.response_compression),
+            ("preflight_spans", &self.preflight_spans),
+            ("bind_address", &self.bind_address),
+            ("resource", &self.resource),
+            ("attribute_limits", &self.attribute_limits),
+            ("span_limits", &self.span_limits),
+            ("metrics", &self.metrics),
+            ("prometheus", &self.prometheus),
+            ("logs", &self.logs),
+            ("json_logs", &self.json_logs),
+        ];
+        settings
+            .into_iter()
+            .map(|(name, value)| (name, format!("{value:?}")))
+            .collect()
+    }
+}
+
+impl FileRedaction {
+    fn into_rules(self) -> Result<Vec<RedactionRule>, ConfigError> {
+        let invalid = |message: String| ConfigError::InvalidSetting(message);
+        match self {
+            FileRedaction::Preset { preset } => match preset.as_str() {
+                "emails" => Ok(vec![RedactionRule::emails()]),
+                "bearer_tokens" => Ok(vec![RedactionRule::bearer_tokens()]),
+                "credit_cards" => Ok(vec![RedactionRule::credit_cards()]),
+                "defaults" => Ok(RedactionRule::defaults()),
+                _ => Err(invalid(format!("unknown redaction preset {preset}"))),
+            },
+            FileRedaction::Pattern { pattern, action } => {
+                let action = match action.as_deref() {
+                    None | Some("mask") => RedactionAction::Mask,
+                    Some("drop") => RedactionAction::Drop,
+                    Some(action) => {
+                        return Err(invalid(format!("unknown redaction action {action}")))
+                    }
+                };
+                let pattern = regex::Regex::new(&pattern)
+                    .map_err(|err| invalid(format!("invalid redaction pattern: {err}")))?;
+                Ok(vec![RedactionRule::new(pattern, action)])
+            }
+        }
+    }
+}
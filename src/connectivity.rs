@@ -0,0 +1,61 @@
+use crate::TelemetryConfig;
+use opentelemetry_sdk::error::OTelSdkError;
+use opentelemetry_sdk::trace::SpanExporter;
+
+/// What [`crate::Telemetry::init`] does when an OTLP backend can't be reached at startup.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConnectivityCheck {
+    /// Fails [`crate::Telemetry::init`] with the failure, so a misconfigured deployment never starts
+    /// serving.
+    FailFast,
+    /// Logs the failure as a warning and starts anyway.
+    FailOpen,
+}
+
+/// An OTLP backend failing the [`check_connectivity`] probe.
+#[derive(Debug, thiserror::Error)]
+#[error("failed to reach the telemetry backend at {endpoint}: {source}")]
+pub struct Unreachable {
+    endpoint: String,
+    source: OTelSdkError,
+}
+
+/// Sends an empty span export to every OTLP backend, which checks the endpoint, TLS and API key
+/// without adding anything to the data, and returns the first failure.
+///
+/// Blocks for up to the export timeout per backend; the other backends aren't probed. Called by
+/// [`crate::Telemetry::init`] when given a [`ConnectivityCheck`], and usable before it, e.g. to
+/// validate a deployment's configuration.
+pub fn check_connectivity(config: &TelemetryConfig) -> Result<(), Unreachable> {
+    let targets: Vec<_> = std::iter::once(&config.exporter)
+        .chain(&config.extra_exporters)
+        .filter_map(|exporter| exporter.otlp_target(config, "traces"))
+        .collect();
+    if targets.is_empty() {
+        return Ok(());
+    }
+
+    // The exporters need a runtime to run on, and blocking the one `init` may be called from would
+    // stall it if it's single-threaded
+    std::thread::spawn(move || {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .expect("failed to start the connectivity check runtime");
+        runtime.block_on(async {
+            for target in targets {
+                let exporter = target.span_exporter();
+                if let Err(source) = exporter.export(Vec::new()).await {
+                    return Err(Unreachable {
+                        endpoint: target.endpoint,
+                        source,
+                    });
+                }
+            }
+            Ok(())
+        })
+    })
+    .join()
+    .expect("the connectivity check panicked")
+}
@@ -0,0 +1,56 @@
+use axum::http::header::ACCESS_CONTROL_REQUEST_METHOD;
+use axum::http::{Method, Request};
+use tower_http::trace::MakeSpan;
+use tracing::Span;
+
+/// What becomes of the spans of CORS preflights, the `OPTIONS` requests browsers send before
+/// cross-origin ones, which otherwise double the spans of browser-facing APIs.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PreflightSpans {
+    /// Like any other request.
+    #[default]
+    Record,
+    /// No span.
+    Skip,
+    /// All named `CORS preflight`, whatever the route, for them to be one entry of the trace backend.
+    Collapse,
+}
+
+fn is_preflight<B>(request: &Request<B>) -> bool {
+    request.method() == Method::OPTIONS
+        && request
+            .headers()
+            .contains_key(ACCESS_CONTROL_REQUEST_METHOD)
+}
+
+/// [`MakeSpan`] applying [`PreflightSpans`] to CORS preflights, and delegating every other request
+/// to `M`.
+#[derive(Clone, Debug, Default)]
+pub struct PreflightMakeSpan<M> {
+    inner: M,
+    preflights: PreflightSpans,
+}
+
+impl<M> PreflightMakeSpan<M> {
+    pub fn new(inner: M, preflights: PreflightSpans) -> Self {
+        Self { inner, preflights }
+    }
+}
+
+impl<B, M: MakeSpan<B>> MakeSpan<B> for PreflightMakeSpan<M> {
+    fn make_span(&mut self, request: &Request<B>) -> Span {
+        if !is_preflight(request) {
+            return self.inner.make_span(request);
+        }
+        match self.preflights {
+            PreflightSpans::Record => self.inner.make_span(request),
+            PreflightSpans::Skip => Span::none(),
+            PreflightSpans::Collapse => {
+                let span = self.inner.make_span(request);
+                span.record("otel.name", "CORS preflight");
+                span
+            }
+        }
+    }
+}
@@ -0,0 +1,163 @@
+use futures_util::future::BoxFuture;
+use futures_util::stream::BoxStream;
+use futures_util::Stream;
+use regex::Regex;
+use sqlx::database::HasStatement;
+use sqlx::{Database, Describe, Either, Execute, Executor, Pool};
+use std::pin::Pin;
+use std::sync::OnceLock;
+use std::task::Poll;
+use tracing::field::Empty;
+use tracing::{Instrument, Span};
+
+static STRING_LITERAL: OnceLock<Regex> = OnceLock::new();
+static NUMBER_LITERAL: OnceLock<Regex> = OnceLock::new();
+
+/// [`sqlx::Pool`] wrapper running every query made through it in a client span following the
+/// OpenTelemetry database conventions, with `db.system`, `db.operation` and the `db.statement` with
+/// its literals replaced by `?`, e.g. `query("...").fetch_one(&pool)` with a `&TracedPool`.
+///
+/// Queries made on connections or transactions taken from the [`inner`](Self::inner) pool aren't
+/// traced.
+#[derive(Clone, Debug)]
+pub struct TracedPool<DB: Database> {
+    pool: Pool<DB>,
+}
+
+impl<DB: Database> TracedPool<DB> {
+    pub fn new(pool: Pool<DB>) -> Self {
+        Self { pool }
+    }
+
+    pub fn inner(&self) -> &Pool<DB> {
+        &self.pool
+    }
+}
+
+impl<'p, DB: Database> Executor<'p> for &'p TracedPool<DB>
+where
+    for<'c> &'c mut DB::Connection: Executor<'c, Database = DB>,
+{
+    type Database = DB;
+
+    fn fetch_many<'e, 'q: 'e, E>(
+        self,
+        query: E,
+    ) -> BoxStream<'e, Result<Either<DB::QueryResult, DB::Row>, sqlx::Error>>
+    where
+        'p: 'e,
+        E: Execute<'q, DB> + 'q,
+    {
+        let span = query_span::<DB>(query.sql());
+        let mut rows = span.in_scope(|| self.pool.fetch_many(query));
+        // The span stays open until the rows have all been read
+        Box::pin(futures_util::stream::poll_fn(move |cx| {
+            let _guard = span.enter();
+            let poll = Pin::new(&mut rows).poll_next(cx);
+            if let Poll::Ready(Some(Err(error))) = &poll {
+                record_error(&span, error);
+            }
+            poll
+        }))
+    }
+
+    fn fetch_optional<'e, 'q: 'e, E>(
+        self,
+        query: E,
+    ) -> BoxFuture<'e, Result<Option<DB::Row>, sqlx::Error>>
+    where
+        'p: 'e,
+        E: Execute<'q, DB> + 'q,
+    {
+        let span = query_span::<DB>(query.sql());
+        let row = self.pool.fetch_optional(query);
+        Box::pin(
+            async move {
+                let result = row.await;
+                if let Err(error) = &result {
+                    record_error(&Span::current(), error);
+                }
+                result
+            }
+            .instrument(span),
+        )
+    }
+
+    fn prepare_with<'e, 'q: 'e>(
+        self,
+        sql: &'q str,
+        parameters: &'e [DB::TypeInfo],
+    ) -> BoxFuture<'e, Result<<DB as HasStatement<'q>>::Statement, sqlx::Error>>
+    where
+        'p: 'e,
+    {
+        self.pool.prepare_with(sql, parameters)
+    }
+
+    fn describe<'e, 'q: 'e>(self, sql: &'q str) -> BoxFuture<'e, Result<Describe<DB>, sqlx::Error>>
+    where
+        'p: 'e,
+    {
+        self.pool.describe(sql)
+    }
+}
+
+fn query_span<DB: Database>(sql: &str) -> Span {
+    let operation = sql
+        .split_whitespace()
+        .next()
+        .map(str::to_ascii_uppercase)
+        .unwrap_or_default();
+    let system = match DB::NAME {
+        "PostgreSQL" => "postgresql".to_string(),
+        "MySQL" => "mysql".to_string(),
+        "SQLite" => "sqlite".to_string(),
+        "MSSQL" => "mssql".to_string(),
+        name => name.to_ascii_lowercase(),
+    };
+
+    tracing::info_span!(
+        "DB query",
+        otel.name = if operation.is_empty() {
+            system.as_str()
+        } else {
+            operation.as_str()
+        },
+        otel.kind = "client",
+        otel.status_code = Empty,
+        db.system = system,
+        db.operation = operation,
+        db.statement = sanitize(sql),
+    )
+}
+
+fn record_error(span: &Span, error: &sqlx::Error) {
+    span.record("otel.status_code", "ERROR");
+    tracing::error!(parent: span, exception.message = %error, "exception");
+}
+
+/// `sql` with its string and number literals replaced by `?`, so statements don't record the
+/// values of queries built without bind parameters; placeholders such as `$1` are kept.
+pub fn sanitize(sql: &str) -> String {
+    let strings = STRING_LITERAL.get_or_init(|| Regex::new(r"'(?:[^']|'')*'").unwrap());
+    let numbers = NUMBER_LITERAL.get_or_init(|| Regex::new(r"(^|[^\w$.])\d+(?:\.\d+)?\b").unwrap());
+    let sql = strings.replace_all(sql, "?");
+    numbers.replace_all(&sql, "${1}?").into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replaces_literals_but_keeps_placeholders_and_names() {
+        assert_eq!(
+            sanitize("SELECT * FROM users WHERE name = 'O''Brien' AND age > 42 AND id = $1"),
+            "SELECT * FROM users WHERE name = ? AND age > ? AND id = $1"
+        );
+        assert_eq!(
+            sanitize("SELECT price * 1.5 FROM t1 WHERE t1.v2 IN (3, -4) LIMIT 10"),
+            "SELECT price * ? FROM t1 WHERE t1.v2 IN (?, -?) LIMIT ?"
+        );
+    }
+}
@@ -0,0 +1,164 @@
+use axum::http::header::AUTHORIZATION;
+use axum::http::{Request, Response};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine as _;
+use opentelemetry::baggage::BaggageExt;
+use opentelemetry::trace::{FutureExt, WithContext};
+use opentelemetry::{Key, KeyValue};
+use std::task::{Context, Poll};
+use tower::{Layer, Service};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+/// Derives attributes from each request, which [`EnrichLayer`] records on the request span and adds
+/// to the baggage propagated to the spans and services it leads to.
+pub trait RequestEnricher: Clone + Send + Sync + 'static {
+    fn enrich<B>(&self, request: &Request<B>) -> Vec<KeyValue>;
+}
+
+/// Where [`TenantEnricher`] looks for the tenant ID.
+#[derive(Clone, Debug, PartialEq)]
+pub enum TenantSource {
+    /// The value of a request header, e.g. `x-tenant-id`.
+    Header(String),
+    /// A claim of the bearer token in the `Authorization` header, e.g. `org_id`.
+    ///
+    /// The token's signature isn't checked, so the claim is only fit for recording, not for
+    /// deciding what the caller may do.
+    JwtClaim(String),
+    /// The path segment at this index, counted from 0, e.g. `1` for `acme` in `/tenants/acme/orders`.
+    PathSegment(usize),
+}
+
+/// [`RequestEnricher`] recording the tenant or customer ID of a request as `tenant.id`, taken from
+/// the first of its sources that has one.
+#[derive(Clone, Debug)]
+pub struct TenantEnricher {
+    attribute: Key,
+    sources: Vec<TenantSource>,
+}
+
+impl TenantEnricher {
+    pub fn new(sources: impl IntoIterator<Item = TenantSource>) -> Self {
+        Self {
+            attribute: Key::from_static_str("tenant.id"),
+            sources: sources.into_iter().collect(),
+        }
+    }
+
+    /// Records the ID under `attribute` rather than `tenant.id`, e.g. `customer.id`.
+    pub fn with_attribute(mut self, attribute: impl Into<Key>) -> Self {
+        self.attribute = attribute.into();
+        self
+    }
+}
+
+impl RequestEnricher for TenantEnricher {
+    fn enrich<B>(&self, request: &Request<B>) -> Vec<KeyValue> {
+        self.sources
+            .iter()
+            .find_map(|source| match source {
+                TenantSource::Header(name) => request
+                    .headers()
+                    .get(name.as_str())?
+                    .to_str()
+                    .ok()
+                    .map(str::to_string),
+                TenantSource::JwtClaim(claim) => jwt_claim(request, claim),
+                TenantSource::PathSegment(index) => request
+                    .uri()
+                    .path()
+                    .split('/')
+                    .filter(|segment| !segment.is_empty())
+                    .nth(*index)
+                    .map(str::to_string),
+            })
+            .filter(|id| !id.is_empty())
+            .map(|id| KeyValue::new(self.attribute.clone(), id))
+            .into_iter()
+            .collect()
+    }
+}
+
+fn jwt_claim<B>(request: &Request<B>, claim: &str) -> Option<String> {
+    let header = request.headers().get(AUTHORIZATION)?.to_str().ok()?;
+    let (scheme, token) = header.split_once(' ')?;
+    if !scheme.eq_ignore_ascii_case("bearer") {
+        return None;
+    }
+    let payload = URL_SAFE_NO_PAD
+        .decode(token.trim().split('.').nth(1)?)
+        .ok()?;
+    match serde_json::from_slice::<serde_json::Value>(&payload)
+        .ok()?
+        .get(claim)?
+    {
+        serde_json::Value::String(value) => Some(value.clone()),
+        serde_json::Value::Number(value) => Some(value.to_string()),
+        _ => None,
+    }
+}
+
+/// Layer recording the attributes of a [`RequestEnricher`] on the request span, and adding them to
+/// the baggage so they reach the spans started while handling the request and, through
+/// [`crate::TracedClient`], the services it calls.
+///
+/// Those spans only get them as attributes when they are listed in
+/// [`crate::Telemetry::baggage_attributes`]. Add it to the instrumented router, e.g.
+/// `telemetry.instrument(router.layer(EnrichLayer::new(TenantEnricher::new(sources))))`.
+#[derive(Clone, Debug)]
+pub struct EnrichLayer<E> {
+    enricher: E,
+}
+
+impl<E: RequestEnricher> EnrichLayer<E> {
+    pub fn new(enricher: E) -> Self {
+        Self { enricher }
+    }
+}
+
+impl<S, E: Clone> Layer<S> for EnrichLayer<E> {
+    type Service = Enrich<S, E>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Enrich {
+            inner,
+            enricher: self.enricher.clone(),
+        }
+    }
+}
+
+/// Service created by [`EnrichLayer`].
+#[derive(Clone, Debug)]
+pub struct Enrich<S, E> {
+    inner: S,
+    enricher: E,
+}
+
+impl<S, E, B, ResBody> Service<Request<B>> for Enrich<S, E>
+where
+    S: Service<Request<B>, Response = Response<ResBody>>,
+    E: RequestEnricher,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = WithContext<S::Future>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<B>) -> Self::Future {
+        let attributes = self.enricher.enrich(&request);
+        // The request span is entered here, so the current context is the one it was started in
+        let mut cx = opentelemetry::Context::current();
+        if !attributes.is_empty() {
+            let span = tracing::Span::current();
+            for attribute in &attributes {
+                span.set_attribute(attribute.key.clone(), attribute.value.clone());
+            }
+            cx = cx.with_baggage(attributes);
+        }
+        // Spans started while the handler is polled take their parent from this context
+        self.inner.call(request).with_context(cx)
+    }
+}
@@ -0,0 +1,794 @@
+use crate::config::{
+    ConfigError, HoneycombKeyKind, HONEYCOMB_DATASET_HEADER, HONEYCOMB_TEAM_HEADER,
+};
+use crate::TelemetryConfig;
+use async_trait::async_trait;
+use opentelemetry_http::{Bytes, HttpClient, HttpError, Request, Response};
+#[cfg(feature = "grpc")]
+use opentelemetry_otlp::{Compression, WithTonicConfig};
+use opentelemetry_otlp::{Protocol, WithExportConfig, WithHttpConfig};
+use opentelemetry_proto::tonic::collector::trace::v1::ExportTraceServiceRequest;
+use opentelemetry_proto::transform::common::tonic::ResourceAttributesWithSchema;
+use opentelemetry_proto::transform::trace::tonic::group_spans_by_resource_and_scope;
+use opentelemetry_sdk::error::{OTelSdkError, OTelSdkResult};
+use opentelemetry_sdk::trace::{BatchConfig, BatchConfigBuilder, SpanData, SpanExporter};
+use opentelemetry_sdk::Resource;
+use reqwest::header::{HeaderValue, CONTENT_ENCODING};
+use std::collections::HashMap;
+use std::fmt;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+#[cfg(feature = "grpc")]
+use tonic::metadata::{MetadataKey, MetadataMap};
+#[cfg(feature = "grpc")]
+use tonic::transport::ClientTlsConfig;
+
+/// Where finished spans are sent.
+pub enum ExporterBackend {
+    /// Honeycomb's OTLP/HTTP endpoint, authenticated with the given API key.
+    ///
+    /// With a classic key, everything goes to `dataset`, defaulting to the service name. Environment
+    /// keys route spans and logs by service name, so only the metrics go to `dataset`.
+    Honeycomb {
+        api_key: String,
+        dataset: Option<String>,
+    },
+    /// An OTLP/gRPC receiver such as a local collector on `http://localhost:4317`; `headers` are sent as metadata.
+    #[cfg(feature = "grpc")]
+    OtlpGrpc {
+        endpoint: String,
+        headers: HashMap<String, String>,
+    },
+    /// An OTLP/HTTP receiver; `endpoint` is the full traces URL, e.g. `http://localhost:4318/v1/traces`.
+    OtlpHttp {
+        endpoint: String,
+        headers: HashMap<String, String>,
+    },
+    /// The Jaeger instance at `endpoint`, a `host` or `host:port` such as `localhost`. The Jaeger
+    /// exporters are gone upstream, so the spans are sent to the OTLP receiver Jaeger runs, on the
+    /// port given or otherwise over gRPC on port 4317 with the `grpc` feature and HTTP on port
+    /// 4318 without. Port 4317 is always gRPC. The ports of Jaeger's own protocols, such as the
+    /// agent's 6831, are rejected, as nothing would receive OTLP on them.
+    #[cfg(feature = "jaeger")]
+    JaegerAgent { endpoint: String },
+    /// The Jaeger collector at `endpoint`, such as `http://localhost:4318`. The Jaeger exporters
+    /// are gone upstream, so the spans are sent over OTLP/HTTP to `/v1/traces` on the same host, on
+    /// the port given or 4318, or over gRPC to port 4317. The ports of Jaeger's own protocols, such
+    /// as the collector's 14268, are rejected, as nothing would receive OTLP on them.
+    #[cfg(feature = "jaeger")]
+    JaegerCollector { endpoint: String },
+    /// A Zipkin collector receiving v2 JSON spans at `endpoint`, such as
+    /// `http://localhost:9411/api/v2/spans`.
+    #[cfg(feature = "zipkin")]
+    Zipkin { endpoint: String },
+    /// Prints spans to stdout as they finish, as OTLP JSON.
+    Stdout,
+    /// Prints spans to stdout as indented OTLP JSON, for reading in a terminal during development.
+    StdoutPretty,
+    /// Appends spans as OTLP JSON lines to `<prefix>.<date>` files in `directory`, starting a new
+    /// file every day; metrics and logs are not written.
+    File { directory: PathBuf, prefix: String },
+    /// Records spans but never exports them.
+    None,
+}
+
+/// Tuning for the batch span processor; unset fields keep the SDK defaults, which honour the
+/// `OTEL_BSP_*` variables.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct BatchSettings {
+    /// Spans buffered before new ones are dropped; defaults to 2048.
+    pub max_queue_size: Option<usize>,
+    /// Spans sent per export, capped at the queue size; defaults to 512.
+    pub max_export_batch_size: Option<usize>,
+    /// How long to wait between exports; defaults to 5 seconds.
+    pub scheduled_delay: Option<Duration>,
+    /// Exports allowed in flight at once; defaults to 1.
+    pub max_concurrent_exports: Option<usize>,
+}
+
+impl BatchSettings {
+    pub(crate) fn config(&self) -> BatchConfig {
+        // The queue size goes first, as the batch size is capped at it
+        let mut builder = BatchConfigBuilder::default();
+        if let Some(size) = self.max_queue_size {
+            builder = builder.with_max_queue_size(size);
+        }
+        if let Some(size) = self.max_export_batch_size {
+            builder = builder.with_max_export_batch_size(size);
+        }
+        if let Some(delay) = self.scheduled_delay {
+            builder = builder.with_scheduled_delay(delay);
+        }
+        if let Some(max) = self.max_concurrent_exports {
+            builder = builder.with_max_concurrent_exports(max);
+        }
+        builder.build()
+    }
+}
+
+/// How the OTLP exporters verify the receiver and authenticate to it over `https://`; the
+/// certificates and key are PEM files, checked by [`TelemetryConfig::validate`] and read again when
+/// the exporters are built.
+#[derive(Clone, Debug, Default, PartialEq, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TlsSettings {
+    /// CA bundle trusted on top of the system roots, for receivers behind an internal CA.
+    pub ca_certificate: Option<PathBuf>,
+    /// Certificate presented to the receiver for mutual TLS, along with `client_key`.
+    pub client_certificate: Option<PathBuf>,
+    pub client_key: Option<PathBuf>,
+    /// Accepts any receiver certificate, for development against self-signed ones. Only the
+    /// OTLP/HTTP exporters honour it, as tonic can't turn verification off.
+    pub insecure_skip_verify: bool,
+}
+
+impl TlsSettings {
+    /// Reads and parses the certificates and key, so [`crate::Telemetry::init`] can reject them
+    /// before anything is installed.
+    pub(crate) fn validate(&self) -> Result<(), ConfigError> {
+        self.ca_certificates()?;
+        self.reqwest_identity()?;
+        Ok(())
+    }
+
+    #[cfg(feature = "grpc")]
+    fn tonic_config(&self) -> ClientTlsConfig {
+        let mut config = ClientTlsConfig::new().with_native_roots();
+        if let Some(path) = &self.ca_certificate {
+            let certificate = read_pem(path).unwrap_or_else(|err| panic!("{err}"));
+            config = config.ca_certificate(tonic::transport::Certificate::from_pem(certificate));
+        }
+        if let Some((certificate, key)) =
+            self.client_identity().unwrap_or_else(|err| panic!("{err}"))
+        {
+            config = config.identity(tonic::transport::Identity::from_pem(certificate, key));
+        }
+        config
+    }
+
+    fn configure_reqwest(&self, mut builder: reqwest::ClientBuilder) -> reqwest::ClientBuilder {
+        builder = builder.danger_accept_invalid_certs(self.insecure_skip_verify);
+        for certificate in self.ca_certificates().unwrap_or_else(|err| panic!("{err}")) {
+            builder = builder.add_root_certificate(certificate);
+        }
+        if let Some(identity) = self
+            .reqwest_identity()
+            .unwrap_or_else(|err| panic!("{err}"))
+        {
+            builder = builder.identity(identity);
+        }
+        builder
+    }
+
+    fn ca_certificates(&self) -> Result<Vec<reqwest::Certificate>, ConfigError> {
+        let Some(path) = &self.ca_certificate else {
+            return Ok(Vec::new());
+        };
+        let invalid = |message: String| ConfigError::InvalidCertificate {
+            path: path.clone(),
+            message,
+        };
+        // Files without any PEM block parse as an empty bundle
+        match reqwest::Certificate::from_pem_bundle(&read_pem(path)?) {
+            Ok(certificates) if certificates.is_empty() => {
+                Err(invalid("no PEM certificate found".to_string()))
+            }
+            Ok(certificates) => Ok(certificates),
+            Err(err) => Err(invalid(err.to_string())),
+        }
+    }
+
+    fn reqwest_identity(&self) -> Result<Option<reqwest::Identity>, ConfigError> {
+        let Some((mut certificate, key)) = self.client_identity()? else {
+            return Ok(None);
+        };
+        // reqwest reads the certificate and key from a single PEM buffer
+        certificate.push(b'\n');
+        certificate.extend(key);
+        reqwest::Identity::from_pem(&certificate)
+            .map(Some)
+            .map_err(|err| ConfigError::InvalidCertificate {
+                path: self.client_certificate.clone().unwrap_or_default(),
+                message: err.to_string(),
+            })
+    }
+
+    // The certificate and key are only useful together, so setting one alone is a mistake
+    fn client_identity(&self) -> Result<Option<PemIdentity>, ConfigError> {
+        match (&self.client_certificate, &self.client_key) {
+            (Some(certificate), Some(key)) => Ok(Some((read_pem(certificate)?, read_pem(key)?))),
+            (None, None) => Ok(None),
+            _ => Err(ConfigError::InvalidSetting(
+                "the TLS client certificate and key must be set together".to_string(),
+            )),
+        }
+    }
+}
+
+// The PEM certificate and key of the client
+type PemIdentity = (Vec<u8>, Vec<u8>);
+
+/// Proxy the OTLP/HTTP exporters connect through instead of the one `HTTPS_PROXY`, `HTTP_PROXY` and
+/// `NO_PROXY` pick, which they follow otherwise. The OTLP/gRPC exporters always connect directly.
+#[derive(Clone, Debug, PartialEq, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProxySettings {
+    /// Proxy URL such as `http://proxy.internal:3128`, with the credentials in it if it needs them.
+    pub url: String,
+    /// Comma-separated hosts, domains and IP ranges reached directly, as in `NO_PROXY`.
+    #[serde(default)]
+    pub no_proxy: Option<String>,
+}
+
+impl ProxySettings {
+    /// Rejects URLs reqwest can't proxy through, so they fail when the configuration is loaded or
+    /// validated instead of when the exporters are built.
+    pub(crate) fn validate(&self) -> Result<(), ConfigError> {
+        self.parse().map(drop)
+    }
+
+    fn parse(&self) -> Result<reqwest::Proxy, ConfigError> {
+        reqwest::Proxy::all(&self.url).map_err(|err| {
+            ConfigError::InvalidSetting(format!("invalid proxy URL {}: {err}", self.url))
+        })
+    }
+
+    fn proxy(&self) -> reqwest::Proxy {
+        let proxy = self.parse().unwrap_or_else(|err| panic!("{err}"));
+        proxy.no_proxy(
+            self.no_proxy
+                .as_deref()
+                .and_then(reqwest::NoProxy::from_string),
+        )
+    }
+}
+
+/// Algorithm compressing the OTLP export payloads.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CompressionAlgorithm {
+    Gzip,
+    /// Compresses better than gzip for the same CPU, but not every receiver accepts it.
+    Zstd,
+}
+
+/// How the OTLP export payloads are compressed, trading CPU for egress bandwidth.
+///
+/// `OTEL_EXPORTER_OTLP_COMPRESSION` is read by the exporters themselves, and takes precedence at the
+/// algorithm's default level.
+#[derive(Clone, Debug, PartialEq, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CompressionSettings {
+    pub algorithm: CompressionAlgorithm,
+    /// From 0 to 9 for gzip, defaulting to 6, and from 1 to 22 for zstd, defaulting to 3. Only the
+    /// OTLP/HTTP exporters honour it, as tonic always uses the default.
+    #[serde(default)]
+    pub level: Option<u32>,
+}
+
+impl CompressionSettings {
+    fn compress(&self, body: &[u8]) -> std::io::Result<Vec<u8>> {
+        match self.algorithm {
+            CompressionAlgorithm::Gzip => {
+                let level = self.level.map_or(flate2::Compression::default(), |level| {
+                    flate2::Compression::new(level)
+                });
+                let mut encoder = flate2::write::GzEncoder::new(Vec::new(), level);
+                encoder.write_all(body)?;
+                encoder.finish()
+            }
+            CompressionAlgorithm::Zstd => {
+                // Level 0 is zstd's default
+                let level = self.level.unwrap_or(0);
+                zstd::encode_all(body, i32::try_from(level).unwrap_or(i32::MAX))
+            }
+        }
+    }
+
+    fn content_encoding(&self) -> &'static str {
+        match self.algorithm {
+            CompressionAlgorithm::Gzip => "gzip",
+            CompressionAlgorithm::Zstd => "zstd",
+        }
+    }
+
+    /// Rejects out of range levels, which would only fail, or be clamped, once the first batch is
+    /// exported.
+    pub(crate) fn validate(&self) -> Result<(), ConfigError> {
+        let range = match self.algorithm {
+            CompressionAlgorithm::Gzip => 0..=9,
+            CompressionAlgorithm::Zstd => 1..=22,
+        };
+        match self.level {
+            Some(level) if !range.contains(&level) => Err(ConfigError::InvalidSetting(format!(
+                "{:?} compression levels range from {} to {}, not {level}",
+                self.algorithm,
+                range.start(),
+                range.end()
+            ))),
+            _ => Ok(()),
+        }
+    }
+}
+
+fn read_pem(path: &Path) -> Result<Vec<u8>, ConfigError> {
+    std::fs::read(path).map_err(|source| ConfigError::ReadFile {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+/// An OTLP receiver for one signal (traces, metrics or logs).
+pub(crate) struct OtlpTarget {
+    pub(crate) protocol: Protocol,
+    pub(crate) endpoint: String,
+    pub(crate) headers: HashMap<String, String>,
+    tls: TlsSettings,
+    proxy: Option<ProxySettings>,
+    compression: Option<CompressionSettings>,
+}
+
+impl ExporterBackend {
+    /// Where to send `signal` (`"traces"`, `"metrics"`, ...) over OTLP, or `None` for the non-OTLP backends.
+    ///
+    /// `config.protocol` only applies to Honeycomb; the OTLP backends carry their own.
+    pub(crate) fn otlp_target(&self, config: &TelemetryConfig, signal: &str) -> Option<OtlpTarget> {
+        let (protocol, endpoint, headers) = match self {
+            ExporterBackend::Honeycomb { api_key, dataset } => {
+                let endpoint = match config.protocol {
+                    #[cfg(feature = "grpc")]
+                    Protocol::Grpc => HONEYCOMB_GRPC_ENDPOINT.to_string(),
+                    _ => format!("{HONEYCOMB_HTTP_ENDPOINT}/v1/{signal}"),
+                };
+                let dataset = dataset.as_deref().unwrap_or(&config.service_name);
+                let headers = honeycomb_headers(api_key, dataset, signal);
+                (config.protocol, endpoint, headers)
+            }
+            #[cfg(feature = "grpc")]
+            ExporterBackend::OtlpGrpc { endpoint, headers } => {
+                (Protocol::Grpc, endpoint.clone(), headers.clone())
+            }
+            // The configured URL is the traces one, so other signals swap the standard path suffix
+            ExporterBackend::OtlpHttp { endpoint, headers } => {
+                let endpoint = match endpoint.strip_suffix("/v1/traces") {
+                    Some(base) => format!("{base}/v1/{signal}"),
+                    None => endpoint.clone(),
+                };
+                (Protocol::HttpBinary, endpoint, headers.clone())
+            }
+            #[cfg(feature = "jaeger")]
+            ExporterBackend::JaegerAgent { .. } | ExporterBackend::JaegerCollector { .. } => {
+                let (protocol, endpoint) = self
+                    .jaeger_otlp_endpoint(signal)
+                    .unwrap_or_else(|err| panic!("{err}"));
+                (protocol, endpoint, HashMap::new())
+            }
+            _ => return None,
+        };
+        Some(OtlpTarget {
+            protocol,
+            endpoint,
+            headers,
+            tls: config.tls.clone(),
+            proxy: config.proxy.clone(),
+            compression: config.compression.clone(),
+        })
+    }
+
+    /// Rejects Honeycomb keys in neither format, Jaeger endpoints without a host to send OTLP to and
+    /// Zipkin endpoints that aren't absolute URLs, so they fail when the configuration is validated
+    /// instead of when the exporters are built.
+    pub(crate) fn validate(&self) -> Result<(), ConfigError> {
+        match self {
+            ExporterBackend::Honeycomb { api_key, .. } => {
+                HoneycombKeyKind::detect(api_key).map(drop)
+            }
+            #[cfg(feature = "jaeger")]
+            ExporterBackend::JaegerAgent { .. } | ExporterBackend::JaegerCollector { .. } => {
+                self.jaeger_otlp_endpoint("traces").map(drop)
+            }
+            #[cfg(feature = "zipkin")]
+            ExporterBackend::Zipkin { endpoint } => {
+                // Parsed as the Zipkin exporter does
+                let uri = endpoint.parse::<axum::http::Uri>().map_err(|err| {
+                    ConfigError::InvalidSetting(format!(
+                        "invalid Zipkin endpoint {endpoint}: {err}"
+                    ))
+                })?;
+                if uri.scheme().is_none() || uri.host().is_none() {
+                    return Err(ConfigError::InvalidSetting(format!(
+                        "invalid Zipkin endpoint {endpoint}: expected a URL such as \
+                         http://localhost:9411/api/v2/spans"
+                    )));
+                }
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // The OTLP receiver Jaeger runs beside its agent and collector
+    #[cfg(feature = "jaeger")]
+    fn jaeger_otlp_endpoint(&self, signal: &str) -> Result<(Protocol, String), ConfigError> {
+        let (endpoint, url, agent) = match self {
+            // Agent endpoints are a bare `host:port`
+            ExporterBackend::JaegerAgent { endpoint } => (
+                endpoint,
+                reqwest::Url::parse(&format!("http://{endpoint}")),
+                true,
+            ),
+            ExporterBackend::JaegerCollector { endpoint } => {
+                (endpoint, reqwest::Url::parse(endpoint), false)
+            }
+            _ => unreachable!("not a Jaeger backend"),
+        };
+        let url = url
+            .ok()
+            .filter(|url| url.host_str().is_some())
+            .ok_or_else(|| {
+                ConfigError::InvalidSetting(format!("invalid Jaeger endpoint {endpoint}"))
+            })?;
+        let host = url.host_str().unwrap_or_default();
+
+        let grpc = match url.port() {
+            Some(port @ (5778 | 6831 | 6832 | 14250 | 14268)) => {
+                return Err(ConfigError::InvalidSetting(format!(
+                    "Jaeger endpoint {endpoint} is on port {port}, one of Jaeger's own protocols, \
+                     but spans are sent to Jaeger over OTLP: use the port it receives OTLP on, \
+                     {JAEGER_OTLP_GRPC_PORT} for gRPC or {JAEGER_OTLP_HTTP_PORT} for HTTP by \
+                     default"
+                )))
+            }
+            Some(JAEGER_OTLP_GRPC_PORT) => true,
+            _ => agent && cfg!(feature = "grpc"),
+        };
+        if grpc {
+            #[cfg(feature = "grpc")]
+            {
+                let port = url.port().unwrap_or(JAEGER_OTLP_GRPC_PORT);
+                return Ok((Protocol::Grpc, format!("http://{host}:{port}")));
+            }
+            #[cfg(not(feature = "grpc"))]
+            return Err(ConfigError::InvalidSetting(format!(
+                "Jaeger endpoint {endpoint} is on port {JAEGER_OTLP_GRPC_PORT}, Jaeger's OTLP/gRPC \
+                 port, which needs the `grpc` feature: use {JAEGER_OTLP_HTTP_PORT} for OTLP/HTTP"
+            )));
+        }
+        let port = url.port().unwrap_or(JAEGER_OTLP_HTTP_PORT);
+        let endpoint = format!("{}://{host}:{port}/v1/{signal}", url.scheme());
+        Ok((Protocol::HttpBinary, endpoint))
+    }
+
+    /// Exporter for the Zipkin backend, or `None` for the others.
+    // Deprecated upstream now that Zipkin accepts OTLP, but older deployments only take its own format
+    #[cfg(feature = "zipkin")]
+    #[allow(deprecated)]
+    pub(crate) fn zipkin_span_exporter(&self) -> Option<opentelemetry_zipkin::ZipkinExporter> {
+        let ExporterBackend::Zipkin { endpoint } = self else {
+            return None;
+        };
+        let exporter = opentelemetry_zipkin::ZipkinExporter::builder()
+            .with_collector_endpoint(endpoint)
+            .build()
+            .unwrap_or_else(|err| panic!("invalid Zipkin endpoint {endpoint}: {err}"));
+        Some(exporter)
+    }
+
+    /// Exporter for the backends that need no network, or `None` for the others.
+    pub(crate) fn local_span_exporter(&self) -> Option<JsonSpanExporter> {
+        let exporter = match self {
+            ExporterBackend::Stdout => JsonSpanExporter::new(std::io::stdout(), false),
+            ExporterBackend::StdoutPretty => JsonSpanExporter::new(std::io::stdout(), true),
+            ExporterBackend::File { directory, prefix } => {
+                JsonSpanExporter::new(tracing_appender::rolling::daily(directory, prefix), false)
+            }
+            _ => return None,
+        };
+        Some(exporter)
+    }
+
+    /// Whether metrics and logs are printed to stdout.
+    #[cfg(any(feature = "logs", feature = "metrics"))]
+    pub(crate) fn is_stdout(&self) -> bool {
+        matches!(
+            self,
+            ExporterBackend::Stdout | ExporterBackend::StdoutPretty
+        )
+    }
+}
+
+// Getting these wrong doesn't fail the exports, Honeycomb just files the data somewhere unexpected,
+// so keys in neither format are refused outright
+fn honeycomb_headers(api_key: &str, dataset: &str, signal: &str) -> HashMap<String, String> {
+    let kind = HoneycombKeyKind::detect(api_key)
+        .unwrap_or_else(|err| panic!("invalid Honeycomb API key: {err}"));
+    let mut headers = HashMap::from([(HONEYCOMB_TEAM_HEADER.to_string(), api_key.to_string())]);
+    // Metrics have no service to be routed by, even with an environment key
+    if kind == HoneycombKeyKind::Classic || signal == "metrics" {
+        headers.insert(HONEYCOMB_DATASET_HEADER.to_string(), dataset.to_string());
+    }
+    headers
+}
+
+const EXPORT_TIMEOUT: Duration = Duration::from_secs(3);
+const HONEYCOMB_HTTP_ENDPOINT: &str = "https://api.honeycomb.io";
+#[cfg(feature = "jaeger")]
+const JAEGER_OTLP_GRPC_PORT: u16 = 4317;
+#[cfg(feature = "jaeger")]
+const JAEGER_OTLP_HTTP_PORT: u16 = 4318;
+#[cfg(feature = "grpc")]
+const HONEYCOMB_GRPC_ENDPOINT: &str = "https://api.honeycomb.io:443";
+
+impl OtlpTarget {
+    /// OTLP span exporter sending to the target.
+    pub(crate) fn span_exporter(&self) -> opentelemetry_otlp::SpanExporter {
+        let builder = opentelemetry_otlp::SpanExporter::builder();
+        let exporter = match self.protocol {
+            #[cfg(feature = "grpc")]
+            Protocol::Grpc => self.tonic(builder.with_tonic()).build(),
+            _ => self.http(builder.with_http()).build(),
+        };
+        exporter.expect("failed to build the OTLP span exporter")
+    }
+
+    /// The headers as gRPC metadata, failing on those that can't be sent as such, e.g. with spaces
+    /// in their name or non-ASCII characters in their value.
+    #[cfg(feature = "grpc")]
+    pub(crate) fn metadata(&self) -> Result<MetadataMap, ConfigError> {
+        let mut metadata = MetadataMap::with_capacity(self.headers.len());
+        for (key, value) in &self.headers {
+            let invalid = |err: &dyn fmt::Display| {
+                ConfigError::InvalidSetting(format!("invalid gRPC metadata {key}: {err}"))
+            };
+            let key = MetadataKey::from_bytes(key.as_bytes()).map_err(|err| invalid(&err))?;
+            let value = value.parse().map_err(|err| invalid(&err))?;
+            metadata.insert(key, value);
+        }
+        Ok(metadata)
+    }
+
+    /// Points an OTLP/gRPC exporter builder for any signal at the target.
+    #[cfg(feature = "grpc")]
+    pub(crate) fn tonic<B: WithExportConfig + WithTonicConfig>(&self, builder: B) -> B {
+        let metadata = self.metadata().unwrap_or_else(|err| panic!("{err}"));
+        let mut builder = builder
+            .with_endpoint(&self.endpoint)
+            .with_timeout(EXPORT_TIMEOUT)
+            .with_metadata(metadata);
+        if self.endpoint.starts_with("https://") {
+            builder = builder.with_tls_config(self.tls.tonic_config());
+        }
+        if let Some(compression) = &self.compression {
+            builder = builder.with_compression(match compression.algorithm {
+                CompressionAlgorithm::Gzip => Compression::Gzip,
+                CompressionAlgorithm::Zstd => Compression::Zstd,
+            });
+        }
+        builder
+    }
+
+    /// Points an OTLP/HTTP exporter builder for any signal at the target.
+    pub(crate) fn http<B: WithExportConfig + WithHttpConfig>(&self, builder: B) -> B {
+        builder
+            .with_endpoint(&self.endpoint)
+            .with_timeout(EXPORT_TIMEOUT)
+            .with_headers(self.headers.clone())
+            .with_http_client(StatusPreservingClient {
+                client: self.reqwest_client(),
+                compression: self.compression.clone(),
+            })
+    }
+
+    fn reqwest_client(&self) -> reqwest::Client {
+        // The exporters only apply their timeout to the clients they build themselves
+        let mut builder = self
+            .tls
+            .configure_reqwest(reqwest::Client::builder().timeout(EXPORT_TIMEOUT));
+        if let Some(proxy) = &self.proxy {
+            builder = builder.proxy(proxy.proxy());
+        }
+        builder
+            .build()
+            .expect("failed to build the OTLP/HTTP client")
+    }
+}
+
+/// HTTP client for the OTLP/HTTP exporters handing rejected exports back as responses, and
+/// compressing the payloads at the configured level, which the exporters can't set.
+///
+/// opentelemetry-http's own reqwest client turns them into errors, which the exporters report as a
+/// bare "network error", so [`crate::RetryingExporter`] couldn't tell a 400 from a 503.
+#[derive(Debug)]
+struct StatusPreservingClient {
+    client: reqwest::Client,
+    compression: Option<CompressionSettings>,
+}
+
+#[async_trait]
+impl HttpClient for StatusPreservingClient {
+    async fn send_bytes(&self, mut request: Request<Bytes>) -> Result<Response<Bytes>, HttpError> {
+        // Already compressed by the exporter when `OTEL_EXPORTER_OTLP_COMPRESSION` is set
+        if let Some(compression) = &self.compression {
+            if !request.headers().contains_key(CONTENT_ENCODING) {
+                let body = compression.compress(request.body())?;
+                *request.body_mut() = body.into();
+                request.headers_mut().insert(
+                    CONTENT_ENCODING,
+                    HeaderValue::from_static(compression.content_encoding()),
+                );
+            }
+        }
+
+        let mut response = self.client.execute(request.try_into()?).await?;
+        crate::retry::record_response_status(response.status().as_u16());
+        let headers = std::mem::take(response.headers_mut());
+        let mut http_response = Response::builder()
+            .status(response.status())
+            .body(response.bytes().await?)?;
+        *http_response.headers_mut() = headers;
+        Ok(http_response)
+    }
+}
+
+/// Writes each batch of spans as an OTLP JSON export request, the format the collector's file
+/// exporter writes too.
+pub(crate) struct JsonSpanExporter {
+    writer: Mutex<Box<dyn Write + Send>>,
+    pretty: bool,
+    resource: ResourceAttributesWithSchema,
+}
+
+impl JsonSpanExporter {
+    fn new(writer: impl Write + Send + 'static, pretty: bool) -> Self {
+        Self {
+            writer: Mutex::new(Box::new(writer)),
+            pretty,
+            resource: ResourceAttributesWithSchema::default(),
+        }
+    }
+}
+
+impl fmt::Debug for JsonSpanExporter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("JsonSpanExporter")
+            .field("pretty", &self.pretty)
+            .finish_non_exhaustive()
+    }
+}
+
+impl SpanExporter for JsonSpanExporter {
+    async fn export(&self, batch: Vec<SpanData>) -> OTelSdkResult {
+        let request = ExportTraceServiceRequest {
+            resource_spans: group_spans_by_resource_and_scope(batch, &self.resource),
+        };
+        let json = if self.pretty {
+            serde_json::to_vec_pretty(&request)
+        } else {
+            serde_json::to_vec(&request)
+        };
+        let mut json = json.map_err(|err| OTelSdkError::InternalFailure(err.to_string()))?;
+        json.push(b'\n');
+
+        let mut writer = self.writer.lock().unwrap();
+        writer
+            .write_all(&json)
+            .and_then(|()| writer.flush())
+            .map_err(|err| OTelSdkError::InternalFailure(err.to_string()))
+    }
+
+    fn set_resource(&mut self, resource: &Resource) {
+        self.resource = resource.into();
+    }
+}
+
+#[cfg(all(test, any(feature = "grpc", feature = "jaeger", feature = "zipkin")))]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "jaeger")]
+    #[test]
+    fn sends_to_jaegers_otlp_ports_only() {
+        let agent = |endpoint: &str| ExporterBackend::JaegerAgent {
+            endpoint: endpoint.to_string(),
+        };
+        let collector = |endpoint: &str| ExporterBackend::JaegerCollector {
+            endpoint: endpoint.to_string(),
+        };
+        let target = |backend: ExporterBackend| {
+            let (protocol, endpoint) = backend.jaeger_otlp_endpoint("traces")?;
+            Ok::<_, ConfigError>((protocol != Protocol::HttpBinary, endpoint))
+        };
+
+        for legacy in [
+            agent("localhost:6831"),
+            collector("http://localhost:14268/api/traces"),
+        ] {
+            assert!(matches!(
+                legacy.validate(),
+                Err(ConfigError::InvalidSetting(_))
+            ));
+        }
+        assert_eq!(
+            target(collector("http://jaeger:4318")).unwrap(),
+            (false, "http://jaeger:4318/v1/traces".to_string())
+        );
+        assert_eq!(
+            target(collector("https://jaeger:9999/api/traces")).unwrap(),
+            (false, "https://jaeger:9999/v1/traces".to_string())
+        );
+
+        #[cfg(feature = "grpc")]
+        {
+            assert_eq!(
+                target(agent("jaeger")).unwrap(),
+                (true, "http://jaeger:4317".to_string())
+            );
+            assert_eq!(
+                target(collector("http://jaeger:4317")).unwrap(),
+                (true, "http://jaeger:4317".to_string())
+            );
+        }
+        #[cfg(not(feature = "grpc"))]
+        {
+            assert_eq!(
+                target(agent("jaeger")).unwrap(),
+                (false, "http://jaeger:4318/v1/traces".to_string())
+            );
+            assert!(agent("jaeger:4317").validate().is_err());
+        }
+    }
+
+    #[cfg(feature = "grpc")]
+    #[test]
+    fn rejects_headers_that_arent_grpc_metadata() {
+        let grpc = |key: &str, value: &str| {
+            TelemetryConfig::new(ExporterBackend::OtlpGrpc {
+                endpoint: "http://localhost:4317".to_string(),
+                headers: HashMap::from([(key.to_string(), value.to_string())]),
+            })
+        };
+        assert!(grpc("x-api-key", "abc123").validate().is_ok());
+        for (key, value) in [("x api key", "abc123"), ("x-api-key", "abc\n123")] {
+            assert!(
+                matches!(
+                    grpc(key, value).validate(),
+                    Err(ConfigError::InvalidSetting(_))
+                ),
+                "{key}: {value} was accepted"
+            );
+        }
+
+        // Honeycomb over gRPC sends the dataset as metadata
+        let mut honeycomb = TelemetryConfig::new(ExporterBackend::Honeycomb {
+            api_key: "a".repeat(32),
+            dataset: Some("pick\nlist".to_string()),
+        });
+        honeycomb.protocol = Protocol::Grpc;
+        assert!(matches!(
+            honeycomb.validate(),
+            Err(ConfigError::InvalidSetting(_))
+        ));
+    }
+
+    #[cfg(feature = "zipkin")]
+    #[test]
+    fn rejects_zipkin_endpoints_that_arent_urls() {
+        let zipkin = |endpoint: &str| ExporterBackend::Zipkin {
+            endpoint: endpoint.to_string(),
+        };
+        assert!(zipkin("http://localhost:9411/api/v2/spans")
+            .validate()
+            .is_ok());
+        for endpoint in ["localhost:9411", "/api/v2/spans", "http://local host"] {
+            assert!(
+                matches!(
+                    zipkin(endpoint).validate(),
+                    Err(ConfigError::InvalidSetting(_))
+                ),
+                "{endpoint} was accepted"
+            );
+        }
+    }
+}
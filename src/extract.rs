@@ -0,0 +1,37 @@
+use axum::extract::FromRequestParts;
+use axum::http::request::Parts;
+use axum::http::StatusCode;
+
+/// Extractor giving handlers the IDs of the request span, e.g. to log them or return them to the
+/// caller as correlation IDs, with the IDs in lowercase hex.
+///
+/// It is rejected with a 500 when the request has no span, such as on routes outside
+/// [`crate::TelemetryHandle::instrument`]; extract an `Option<TraceContext>` where that is
+/// expected.
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize)]
+pub struct TraceContext {
+    pub trace_id: String,
+    pub span_id: String,
+    /// Whether the trace is sampled, so the span will be exported.
+    pub sampled: bool,
+}
+
+impl<S: Send + Sync> FromRequestParts<S> for TraceContext {
+    type Rejection = (StatusCode, &'static str);
+
+    async fn from_request_parts(_parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        // Handlers run inside the request span, which is what the IDs are read from
+        let span_context = crate::response::current_span_context();
+        if !span_context.is_valid() {
+            return Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "the request has no trace context",
+            ));
+        }
+        Ok(Self {
+            trace_id: span_context.trace_id().to_string(),
+            span_id: span_context.span_id().to_string(),
+            sampled: span_context.is_sampled(),
+        })
+    }
+}
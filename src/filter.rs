@@ -0,0 +1,113 @@
+use crate::TelemetryConfig;
+use std::sync::OnceLock;
+use tracing::Subscriber;
+use tracing_subscriber::filter::ParseError;
+use tracing_subscriber::{reload, EnvFilter, Registry};
+
+// Set by `Telemetry::init`, for the filter to be changed while the service runs
+static HANDLE: OnceLock<reload::Handle<EnvFilter, Registry>> = OnceLock::new();
+// The same for the filters of the OpenTelemetry and JSON log layers, whose handles' types depend on
+// the layers below them
+static OTEL_HANDLE: OnceLock<ReloadLayerFilter> = OnceLock::new();
+#[cfg(feature = "fmt")]
+static CONSOLE_HANDLE: OnceLock<ReloadLayerFilter> = OnceLock::new();
+
+type ReloadLayerFilter = Box<dyn Fn(EnvFilter) -> Result<(), reload::Error> + Send + Sync>;
+
+#[derive(Debug, thiserror::Error)]
+pub enum FilterError {
+    #[error("invalid filter directive: {0}")]
+    Parse(#[from] ParseError),
+    #[error("no reloadable filter is installed")]
+    NotInstalled,
+    #[error("failed to reload the filter: {0}")]
+    Reload(#[from] reload::Error),
+}
+
+/// Adds the directives tokio-console needs to see the runtime's tasks to `log_filter`.
+#[cfg(feature = "console")]
+pub(crate) fn add_console_directives(log_filter: &mut String) {
+    log_filter.push_str(",tokio=trace,runtime=trace");
+}
+
+/// Filter deciding which spans and events reach the subscriber's layers, replaceable at runtime
+/// through [`set_log_filter`]. Invalid directives in `directive` are skipped.
+pub(crate) fn reloadable(directive: &str) -> reload::Layer<EnvFilter, Registry> {
+    let (layer, handle) = reload::Layer::new(EnvFilter::new(directive));
+    let _ = HANDLE.set(handle);
+    layer
+}
+
+/// Filter for the OpenTelemetry layer, letting through only what `directive` selects out of what
+/// the reloadable filter already does, replaceable at runtime by a config reload. Invalid
+/// directives in `directive` are skipped.
+pub(crate) fn otel_layer_filter<S: Subscriber>(directive: &str) -> reload::Layer<EnvFilter, S> {
+    reloadable_layer_filter(directive, &OTEL_HANDLE)
+}
+
+/// [`otel_layer_filter`] for the JSON log layer.
+#[cfg(feature = "fmt")]
+pub(crate) fn console_layer_filter<S: Subscriber>(directive: &str) -> reload::Layer<EnvFilter, S> {
+    reloadable_layer_filter(directive, &CONSOLE_HANDLE)
+}
+
+fn reloadable_layer_filter<S: Subscriber>(
+    directive: &str,
+    slot: &OnceLock<ReloadLayerFilter>,
+) -> reload::Layer<EnvFilter, S> {
+    let (layer, handle) = reload::Layer::new(EnvFilter::new(directive));
+    let _ = slot.set(Box::new(move |filter| handle.reload(filter)));
+    layer
+}
+
+/// The filters of a reloaded configuration, all parsed before any is swapped in, so an invalid
+/// directive leaves every filter as it was.
+pub(crate) struct Filters {
+    log: EnvFilter,
+    otel: EnvFilter,
+    #[cfg(feature = "fmt")]
+    console: EnvFilter,
+}
+
+impl Filters {
+    pub(crate) fn parse(config: &TelemetryConfig) -> Result<Self, FilterError> {
+        Ok(Self {
+            log: EnvFilter::try_new(&config.log_filter)?,
+            otel: EnvFilter::try_new(&config.otel_filter)?,
+            #[cfg(feature = "fmt")]
+            console: EnvFilter::try_new(&config.console_filter)?,
+        })
+    }
+
+    /// Replaces the filters installed by [`crate::Telemetry::init`]; the JSON log one is skipped
+    /// when the service started without JSON logs.
+    pub(crate) fn reload(self) -> Result<(), FilterError> {
+        let otel = OTEL_HANDLE.get().ok_or(FilterError::NotInstalled)?;
+        HANDLE
+            .get()
+            .ok_or(FilterError::NotInstalled)?
+            .reload(self.log)?;
+        otel(self.otel)?;
+        #[cfg(feature = "fmt")]
+        if let Some(console) = CONSOLE_HANDLE.get() {
+            console(self.console)?;
+        }
+        Ok(())
+    }
+}
+
+/// Replaces the filter installed by [`crate::Telemetry::init`] with `directive`, in the `RUST_LOG`
+/// syntax, e.g. `info,axum_picklist=debug`.
+pub fn set_log_filter(directive: &str) -> Result<(), FilterError> {
+    let filter = EnvFilter::try_new(directive)?;
+    HANDLE
+        .get()
+        .ok_or(FilterError::NotInstalled)?
+        .reload(filter)?;
+    Ok(())
+}
+
+/// The directive of the filter installed by [`crate::Telemetry::init`], if any.
+pub fn log_filter() -> Option<String> {
+    HANDLE.get()?.with_current(ToString::to_string).ok()
+}
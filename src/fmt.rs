@@ -0,0 +1,89 @@
+use serde_json::{Map, Value};
+use std::fmt;
+use tracing::field::{Field, Visit};
+use tracing::{Event, Subscriber};
+use tracing_log::NormalizeEvent;
+use tracing_subscriber::fmt::format::Writer;
+use tracing_subscriber::fmt::time::{FormatTime, SystemTime};
+use tracing_subscriber::fmt::{FmtContext, FormatEvent, FormatFields};
+use tracing_subscriber::registry::LookupSpan;
+
+/// Event formatter writing one JSON object per line, including the `trace_id` and `span_id` of the
+/// span the event was emitted in so local logs can be matched with the exported traces.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct JsonFormat;
+
+impl<S, N> FormatEvent<S, N> for JsonFormat
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> fmt::Result {
+        let normalized = event.normalized_metadata();
+        let metadata = normalized.as_ref().unwrap_or_else(|| event.metadata());
+        let mut object = Map::new();
+        object.insert("level".into(), metadata.level().as_str().into());
+        object.insert("target".into(), metadata.target().into());
+
+        if let Some(span) = ctx.parent_span() {
+            object.insert("span".into(), span.name().into());
+            // The SDK's own events can be emitted while the OpenTelemetry layer starts the span,
+            // holding the extensions this would read
+            let span_context = (!crate::sdk_errors::is_sdk_target(metadata.target()))
+                .then(|| crate::span::otel_span_context(&span.id()))
+                .flatten();
+            if let Some(span_context) = span_context {
+                object.insert(
+                    "trace_id".into(),
+                    span_context.trace_id().to_string().into(),
+                );
+                object.insert("span_id".into(), span_context.span_id().to_string().into());
+            }
+        }
+
+        event.record(&mut JsonVisitor(&mut object));
+
+        // The timestamp can only be formatted straight into the writer, so it leads the object
+        write!(writer, "{{\"timestamp\":\"")?;
+        SystemTime.format_time(&mut writer)?;
+        write!(writer, "\"")?;
+        for (key, value) in object {
+            write!(writer, ",{}:{value}", Value::String(key))?;
+        }
+        writeln!(writer, "}}")
+    }
+}
+
+struct JsonVisitor<'a>(&'a mut Map<String, Value>);
+
+impl Visit for JsonVisitor<'_> {
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.0.insert(field.name().into(), value.into());
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.0.insert(field.name().into(), value.into());
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.0.insert(field.name().into(), value.into());
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.0.insert(field.name().into(), value.into());
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().into(), value.into());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.0
+            .insert(field.name().into(), format!("{value:?}").into());
+    }
+}
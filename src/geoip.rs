@@ -0,0 +1,59 @@
+use crate::client_ip::ClientIp;
+use crate::RequestEnricher;
+use axum::http::Request;
+use maxminddb::geoip2::City;
+use maxminddb::{MaxMindDbError, Reader};
+use opentelemetry::KeyValue;
+use std::path::Path;
+use std::sync::Arc;
+
+/// [`RequestEnricher`] looking up the [`ClientIp`] of each request in a local MaxMind GeoIP2 or
+/// GeoLite2 database, recording its ISO country code as `client.geo.country` and its English city
+/// name as `client.geo.city`.
+///
+/// Country databases only give the country, and private or unknown addresses give nothing. Like
+/// every enrichment the attributes are propagated as baggage, e.g.
+/// `telemetry.instrument(router.layer(EnrichLayer::new(GeoIpEnricher::open(path)?)))`.
+#[derive(Clone)]
+pub struct GeoIpEnricher {
+    // Held in memory, as lookups then never touch the disk
+    reader: Arc<Reader<Vec<u8>>>,
+}
+
+impl GeoIpEnricher {
+    /// Reads the `.mmdb` database at `path`.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, MaxMindDbError> {
+        Ok(Self {
+            reader: Arc::new(Reader::open_readfile(path)?),
+        })
+    }
+}
+
+impl RequestEnricher for GeoIpEnricher {
+    fn enrich<B>(&self, request: &Request<B>) -> Vec<KeyValue> {
+        let Some(ClientIp(ip)) = request.extensions().get::<ClientIp>() else {
+            return Vec::new();
+        };
+        let city = match self
+            .reader
+            .lookup(*ip)
+            .and_then(|result| result.decode::<City>())
+        {
+            Ok(Some(city)) => city,
+            Ok(None) => return Vec::new(),
+            Err(err) => {
+                tracing::debug!(error = %err, "failed to look up the client address");
+                return Vec::new();
+            }
+        };
+
+        let mut attributes = Vec::new();
+        if let Some(country) = city.country.iso_code {
+            attributes.push(KeyValue::new("client.geo.country", country.to_string()));
+        }
+        if let Some(name) = city.city.names.english {
+            attributes.push(KeyValue::new("client.geo.city", name.to_string()));
+        }
+        attributes
+    }
+}
@@ -0,0 +1,190 @@
+use crate::propagation::{extract_context, PropagateContextLayer};
+use axum::http::{HeaderMap, Request, Response};
+use std::time::Duration;
+use tower::layer::util::{Identity, Stack};
+use tower::ServiceBuilder;
+use tower_http::classify::{GrpcErrorsAsFailures, GrpcFailureClass, SharedClassifier};
+use tower_http::trace::{
+    DefaultOnBodyChunk, DefaultOnRequest, MakeSpan, OnEos, OnFailure, OnResponse, TraceLayer,
+};
+use tracing::field::Empty;
+use tracing::Span;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+const GRPC_STATUS: &str = "grpc-status";
+
+/// The gRPC server tracing layer returned by [`grpc_trace_layer`].
+pub type GrpcTraceLayer = TraceLayer<
+    SharedClassifier<GrpcErrorsAsFailures>,
+    GrpcMakeSpan,
+    DefaultOnRequest,
+    GrpcOnResponse,
+    DefaultOnBodyChunk,
+    GrpcOnEos,
+    GrpcOnFailure,
+>;
+
+/// The gRPC client layers returned by [`grpc_client_layer`].
+pub type GrpcClientLayer = ServiceBuilder<
+    Stack<
+        PropagateContextLayer,
+        Stack<
+            TraceLayer<
+                SharedClassifier<GrpcErrorsAsFailures>,
+                GrpcClientMakeSpan,
+                DefaultOnRequest,
+                GrpcOnResponse,
+                DefaultOnBodyChunk,
+                GrpcOnEos,
+                GrpcOnFailure,
+            >,
+            Identity,
+        >,
+    >,
+>;
+
+/// Layer giving every call to a tonic server its own span, continuing the caller's trace when its
+/// metadata carries one, e.g. `Server::builder().layer(grpc_trace_layer()).add_service(...)`.
+///
+/// Spans follow the OpenTelemetry RPC conventions, named after the method, e.g.
+/// `shop.Cart/AddItem`, and recording `rpc.system`, `rpc.service`, `rpc.method` and
+/// `rpc.grpc.status_code`. Streaming calls are recorded until the response stream ends.
+pub fn grpc_trace_layer() -> GrpcTraceLayer {
+    TraceLayer::new_for_grpc()
+        .make_span_with(GrpcMakeSpan)
+        .on_response(GrpcOnResponse::default())
+        .on_eos(GrpcOnEos::default())
+        .on_failure(GrpcOnFailure)
+}
+
+/// Layers for a tonic channel running every call in a client span, and writing its trace context
+/// into the call's metadata so the service called continues the trace, e.g.
+/// `CartClient::new(ServiceBuilder::new().layer(grpc_client_layer()).service(channel))`.
+///
+/// Spans are named and attributed like those of [`grpc_trace_layer`], and calls ending with any
+/// status other than `OK` are marked as errors.
+pub fn grpc_client_layer() -> GrpcClientLayer {
+    ServiceBuilder::new()
+        .layer(
+            TraceLayer::new_for_grpc()
+                .make_span_with(GrpcClientMakeSpan)
+                .on_response(GrpcOnResponse::for_client())
+                .on_eos(GrpcOnEos::for_client())
+                .on_failure(GrpcOnFailure),
+        )
+        .layer(PropagateContextLayer)
+}
+
+/// [`MakeSpan`] recording a gRPC call using the OpenTelemetry RPC semantic conventions, parented on
+/// the trace context propagated in the call's metadata.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct GrpcMakeSpan;
+
+impl<B> MakeSpan<B> for GrpcMakeSpan {
+    fn make_span(&mut self, request: &Request<B>) -> Span {
+        let span = rpc_span(request, "server");
+        let _ = span.set_parent(extract_context(request.headers()));
+        span
+    }
+}
+
+/// [`MakeSpan`] recording an outgoing gRPC call using the OpenTelemetry RPC semantic conventions.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct GrpcClientMakeSpan;
+
+impl<B> MakeSpan<B> for GrpcClientMakeSpan {
+    fn make_span(&mut self, request: &Request<B>) -> Span {
+        rpc_span(request, "client")
+    }
+}
+
+fn rpc_span<B>(request: &Request<B>, kind: &'static str) -> Span {
+    // gRPC paths are `/<package>.<Service>/<Method>`
+    let name = request.uri().path().trim_start_matches('/');
+    let (service, method) = name.split_once('/').unwrap_or((name, ""));
+
+    tracing::info_span!(
+        "gRPC request",
+        otel.name = name,
+        otel.kind = kind,
+        otel.status_code = Empty,
+        rpc.system = "grpc",
+        rpc.service = service,
+        rpc.method = method,
+        rpc.grpc.status_code = Empty,
+    )
+}
+
+/// [`OnResponse`] recording the status of calls answered without a body, which gRPC sends in the
+/// response headers.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct GrpcOnResponse {
+    client: bool,
+}
+
+impl GrpcOnResponse {
+    /// Marks every status other than `OK` as an error, rather than only server errors.
+    pub fn for_client() -> Self {
+        Self { client: true }
+    }
+}
+
+impl<B> OnResponse<B> for GrpcOnResponse {
+    fn on_response(self, response: &Response<B>, _latency: Duration, span: &Span) {
+        record_status(Some(response.headers()), span, self.client);
+    }
+}
+
+/// [`OnEos`] recording the status gRPC sends in the trailers once the response stream ends.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct GrpcOnEos {
+    client: bool,
+}
+
+impl GrpcOnEos {
+    /// Marks every status other than `OK` as an error, rather than only server errors.
+    pub fn for_client() -> Self {
+        Self { client: true }
+    }
+}
+
+impl OnEos for GrpcOnEos {
+    fn on_eos(self, trailers: Option<&HeaderMap>, _stream_duration: Duration, span: &Span) {
+        record_status(trailers, span, self.client);
+    }
+}
+
+/// [`OnFailure`] marking the span as an error when the service or the response stream fails
+/// without a gRPC status, and recording the failure as an `exception` event.
+///
+/// Calls failing with a status are already marked by [`GrpcOnResponse`] and [`GrpcOnEos`].
+#[derive(Clone, Copy, Debug, Default)]
+pub struct GrpcOnFailure;
+
+impl OnFailure<GrpcFailureClass> for GrpcOnFailure {
+    fn on_failure(&mut self, failure: GrpcFailureClass, _latency: Duration, span: &Span) {
+        if let GrpcFailureClass::Error(message) = failure {
+            span.record("otel.status_code", "ERROR");
+            tracing::error!(parent: span, exception.message = message, "exception");
+        }
+    }
+}
+
+fn record_status(headers: Option<&HeaderMap>, span: &Span, client: bool) {
+    let Some(code) = headers
+        .and_then(|headers| headers.get(GRPC_STATUS))
+        .and_then(|value| value.to_str().ok()?.parse::<i64>().ok())
+    else {
+        return;
+    };
+    span.record("rpc.grpc.status_code", code);
+    if (client && code != 0) || is_server_error(code) {
+        span.record("otel.status_code", "ERROR");
+    }
+}
+
+// The codes the OpenTelemetry conventions count as errors of the server, rather than of the caller:
+// UNKNOWN, DEADLINE_EXCEEDED, UNIMPLEMENTED, INTERNAL, UNAVAILABLE and DATA_LOSS
+fn is_server_error(code: i64) -> bool {
+    matches!(code, 2 | 4 | 12 | 13 | 14 | 15)
+}
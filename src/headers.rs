@@ -0,0 +1,152 @@
+use axum::http::{HeaderMap, Request, Response};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, OnceLock};
+use std::task::{Context, Poll};
+use tower::{Layer, Service};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+// Set by `Telemetry::init` for `HeaderCaptureLayer::from_telemetry` to pick up
+static CONFIG: OnceLock<HeaderCaptureConfig> = OnceLock::new();
+
+/// Headers that carry credentials, which are never recorded whatever the configuration says.
+pub const ALWAYS_DENIED: [&str; 4] = [
+    "authorization",
+    "cookie",
+    "proxy-authorization",
+    "set-cookie",
+];
+
+/// Which headers [`HeaderCaptureLayer`] records; names are matched case-insensitively.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HeaderCaptureConfig {
+    /// Request headers recorded as `http.request.header.<name>`.
+    pub request: Vec<String>,
+    /// Response headers recorded as `http.response.header.<name>`.
+    pub response: Vec<String>,
+    /// Headers never recorded even when listed above, on top of [`ALWAYS_DENIED`].
+    pub deny: Vec<String>,
+}
+
+impl Default for HeaderCaptureConfig {
+    fn default() -> Self {
+        Self {
+            request: vec!["accept".to_string(), "x-request-id".to_string()],
+            response: Vec::new(),
+            deny: Vec::new(),
+        }
+    }
+}
+
+impl HeaderCaptureConfig {
+    fn allowed<'a>(&'a self, names: &'a [String]) -> impl Iterator<Item = String> + 'a {
+        names
+            .iter()
+            .map(|name| name.to_ascii_lowercase())
+            .filter(|name| !ALWAYS_DENIED.contains(&name.as_str()))
+            .filter(|name| {
+                !self
+                    .deny
+                    .iter()
+                    .any(|denied| denied.eq_ignore_ascii_case(name))
+            })
+    }
+}
+
+pub(crate) fn set_config(config: HeaderCaptureConfig) {
+    let _ = CONFIG.set(config);
+}
+
+/// Layer recording the configured request and response headers on the request span.
+///
+/// Repeated headers are recorded as one comma-separated value.
+#[derive(Clone, Debug)]
+pub struct HeaderCaptureLayer {
+    request: Arc<[String]>,
+    response: Arc<[String]>,
+}
+
+impl HeaderCaptureLayer {
+    pub fn new(config: &HeaderCaptureConfig) -> Self {
+        Self {
+            request: config.allowed(&config.request).collect(),
+            response: config.allowed(&config.response).collect(),
+        }
+    }
+
+    /// Layer using the configuration given to [`crate::Telemetry::init`], or the default one.
+    pub fn from_telemetry() -> Self {
+        // Not initialised, so a layer built before `init` doesn't keep `init` from setting it
+        Self::new(&CONFIG.get().cloned().unwrap_or_default())
+    }
+}
+
+impl<S> Layer<S> for HeaderCaptureLayer {
+    type Service = HeaderCapture<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        HeaderCapture {
+            inner,
+            request: self.request.clone(),
+            response: self.response.clone(),
+        }
+    }
+}
+
+/// Service created by [`HeaderCaptureLayer`].
+#[derive(Clone, Debug)]
+pub struct HeaderCapture<S> {
+    inner: S,
+    request: Arc<[String]>,
+    response: Arc<[String]>,
+}
+
+impl<S, B, ResBody> Service<Request<B>> for HeaderCapture<S>
+where
+    S: Service<Request<B>, Response = Response<ResBody>>,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<B>) -> Self::Future {
+        let span = tracing::Span::current();
+        record(
+            &span,
+            "http.request.header",
+            &self.request,
+            request.headers(),
+        );
+        let response_headers = self.response.clone();
+        let future = self.inner.call(request);
+
+        Box::pin(async move {
+            let response = future.await?;
+            record(
+                &span,
+                "http.response.header",
+                &response_headers,
+                response.headers(),
+            );
+            Ok(response)
+        })
+    }
+}
+
+fn record(span: &tracing::Span, prefix: &str, names: &[String], headers: &HeaderMap) {
+    for name in names {
+        let values: Vec<&str> = headers
+            .get_all(name.as_str())
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .collect();
+        if !values.is_empty() {
+            span.set_attribute(format!("{prefix}.{name}"), values.join(", "));
+        }
+    }
+}
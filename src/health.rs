@@ -0,0 +1,64 @@
+use axum::extract::State;
+use axum::http::{Request, StatusCode};
+use axum::routing::get;
+use axum::Router;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tower_http::trace::MakeSpan;
+use tracing::Span;
+
+pub const HEALTHZ_PATH: &str = "/healthz";
+pub const READYZ_PATH: &str = "/readyz";
+
+/// Whether the service should receive traffic, as reported by `/readyz`; starts out not ready.
+#[derive(Clone, Debug, Default)]
+pub struct Readiness(Arc<AtomicBool>);
+
+impl Readiness {
+    pub fn set_ready(&self, ready: bool) {
+        self.0.store(ready, Ordering::Relaxed);
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Liveness and readiness probe endpoints: `/healthz` always answers 200, `/readyz` answers 200 once
+/// `readiness` is set and 503 until then.
+pub fn health_router(readiness: Readiness) -> Router {
+    Router::new()
+        .route(HEALTHZ_PATH, get(|| async { StatusCode::OK }))
+        .route(READYZ_PATH, get(readyz))
+        .with_state(readiness)
+}
+
+async fn readyz(State(readiness): State<Readiness>) -> StatusCode {
+    if readiness.is_ready() {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    }
+}
+
+/// [`MakeSpan`] returning a disabled span for the probe endpoints served by [`health_router`], so
+/// frequent Kubernetes probes export nothing, and delegating every other request to `M`.
+#[derive(Clone, Debug, Default)]
+pub struct SkipHealthChecks<M> {
+    inner: M,
+}
+
+impl<M> SkipHealthChecks<M> {
+    pub fn new(inner: M) -> Self {
+        Self { inner }
+    }
+}
+
+impl<B, M: MakeSpan<B>> MakeSpan<B> for SkipHealthChecks<M> {
+    fn make_span(&mut self, request: &Request<B>) -> Span {
+        match request.uri().path() {
+            HEALTHZ_PATH | READYZ_PATH => Span::none(),
+            _ => self.inner.make_span(request),
+        }
+    }
+}
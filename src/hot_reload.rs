@@ -0,0 +1,185 @@
+use crate::config::ConfigError;
+use crate::config_file::FileConfig;
+use crate::filter::Filters;
+use crate::redact::RedactionRule;
+use crate::sampling::RouteRule;
+use crate::{ExporterBackend, SamplingStrategy, TelemetryConfig};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, SystemTime};
+use tokio::task::JoinHandle;
+
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+// Set by `Telemetry::init`, as the starting point each reload applies the file onto
+static BASELINE: OnceLock<Reloadable> = OnceLock::new();
+
+// The settings of the file only read at startup, as they were when it was first read here
+static STARTUP_SETTINGS: Mutex<Option<Vec<(&'static str, String)>>> = Mutex::new(None);
+
+// The settings that can change without a restart
+#[derive(Clone)]
+struct Reloadable {
+    sampler: SamplingStrategy,
+    route_sampling: Vec<RouteRule>,
+    redaction: Vec<RedactionRule>,
+    log_filter: String,
+    otel_filter: String,
+    #[cfg(feature = "fmt")]
+    console_filter: String,
+    #[cfg(feature = "console")]
+    tokio_console: bool,
+}
+
+pub(crate) fn set_baseline(config: &TelemetryConfig) {
+    let _ = BASELINE.set(Reloadable {
+        sampler: config.sampler.clone(),
+        route_sampling: config.route_sampling.clone(),
+        redaction: config.redaction.clone(),
+        log_filter: config.log_filter.clone(),
+        otel_filter: config.otel_filter.clone(),
+        #[cfg(feature = "fmt")]
+        console_filter: config.console_filter.clone(),
+        #[cfg(feature = "console")]
+        tokio_console: config.tokio_console,
+    });
+}
+
+/// Re-reads the TOML or YAML file at `path`, in the format of [`TelemetryConfig::from_file`], and
+/// applies its sampler, route sample rates, redaction rules and log, OpenTelemetry and JSON log
+/// filters to the running pipeline.
+///
+/// The environment variables still take precedence, and settings missing from the file keep the
+/// value the service started with. The other settings only apply at startup, so changes to them
+/// since the file was first read, by the first reload or [`watch_config_file`], are logged as
+/// ignored. Nothing changes when the file is invalid. Redaction rules only
+/// apply to spans exported in batches, so not to the stdout exporters when the service started
+/// without rules or tail sampling.
+pub fn reload_config_file(path: impl AsRef<Path>) -> Result<(), ConfigError> {
+    let file = FileConfig::read(path.as_ref())?;
+    let ignored = changed_startup_settings(&file);
+    let baseline = BASELINE.get().cloned().ok_or_else(|| {
+        ConfigError::InvalidSetting("the telemetry pipeline isn't installed".to_string())
+    })?;
+
+    let mut config = TelemetryConfig::new(ExporterBackend::None);
+    config.sampler = baseline.sampler;
+    config.route_sampling = baseline.route_sampling;
+    config.redaction = baseline.redaction;
+    config.log_filter = baseline.log_filter;
+    config.otel_filter = baseline.otel_filter;
+    #[cfg(feature = "fmt")]
+    {
+        config.console_filter = baseline.console_filter;
+    }
+    file.apply(&mut config)?;
+    config = config.with_env();
+    #[cfg(feature = "console")]
+    if baseline.tokio_console {
+        crate::filter::add_console_directives(&mut config.log_filter);
+    }
+
+    // The only settings that can still be rejected, so they go first
+    Filters::parse(&config)
+        .and_then(Filters::reload)
+        .map_err(|err| ConfigError::InvalidSetting(err.to_string()))?;
+    crate::sampling::reconfigure(config.route_sampling, &config.sampler);
+    crate::redact::reconfigure(config.redaction);
+    if !ignored.is_empty() {
+        tracing::warn!(
+            settings = %ignored.join(", "),
+            "ignoring changed settings that only apply at startup"
+        );
+    }
+    Ok(())
+}
+
+/// Spawns a task reloading the file at `path` with [`reload_config_file`] whenever it changes, and on
+/// SIGHUP. Reloads are logged, and failed ones leave the running settings as they were.
+pub fn watch_config_file(path: impl Into<PathBuf>) -> JoinHandle<()> {
+    let path = path.into();
+    // Taken as the startup settings the reloads compare theirs with
+    if let Ok(file) = FileConfig::read(&path) {
+        changed_startup_settings(&file);
+    }
+    tokio::spawn(async move {
+        #[cfg(unix)]
+        let mut hangup = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup())
+            .expect("failed to install SIGHUP handler");
+        // Polled rather than watched, as that works the same everywhere, including with the symlink
+        // swaps Kubernetes does to update mounted ConfigMaps
+        let mut interval = tokio::time::interval(POLL_INTERVAL);
+        let mut last_modified = modified(&path);
+
+        loop {
+            #[cfg(unix)]
+            let signaled = tokio::select! {
+                _ = interval.tick() => false,
+                _ = hangup.recv() => true,
+            };
+            #[cfg(not(unix))]
+            let signaled = {
+                interval.tick().await;
+                false
+            };
+
+            let current = modified(&path);
+            if !signaled && current == last_modified {
+                continue;
+            }
+            last_modified = current;
+
+            match reload_config_file(&path) {
+                Ok(()) => {
+                    tracing::info!(path = %path.display(), "reloaded the telemetry configuration")
+                }
+                Err(err) => tracing::warn!(
+                    error = %err,
+                    path = %path.display(),
+                    "failed to reload the telemetry configuration"
+                ),
+            }
+        }
+    })
+}
+
+// The startup settings of `file` that differ from those of the file first read, which it becomes
+// when none was read before
+fn changed_startup_settings(file: &FileConfig) -> Vec<&'static str> {
+    let settings = file.startup_settings();
+    let mut first = STARTUP_SETTINGS.lock().unwrap();
+    let first = first.get_or_insert_with(|| settings.clone());
+    settings
+        .iter()
+        .zip(first.iter())
+        .filter(|(setting, first)| setting != first)
+        .map(|((name, _), _)| *name)
+        .collect()
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tells_which_startup_settings_changed() {
+        let file = |toml: &str| -> FileConfig { toml::from_str(toml).unwrap() };
+
+        let first = file("service_name = \"api\"\nlog_filter = \"info\"");
+        assert!(changed_startup_settings(&first).is_empty());
+        // Reloadable settings can change freely
+        let reloaded = file("service_name = \"api\"\nlog_filter = \"debug\"");
+        assert!(changed_startup_settings(&reloaded).is_empty());
+        let changed = file("service_name = \"billing\"\nbind_address = \"127.0.0.1:9000\"");
+        assert_eq!(
+            changed_startup_settings(&changed),
+            ["service_name", "bind_address"]
+        );
+    }
+}
@@ -0,0 +1,48 @@
+use opentelemetry::trace::{SpanId, TraceId};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+pub use opentelemetry_sdk::trace::IdGenerator;
+
+/// [`IdGenerator`] numbering traces and spans from 1, each on their own, so the spans of a test
+/// get the same IDs every run, e.g. for their export to be compared to a golden file.
+///
+/// Given to [`crate::Telemetry::id_generator`], and used by the `TestTelemetry` of the `testing`
+/// feature. The IDs are only deterministic when the spans are started in the same
+/// order, so from a single thread, and are no better than a counter at being unique, so it's no
+/// generator for production.
+#[derive(Debug, Default)]
+pub struct SequentialIdGenerator {
+    traces: AtomicU64,
+    spans: AtomicU64,
+}
+
+impl SequentialIdGenerator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl IdGenerator for SequentialIdGenerator {
+    fn new_trace_id(&self) -> TraceId {
+        TraceId::from(u128::from(self.traces.fetch_add(1, Ordering::Relaxed) + 1))
+    }
+
+    fn new_span_id(&self) -> SpanId {
+        SpanId::from(self.spans.fetch_add(1, Ordering::Relaxed) + 1)
+    }
+}
+
+// The tracer provider builder takes its generator by value
+#[derive(Debug)]
+pub(crate) struct SharedIdGenerator(pub(crate) Arc<dyn IdGenerator>);
+
+impl IdGenerator for SharedIdGenerator {
+    fn new_trace_id(&self) -> TraceId {
+        self.0.new_trace_id()
+    }
+
+    fn new_span_id(&self) -> SpanId {
+        self.0.new_span_id()
+    }
+}
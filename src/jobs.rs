@@ -0,0 +1,88 @@
+use opentelemetry::metrics::{Counter, Histogram};
+use opentelemetry::KeyValue;
+use std::fmt::Display;
+use std::future::Future;
+use std::time::{Duration, Instant};
+use tokio::task::JoinHandle;
+use tokio::time::MissedTickBehavior;
+use tracing::field::Empty;
+use tracing::Instrument;
+
+struct Instruments {
+    duration: Histogram<f64>,
+    runs: Counter<u64>,
+}
+
+impl Instruments {
+    fn new() -> Self {
+        let meter = opentelemetry::global::meter(env!("CARGO_PKG_NAME"));
+        Self {
+            duration: meter
+                .f64_histogram("job.duration")
+                .with_unit("s")
+                .with_description("Duration of scheduled job runs")
+                .build(),
+            runs: meter
+                .u64_counter("job.run.count")
+                .with_description("Number of scheduled job runs")
+                .build(),
+        }
+    }
+}
+
+/// Spawns a task running `job` every `period`, the first time straight away, each run in a new
+/// trace whose root span is named `name`, so spans and logs of the job are exported like those of
+/// requests.
+///
+/// Every run is counted in `job.run.count` and timed in `job.duration`, both attributed by
+/// `job.name` and `job.outcome`, `success` or `failure`. A run returning an error marks its span as
+/// an error and records the error as an `exception` event; the next runs go ahead regardless. A run
+/// taking longer than `period` delays the following ones rather than having them catch up.
+///
+/// Call this after [`crate::Telemetry::init`], as the metric instruments are created from the global
+/// meter provider.
+pub fn spawn_job<F, Fut, E>(name: &'static str, period: Duration, mut job: F) -> JoinHandle<()>
+where
+    F: FnMut() -> Fut + Send + 'static,
+    Fut: Future<Output = Result<(), E>> + Send,
+    E: Display,
+{
+    let instruments = Instruments::new();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(period);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        loop {
+            interval.tick().await;
+            let span = tracing::info_span!(
+                parent: None,
+                "job run",
+                otel.name = name,
+                otel.kind = "internal",
+                otel.status_code = Empty,
+                code.function = name,
+                job.name = name,
+            );
+
+            let start = Instant::now();
+            let result = job().instrument(span.clone()).await;
+            let outcome = match result {
+                Ok(()) => "success",
+                Err(error) => {
+                    span.record("otel.status_code", "ERROR");
+                    tracing::error!(parent: &span, exception.message = %error, "exception");
+                    "failure"
+                }
+            };
+
+            let attributes = [
+                KeyValue::new("job.name", name),
+                KeyValue::new("job.outcome", outcome),
+            ];
+            instruments
+                .duration
+                .record(start.elapsed().as_secs_f64(), &attributes);
+            instruments.runs.add(1, &attributes);
+        }
+    })
+}
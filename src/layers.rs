@@ -0,0 +1,130 @@
+use crate::body::BodyTimingLayer;
+use crate::body_limit::BodyLimitLayer;
+use crate::client_ip::ClientIpLayer;
+use crate::compression::ResponseCompressionLayer;
+use crate::cors::PreflightMakeSpan;
+use crate::load_shed::LoadShedLayer;
+use crate::rate_limit::RateLimitLayer;
+use crate::timeout::RequestTimeoutLayer;
+use crate::{
+    HeaderCaptureLayer, HttpMetricsLayer, OtelMakeSpan, OtelOnFailure, OtelOnRequest,
+    OtelOnResponse, PropagatingMakeSpan, SkipHealthChecks, TelemetryConfig, TraceResponseLayer,
+};
+use tower::layer::util::{Identity, Stack};
+use tower::util::Either;
+use tower::ServiceBuilder;
+use tower_http::catch_panic::CatchPanicLayer;
+use tower_http::classify::{ServerErrorsAsFailures, SharedClassifier};
+use tower_http::cors::CorsLayer;
+use tower_http::request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer};
+use tower_http::trace::{DefaultOnBodyChunk, DefaultOnEos, TraceLayer};
+
+/// The HTTP tracing layer built by [`telemetry_layers`].
+pub type OtelTraceLayer = TraceLayer<
+    SharedClassifier<ServerErrorsAsFailures>,
+    SkipHealthChecks<PreflightMakeSpan<PropagatingMakeSpan<OtelMakeSpan>>>,
+    OtelOnRequest,
+    OtelOnResponse,
+    DefaultOnBodyChunk,
+    DefaultOnEos,
+    OtelOnFailure,
+>;
+
+/// The middleware stack returned by [`telemetry_layers`], outermost layer last.
+pub type TelemetryLayers = ServiceBuilder<
+    Stack<
+        CatchPanicLayer<crate::panic::RecordPanic>,
+        Stack<
+            RequestTimeoutLayer,
+            Stack<
+                BodyLimitLayer,
+                Stack<
+                    LoadShedLayer,
+                    Stack<
+                        RateLimitLayer,
+                        Stack<
+                            Either<CorsLayer, Identity>,
+                            Stack<
+                                HttpMetricsLayer,
+                                Stack<
+                                    HeaderCaptureLayer,
+                                    Stack<
+                                        TraceResponseLayer,
+                                        Stack<
+                                            ResponseCompressionLayer,
+                                            Stack<
+                                                BodyTimingLayer,
+                                                Stack<
+                                                    OtelTraceLayer,
+                                                    Stack<
+                                                        ClientIpLayer,
+                                                        Stack<
+                                                            PropagateRequestIdLayer,
+                                                            Stack<
+                                                                SetRequestIdLayer<MakeRequestUuid>,
+                                                                Identity,
+                                                            >,
+                                                        >,
+                                                    >,
+                                                >,
+                                            >,
+                                        >,
+                                    >,
+                                >,
+                            >,
+                        >,
+                    >,
+                >,
+            >,
+        >,
+    >,
+>;
+
+/// The middleware [`crate::TelemetryHandle::instrument`] adds, for routers that need it applied
+/// with `.layer(telemetry_layers(&config))` instead, e.g. to add their own layers around it.
+///
+/// The layers run in this order: request ID generation and echoing, client address resolution, the
+/// request span continuing the caller's trace and left open until the response body is fully sent,
+/// the [`TelemetryConfig::response_compression`], the `traceresponse` header, header capture,
+/// request metrics, the [`TelemetryConfig::cors`] policy, the [`TelemetryConfig::rate_limit`], the
+/// [`TelemetryConfig::max_concurrent_requests`], the [`TelemetryConfig::max_request_body`], the
+/// [`TelemetryConfig::request_timeout`], and panic catching. Each layer that records on the request
+/// span is inside the one creating it, and rate limited, shed, oversized, timed out and panicking
+/// requests still get a response the span and metrics record.
+///
+/// The layers also exported on their own record on the current span, so wherever they are added
+/// they must run inside the [`tower_http::trace::TraceLayer`] for that to be the request span, as
+/// they do within a router passed to [`crate::TelemetryHandle::instrument`].
+///
+/// Call this after [`crate::Telemetry::init`], as the metric instruments are created from the global
+/// meter provider, or use the [`crate::TelemetryHandle::layers`] it returns.
+pub fn telemetry_layers(config: &TelemetryConfig) -> TelemetryLayers {
+    ServiceBuilder::new()
+        .layer(SetRequestIdLayer::x_request_id(MakeRequestUuid))
+        .layer(PropagateRequestIdLayer::x_request_id())
+        .layer(ClientIpLayer::new(config.client_ip.clone()))
+        .layer(
+            TraceLayer::new_for_http()
+                .make_span_with(SkipHealthChecks::new(PreflightMakeSpan::new(
+                    PropagatingMakeSpan::new(OtelMakeSpan),
+                    config.preflight_spans,
+                )))
+                .on_request(OtelOnRequest)
+                .on_response(OtelOnResponse::new())
+                .on_failure(OtelOnFailure),
+        )
+        .layer(BodyTimingLayer::new())
+        .layer(ResponseCompressionLayer::new(config.response_compression))
+        .layer(TraceResponseLayer::new())
+        .layer(HeaderCaptureLayer::new(&config.headers))
+        .layer(HttpMetricsLayer::new())
+        .option_layer(config.cors.clone())
+        .layer(RateLimitLayer::new(config.rate_limit.clone()))
+        .layer(LoadShedLayer::new(config.max_concurrent_requests))
+        .layer(BodyLimitLayer::from_limits(
+            config.max_request_body,
+            config.route_body_limits.clone(),
+        ))
+        .layer(RequestTimeoutLayer::from_config(config))
+        .layer(crate::catch_panic_layer())
+}
@@ -0,0 +1,850 @@
+#![deny(unused_crate_dependencies)]
+
+pub mod admin;
+pub mod auth;
+pub mod authz;
+pub mod baggage;
+pub mod body;
+pub mod body_limit;
+pub mod cardinality;
+pub mod client;
+pub mod client_ip;
+pub mod compression;
+pub mod config;
+mod config_file;
+pub mod connectivity;
+pub mod cors;
+#[cfg(feature = "sqlx")]
+pub mod db;
+pub mod enrich;
+pub mod exporter;
+pub mod extract;
+pub mod filter;
+#[cfg(feature = "fmt")]
+pub mod fmt;
+#[cfg(feature = "geoip")]
+pub mod geoip;
+pub mod grpc;
+pub mod headers;
+pub mod health;
+pub mod hot_reload;
+pub mod ids;
+pub mod jobs;
+pub mod layers;
+pub mod load_shed;
+pub mod loadgen;
+#[cfg(feature = "logs")]
+pub mod logs;
+pub mod messaging;
+pub mod metrics;
+#[cfg(feature = "testing")]
+pub mod mock_collector;
+pub mod ops;
+pub mod panic;
+#[cfg(feature = "metrics")]
+pub mod process_metrics;
+pub mod propagation;
+#[cfg(feature = "proxy")]
+pub mod proxy;
+pub mod rate_limit;
+pub mod redact;
+#[cfg(feature = "redis")]
+pub mod redis_client;
+pub mod resource;
+pub mod response;
+pub mod retry;
+#[cfg(feature = "metrics")]
+pub mod runtime_metrics;
+pub mod sampling;
+mod sdk_errors;
+mod self_metrics;
+pub mod shutdown;
+#[cfg(unix)]
+pub mod signals;
+pub mod span;
+pub mod span_ext;
+pub mod spill;
+pub mod sse;
+#[cfg(feature = "fs")]
+pub mod static_files;
+pub mod task;
+#[cfg(feature = "testing")]
+pub mod testing;
+pub mod timeout;
+#[cfg(feature = "tls")]
+pub mod tls;
+#[cfg(unix)]
+pub mod uds;
+#[cfg(feature = "user-agent")]
+pub mod user_agent;
+#[cfg(feature = "ws")]
+pub mod ws;
+
+pub use admin::admin_router;
+pub use auth::{AuthError, AuthLayer, Principal, TokenValidator};
+pub use authz::{
+    record_decision, AuditEvent, AuditSink, AuthorizeLayer, Decision, JsonLinesAuditSink, Policy,
+};
+pub use axum_picklist_macros::traced;
+pub use baggage::BaggageSpanProcessor;
+pub use body::{BodyCaptureConfig, BodyCaptureLayer, BodyTiming, BodyTimingLayer};
+pub use body_limit::{BodyLimit, BodyLimitLayer, RouteBodyLimit};
+pub use cardinality::{AttributeLimitProcessor, AttributeLimits, SpanLimitSettings};
+pub use client::TracedClient;
+pub use client_ip::{ClientIp, ClientIpConfig, ClientIpLayer, ForwardedHeader};
+pub use compression::{ResponseCompression, ResponseCompressionLayer};
+pub use config::{ApiKeySource, HoneycombKeyKind, TelemetryConfig};
+pub use connectivity::{check_connectivity, ConnectivityCheck, Unreachable};
+pub use cors::{PreflightMakeSpan, PreflightSpans};
+#[cfg(feature = "sqlx")]
+pub use db::TracedPool;
+pub use enrich::{EnrichLayer, RequestEnricher, TenantEnricher, TenantSource};
+pub use exporter::{
+    BatchSettings, CompressionAlgorithm, CompressionSettings, ExporterBackend, ProxySettings,
+    TlsSettings,
+};
+pub use extract::TraceContext;
+pub use filter::{log_filter, set_log_filter};
+#[cfg(feature = "fmt")]
+pub use fmt::JsonFormat;
+#[cfg(feature = "geoip")]
+pub use geoip::GeoIpEnricher;
+pub use grpc::{grpc_client_layer, grpc_trace_layer};
+pub use headers::{HeaderCaptureConfig, HeaderCaptureLayer};
+pub use health::{health_router, Readiness, SkipHealthChecks};
+pub use hot_reload::{reload_config_file, watch_config_file};
+pub use ids::{IdGenerator, SequentialIdGenerator};
+pub use jobs::spawn_job;
+pub use layers::telemetry_layers;
+pub use load_shed::LoadShedLayer;
+pub use loadgen::{LoadGen, LoadGenReport};
+#[cfg(feature = "logs")]
+pub use logs::LogBridgeLayer;
+pub use messaging::{batch_span, consumer_span, extract_context, inject_context, producer_span};
+#[cfg(feature = "metrics")]
+pub use metrics::prometheus_router;
+pub use metrics::HttpMetricsLayer;
+pub use opentelemetry_otlp::Protocol;
+pub use ops::{ops_router, serve_ops};
+pub use panic::catch_panic_layer;
+pub use propagation::{PropagateContextLayer, PropagatingMakeSpan, PropagationFormat};
+#[cfg(feature = "proxy")]
+pub use proxy::ReverseProxy;
+pub use rate_limit::{RateLimit, RateLimitKey, RateLimitLayer};
+pub use redact::{RedactingSpanProcessor, RedactionAction, RedactionRule};
+#[cfg(feature = "redis")]
+pub use redis_client::TracedRedis;
+pub use response::{ErrorBodyLayer, TraceResponseLayer};
+pub use retry::{RetryPolicy, RetryingExporter};
+pub use sampling::{
+    RouteRule, SamplingStrategy, SkipSampledOut, TailSampling, TailSamplingProcessor,
+};
+pub use shutdown::{force_flush_spans, shutdown_providers, Drain, DrainLayer, ShutdownCoordinator};
+pub use span::{OtelMakeSpan, OtelOnFailure, OtelOnRequest, OtelOnResponse};
+pub use span_ext::{current_span, AttributeValue, SpanExt};
+pub use spill::SpillConfig;
+pub use sse::{traced_sse, TracedSse};
+#[cfg(feature = "fs")]
+pub use static_files::{traced_serve_dir, AssetMakeSpan, AssetOnResponse, TracedServeDir};
+pub use task::spawn_traced;
+pub use timeout::{RequestTimeoutLayer, RouteTimeout};
+#[cfg(feature = "tls")]
+pub use tls::{TlsCertificates, TlsConnection, TlsListener, TlsMakeService, TlsSession};
+#[cfg(unix)]
+pub use uds::{bind_unix, UdsConnection, UdsMakeService, UnixPeer};
+#[cfg(feature = "user-agent")]
+pub use user_agent::UserAgentEnricher;
+#[cfg(feature = "ws")]
+pub use ws::{traced_upgrade, TracedWebSocket};
+
+use axum::http::StatusCode;
+use axum::Router;
+// Only used by the command line of the binary
+use clap as _;
+// Only used by the benchmarks
+#[cfg(test)]
+use criterion as _;
+use ids::SharedIdGenerator;
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry::InstrumentationScope;
+use opentelemetry_sdk::trace::span_processor_with_async_runtime::BatchSpanProcessor;
+use opentelemetry_sdk::trace::{self as sdktrace, SpanExporter};
+use sampling::RouteSampler;
+use sdk_errors::SdkErrorLayer;
+use self_metrics::{CountingExporter, CountingProcessor, StartCountingProcessor};
+use std::sync::Arc;
+use std::time::Duration;
+use tower_http::cors::CorsLayer;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::Layer as _;
+
+// Used by the code `#[traced]` expands to, so callers don't need their own `tracing` dependency
+#[doc(hidden)]
+pub mod __private {
+    pub use tracing;
+}
+
+/// Builder for the tracing subscriber and OpenTelemetry export pipeline of an axum service.
+pub struct Telemetry {
+    config: TelemetryConfig,
+}
+
+impl Telemetry {
+    pub fn new(exporter: ExporterBackend) -> Self {
+        Self::from_config(TelemetryConfig::new(exporter))
+    }
+
+    pub fn from_config(config: TelemetryConfig) -> Self {
+        Self { config }
+    }
+
+    pub fn service_name(mut self, service_name: impl Into<String>) -> Self {
+        self.config.service_name = service_name.into();
+        self
+    }
+
+    /// Deployment environment the service runs in, e.g. `prod`, recorded as the
+    /// `deployment.environment` resource attribute.
+    pub fn environment(mut self, environment: impl Into<String>) -> Self {
+        self.config.environment = Some(environment.into());
+        self
+    }
+
+    /// Also sends spans to `exporter`, alongside the main backend, e.g. to a local collector while
+    /// migrating; metrics and logs still only go to the main backend.
+    pub fn also_export_to(mut self, exporter: ExporterBackend) -> Self {
+        self.config.extra_exporters.push(exporter);
+        self
+    }
+
+    /// Which traces to keep; defaults to following the caller's decision and keeping all new traces.
+    pub fn sampler(mut self, sampler: SamplingStrategy) -> Self {
+        self.config.sampler = sampler;
+        self
+    }
+
+    /// Keeps `ratio` of the requests whose route starts with `prefix`, whatever the
+    /// [`sampler`](Self::sampler) would decide; the longest matching prefix wins.
+    ///
+    /// For example `.route_sample_rate("/healthz", 0.01)` drops 99% of health checks.
+    pub fn route_sample_rate(mut self, prefix: impl Into<String>, ratio: f64) -> Self {
+        self.config.route_sampling.push(RouteRule {
+            prefix: prefix.into(),
+            ratio,
+        });
+        self
+    }
+
+    /// Holds spans until their trace completes and exports only traces with a 5xx response, traces
+    /// slower than the threshold, and a sample of the rest.
+    ///
+    /// Spans dropped by the head [`sampler`](Self::sampler) never reach the tail sampler, so keep it on.
+    pub fn tail_sampling(mut self, policy: TailSampling) -> Self {
+        self.config.tail_sampling = Some(policy);
+        self
+    }
+
+    /// Generates the trace and span IDs with `generator` rather than randomly, e.g. a
+    /// [`SequentialIdGenerator`] for the exported spans of a test to be reproducible.
+    pub fn id_generator(mut self, generator: impl IdGenerator + 'static) -> Self {
+        self.config.id_generator = Some(Arc::new(generator));
+        self
+    }
+
+    /// Bounds the number and length of the attributes of each exported span, and hashes the values
+    /// of the high cardinality keys, after any [`redact`](Self::redact)ion.
+    pub fn attribute_limits(mut self, limits: AttributeLimits) -> Self {
+        self.config.attribute_limits = Some(limits);
+        self
+    }
+
+    /// Bounds the attributes, events and links the SDK keeps per span, and the length of their
+    /// values, e.g. for handlers logging many debug events into their spans.
+    pub fn span_limits(mut self, limits: SpanLimitSettings) -> Self {
+        self.config.span_limits = limits;
+        self
+    }
+
+    /// Scrubs attribute values matching `rules` from spans before they are exported, e.g.
+    /// `.redact(RedactionRule::defaults())` for emails, bearer tokens and card numbers.
+    pub fn redact(mut self, rules: impl IntoIterator<Item = RedactionRule>) -> Self {
+        self.config.redaction.extend(rules);
+        self
+    }
+
+    /// Trace context header formats honored on ingress and injected on egress.
+    pub fn propagation(mut self, formats: impl IntoIterator<Item = PropagationFormat>) -> Self {
+        self.config.propagation = formats.into_iter().collect();
+        self
+    }
+
+    /// Copies the baggage entries named in `keys`, e.g. `tenant.id`, from the incoming request onto
+    /// every span created while handling it, provided [`PropagationFormat::Baggage`] is among the
+    /// [`propagation`](Self::propagation) formats, as it is by default.
+    pub fn baggage_attributes(mut self, keys: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.config
+            .baggage_attributes
+            .extend(keys.into_iter().map(Into::into));
+        self
+    }
+
+    /// Queue and batch sizes for exporting spans, for tuning when spans are dropped under load.
+    pub fn batch(mut self, batch: BatchSettings) -> Self {
+        self.config.batch = batch;
+        self
+    }
+
+    /// CA bundle, client certificate and verification for the OTLP exporters, for receivers behind an
+    /// internal CA or requiring mutual TLS.
+    pub fn tls(mut self, tls: TlsSettings) -> Self {
+        self.config.tls = tls;
+        self
+    }
+
+    /// Routes the OTLP/HTTP exports through a proxy, rather than the one the `HTTPS_PROXY` and
+    /// `NO_PROXY` variables select.
+    pub fn proxy(mut self, proxy: ProxySettings) -> Self {
+        self.config.proxy = Some(proxy);
+        self
+    }
+
+    /// Compresses the OTLP export payloads, to cut the egress bandwidth they use.
+    pub fn compression(mut self, compression: CompressionSettings) -> Self {
+        self.config.compression = Some(compression);
+        self
+    }
+
+    /// Probes the OTLP backends when [`init`](Self::init) runs, so a wrong endpoint or key shows at
+    /// startup rather than as export errors minutes later.
+    pub fn connectivity_check(mut self, check: ConnectivityCheck) -> Self {
+        self.config.connectivity_check = Some(check);
+        self
+    }
+
+    /// How failed OTLP span exports are retried; retries back off exponentially by default.
+    pub fn retry(mut self, retry: RetryPolicy) -> Self {
+        self.config.retry = retry;
+        self
+    }
+
+    /// Keeps OTLP span batches that can't be exported on disk, up to a size limit, and replays them
+    /// once the backend is reachable again, so collector outages don't lose spans.
+    pub fn spill_to_disk(mut self, spill: SpillConfig) -> Self {
+        self.config.spill = Some(spill);
+        self
+    }
+
+    /// How long [`shutdown_signal`] waits for pending telemetry to be flushed before giving up, e.g.
+    /// a little under the Kubernetes termination grace period.
+    pub fn shutdown_timeout(mut self, timeout: Duration) -> Self {
+        self.config.shutdown_timeout = timeout;
+        self
+    }
+
+    /// Handles `SIGUSR1` by logging the telemetry pipeline's state, `SIGUSR2` by flushing the
+    /// pending spans, and `SIGQUIT` by logging the tokio runtime's; see
+    /// [`signals::spawn_signal_handlers`]. Only on Unix.
+    pub fn signal_handlers(mut self, enabled: bool) -> Self {
+        self.config.signal_handlers = enabled;
+        self
+    }
+
+    /// How long a [`ShutdownCoordinator`] waits for the requests in flight to finish once the
+    /// shutdown started, before flushing the telemetry without them; defaults to 20 seconds.
+    pub fn drain_timeout(mut self, timeout: Duration) -> Self {
+        self.config.drain_timeout = timeout;
+        self
+    }
+
+    /// Selects OTLP/HTTP or OTLP/gRPC for the Honeycomb exporter.
+    pub fn protocol(mut self, protocol: Protocol) -> Self {
+        self.config.protocol = protocol;
+        self
+    }
+
+    /// Answers requests still running after `timeout` with 408 Request Timeout, or the
+    /// [`timeout_status`](Self::timeout_status), recording a `request.timeout` event on their span.
+    pub fn request_timeout(mut self, timeout: Duration) -> Self {
+        self.config.request_timeout = Some(timeout);
+        self
+    }
+
+    /// Gives the requests whose path starts with `prefix` `timeout` rather than the
+    /// [`request_timeout`](Self::request_timeout), the longest matching prefix winning.
+    pub fn route_timeout(mut self, prefix: impl Into<String>, timeout: Duration) -> Self {
+        self.config.route_timeouts.push(RouteTimeout {
+            prefix: prefix.into(),
+            timeout,
+        });
+        self
+    }
+
+    /// Status answering timed out requests, e.g. 504 Gateway Timeout for a proxy; defaults to 408
+    /// Request Timeout.
+    pub fn timeout_status(mut self, status: StatusCode) -> Self {
+        self.config.timeout_status = status;
+        self
+    }
+
+    /// Sheds the requests arriving while `max` are already being handled, answering them with 503
+    /// Service Unavailable on a span tagged `load_shed = true`, and counting them in
+    /// `http.server.requests.rejected`.
+    pub fn max_concurrent_requests(mut self, max: usize) -> Self {
+        self.config.max_concurrent_requests = Some(max);
+        self
+    }
+
+    /// Answers the requests whose body is larger than `max_bytes` with 413 Payload Too Large,
+    /// counting them in `request.rejected.too_large`, and records the body size of the others as
+    /// `http.request.body.size`.
+    pub fn max_request_body(mut self, max_bytes: usize) -> Self {
+        self.config.max_request_body = Some(max_bytes);
+        self
+    }
+
+    /// Gives the requests whose path starts with `prefix` a body size limit of `max_bytes` instead of
+    /// the [`max_request_body`](Self::max_request_body) one, the longest matching prefix winning.
+    pub fn route_body_limit(mut self, prefix: impl Into<String>, max_bytes: usize) -> Self {
+        self.config.route_body_limits.push(RouteBodyLimit {
+            prefix: prefix.into(),
+            max_bytes,
+        });
+        self
+    }
+
+    /// Rate limits each client with a token bucket, answering the requests over it with 429 Too Many
+    /// Requests, and recording `rate_limit.exceeded` and `rate_limit.remaining` on every request span.
+    pub fn rate_limit(mut self, limit: RateLimit) -> Self {
+        self.config.rate_limit = Some(limit);
+        self
+    }
+
+    /// Proxies trusted to pass the client address on in forwarding headers, for `client.address` to
+    /// be the client's rather than the load balancer's.
+    pub fn client_ip(mut self, client_ip: ClientIpConfig) -> Self {
+        self.config.client_ip = client_ip;
+        self
+    }
+
+    /// Compresses the response bodies of [`TelemetryHandle::instrument`]ed routers with brotli or
+    /// gzip for the clients accepting it, as by default, recording the encoding and the size saved
+    /// on the spans.
+    pub fn response_compression(mut self, enabled: bool) -> Self {
+        self.config.response_compression = enabled;
+        self
+    }
+
+    /// Answers CORS preflights and adds the CORS headers to the responses of
+    /// [`TelemetryHandle::instrument`]ed routers with `cors`, e.g. `CorsLayer::permissive()`.
+    pub fn cors(mut self, cors: CorsLayer) -> Self {
+        self.config.cors = Some(cors);
+        self
+    }
+
+    /// Skips or collapses into one `CORS preflight` name the spans of CORS preflights, which
+    /// otherwise get a span each like any request.
+    pub fn preflight_spans(mut self, preflights: PreflightSpans) -> Self {
+        self.config.preflight_spans = preflights;
+        self
+    }
+
+    /// Sends every authorization decision recorded with [`record_decision`] to `sink` as well, for
+    /// an audit trail kept apart from the sampled traces.
+    pub fn audit_sink(mut self, sink: impl AuditSink) -> Self {
+        self.config.audit_sink = Some(Arc::new(sink));
+        self
+    }
+
+    /// Request and response headers to record on the request spans made by
+    /// [`TelemetryHandle::instrument`].
+    pub fn capture_headers(mut self, headers: HeaderCaptureConfig) -> Self {
+        self.config.headers = headers;
+        self
+    }
+
+    /// Which spans and events are recorded, in the `RUST_LOG` syntax, e.g. `info,hyper=warn`;
+    /// everything is recorded by default. It can be changed while running with [`set_log_filter`].
+    pub fn log_filter(mut self, directive: impl Into<String>) -> Self {
+        self.config.log_filter = directive.into();
+        self
+    }
+
+    /// Narrows what is exported over OpenTelemetry, as spans or log records, out of what the
+    /// [`log_filter`](Self::log_filter) records; defaults to `info`, so debug spans stay local. It
+    /// can be changed while running by [`reload_config_file`].
+    pub fn otel_filter(mut self, directive: impl Into<String>) -> Self {
+        self.config.otel_filter = directive.into();
+        self
+    }
+
+    /// Narrows what the [`json_logs`](Self::json_logs) print out of what the
+    /// [`log_filter`](Self::log_filter) records; defaults to `debug`. It can be changed while
+    /// running by [`reload_config_file`].
+    #[cfg(feature = "fmt")]
+    pub fn console_filter(mut self, directive: impl Into<String>) -> Self {
+        self.config.console_filter = directive.into();
+        self
+    }
+
+    /// Also exports request metrics through the same backend as the traces.
+    #[cfg(feature = "metrics")]
+    pub fn metrics(mut self, enabled: bool) -> Self {
+        self.config.metrics = enabled;
+        self
+    }
+
+    /// Also records metrics for the Prometheus endpoint served by [`metrics::prometheus_router`].
+    #[cfg(feature = "metrics")]
+    pub fn prometheus(mut self, enabled: bool) -> Self {
+        self.config.prometheus = enabled;
+        self
+    }
+
+    /// Also records the metrics of the tokio runtime [`Telemetry::init`] is called on, sampled every
+    /// `interval`, through the metrics pipeline; see [`runtime_metrics::spawn_runtime_metrics`].
+    #[cfg(feature = "metrics")]
+    pub fn runtime_metrics(mut self, interval: Duration) -> Self {
+        self.config.runtime_metrics = Some(interval);
+        self
+    }
+
+    /// Also records the CPU, memory and file descriptor use of the process through the metrics
+    /// pipeline; see [`process_metrics::register_process_metrics`].
+    #[cfg(feature = "metrics")]
+    pub fn process_metrics(mut self, enabled: bool) -> Self {
+        self.config.process_metrics = enabled;
+        self
+    }
+
+    /// Also serves the task diagnostics `tokio-console` connects to, on `127.0.0.1:6669` unless
+    /// `TOKIO_CONSOLE_BIND` says otherwise, alongside the OpenTelemetry export.
+    ///
+    /// Tokio only records them when built with `RUSTFLAGS="--cfg tokio_unstable"`, which the build
+    /// warns about when missing. The [`log_filter`](Self::log_filter) is widened to let through the
+    /// `tokio` and `runtime` spans and events they are made of.
+    #[cfg(feature = "console")]
+    pub fn tokio_console(mut self, enabled: bool) -> Self {
+        self.config.tokio_console = enabled;
+        self
+    }
+
+    /// Also ships `tracing` events as OpenTelemetry log records through the same backend as the traces.
+    #[cfg(feature = "logs")]
+    pub fn logs(mut self, enabled: bool) -> Self {
+        self.config.logs = enabled;
+        self
+    }
+
+    /// Also prints events to stdout as JSON lines carrying their trace and span IDs.
+    #[cfg(feature = "fmt")]
+    pub fn json_logs(mut self, enabled: bool) -> Self {
+        self.config.json_logs = enabled;
+        self
+    }
+
+    /// Installs the OpenTelemetry tracer and registers it as the global tracing subscriber,
+    /// returning the [`TelemetryHandle`] that instruments routers with this configuration.
+    ///
+    /// Fails, before anything is installed, when the configuration doesn't
+    /// [validate](TelemetryConfig::validate), when a global tracing subscriber is already installed,
+    /// e.g. by an earlier call, or when the [`connectivity_check`](Self::connectivity_check) fails
+    /// fast.
+    #[cfg_attr(not(feature = "console"), allow(unused_mut))]
+    pub fn init(mut self) -> Result<TelemetryHandle, config::ConfigError> {
+        self.config.validate()?;
+        if tracing::dispatcher::has_been_set() {
+            return Err(config::ConfigError::SubscriberInstalled);
+        }
+
+        // Probed before anything is installed, so failing fast leaves nothing half set up, but only
+        // logged once the subscriber is
+        let unreachable = match self.config.connectivity_check {
+            Some(check) => match connectivity::check_connectivity(&self.config) {
+                Err(err) if check == ConnectivityCheck::FailFast => return Err(err.into()),
+                result => result.err(),
+            },
+            None => None,
+        };
+
+        headers::set_config(self.config.headers.clone());
+        client_ip::set_config(self.config.client_ip.clone());
+        if let Some(sink) = self.config.audit_sink.clone() {
+            authz::set_audit_sink(sink);
+        }
+        shutdown::set_timeout(self.config.shutdown_timeout);
+        shutdown::set_drain_timeout(self.config.drain_timeout);
+        #[cfg(unix)]
+        if self.config.signal_handlers {
+            signals::spawn_signal_handlers();
+        }
+        // After the baseline is taken, as reloads add the directives to whichever filter they end
+        // up with
+        hot_reload::set_baseline(&self.config);
+        #[cfg(feature = "console")]
+        if self.config.tokio_console {
+            filter::add_console_directives(&mut self.config.log_filter);
+        }
+        #[cfg(feature = "metrics")]
+        {
+            if self.config.metrics || self.config.prometheus {
+                metrics::init_meter_provider(&self.config);
+            }
+            if let Some(interval) = self.config.runtime_metrics {
+                runtime_metrics::spawn_runtime_metrics(interval);
+            }
+            if self.config.process_metrics {
+                process_metrics::register_process_metrics();
+            }
+        }
+        #[cfg(feature = "logs")]
+        let logs = self
+            .config
+            .logs
+            .then(|| logs::init_logger_provider(&self.config))
+            .flatten()
+            .map(LogBridgeLayer::new);
+        #[cfg(not(feature = "logs"))]
+        let logs = None::<tracing_subscriber::layer::Identity>;
+        #[cfg(feature = "fmt")]
+        let json_logs = self.config.json_logs.then(|| {
+            tracing_subscriber::fmt::layer()
+                .event_format(JsonFormat)
+                .with_writer(std::io::stdout)
+                .with_filter(filter::console_layer_filter(&self.config.console_filter))
+        });
+        #[cfg(not(feature = "fmt"))]
+        let json_logs = None::<tracing_subscriber::layer::Identity>;
+        #[cfg(feature = "console")]
+        let console = self.config.tokio_console.then(|| {
+            console_subscriber::ConsoleLayer::builder()
+                .with_default_env()
+                .spawn()
+        });
+        #[cfg(not(feature = "console"))]
+        let console = None::<tracing_subscriber::layer::Identity>;
+        let filter = filter::reloadable(&self.config.log_filter);
+        let otel_filter = filter::otel_layer_filter(&self.config.otel_filter);
+        let config = Arc::new(self.config);
+        let tracer = install_tracer(&config);
+
+        // The log bridge reads the span IDs assigned by the OpenTelemetry layer, so they are filtered
+        // together, except for the skipping of the spans below the requests the sampler dropped, as
+        // their events are still logs
+        let opentelemetry = SkipSampledOut::new(tracing_opentelemetry::layer().with_tracer(tracer))
+            .and_then(logs)
+            .with_filter(otel_filter);
+        tracing_subscriber::registry()
+            .with(filter)
+            .with(SdkErrorLayer::new())
+            .with(opentelemetry)
+            .with(json_logs)
+            .with(console)
+            .try_init()
+            .map_err(|_| config::ConfigError::SubscriberInstalled)?;
+
+        if let Some(err) = unreachable {
+            tracing::warn!(error = %err, "starting with an unreachable telemetry backend");
+        }
+        Ok(TelemetryHandle { config })
+    }
+}
+
+/// The pipeline installed by [`Telemetry::init`], building the HTTP middleware from the
+/// configuration it was given.
+#[derive(Clone)]
+pub struct TelemetryHandle {
+    config: Arc<TelemetryConfig>,
+}
+
+impl TelemetryHandle {
+    #[cfg(feature = "testing")]
+    pub(crate) fn from_config(config: TelemetryConfig) -> Self {
+        Self {
+            config: Arc::new(config),
+        }
+    }
+
+    /// Wraps `router` in the HTTP tracing and metrics middleware so every request gets its own
+    /// span, continuing the caller's trace when the request carries one, and returning its trace ID
+    /// in the `traceresponse` header. Requests without an `x-request-id` header are given a random
+    /// UUID one, which is recorded on the span and echoed in the response. The headers configured
+    /// with [`Telemetry::capture_headers`] are recorded, requests exceeding the
+    /// [`Telemetry::request_timeout`] get a 408 and a `request.timeout` span event, handler panics
+    /// become 500 responses recorded on the span, and requests to the [`health_router`] probes get
+    /// no span. Requests the sampler drops don't get the client and connection attributes, and the
+    /// spans below them are [skipped](SkipSampledOut).
+    ///
+    /// These are the [`layers`](Self::layers).
+    pub fn instrument(&self, router: Router) -> Router {
+        router.layer(self.layers())
+    }
+
+    /// The [`telemetry_layers`] of the configuration given to [`Telemetry::init`].
+    pub fn layers(&self) -> layers::TelemetryLayers {
+        telemetry_layers(&self.config)
+    }
+
+    /// The configuration given to [`Telemetry::init`].
+    pub fn config(&self) -> &TelemetryConfig {
+        &self.config
+    }
+}
+
+impl std::fmt::Debug for TelemetryHandle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TelemetryHandle")
+            .field("service_name", &self.config.service_name)
+            .finish_non_exhaustive()
+    }
+}
+
+pub fn init_tracer(config: TelemetryConfig) -> sdktrace::SdkTracer {
+    install_tracer(&config)
+}
+
+fn install_tracer(config: &TelemetryConfig) -> sdktrace::SdkTracer {
+    propagation::install_propagators(&config.propagation);
+
+    let builder = config
+        .span_limits
+        .apply(sdktrace::SdkTracerProvider::builder());
+    let mut provider = builder
+        .with_sampler(RouteSampler::install(
+            config.route_sampling.clone(),
+            config.sampler.to_sampler(),
+        ))
+        .with_resource(resource::build(config))
+        .with_span_processor(StartCountingProcessor);
+    if let Some(generator) = config.id_generator.clone() {
+        provider = provider.with_id_generator(SharedIdGenerator(generator));
+    }
+    if !config.baggage_attributes.is_empty() {
+        provider = provider
+            .with_span_processor(BaggageSpanProcessor::new(config.baggage_attributes.clone()));
+    }
+    for (index, exporter) in std::iter::once(&config.exporter)
+        .chain(&config.extra_exporters)
+        .enumerate()
+    {
+        provider = with_exporter(provider, index, exporter, config);
+    }
+    install_provider(provider.build())
+}
+
+// `index` is the exporter's position among those of `config`, keeping its spilled batches apart
+fn with_exporter(
+    provider: sdktrace::TracerProviderBuilder,
+    index: usize,
+    exporter: &ExporterBackend,
+    config: &TelemetryConfig,
+) -> sdktrace::TracerProviderBuilder {
+    if let Some(target) = exporter.otlp_target(config, "traces") {
+        let spill = config
+            .spill
+            .as_ref()
+            .map(|spill| spill.for_exporter(&config.service_name, index, &target.endpoint));
+        let exporter = RetryingExporter::new(target.span_exporter(), config.retry.clone());
+        return match spill {
+            Some(spill) => with_batch_exporter(provider, exporter.with_spill(spill), config),
+            None => with_batch_exporter(provider, exporter, config),
+        };
+    }
+    #[cfg(feature = "zipkin")]
+    if let Some(exporter) = exporter.zipkin_span_exporter() {
+        return with_batch_exporter(provider, exporter, config);
+    }
+
+    let Some(exporter) = exporter.local_span_exporter() else {
+        return provider;
+    };
+    if config.tail_sampling.is_none()
+        && config.redaction.is_empty()
+        && config.attribute_limits.is_none()
+        && config.span_limits.max_attribute_value_length.is_none()
+    {
+        return provider.with_simple_exporter(exporter);
+    }
+    // The SDK's simple processor can't be wrapped, so wrapped spans go through a batch one
+    with_batch_exporter(provider, exporter, config)
+}
+
+// The processor running on tokio rather than its own thread keeps the retries' sleeps async
+fn with_batch_exporter<E: SpanExporter + 'static>(
+    provider: sdktrace::TracerProviderBuilder,
+    exporter: E,
+    config: &TelemetryConfig,
+) -> sdktrace::TracerProviderBuilder {
+    let processor = BatchSpanProcessor::builder(
+        CountingExporter::new(exporter),
+        opentelemetry_sdk::runtime::Tokio,
+    )
+    .with_batch_config(config.batch.config())
+    .build();
+    with_processor(provider, CountingProcessor::new(processor), config)
+}
+
+// Wraps the exporting processor in the attribute limiting one when limits or a value length are
+// configured, then in the redacting one, even without rules so they can be added by a reload, so
+// values are redacted before being cut, and in the tail sampling one when it is configured
+fn with_processor<P: sdktrace::SpanProcessor + 'static>(
+    provider: sdktrace::TracerProviderBuilder,
+    processor: P,
+    config: &TelemetryConfig,
+) -> sdktrace::TracerProviderBuilder {
+    let limits = config
+        .span_limits
+        .attribute_limits(config.attribute_limits.as_ref());
+    match limits {
+        Some(limits) => with_redaction(
+            provider,
+            AttributeLimitProcessor::new(processor, limits),
+            config,
+        ),
+        None => with_redaction(provider, processor, config),
+    }
+}
+
+fn with_redaction<P: sdktrace::SpanProcessor + 'static>(
+    provider: sdktrace::TracerProviderBuilder,
+    processor: P,
+    config: &TelemetryConfig,
+) -> sdktrace::TracerProviderBuilder {
+    let processor = RedactingSpanProcessor::installed(processor, &config.redaction);
+    with_tail_sampling(provider, processor, config)
+}
+
+fn with_tail_sampling<P: sdktrace::SpanProcessor + 'static>(
+    provider: sdktrace::TracerProviderBuilder,
+    processor: P,
+    config: &TelemetryConfig,
+) -> sdktrace::TracerProviderBuilder {
+    match &config.tail_sampling {
+        Some(policy) => {
+            provider.with_span_processor(TailSamplingProcessor::new(processor, policy.clone()))
+        }
+        None => provider.with_span_processor(processor),
+    }
+}
+
+fn install_provider(provider: sdktrace::SdkTracerProvider) -> sdktrace::SdkTracer {
+    let tracer = provider.tracer_with_scope(
+        InstrumentationScope::builder(env!("CARGO_PKG_NAME"))
+            .with_version(env!("CARGO_PKG_VERSION"))
+            .build(),
+    );
+    opentelemetry::global::set_tracer_provider(provider.clone());
+    shutdown::set_tracer_provider(provider);
+    tracer
+}
+
+/// Resolves on Ctrl+C or SIGTERM, then flushes and shuts down the global tracer, meter and logger
+/// providers, waiting at most the [`Telemetry::shutdown_timeout`].
+///
+/// Given to `with_graceful_shutdown`, it flushes while the requests in flight are still running, so
+/// their spans are lost; a [`ShutdownCoordinator`] flushes once they're done.
+pub async fn shutdown_signal() {
+    shutdown::wait_for_signal().await;
+    tracing::warn!("signal received, starting graceful shutdown");
+    shutdown::shutdown_configured_providers().await;
+}
@@ -0,0 +1,104 @@
+use axum::extract::MatchedPath;
+use axum::http::{Request, Response, StatusCode};
+use opentelemetry::metrics::Counter;
+use opentelemetry::KeyValue;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tokio::sync::Semaphore;
+use tower::{Layer, Service};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+/// Layer letting at most `max_concurrent` requests run at once, and answering the ones over the
+/// limit straight away with an empty 503 Service Unavailable response rather than queueing them;
+/// without a limit every request runs.
+///
+/// Shed requests get a `load_shed = true` attribute on the current span and are counted in the
+/// `http.server.requests.rejected` metric. The limit is shared by the services the layer makes, so
+/// by every connection.
+#[derive(Clone)]
+pub struct LoadShedLayer {
+    permits: Option<Arc<Semaphore>>,
+    rejected: Counter<u64>,
+}
+
+impl LoadShedLayer {
+    pub fn new(max_concurrent: Option<usize>) -> Self {
+        let rejected = opentelemetry::global::meter(env!("CARGO_PKG_NAME"))
+            .u64_counter("http.server.requests.rejected")
+            .with_description("HTTP server requests rejected without being handled")
+            .build();
+        Self {
+            permits: max_concurrent.map(|max| Arc::new(Semaphore::new(max))),
+            rejected,
+        }
+    }
+}
+
+impl<S> Layer<S> for LoadShedLayer {
+    type Service = LoadShed<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        LoadShed {
+            inner,
+            permits: self.permits.clone(),
+            rejected: self.rejected.clone(),
+        }
+    }
+}
+
+/// Service created by [`LoadShedLayer`].
+#[derive(Clone)]
+pub struct LoadShed<S> {
+    inner: S,
+    permits: Option<Arc<Semaphore>>,
+    rejected: Counter<u64>,
+}
+
+impl<S, B, ResBody> Service<Request<B>> for LoadShed<S>
+where
+    S: Service<Request<B>, Response = Response<ResBody>>,
+    S::Future: Send + 'static,
+    ResBody: Default,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<B>) -> Self::Future {
+        let Some(permits) = &self.permits else {
+            return Box::pin(self.inner.call(request));
+        };
+
+        // Held until the handler's future completes or is dropped
+        let Ok(permit) = permits.clone().try_acquire_owned() else {
+            let mut attributes = vec![
+                KeyValue::new("http.request.method", request.method().to_string()),
+                KeyValue::new("reason", "load_shed"),
+            ];
+            if let Some(route) = request.extensions().get::<MatchedPath>() {
+                attributes.push(KeyValue::new("http.route", route.as_str().to_string()));
+            }
+            self.rejected.add(1, &attributes);
+            tracing::Span::current().set_attribute("load_shed", true);
+
+            return Box::pin(async {
+                let mut response = Response::new(ResBody::default());
+                *response.status_mut() = StatusCode::SERVICE_UNAVAILABLE;
+                Ok(response)
+            });
+        };
+
+        let future = self.inner.call(request);
+        Box::pin(async move {
+            let result = future.await;
+            drop(permit);
+            result
+        })
+    }
+}
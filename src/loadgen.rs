@@ -0,0 +1,164 @@
+use crate::SpanExt;
+use rand::seq::SliceRandom;
+use rand::Rng;
+use std::time::{Duration, Instant};
+use tokio::time::MissedTickBehavior;
+use tracing::field::Empty;
+use tracing::Span;
+
+// Traces are generated in bursts this far apart, as many as are due each time
+const TICK: Duration = Duration::from_millis(10);
+
+const ROUTES: [(&str, &str); 5] = [
+    ("GET", "/items"),
+    ("GET", "/items/{id}"),
+    ("POST", "/items"),
+    ("PUT", "/lists/{id}/items"),
+    ("DELETE", "/lists/{id}"),
+];
+
+const OPERATIONS: [(&str, &str); 4] = [
+    ("SELECT items", "client"),
+    ("GET cache", "client"),
+    ("HTTP client request", "client"),
+    ("render", "internal"),
+];
+
+/// Shape and volume of the synthetic traces [`generate`] sends through the installed pipeline, to
+/// load test the exporter batching and the collector before a rollout.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LoadGen {
+    pub traces_per_second: u32,
+    pub duration: Duration,
+    /// Levels of spans below each server span, 0 for traces of a single span.
+    pub depth: u32,
+    /// Children of each span above the deepest level.
+    pub fanout: u32,
+    /// Random `loadgen.attribute.<n>` attributes on every span, on top of the HTTP ones.
+    pub attributes: u32,
+    /// Share of the traces, from 0 to 1, whose server span answers 500 and is marked as an error.
+    pub error_rate: f64,
+}
+
+impl Default for LoadGen {
+    fn default() -> Self {
+        Self {
+            traces_per_second: 100,
+            duration: Duration::from_secs(10),
+            depth: 2,
+            fanout: 3,
+            attributes: 8,
+            error_rate: 0.01,
+        }
+    }
+}
+
+impl LoadGen {
+    /// Spans in each trace.
+    pub fn spans_per_trace(&self) -> u64 {
+        (0..=self.depth)
+            .map(|level| u64::from(self.fanout).pow(level))
+            .sum()
+    }
+}
+
+/// What a [`generate`] run sent, and what became of it once flushed.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LoadGenReport {
+    pub traces: u64,
+    pub spans: u64,
+    pub errors: u64,
+    /// Spans the exporters accepted during the run.
+    pub exported: u64,
+    /// Spans lost to failed exports during the run.
+    pub failed: u64,
+    /// Spans still neither exported nor failed after the flush, mostly the ones dropped by full
+    /// batch queues.
+    pub unflushed: u64,
+    pub elapsed: Duration,
+}
+
+/// Generates the [`LoadGen`] traces at its steady rate for its duration, then flushes the pending
+/// spans and reports how many the exporters took.
+///
+/// The traces look like requests to an HTTP service: a server span per trace, under which are
+/// database, cache, client and internal spans. Spans end as soon as they are generated, so the
+/// rate is only bounded by how fast the pipeline takes them. The counts include the spans of any
+/// other traffic of the process, so it's best run on its own, e.g. from the `loadgen` command.
+pub async fn generate(load: &LoadGen) -> LoadGenReport {
+    let exported = crate::self_metrics::exported();
+    let failed = crate::self_metrics::failed();
+    let start = Instant::now();
+    let total = (load.duration.as_secs_f64() * f64::from(load.traces_per_second)) as u64;
+    let mut report = LoadGenReport::default();
+
+    let mut interval = tokio::time::interval(TICK);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    while report.traces < total {
+        interval.tick().await;
+        let due = (start.elapsed().as_secs_f64() * f64::from(load.traces_per_second)) as u64;
+        // Not kept across ticks, as it can't be sent to another thread
+        let mut rng = rand::thread_rng();
+        for _ in report.traces..due.min(total) {
+            let error = rng.gen_bool(load.error_rate.clamp(0.0, 1.0));
+            report.spans += trace(load, error, &mut rng);
+            report.errors += u64::from(error);
+            report.traces += 1;
+        }
+    }
+
+    if let Err(err) = crate::force_flush_spans().await {
+        tracing::warn!(error = %err, "failed to flush the generated spans");
+    }
+    report.exported = crate::self_metrics::exported() - exported;
+    report.failed = crate::self_metrics::failed() - failed;
+    report.unflushed = crate::self_metrics::pending();
+    report.elapsed = start.elapsed();
+    report
+}
+
+// Returns the number of spans generated
+fn trace(load: &LoadGen, error: bool, rng: &mut impl Rng) -> u64 {
+    let (method, route) = ROUTES.choose(rng).copied().unwrap_or(ROUTES[0]);
+    let status: i64 = if error { 500 } else { 200 };
+    let span = tracing::info_span!(
+        parent: None,
+        "HTTP request",
+        otel.name = format!("{method} {route}"),
+        otel.kind = "server",
+        otel.status_code = Empty,
+        http.method = method,
+        http.route = route,
+        http.status_code = status,
+        loadgen = true,
+    );
+    random_attributes(&span, load.attributes, rng);
+    if error {
+        span.record("otel.status_code", "ERROR");
+        span.add_event("exception", [("exception.message", "synthetic failure")]);
+    }
+    let _entered = span.enter();
+    1 + children(load, load.depth, rng)
+}
+
+fn children(load: &LoadGen, depth: u32, rng: &mut impl Rng) -> u64 {
+    if depth == 0 {
+        return 0;
+    }
+    let mut spans = 0;
+    for _ in 0..load.fanout {
+        let (name, kind) = OPERATIONS.choose(rng).copied().unwrap_or(OPERATIONS[0]);
+        let span = tracing::info_span!("operation", otel.name = name, otel.kind = kind);
+        random_attributes(&span, load.attributes, rng);
+        let _entered = span.enter();
+        spans += 1 + children(load, depth - 1, rng);
+    }
+    spans
+}
+
+fn random_attributes(span: &Span, attributes: u32, rng: &mut impl Rng) {
+    for n in 0..attributes {
+        let value: u32 = rng.gen();
+        span.set_attr(format!("loadgen.attribute.{n}"), format!("{value:08x}"));
+    }
+}
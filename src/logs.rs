@@ -0,0 +1,178 @@
+use crate::TelemetryConfig;
+use opentelemetry::logs::{AnyValue, LogRecord as _, Logger as _, LoggerProvider as _, Severity};
+use opentelemetry::Key;
+#[cfg(feature = "grpc")]
+use opentelemetry_otlp::Protocol;
+use opentelemetry_sdk::logs::log_processor_with_async_runtime::BatchLogProcessor;
+use opentelemetry_sdk::logs::{SdkLogRecord, SdkLogger, SdkLoggerProvider};
+use std::sync::Mutex;
+use std::time::SystemTime;
+use tracing::field::{Field, Visit};
+use tracing::{Event, Level, Subscriber};
+use tracing_log::NormalizeEvent;
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Layer;
+
+// Kept so the pending records can be flushed on shutdown, as there is no global logger provider
+static LOGGER_PROVIDER: Mutex<Option<SdkLoggerProvider>> = Mutex::new(None);
+
+/// Builds a logger provider exporting through the configured backend, returning the logger for
+/// [`LogBridgeLayer`], or `None` when the backend has no logs support.
+pub(crate) fn init_logger_provider(config: &TelemetryConfig) -> Option<SdkLogger> {
+    let provider = SdkLoggerProvider::builder().with_resource(crate::resource::build(config));
+
+    let provider = if let Some(target) = config.exporter.otlp_target(config, "logs") {
+        let builder = opentelemetry_otlp::LogExporter::builder();
+        let exporter = match target.protocol {
+            #[cfg(feature = "grpc")]
+            Protocol::Grpc => target.tonic(builder.with_tonic()).build(),
+            _ => target.http(builder.with_http()).build(),
+        };
+        let processor =
+            BatchLogProcessor::builder(exporter.unwrap(), opentelemetry_sdk::runtime::Tokio)
+                .build();
+        provider.with_log_processor(processor)
+    } else if config.exporter.is_stdout() {
+        provider.with_simple_exporter(opentelemetry_stdout::LogExporter::default())
+    } else {
+        return None;
+    };
+
+    let provider = provider.build();
+    let logger = provider.logger(env!("CARGO_PKG_NAME"));
+    *LOGGER_PROVIDER.lock().unwrap() = Some(provider);
+    Some(logger)
+}
+
+/// Flushes and shuts down the logger provider installed by [`crate::Telemetry::init`], if any.
+pub fn shutdown_logger_provider() {
+    if let Some(provider) = LOGGER_PROVIDER.lock().unwrap().take() {
+        if let Err(err) = provider.shutdown() {
+            tracing::warn!(error = %err, "failed to shut down the logger provider");
+        }
+    }
+}
+
+/// Layer shipping `tracing` events as OpenTelemetry log records, carrying the trace and span IDs of the
+/// span they were emitted in so Honeycomb can link each log line to its trace.
+///
+/// Must be composed after the `tracing_opentelemetry` layer, which assigns those IDs.
+pub struct LogBridgeLayer {
+    logger: SdkLogger,
+}
+
+impl LogBridgeLayer {
+    pub fn new(logger: SdkLogger) -> Self {
+        Self { logger }
+    }
+}
+
+impl<S> Layer<S> for LogBridgeLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        // Events forwarded from the `log` crate carry their real target in their fields
+        let normalized = event.normalized_metadata();
+        let metadata = normalized.as_ref().unwrap_or_else(|| event.metadata());
+        if is_exporter_internal(metadata.target()) {
+            return;
+        }
+
+        let mut record = self.logger.create_log_record();
+        record.set_timestamp(SystemTime::now());
+        record.set_severity_number(severity(metadata.level()));
+        record.set_severity_text(metadata.level().as_str());
+        if let Some(span_context) = ctx
+            .event_span(event)
+            .and_then(|span| crate::span::otel_span_context(&span.id()))
+        {
+            record.set_trace_context(
+                span_context.trace_id(),
+                span_context.span_id(),
+                Some(span_context.trace_flags()),
+            );
+        }
+
+        let mut visitor = RecordVisitor {
+            record: &mut record,
+        };
+        event.record(&mut visitor);
+        visitor.attribute(
+            Key::from_static_str("target"),
+            metadata.target().to_string().into(),
+        );
+
+        self.logger.emit(record);
+    }
+}
+
+// The exporters and their own HTTP and gRPC clients log through `tracing` too, and shipping those
+// records would make every export trigger another one
+fn is_exporter_internal(target: &str) -> bool {
+    const TARGETS: [&str; 5] = ["hyper", "h2", "reqwest", "tonic", "tower"];
+    crate::sdk_errors::is_sdk_target(target)
+        || TARGETS.iter().any(|prefix| {
+            target
+                .strip_prefix(prefix)
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with("::"))
+        })
+}
+
+fn severity(level: &Level) -> Severity {
+    match *level {
+        Level::TRACE => Severity::Trace,
+        Level::DEBUG => Severity::Debug,
+        Level::INFO => Severity::Info,
+        Level::WARN => Severity::Warn,
+        Level::ERROR => Severity::Error,
+    }
+}
+
+struct RecordVisitor<'a> {
+    record: &'a mut SdkLogRecord,
+}
+
+impl RecordVisitor<'_> {
+    fn attribute(&mut self, key: Key, value: AnyValue) {
+        self.record.add_attribute(key, value);
+    }
+
+    fn field(&mut self, field: &Field, value: AnyValue) {
+        if field.name() == "message" {
+            self.record.set_body(value);
+        } else {
+            self.attribute(Key::from_static_str(field.name()), value);
+        }
+    }
+}
+
+impl Visit for RecordVisitor<'_> {
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.field(field, value.into());
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.field(field, value.into());
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        match i64::try_from(value) {
+            Ok(value) => self.field(field, value.into()),
+            Err(_) => self.field(field, value.to_string().into()),
+        }
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.field(field, value.into());
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.field(field, value.to_string().into());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        self.field(field, format!("{value:?}").into());
+    }
+}
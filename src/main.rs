@@ -1,101 +1,274 @@
-#![deny(unused_crate_dependencies)]
-
 use axum::routing::get;
 use axum::Router;
-use opentelemetry::sdk::{trace as sdktrace, Resource};
-use opentelemetry::KeyValue;
-use opentelemetry_otlp::{ExportConfig, Protocol, WithExportConfig};
-use reqwest as _; // Need to pin version of reqwest to avoid "error trying to connect: invalid URL, scheme is not http"
-use std::collections::HashMap;
+use axum_picklist::config::{ConfigError, HONEYCOMB_API_KEY};
+use axum_picklist::{
+    check_connectivity, force_flush_spans, loadgen, serve_ops, shutdown_signal, ApiKeySource,
+    ErrorBodyLayer, ExporterBackend, LoadGen, Readiness, Telemetry, TelemetryConfig,
+};
+use clap::{Parser, Subcommand};
+use opentelemetry::trace::TraceContextExt;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::process::ExitCode;
 use std::time::Duration;
-use tower::ServiceBuilder;
-use tower_http::trace::TraceLayer;
 use tracing::{span, Level};
-use tracing_subscriber::layer::SubscriberExt;
-use tracing_subscriber::util::SubscriberInitExt;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
 
-// Expecting a config/.honeycomb_api_key file with a single line that is the Honeycomb API key
-const HONEYCOMB_API_KEY: &str = include_str!("../config/.honeycomb_api_key");
+/// Pick list service, tracing its requests with OpenTelemetry.
+#[derive(Parser)]
+#[command(version, about)]
+struct Cli {
+    /// Print spans to the terminal instead, so the service runs without a key or network
+    #[arg(long, global = true)]
+    dev: bool,
+    /// TOML or YAML telemetry configuration, in the format of `TelemetryConfig::from_file`
+    #[arg(long, global = true, value_name = "FILE")]
+    config: Option<PathBuf>,
+    /// File holding the Honeycomb API key when there is no configuration file, otherwise read from
+    /// the HONEYCOMB_API_KEY variable
+    #[arg(long, global = true, value_name = "FILE")]
+    api_key_file: Option<PathBuf>,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
 
-#[tokio::main]
-async fn main() {
-    let tracer = init_tracer();
-
-    let opentelemetry = tracing_opentelemetry::layer().with_tracer(tracer);
-    tracing_subscriber::registry()
-        .with(opentelemetry)
-        .try_init()
-        .unwrap();
-
-    let app = Router::new()
-        .route("/", get(handler))
-        .layer(ServiceBuilder::new().layer(TraceLayer::new_for_http()));
-
-    axum::Server::bind(&"0.0.0.0:3000".parse().unwrap())
-        .serve(app.into_make_service())
-        .with_graceful_shutdown(shutdown_signal())
-        .await
-        .unwrap();
+#[derive(Subcommand)]
+enum Command {
+    /// Run the server, what happens without a subcommand
+    Serve {
+        /// Serve on this address rather than the configured one, port 0 being any free port
+        #[arg(long)]
+        bind: Option<SocketAddr>,
+    },
+    /// Validate the telemetry configuration and check that its backends can be reached
+    CheckConfig,
+    /// Send a synthetic trace through the telemetry pipeline and wait for it to be exported
+    SendTestSpan,
+    /// Send synthetic traces at a steady rate to load test the exporters and the collector
+    Loadgen {
+        #[arg(long, default_value_t = 100)]
+        traces_per_second: u32,
+        /// How long to send for, in seconds
+        #[arg(long, default_value_t = 10)]
+        duration: u64,
+        /// Levels of spans below the server span of each trace
+        #[arg(long, default_value_t = 2)]
+        depth: u32,
+        /// Children of each span above the deepest level
+        #[arg(long, default_value_t = 3)]
+        fanout: u32,
+        /// Random attributes on every span
+        #[arg(long, default_value_t = 8)]
+        attributes: u32,
+        /// Share of the traces marked as errors, from 0 to 1
+        #[arg(long, default_value_t = 0.01)]
+        error_rate: f64,
+    },
 }
 
-fn init_tracer() -> sdktrace::Tracer {
-    let metadata = HashMap::from([(
-        "x-honeycomb-team".to_string(),
-        HONEYCOMB_API_KEY.to_string(),
-    )]);
+impl Cli {
+    // The environment variables override the file, and `--dev` everything
+    fn telemetry_config(&self) -> Result<TelemetryConfig, ConfigError> {
+        let mut config = match &self.config {
+            Some(path) => TelemetryConfig::from_file(path)?,
+            None if self.dev => TelemetryConfig::new(ExporterBackend::StdoutPretty).with_env(),
+            None => {
+                let api_key_source = match &self.api_key_file {
+                    Some(path) => ApiKeySource::File(path.clone()),
+                    None => ApiKeySource::Env(HONEYCOMB_API_KEY.to_string()),
+                };
+                let exporter = ExporterBackend::Honeycomb {
+                    api_key: api_key_source.load()?,
+                    dataset: None,
+                };
+                TelemetryConfig::new(exporter).with_env()
+            }
+        };
+        if self.dev {
+            config.exporter = ExporterBackend::StdoutPretty;
+        }
+        Ok(config)
+    }
+}
 
-    let export_config = ExportConfig {
-        endpoint: "https://api.honeycomb.io/v1/traces".to_string(),
-        timeout: Duration::from_secs(3),
-        protocol: Protocol::HttpBinary,
+#[tokio::main]
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let mut config = match cli.telemetry_config() {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("invalid telemetry configuration: {err}");
+            return ExitCode::FAILURE;
+        }
     };
 
-    let trace_config =
-        opentelemetry::sdk::trace::config().with_resource(Resource::new(vec![KeyValue::new(
-            opentelemetry_semantic_conventions::resource::SERVICE_NAME,
-            "Pick List",
-        )]));
-
-    let otlp_exporter = opentelemetry_otlp::new_exporter()
-        .http()
-        .with_headers(metadata)
-        .with_export_config(export_config);
-
-    let tracer = opentelemetry_otlp::new_pipeline()
-        .tracing()
-        .with_exporter(otlp_exporter)
-        .with_trace_config(trace_config)
-        .install_batch(opentelemetry::runtime::Tokio)
-        .unwrap();
-
-    tracer
+    match cli.command.unwrap_or(Command::Serve { bind: None }) {
+        Command::Serve { bind } => {
+            if let Some(address) = bind {
+                config.bind_address = address;
+            }
+            serve(config).await
+        }
+        Command::CheckConfig => check_config(&config),
+        Command::SendTestSpan => send_test_span(config).await,
+        Command::Loadgen {
+            traces_per_second,
+            duration,
+            depth,
+            fanout,
+            attributes,
+            error_rate,
+        } => {
+            let load = LoadGen {
+                traces_per_second,
+                duration: Duration::from_secs(duration),
+                depth,
+                fanout,
+                attributes,
+                error_rate,
+            };
+            loadgen(config, &load).await
+        }
+    }
 }
 
-async fn shutdown_signal() {
-    let ctrl_c = async {
-        tokio::signal::ctrl_c()
-            .await
-            .expect("failed to install Ctrl+C handler");
-    };
+fn check_config(config: &TelemetryConfig) -> ExitCode {
+    // Checked first, as the exporters refuse to be built with an invalid key or TLS file
+    if let Err(err) = config.validate() {
+        eprintln!("invalid telemetry configuration: {err}");
+        return ExitCode::FAILURE;
+    }
+    match check_connectivity(config) {
+        Ok(()) => {
+            println!("the telemetry configuration is valid and its backends are reachable");
+            ExitCode::SUCCESS
+        }
+        Err(err) => {
+            eprintln!("{err}");
+            ExitCode::FAILURE
+        }
+    }
+}
 
-    #[cfg(unix)]
-    let terminate = async {
-        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
-            .expect("failed to install signal handler")
-            .recv()
-            .await;
+async fn send_test_span(config: TelemetryConfig) -> ExitCode {
+    if let Err(err) = Telemetry::from_config(config).init() {
+        eprintln!("failed to start the telemetry: {err}");
+        return ExitCode::FAILURE;
+    }
+
+    let span = tracing::info_span!("test span", test.synthetic = true);
+    let span_context = span.context().span().span_context().clone();
+    span.in_scope(|| {
+        tracing::info_span!("test child span").in_scope(|| tracing::info!("test event"));
+    });
+    drop(span);
+
+    if !span_context.is_sampled() {
+        eprintln!("the test trace was dropped by the configured sampler");
+        return ExitCode::FAILURE;
+    }
+    match force_flush_spans().await {
+        Ok(()) => {
+            println!("exported trace {}", span_context.trace_id());
+            ExitCode::SUCCESS
+        }
+        Err(err) => {
+            eprintln!("failed to export trace {}: {err}", span_context.trace_id());
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn loadgen(config: TelemetryConfig, load: &LoadGen) -> ExitCode {
+    if let Err(err) = Telemetry::from_config(config).init() {
+        eprintln!("failed to start the telemetry: {err}");
+        return ExitCode::FAILURE;
+    }
+    println!(
+        "sending {} traces a second, of {} spans each, for {:?}",
+        load.traces_per_second,
+        load.spans_per_trace(),
+        load.duration
+    );
+
+    let report = loadgen::generate(load).await;
+    let seconds = report.elapsed.as_secs_f64();
+    println!(
+        "sent {} traces, {} with errors, and {} spans in {seconds:.1}s, {:.0} spans a second",
+        report.traces,
+        report.errors,
+        report.spans,
+        report.spans as f64 / seconds
+    );
+    println!(
+        "exported {} spans, {} failed, {} unflushed",
+        report.exported, report.failed, report.unflushed
+    );
+    if report.failed + report.unflushed > 0 {
+        return ExitCode::FAILURE;
+    }
+    ExitCode::SUCCESS
+}
+
+async fn serve(config: TelemetryConfig) -> ExitCode {
+    let address = config.bind_address;
+    let telemetry = match Telemetry::from_config(config).init() {
+        Ok(telemetry) => telemetry,
+        Err(err) => {
+            eprintln!("failed to start the telemetry: {err}");
+            return ExitCode::FAILURE;
+        }
     };
 
-    #[cfg(not(unix))]
-    let terminate = std::future::pending::<()>();
+    // The probes, metrics and admin endpoints are only served on the loopback interface
+    let readiness = Readiness::default();
+    serve_ops(SocketAddr::from(([127, 0, 0, 1], 9090)), readiness.clone())
+        .await
+        .expect("failed to bind the operational listener");
+    let app = Router::new().route("/", get(handler));
+    let app = telemetry.instrument(app.layer(ErrorBodyLayer::new()));
+
+    readiness.set_ready(true);
+    // HTTPS when given a certificate and key, e.g. `TLS_CERT_FILE=cert.pem TLS_KEY_FILE=key.pem`
+    #[cfg(feature = "tls")]
+    if let (Some(cert), Some(key)) = (
+        std::env::var_os("TLS_CERT_FILE"),
+        std::env::var_os("TLS_KEY_FILE"),
+    ) {
+        let certificates = axum_picklist::TlsCertificates::load(cert, key)
+            .expect("failed to load the TLS certificate");
+        let listener = axum_picklist::TlsListener::bind(address, certificates)
+            .await
+            .unwrap();
+        axum::serve(listener, axum_picklist::TlsMakeService::new(app))
+            .with_graceful_shutdown(shutdown_signal())
+            .await
+            .unwrap();
+        return ExitCode::SUCCESS;
+    }
 
-    tokio::select! {
-        _ = ctrl_c => {},
-        _ = terminate => {},
+    // Behind a local proxy instead, e.g. `UNIX_SOCKET=/run/app/http.sock`
+    #[cfg(unix)]
+    if let Some(path) = std::env::var_os("UNIX_SOCKET") {
+        let listener = axum_picklist::bind_unix(path).unwrap();
+        axum::serve(listener, axum_picklist::UdsMakeService::new(app))
+            .with_graceful_shutdown(shutdown_signal())
+            .await
+            .unwrap();
+        return ExitCode::SUCCESS;
     }
 
-    tracing::warn!("signal received, starting graceful shutdown");
-    opentelemetry::global::shutdown_tracer_provider();
+    let listener = tokio::net::TcpListener::bind(address).await.unwrap();
+    // The port actually bound, for when port 0 was asked for
+    tracing::info!(address = %listener.local_addr().unwrap(), "serving HTTP");
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .with_graceful_shutdown(shutdown_signal())
+    .await
+    .unwrap();
+    ExitCode::SUCCESS
 }
 
 async fn handler() -> &'static str {
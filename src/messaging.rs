@@ -0,0 +1,242 @@
+use opentelemetry::propagation::{Extractor, Injector};
+use opentelemetry::trace::TraceContextExt;
+use opentelemetry::KeyValue;
+use std::fmt::Display;
+use tracing::field::Empty;
+use tracing::Span;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+/// The trace context the configured propagators find in the headers of a received message.
+pub fn extract_context(headers: &dyn Extractor) -> opentelemetry::Context {
+    opentelemetry::global::get_text_map_propagator(|propagator| propagator.extract(headers))
+}
+
+/// Writes the trace context of the current span into the headers of a message about to be sent,
+/// in the formats of the configured propagators.
+pub fn inject_context(headers: &mut dyn Injector) {
+    let context = crate::span::otel_context(&Span::current());
+    opentelemetry::global::get_text_map_propagator(|propagator| {
+        propagator.inject_context(&context, headers)
+    });
+}
+
+/// Span for processing a message received from `destination`, the topic or queue, continuing the
+/// trace found in its `headers`. `system` is the messaging system, e.g. `kafka` or `rabbitmq`.
+///
+/// Enter it, or instrument the processing future with it, so the work it does is part of the
+/// producer's trace.
+pub fn consumer_span(system: &'static str, destination: &str, headers: &dyn Extractor) -> Span {
+    let span = tracing::info_span!(
+        "message process",
+        otel.name = format!("{destination} process"),
+        otel.kind = "consumer",
+        otel.status_code = Empty,
+        messaging.system = system,
+        messaging.operation = "process",
+        messaging.destination.name = destination,
+    );
+    let _ = span.set_parent(extract_context(headers));
+    span
+}
+
+/// Span for sending a message to `destination`, the topic or queue, over the messaging `system`.
+///
+/// Call [`inject_context`] with it entered, so consumers continue the trace from it.
+pub fn producer_span(system: &'static str, destination: &str) -> Span {
+    tracing::info_span!(
+        "message publish",
+        otel.name = format!("{destination} publish"),
+        otel.kind = "producer",
+        otel.status_code = Empty,
+        messaging.system = system,
+        messaging.operation = "publish",
+        messaging.destination.name = destination,
+    )
+}
+
+/// Span for processing a batch of messages received together from `destination`, linking to the
+/// trace of each message found in its `headers` rather than continuing any of them, as the
+/// messaging conventions ask for batches.
+///
+/// The span is a child of the current span and records `messaging.batch.message_count`. Each link
+/// carries the position of its message as `messaging.batch.message_index`, which
+/// [`BatchSpan::record_failure`] refers to.
+pub fn batch_span<E: Extractor>(
+    system: &'static str,
+    destination: &str,
+    headers: impl IntoIterator<Item = E>,
+) -> BatchSpan {
+    let span = tracing::info_span!(
+        "message batch process",
+        otel.name = format!("{destination} process"),
+        otel.kind = "consumer",
+        otel.status_code = Empty,
+        messaging.system = system,
+        messaging.operation = "process",
+        messaging.destination.name = destination,
+        messaging.batch.message_count = Empty,
+        messaging.batch.succeeded_count = 0_i64,
+        messaging.batch.failed_count = 0_i64,
+    );
+
+    let mut count = 0_i64;
+    for (index, headers) in headers.into_iter().enumerate() {
+        let producer = extract_context(&headers).span().span_context().clone();
+        span.add_link_with_attributes(
+            producer,
+            vec![KeyValue::new("messaging.batch.message_index", index as i64)],
+        );
+        count += 1;
+    }
+    span.record("messaging.batch.message_count", count);
+
+    BatchSpan {
+        span,
+        succeeded: 0,
+        failed: 0,
+    }
+}
+
+/// Span created by [`batch_span`], counting the outcome of each message as
+/// `messaging.batch.succeeded_count` and `messaging.batch.failed_count`.
+#[derive(Debug)]
+pub struct BatchSpan {
+    span: Span,
+    succeeded: i64,
+    failed: i64,
+}
+
+impl BatchSpan {
+    /// The span to enter, or instrument the processing future with.
+    pub fn span(&self) -> &Span {
+        &self.span
+    }
+
+    /// Counts one more message processed successfully.
+    pub fn record_success(&mut self) {
+        self.succeeded += 1;
+        self.span
+            .record("messaging.batch.succeeded_count", self.succeeded);
+    }
+
+    /// Counts the message at `index` as failed, recording `error` as an `exception` event with its
+    /// `messaging.batch.message_index`, and marks the span as an error.
+    pub fn record_failure(&mut self, index: usize, error: &dyn Display) {
+        self.failed += 1;
+        self.span
+            .record("messaging.batch.failed_count", self.failed);
+        self.span.record("otel.status_code", "ERROR");
+        tracing::error!(
+            parent: &self.span,
+            messaging.batch.message_index = index as i64,
+            exception.message = %error,
+            "exception"
+        );
+    }
+}
+
+/// Message headers as a list of names and byte values, the form most messaging clients expose
+/// them in. Values that aren't UTF-8 are skipped.
+#[derive(Debug)]
+pub struct ByteHeaders<'a>(pub &'a [(String, Vec<u8>)]);
+
+impl Extractor for ByteHeaders<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(key))
+            .and_then(|(_, value)| std::str::from_utf8(value).ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.iter().map(|(name, _)| name.as_str()).collect()
+    }
+}
+
+/// Writable [`ByteHeaders`], replacing headers that are already set.
+#[derive(Debug)]
+pub struct ByteHeadersMut<'a>(pub &'a mut Vec<(String, Vec<u8>)>);
+
+impl Injector for ByteHeadersMut<'_> {
+    fn set(&mut self, key: &str, value: String) {
+        self.0.retain(|(name, _)| !name.eq_ignore_ascii_case(key));
+        self.0.push((key.to_string(), value.into_bytes()));
+    }
+}
+
+/// Headers of a Kafka message received with `rdkafka`, e.g. `KafkaHeaders(message.headers()?)`.
+#[cfg(feature = "kafka")]
+#[derive(Debug)]
+pub struct KafkaHeaders<'a, H>(pub &'a H);
+
+#[cfg(feature = "kafka")]
+impl<H: rdkafka::message::Headers> Extractor for KafkaHeaders<'_, H> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0
+            .iter()
+            .find(|header| header.key.eq_ignore_ascii_case(key))
+            .and_then(|header| std::str::from_utf8(header.value?).ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.iter().map(|header| header.key).collect()
+    }
+}
+
+/// Headers of a Kafka message about to be sent with `rdkafka`, e.g.
+/// `inject_context(&mut KafkaHeadersMut(&mut headers))` before `record.headers(headers)`.
+#[cfg(feature = "kafka")]
+#[derive(Debug)]
+pub struct KafkaHeadersMut<'a>(pub &'a mut rdkafka::message::OwnedHeaders);
+
+#[cfg(feature = "kafka")]
+impl Injector for KafkaHeadersMut<'_> {
+    fn set(&mut self, key: &str, value: String) {
+        let headers = std::mem::take(self.0);
+        *self.0 = headers.insert(rdkafka::message::Header {
+            key,
+            value: Some(&value),
+        });
+    }
+}
+
+/// Headers of an AMQP message received with `lapin`, e.g.
+/// `AmqpHeaders(delivery.properties.headers().as_ref()?)`. String values are read whether sent as
+/// long or short strings.
+#[cfg(feature = "amqp")]
+#[derive(Debug)]
+pub struct AmqpHeaders<'a>(pub &'a lapin::types::FieldTable);
+
+#[cfg(feature = "amqp")]
+impl Extractor for AmqpHeaders<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        match self.0.inner().get(key)? {
+            lapin::types::AMQPValue::LongString(value) => {
+                std::str::from_utf8(value.as_bytes()).ok()
+            }
+            lapin::types::AMQPValue::ShortString(value) => Some(value.as_str()),
+            _ => None,
+        }
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.inner().keys().map(|key| key.as_str()).collect()
+    }
+}
+
+/// Headers of an AMQP message about to be published with `lapin`, written as long strings, e.g.
+/// `inject_context(&mut AmqpHeadersMut(&mut headers))` before
+/// `BasicProperties::default().with_headers(headers)`.
+#[cfg(feature = "amqp")]
+#[derive(Debug)]
+pub struct AmqpHeadersMut<'a>(pub &'a mut lapin::types::FieldTable);
+
+#[cfg(feature = "amqp")]
+impl Injector for AmqpHeadersMut<'_> {
+    fn set(&mut self, key: &str, value: String) {
+        self.0.insert(
+            key.into(),
+            lapin::types::AMQPValue::LongString(value.into()),
+        );
+    }
+}
@@ -0,0 +1,335 @@
+use axum::extract::MatchedPath;
+use axum::http::{Request, Response};
+use opentelemetry::metrics::{Counter, Histogram, UpDownCounter};
+use opentelemetry::KeyValue;
+#[cfg(all(feature = "metrics", feature = "grpc"))]
+use opentelemetry_otlp::Protocol;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Instant;
+use tower::{Layer, Service};
+#[cfg(feature = "metrics")]
+use {
+    crate::TelemetryConfig,
+    axum::http::header::CONTENT_TYPE,
+    axum::http::StatusCode,
+    axum::response::IntoResponse,
+    axum::routing::get,
+    axum::Router,
+    opentelemetry_sdk::metrics::periodic_reader_with_async_runtime::PeriodicReader,
+    opentelemetry_sdk::metrics::{
+        Aggregation, Instrument, InstrumentKind, SdkMeterProvider, Stream,
+    },
+    opentelemetry_sdk::runtime::Tokio,
+    prometheus::{Encoder, TextEncoder},
+    std::sync::{Mutex, OnceLock},
+};
+
+#[cfg(feature = "metrics")]
+// Kept so the pending metrics can be flushed on shutdown, as the global provider can't be shut down
+static METER_PROVIDER: Mutex<Option<SdkMeterProvider>> = Mutex::new(None);
+#[cfg(feature = "metrics")]
+static PROMETHEUS_REGISTRY: OnceLock<prometheus::Registry> = OnceLock::new();
+
+/// Installs a global meter provider exporting through the configured backend and, when enabled, to
+/// the registry served by [`prometheus_router`].
+#[cfg(feature = "metrics")]
+pub(crate) fn init_meter_provider(config: &TelemetryConfig) {
+    let mut provider = SdkMeterProvider::builder()
+        .with_resource(crate::resource::build(config))
+        .with_view(seconds_histogram);
+    let mut has_reader = false;
+
+    if !config.metrics {
+        // Only Prometheus was asked for
+    } else if let Some(target) = config.exporter.otlp_target(config, "metrics") {
+        let builder = opentelemetry_otlp::MetricExporter::builder();
+        let exporter = match target.protocol {
+            #[cfg(feature = "grpc")]
+            Protocol::Grpc => target.tonic(builder.with_tonic()).build(),
+            _ => target.http(builder.with_http()).build(),
+        };
+        provider = provider.with_reader(PeriodicReader::builder(exporter.unwrap(), Tokio).build());
+        has_reader = true;
+    } else if config.exporter.is_stdout() {
+        let exporter = opentelemetry_stdout::MetricExporter::default();
+        provider = provider.with_reader(PeriodicReader::builder(exporter, Tokio).build());
+        has_reader = true;
+    }
+
+    if config.prometheus {
+        let registry = prometheus::Registry::new();
+        let exporter = opentelemetry_prometheus::exporter()
+            .with_registry(registry.clone())
+            .build()
+            .unwrap();
+        provider = provider.with_reader(exporter);
+        has_reader = true;
+        let _ = PROMETHEUS_REGISTRY.set(registry);
+    }
+
+    if !has_reader {
+        return;
+    }
+
+    let provider = provider.build();
+    opentelemetry::global::set_meter_provider(provider.clone());
+    *METER_PROVIDER.lock().unwrap() = Some(provider);
+}
+
+/// Router serving the Prometheus exposition format at `/metrics`, for when
+/// [`TelemetryConfig::prometheus`] is enabled.
+///
+/// Merge it into the application router, or serve it on a separate listener to keep it private.
+#[cfg(feature = "metrics")]
+pub fn prometheus_router() -> Router {
+    Router::new().route("/metrics", get(prometheus_metrics))
+}
+
+#[cfg(feature = "metrics")]
+async fn prometheus_metrics() -> axum::response::Response {
+    let Some(registry) = PROMETHEUS_REGISTRY.get() else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+
+    let encoder = TextEncoder::new();
+    let mut body = Vec::new();
+    match encoder.encode(&registry.gather(), &mut body) {
+        Ok(()) => ([(CONTENT_TYPE, encoder.format_type().to_string())], body).into_response(),
+        Err(err) => (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response(),
+    }
+}
+
+/// Flushes and shuts down the meter provider installed by [`crate::Telemetry::init`], if any.
+#[cfg(feature = "metrics")]
+pub fn shutdown_meter_provider() {
+    if let Some(provider) = METER_PROVIDER.lock().unwrap().take() {
+        if let Err(err) = provider.shutdown() {
+            tracing::warn!(error = %err, "failed to shut down the meter provider");
+        }
+    }
+}
+
+#[cfg(feature = "metrics")]
+// The SDK's default buckets suit milliseconds, but the semantic conventions record durations in seconds
+const DURATION_BUCKETS: [f64; 14] = [
+    0.005, 0.01, 0.025, 0.05, 0.075, 0.1, 0.25, 0.5, 0.75, 1.0, 2.5, 5.0, 7.5, 10.0,
+];
+
+// Only for the histograms recording seconds, so applications' own histograms of sizes or counts
+// keep the SDK's buckets
+#[cfg(feature = "metrics")]
+fn seconds_histogram(instrument: &Instrument) -> Option<Stream> {
+    if instrument.kind() != InstrumentKind::Histogram || instrument.unit() != "s" {
+        return None;
+    }
+    Stream::builder()
+        .with_aggregation(Aggregation::ExplicitBucketHistogram {
+            boundaries: DURATION_BUCKETS.to_vec(),
+            record_min_max: true,
+        })
+        .build()
+        .ok()
+}
+
+struct Instruments {
+    duration: Histogram<f64>,
+    requests: Counter<u64>,
+    active: UpDownCounter<i64>,
+}
+
+/// Layer recording `http.server.request.duration`, `http.server.request.count` and
+/// `http.server.active_requests` for every request, attributed by method and route template.
+///
+/// Add it with `Router::layer` so the matched route is known.
+#[derive(Clone)]
+pub struct HttpMetricsLayer {
+    instruments: Arc<Instruments>,
+}
+
+impl HttpMetricsLayer {
+    pub fn new() -> Self {
+        let meter = opentelemetry::global::meter(env!("CARGO_PKG_NAME"));
+        let instruments = Instruments {
+            duration: meter
+                .f64_histogram("http.server.request.duration")
+                .with_unit("s")
+                .with_description("Duration of HTTP server requests")
+                .build(),
+            requests: meter
+                .u64_counter("http.server.request.count")
+                .with_description("Number of HTTP server requests")
+                .build(),
+            active: meter
+                .i64_up_down_counter("http.server.active_requests")
+                .with_description("Number of HTTP server requests in flight")
+                .build(),
+        };
+
+        Self {
+            instruments: Arc::new(instruments),
+        }
+    }
+}
+
+impl Default for HttpMetricsLayer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S> Layer<S> for HttpMetricsLayer {
+    type Service = HttpMetrics<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        HttpMetrics {
+            inner,
+            instruments: self.instruments.clone(),
+        }
+    }
+}
+
+/// Service created by [`HttpMetricsLayer`].
+#[derive(Clone)]
+pub struct HttpMetrics<S> {
+    inner: S,
+    instruments: Arc<Instruments>,
+}
+
+impl<S, B, ResBody> Service<Request<B>> for HttpMetrics<S>
+where
+    S: Service<Request<B>, Response = Response<ResBody>>,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<B>) -> Self::Future {
+        let mut attributes = vec![KeyValue::new(
+            "http.request.method",
+            request.method().to_string(),
+        )];
+        if let Some(route) = request.extensions().get::<MatchedPath>() {
+            attributes.push(KeyValue::new("http.route", route.as_str().to_string()));
+        }
+
+        let active = ActiveRequest::start(self.instruments.clone(), attributes.clone());
+        let start = Instant::now();
+        let future = self.inner.call(request);
+
+        Box::pin(async move {
+            let result = future.await;
+
+            if let Ok(response) = &result {
+                attributes.push(KeyValue::new(
+                    "http.response.status_code",
+                    i64::from(response.status().as_u16()),
+                ));
+            }
+            let instruments = &active.instruments;
+            instruments
+                .duration
+                .record(start.elapsed().as_secs_f64(), &attributes);
+            instruments.requests.add(1, &attributes);
+
+            result
+        })
+    }
+}
+
+// Decrements the in-flight count on drop, so cancelled requests are not counted as active forever
+struct ActiveRequest {
+    instruments: Arc<Instruments>,
+    attributes: Vec<KeyValue>,
+}
+
+impl ActiveRequest {
+    fn start(instruments: Arc<Instruments>, attributes: Vec<KeyValue>) -> Self {
+        instruments.active.add(1, &attributes);
+        Self {
+            instruments,
+            attributes,
+        }
+    }
+}
+
+impl Drop for ActiveRequest {
+    fn drop(&mut self) {
+        self.instruments.active.add(-1, &self.attributes);
+    }
+}
+
+#[cfg(all(test, feature = "metrics"))]
+mod tests {
+    use super::*;
+    use opentelemetry::metrics::MeterProvider as _;
+    use opentelemetry_sdk::error::OTelSdkResult;
+    use opentelemetry_sdk::metrics::data::{AggregatedMetrics, MetricData, ResourceMetrics};
+    use opentelemetry_sdk::metrics::reader::MetricReader;
+    use opentelemetry_sdk::metrics::{ManualReader, Pipeline, Temporality};
+    use std::sync::Weak;
+    use std::time::Duration;
+
+    // The provider takes ownership of its readers, so the test collects through a shared one
+    #[derive(Clone, Debug)]
+    struct SharedReader(Arc<ManualReader>);
+
+    impl MetricReader for SharedReader {
+        fn register_pipeline(&self, pipeline: Weak<Pipeline>) {
+            self.0.register_pipeline(pipeline);
+        }
+
+        fn collect(&self, rm: &mut ResourceMetrics) -> OTelSdkResult {
+            self.0.collect(rm)
+        }
+
+        fn force_flush(&self) -> OTelSdkResult {
+            self.0.force_flush()
+        }
+
+        fn shutdown_with_timeout(&self, timeout: Duration) -> OTelSdkResult {
+            self.0.shutdown_with_timeout(timeout)
+        }
+
+        fn temporality(&self, kind: InstrumentKind) -> Temporality {
+            self.0.temporality(kind)
+        }
+    }
+
+    #[test]
+    fn buckets_only_histograms_of_seconds_for_durations() {
+        let reader = SharedReader(Arc::new(ManualReader::builder().build()));
+        let provider = SdkMeterProvider::builder()
+            .with_reader(reader.clone())
+            .with_view(seconds_histogram)
+            .build();
+        let meter = provider.meter("test");
+        let duration = meter.f64_histogram("duration").with_unit("s").build();
+        let size = meter.f64_histogram("size").with_unit("By").build();
+        duration.record(0.2, &[]);
+        size.record(2048.0, &[]);
+
+        let mut metrics = ResourceMetrics::default();
+        reader.collect(&mut metrics).unwrap();
+        let bounds = |name: &str| -> Vec<f64> {
+            let metric = metrics
+                .scope_metrics()
+                .flat_map(|scope| scope.metrics())
+                .find(|metric| metric.name() == name)
+                .unwrap();
+            let AggregatedMetrics::F64(MetricData::Histogram(histogram)) = metric.data() else {
+                panic!("{name} isn't a histogram");
+            };
+            histogram.data_points().next().unwrap().bounds().collect()
+        };
+        assert_eq!(bounds("duration"), DURATION_BUCKETS);
+        assert_ne!(bounds("size"), DURATION_BUCKETS);
+    }
+}
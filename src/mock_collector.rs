@@ -0,0 +1,271 @@
+use axum::body::Bytes;
+use axum::extract::{DefaultBodyLimit, State};
+use axum::http::header::{CONTENT_ENCODING, CONTENT_TYPE};
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::post;
+use axum::Router;
+use opentelemetry_proto::tonic::collector::trace::v1::ExportTraceServiceRequest;
+use opentelemetry_proto::tonic::trace::v1::Span;
+use prost::Message;
+use std::io::{self, Read};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::net::TcpListener;
+use tokio::task::JoinHandle;
+
+/// How an export reached the [`MockCollector`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Transport {
+    Http,
+    Grpc,
+}
+
+/// An export request the [`MockCollector`] received, decompressed and decoded.
+#[derive(Clone, Debug)]
+pub struct ReceivedExport {
+    pub transport: Transport,
+    /// The HTTP headers, or the gRPC metadata, e.g. to check the API key was sent.
+    pub headers: HeaderMap,
+    /// The `content-encoding` of OTLP/HTTP exports, or the `grpc-encoding` of OTLP/gRPC ones.
+    pub compression: Option<String>,
+    pub request: ExportTraceServiceRequest,
+}
+
+impl ReceivedExport {
+    pub fn spans(&self) -> impl Iterator<Item = &Span> {
+        self.request
+            .resource_spans
+            .iter()
+            .flat_map(|resource| &resource.scope_spans)
+            .flat_map(|scope| &scope.spans)
+    }
+}
+
+#[derive(Debug)]
+struct Received {
+    exports: Vec<ReceivedExport>,
+    status: StatusCode,
+}
+
+type Shared = Arc<Mutex<Received>>;
+
+/// In-process OTLP receiver for end-to-end tests of the export path, e.g. that the configured
+/// headers and compression make it to the wire:
+///
+/// ```ignore
+/// let collector = MockCollector::start().await?;
+/// let exporter = ExporterBackend::OtlpHttp { endpoint: collector.http_endpoint(), headers };
+/// // Send requests, then flush
+/// let spans = collector.wait_for_spans(1, Duration::from_secs(5)).await;
+/// ```
+///
+/// It serves OTLP/HTTP in protobuf or JSON on `/v1/traces` and, with the `grpc` feature, OTLP/gRPC,
+/// both on the same loopback port, and accepts gzip and zstd compressed exports. Every export is
+/// kept, whatever it was answered, until the collector is dropped.
+#[derive(Debug)]
+pub struct MockCollector {
+    address: SocketAddr,
+    received: Shared,
+    server: JoinHandle<io::Result<()>>,
+}
+
+impl MockCollector {
+    /// Starts the collector on a free port of `127.0.0.1`.
+    pub async fn start() -> io::Result<Self> {
+        let received = Arc::new(Mutex::new(Received {
+            exports: Vec::new(),
+            status: StatusCode::OK,
+        }));
+        let router = Router::new()
+            .route("/v1/traces", post(http_export))
+            .layer(DefaultBodyLimit::disable())
+            .with_state(received.clone());
+        #[cfg(feature = "grpc")]
+        let router = router.route_service(
+            "/opentelemetry.proto.collector.trace.v1.TraceService/Export",
+            grpc::server(received.clone()),
+        );
+
+        let listener = TcpListener::bind(SocketAddr::from(([127, 0, 0, 1], 0))).await?;
+        let address = listener.local_addr()?;
+        // Served with HTTP/2 too, for the gRPC exports
+        let server = tokio::spawn(async move { axum::serve(listener, router).await });
+        Ok(Self {
+            address,
+            received,
+            server,
+        })
+    }
+
+    /// The OTLP/HTTP traces endpoint, e.g. `http://127.0.0.1:38211/v1/traces`.
+    pub fn http_endpoint(&self) -> String {
+        format!("http://{}/v1/traces", self.address)
+    }
+
+    /// The OTLP/gRPC endpoint, e.g. `http://127.0.0.1:38211`.
+    #[cfg(feature = "grpc")]
+    pub fn grpc_endpoint(&self) -> String {
+        format!("http://{}", self.address)
+    }
+
+    /// Answers the following exports with `status` instead of 200 OK, e.g. 503 to test retries. For
+    /// gRPC, 429 and the 502 to 504 statuses become `UNAVAILABLE`, and the other errors `INTERNAL`.
+    pub fn respond_with(&self, status: StatusCode) {
+        self.lock().status = status;
+    }
+
+    /// The exports received so far, oldest first.
+    pub fn exports(&self) -> Vec<ReceivedExport> {
+        self.lock().exports.clone()
+    }
+
+    /// The spans of the exports received so far.
+    pub fn spans(&self) -> Vec<Span> {
+        self.lock()
+            .exports
+            .iter()
+            .flat_map(|export| export.spans().cloned())
+            .collect()
+    }
+
+    /// Waits for at least `count` spans to be received, and returns them, panicking if they aren't
+    /// within `timeout`.
+    pub async fn wait_for_spans(&self, count: usize, timeout: Duration) -> Vec<Span> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let spans = self.spans();
+            if spans.len() >= count {
+                return spans;
+            }
+            assert!(
+                Instant::now() < deadline,
+                "expected {count} spans within {timeout:?}, received {}",
+                spans.len()
+            );
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Received> {
+        self.received.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+impl Drop for MockCollector {
+    fn drop(&mut self) {
+        self.server.abort();
+    }
+}
+
+fn decompress(encoding: Option<&str>, body: &[u8]) -> io::Result<Vec<u8>> {
+    match encoding {
+        None | Some("identity") => Ok(body.to_vec()),
+        Some("gzip") => {
+            let mut decompressed = Vec::new();
+            flate2::read::GzDecoder::new(body).read_to_end(&mut decompressed)?;
+            Ok(decompressed)
+        }
+        Some("zstd") => zstd::decode_all(body),
+        Some(encoding) => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("unsupported encoding {encoding}"),
+        )),
+    }
+}
+
+async fn http_export(State(received): State<Shared>, headers: HeaderMap, body: Bytes) -> Response {
+    let compression = headers
+        .get(CONTENT_ENCODING)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+    let body = match decompress(compression.as_deref(), &body) {
+        Ok(body) => body,
+        Err(err) => return (StatusCode::BAD_REQUEST, err.to_string()).into_response(),
+    };
+    let json = headers
+        .get(CONTENT_TYPE)
+        .is_some_and(|value| value.as_bytes().starts_with(b"application/json"));
+    let request = match json {
+        true => serde_json::from_slice(&body).map_err(|err| err.to_string()),
+        false => ExportTraceServiceRequest::decode(body.as_slice()).map_err(|err| err.to_string()),
+    };
+    let request = match request {
+        Ok(request) => request,
+        Err(err) => return (StatusCode::BAD_REQUEST, err).into_response(),
+    };
+
+    let mut received = received.lock().unwrap_or_else(|err| err.into_inner());
+    received.exports.push(ReceivedExport {
+        transport: Transport::Http,
+        headers,
+        compression,
+        request,
+    });
+    if !received.status.is_success() {
+        return received.status.into_response();
+    }
+    // An empty `ExportTraceServiceResponse`, which encodes to no bytes in either format
+    let (content_type, body) = match json {
+        true => ("application/json", "{}"),
+        false => ("application/x-protobuf", ""),
+    };
+    (received.status, [(CONTENT_TYPE, content_type)], body).into_response()
+}
+
+#[cfg(feature = "grpc")]
+mod grpc {
+    use super::{ReceivedExport, Shared, Transport};
+    use axum::http::StatusCode;
+    use opentelemetry_proto::tonic::collector::trace::v1::trace_service_server::{
+        TraceService, TraceServiceServer,
+    };
+    use opentelemetry_proto::tonic::collector::trace::v1::{
+        ExportTraceServiceRequest, ExportTraceServiceResponse,
+    };
+    use tonic::codec::CompressionEncoding;
+    use tonic::{Request, Response, Status};
+
+    pub(super) fn server(received: Shared) -> TraceServiceServer<Collector> {
+        TraceServiceServer::new(Collector { received })
+            .accept_compressed(CompressionEncoding::Gzip)
+            .accept_compressed(CompressionEncoding::Zstd)
+    }
+
+    pub(super) struct Collector {
+        received: Shared,
+    }
+
+    #[async_trait::async_trait]
+    impl TraceService for Collector {
+        async fn export(
+            &self,
+            request: Request<ExportTraceServiceRequest>,
+        ) -> Result<Response<ExportTraceServiceResponse>, Status> {
+            let headers = request.metadata().clone().into_headers();
+            let compression = headers
+                .get("grpc-encoding")
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string);
+            let mut received = self.received.lock().unwrap_or_else(|err| err.into_inner());
+            received.exports.push(ReceivedExport {
+                transport: Transport::Grpc,
+                headers,
+                compression,
+                request: request.into_inner(),
+            });
+
+            match received.status {
+                status if status.is_success() => Ok(Response::new(ExportTraceServiceResponse {
+                    partial_success: None,
+                })),
+                StatusCode::TOO_MANY_REQUESTS
+                | StatusCode::BAD_GATEWAY
+                | StatusCode::SERVICE_UNAVAILABLE
+                | StatusCode::GATEWAY_TIMEOUT => Err(Status::unavailable("unavailable")),
+                status => Err(Status::internal(status.to_string())),
+            }
+        }
+    }
+}
@@ -0,0 +1,30 @@
+use crate::{admin_router, health_router, Readiness};
+use axum::Router;
+use std::net::SocketAddr;
+use tokio::net::TcpListener;
+use tokio::task::JoinHandle;
+
+/// The operational routes: the [`health_router`] probes, the [`admin_router`] endpoints, and, with
+/// the `metrics` feature, the Prometheus `/metrics` of [`crate::prometheus_router`].
+pub fn ops_router(readiness: Readiness) -> Router {
+    let router = health_router(readiness).merge(admin_router());
+    #[cfg(feature = "metrics")]
+    let router = router.merge(crate::prometheus_router());
+    router
+}
+
+/// Serves the [`ops_router`] on a listener of its own, e.g. `127.0.0.1:9090`, so the operational
+/// routes aren't reachable on the public port, and returns the spawned server's task.
+///
+/// Its requests are left untraced, as scrapes and probes would otherwise outnumber the real ones.
+/// Binding fails straight away if the address is taken.
+pub async fn serve_ops(
+    address: SocketAddr,
+    readiness: Readiness,
+) -> std::io::Result<JoinHandle<std::io::Result<()>>> {
+    let listener = TcpListener::bind(address).await?;
+    tracing::info!(%address, "serving the operational routes");
+    Ok(tokio::spawn(async move {
+        axum::serve(listener, ops_router(readiness)).await
+    }))
+}
@@ -0,0 +1,68 @@
+use axum::body::Body;
+use axum::http::{Response, StatusCode};
+use axum::response::IntoResponse;
+use std::any::Any;
+use std::backtrace::Backtrace;
+use std::cell::RefCell;
+use std::sync::Once;
+use tower_http::catch_panic::{CatchPanicLayer, ResponseForPanic};
+
+thread_local! {
+    // Set by the panic hook, as the backtrace is gone by the time the panic is caught
+    static PANIC_BACKTRACE: RefCell<Option<Backtrace>> = const { RefCell::new(None) };
+}
+
+static INSTALL_HOOK: Once = Once::new();
+
+/// [`CatchPanicLayer`] turning handler panics into 500 responses, and recording each panic on the
+/// request span as an `exception` event with `exception.type`, `exception.message` and
+/// `exception.stacktrace`, so the span still ends and is exported.
+pub fn catch_panic_layer() -> CatchPanicLayer<RecordPanic> {
+    // Chains onto the existing hook so panics are still printed
+    INSTALL_HOOK.call_once(|| {
+        let previous = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            PANIC_BACKTRACE.with(|backtrace| {
+                *backtrace.borrow_mut() = Some(Backtrace::force_capture());
+            });
+            previous(info);
+        }));
+    });
+
+    CatchPanicLayer::custom(RecordPanic)
+}
+
+/// [`ResponseForPanic`] used by [`catch_panic_layer`].
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RecordPanic;
+
+impl ResponseForPanic for RecordPanic {
+    type ResponseBody = Body;
+
+    fn response_for_panic(&mut self, err: Box<dyn Any + Send + 'static>) -> Response<Body> {
+        let message = if let Some(message) = err.downcast_ref::<&str>() {
+            message.to_string()
+        } else if let Some(message) = err.downcast_ref::<String>() {
+            message.clone()
+        } else {
+            "panic with a non-string payload".to_string()
+        };
+        let backtrace = PANIC_BACKTRACE
+            .with(|backtrace| backtrace.borrow_mut().take())
+            .map(|backtrace| backtrace.to_string())
+            .unwrap_or_default();
+
+        tracing::Span::current().record("otel.status_code", "ERROR");
+        // Braced so `type`, a keyword, can be given as a quoted field name
+        tracing::error!(
+            {
+                "exception.type" = "panic",
+                exception.message = message,
+                exception.stacktrace = backtrace,
+            },
+            "exception"
+        );
+
+        StatusCode::INTERNAL_SERVER_ERROR.into_response()
+    }
+}
@@ -0,0 +1,88 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// The kernel reports CPU time in clock ticks of this many milliseconds on every architecture Linux
+// supports
+const CLOCK_TICK_MILLIS: u64 = 10;
+
+/// Registers `process.cpu.utilization`, `process.memory.usage` and
+/// `process.open_file_descriptor.count`, observed each time the metrics are exported, so dashboards
+/// show the health of the process without a separate node exporter.
+///
+/// The CPU utilization is the share of the available CPUs the process used since the previous
+/// export, between `0.0` and `1.0`, and the memory usage is the resident set size in bytes. They
+/// are read from `/proc`, so nothing is recorded on other platforms than Linux.
+///
+/// Call this after [`crate::Telemetry::init`], as the metric instruments are created from the global
+/// meter provider, or have it call this by setting [`crate::Telemetry::process_metrics`].
+pub fn register_process_metrics() {
+    let meter = opentelemetry::global::meter(env!("CARGO_PKG_NAME"));
+    let last_cpu = Mutex::new(cpu_time().map(|time| (Instant::now(), time)));
+    let cpus = std::thread::available_parallelism().map_or(1, |cpus| cpus.get()) as f64;
+
+    meter
+        .f64_observable_gauge("process.cpu.utilization")
+        .with_unit("1")
+        .with_description("Share of the available CPUs used by the process")
+        .with_callback(move |gauge| {
+            let Some(time) = cpu_time() else {
+                return;
+            };
+            let now = Instant::now();
+            let mut last = last_cpu.lock().unwrap();
+            if let Some((then, last_time)) = last.replace((now, time)) {
+                let elapsed = now.duration_since(then).as_secs_f64();
+                if elapsed > 0.0 {
+                    let used = time.saturating_sub(last_time).as_secs_f64();
+                    gauge.observe(used / elapsed / cpus, &[]);
+                }
+            }
+        })
+        .build();
+    meter
+        .u64_observable_gauge("process.memory.usage")
+        .with_unit("By")
+        .with_description("Resident memory of the process")
+        .with_callback(|gauge| {
+            if let Some(bytes) = resident_memory() {
+                gauge.observe(bytes, &[]);
+            }
+        })
+        .build();
+    meter
+        .u64_observable_gauge("process.open_file_descriptor.count")
+        .with_unit("{count}")
+        .with_description("Number of file descriptors the process has open")
+        .with_callback(|gauge| {
+            if let Ok(entries) = std::fs::read_dir("/proc/self/fd") {
+                gauge.observe(entries.count() as u64, &[]);
+            }
+        })
+        .build();
+}
+
+// User and system time, the 14th and 15th fields of `/proc/self/stat`. The command name in the
+// second field may contain spaces, so fields are counted from the parenthesis closing it.
+fn cpu_time() -> Option<Duration> {
+    let stat = std::fs::read_to_string("/proc/self/stat").ok()?;
+    let mut fields = stat
+        .get(stat.rfind(')')? + 1..)?
+        .split_whitespace()
+        .skip(11);
+    let user: u64 = fields.next()?.parse().ok()?;
+    let system: u64 = fields.next()?.parse().ok()?;
+    Some(Duration::from_millis((user + system) * CLOCK_TICK_MILLIS))
+}
+
+fn resident_memory() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let kilobytes = status
+        .lines()
+        .find_map(|line| line.strip_prefix("VmRSS:"))?
+        .trim()
+        .trim_end_matches("kB")
+        .trim()
+        .parse::<u64>()
+        .ok()?;
+    Some(kilobytes * 1024)
+}
@@ -0,0 +1,145 @@
+use axum::http::{HeaderMap, Request};
+use opentelemetry::propagation::{TextMapCompositePropagator, TextMapPropagator};
+use opentelemetry::Context;
+use opentelemetry_http::{HeaderExtractor, HeaderInjector};
+use opentelemetry_sdk::propagation::{BaggagePropagator, TraceContextPropagator};
+#[cfg(feature = "zipkin")]
+#[allow(deprecated)]
+use opentelemetry_zipkin::B3Encoding;
+use std::task::Poll;
+use tower::{Layer, Service};
+use tower_http::trace::{DefaultMakeSpan, MakeSpan};
+use tracing::Span;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+/// Header format used to carry trace context between services.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PropagationFormat {
+    /// W3C Trace Context `traceparent`/`tracestate`.
+    W3C,
+    /// Zipkin's single `b3` header.
+    #[cfg(feature = "zipkin")]
+    B3Single,
+    /// Zipkin's `X-B3-*` headers.
+    #[cfg(feature = "zipkin")]
+    B3Multi,
+    /// Jaeger's `uber-trace-id` header.
+    #[cfg(feature = "jaeger")]
+    Jaeger,
+    /// W3C Baggage `baggage` header, carrying application entries such as a tenant ID alongside the
+    /// trace context.
+    Baggage,
+}
+
+impl PropagationFormat {
+    /// Parses a name as used by `OTEL_PROPAGATORS`.
+    pub fn from_otel_name(name: &str) -> Option<Self> {
+        match name {
+            "tracecontext" => Some(PropagationFormat::W3C),
+            #[cfg(feature = "zipkin")]
+            "b3" => Some(PropagationFormat::B3Single),
+            #[cfg(feature = "zipkin")]
+            "b3multi" => Some(PropagationFormat::B3Multi),
+            #[cfg(feature = "jaeger")]
+            "jaeger" => Some(PropagationFormat::Jaeger),
+            "baggage" => Some(PropagationFormat::Baggage),
+            _ => None,
+        }
+    }
+
+    // The B3 and Jaeger formats are deprecated upstream, but still sent by services that haven't
+    // moved to W3C Trace Context
+    #[allow(deprecated)]
+    fn propagator(self) -> Box<dyn TextMapPropagator + Send + Sync> {
+        match self {
+            PropagationFormat::W3C => Box::new(TraceContextPropagator::new()),
+            #[cfg(feature = "zipkin")]
+            PropagationFormat::B3Single => Box::new(
+                opentelemetry_zipkin::Propagator::with_encoding(B3Encoding::SingleHeader),
+            ),
+            #[cfg(feature = "zipkin")]
+            PropagationFormat::B3Multi => Box::new(
+                opentelemetry_zipkin::Propagator::with_encoding(B3Encoding::MultipleHeader),
+            ),
+            #[cfg(feature = "jaeger")]
+            PropagationFormat::Jaeger => {
+                Box::new(opentelemetry_jaeger_propagator::Propagator::new())
+            }
+            PropagationFormat::Baggage => Box::new(BaggagePropagator::new()),
+        }
+    }
+}
+
+/// Registers a global propagator that injects every format in `formats` and extracts whichever arrives.
+pub fn install_propagators(formats: &[PropagationFormat]) {
+    let propagators = formats.iter().map(|format| format.propagator()).collect();
+    opentelemetry::global::set_text_map_propagator(TextMapCompositePropagator::new(propagators));
+}
+
+/// Reads the upstream trace context from request headers using the globally registered propagator.
+pub fn extract_context(headers: &HeaderMap) -> Context {
+    opentelemetry::global::get_text_map_propagator(|propagator| {
+        propagator.extract(&HeaderExtractor(headers))
+    })
+}
+
+/// [`MakeSpan`] that parents the span built by `M` on the trace context propagated by the caller, so a
+/// request arriving from another instrumented service continues that service's trace.
+#[derive(Clone, Debug, Default)]
+pub struct PropagatingMakeSpan<M = DefaultMakeSpan> {
+    inner: M,
+}
+
+impl<M> PropagatingMakeSpan<M> {
+    pub fn new(inner: M) -> Self {
+        Self { inner }
+    }
+}
+
+impl<B, M: MakeSpan<B>> MakeSpan<B> for PropagatingMakeSpan<M> {
+    fn make_span(&mut self, request: &Request<B>) -> Span {
+        let span = self.inner.make_span(request);
+        // Only fails once the span has started, which a span just made hasn't
+        let _ = span.set_parent(extract_context(request.headers()));
+        span
+    }
+}
+
+/// Layer for outgoing requests writing the trace context of the current span into their headers
+/// with the globally registered propagator, so the service called continues the trace.
+///
+/// It must run inside the layer creating the client span, so that span is current.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PropagateContextLayer;
+
+impl<S> Layer<S> for PropagateContextLayer {
+    type Service = PropagateContext<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        PropagateContext { inner }
+    }
+}
+
+/// Service created by [`PropagateContextLayer`].
+#[derive(Clone, Debug)]
+pub struct PropagateContext<S> {
+    inner: S,
+}
+
+impl<S: Service<Request<B>>, B> Service<Request<B>> for PropagateContext<S> {
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn poll_ready(&mut self, cx: &mut std::task::Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut request: Request<B>) -> Self::Future {
+        let context = crate::span::otel_context(&Span::current());
+        opentelemetry::global::get_text_map_propagator(|propagator| {
+            propagator.inject_context(&context, &mut HeaderInjector(request.headers_mut()))
+        });
+        self.inner.call(request)
+    }
+}
@@ -0,0 +1,177 @@
+use crate::SpanExt;
+use axum::body::Body;
+use axum::extract::ConnectInfo;
+use axum::http::header::{self, HeaderMap, HeaderName, HeaderValue};
+use axum::http::uri::{PathAndQuery, Uri};
+use axum::http::{Request, Response, StatusCode};
+use axum::response::IntoResponse;
+use hyper_util::client::legacy::connect::HttpConnector;
+use hyper_util::client::legacy::Client;
+use hyper_util::rt::TokioExecutor;
+use opentelemetry_http::HeaderInjector;
+use std::convert::Infallible;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tower::Service;
+use tracing::field::Empty;
+use tracing::Instrument;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+// Upstreams sending more `Server-Timing` metrics than this only get the first ones recorded
+const MAX_SERVER_TIMINGS: usize = 16;
+
+const X_FORWARDED_FOR: HeaderName = HeaderName::from_static("x-forwarded-for");
+
+// Headers about the connection rather than the request, which a proxy mustn't forward
+const HOP_BY_HOP: [HeaderName; 7] = [
+    header::CONNECTION,
+    HeaderName::from_static("keep-alive"),
+    header::PROXY_AUTHENTICATE,
+    header::PROXY_AUTHORIZATION,
+    header::TE,
+    header::TRAILER,
+    header::UPGRADE,
+];
+
+/// Service forwarding every request to a plain HTTP upstream, e.g. as the fallback of an API
+/// gateway: `telemetry.instrument(Router::new().fallback_service(ReverseProxy::new(upstream)))`.
+///
+/// Each forwarded request gets a client span, a child of the request span, whose context is
+/// injected into the upstream request so the upstream continues the trace. The metrics of the
+/// upstream's `Server-Timing` header are recorded on it as `upstream.timing.<name>`, in
+/// milliseconds, to tell the upstream's own time from the network's. Requests are forwarded with the
+/// peer appended to `x-forwarded-for`, and upstreams that can't be reached give 502 Bad Gateway.
+#[derive(Clone, Debug)]
+pub struct ReverseProxy {
+    client: Client<HttpConnector, Body>,
+    upstream: Uri,
+}
+
+impl ReverseProxy {
+    /// Proxies to `upstream`, e.g. `http://orders:8080/api`, whose path prefixes the request's.
+    pub fn new(upstream: Uri) -> Self {
+        assert!(
+            upstream.scheme_str() == Some("http") && upstream.authority().is_some(),
+            "the upstream must be an absolute http:// URI"
+        );
+        Self {
+            client: Client::builder(TokioExecutor::new()).build_http(),
+            upstream,
+        }
+    }
+
+    fn upstream_uri(&self, uri: &Uri) -> Uri {
+        let prefix = self.upstream.path().trim_end_matches('/');
+        let path = uri.path_and_query().map_or("/", PathAndQuery::as_str);
+        let mut parts = self.upstream.clone().into_parts();
+        parts.path_and_query = format!("{prefix}{path}").parse().ok();
+        Uri::from_parts(parts).expect("the upstream URI with the request path is valid")
+    }
+
+    pub async fn forward(&self, mut request: Request<Body>) -> Response<Body> {
+        let uri = self.upstream_uri(request.uri());
+        let span = tracing::info_span!(
+            "HTTP client request",
+            otel.name = %request.method(),
+            otel.kind = "client",
+            otel.status_code = Empty,
+            http.method = %request.method(),
+            http.url = %uri,
+            net.peer.name = uri.host(),
+            http.status_code = Empty,
+        );
+
+        let peer = request
+            .extensions()
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| addr.ip());
+        *request.uri_mut() = uri;
+        let headers = request.headers_mut();
+        remove_hop_by_hop(headers);
+        // Set from the URI by the client instead
+        headers.remove(header::HOST);
+        if let Some(peer) = peer {
+            append_forwarded_for(headers, &peer.to_string());
+        }
+        let context = crate::span::otel_context(&span);
+        opentelemetry::global::get_text_map_propagator(|propagator| {
+            propagator.inject_context(&context, &mut HeaderInjector(headers))
+        });
+
+        let mut response = match self.client.request(request).instrument(span.clone()).await {
+            Ok(response) => response.map(Body::new),
+            Err(err) => {
+                span.record_error(&err);
+                return StatusCode::BAD_GATEWAY.into_response();
+            }
+        };
+
+        let status = response.status();
+        span.record("http.status_code", i64::from(status.as_u16()));
+        if status.is_client_error() || status.is_server_error() {
+            span.record("otel.status_code", "ERROR");
+        }
+        for (name, duration) in server_timings(response.headers()) {
+            span.set_attribute(format!("upstream.timing.{name}"), duration);
+        }
+        remove_hop_by_hop(response.headers_mut());
+        response
+    }
+}
+
+fn remove_hop_by_hop(headers: &mut HeaderMap) {
+    for name in &HOP_BY_HOP {
+        headers.remove(name);
+    }
+}
+
+fn append_forwarded_for(headers: &mut HeaderMap, peer: &str) {
+    let value = match headers
+        .get(X_FORWARDED_FOR)
+        .and_then(|value| value.to_str().ok())
+    {
+        Some(forwarded) => format!("{forwarded}, {peer}"),
+        None => peer.to_string(),
+    };
+    if let Ok(value) = HeaderValue::from_str(&value) {
+        headers.insert(X_FORWARDED_FOR, value);
+    }
+}
+
+// `db;dur=53.2;desc="Query", app;dur=47.2` gives `db` and `app` with their durations
+fn server_timings(headers: &HeaderMap) -> Vec<(String, f64)> {
+    headers
+        .get_all("server-timing")
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter_map(|metric| {
+            let mut params = metric.split(';').map(str::trim);
+            let name = params.next()?;
+            let valid = !name.is_empty()
+                && name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-' || c == '.');
+            let duration = params.find_map(|param| param.strip_prefix("dur="))?;
+            Some((name.to_string(), duration.parse().ok()?)).filter(|_| valid)
+        })
+        .take(MAX_SERVER_TIMINGS)
+        .collect()
+}
+
+impl Service<Request<Body>> for ReverseProxy {
+    type Response = Response<Body>;
+    type Error = Infallible;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: Request<Body>) -> Self::Future {
+        let proxy = self.clone();
+        Box::pin(async move { Ok(proxy.forward(request).await) })
+    }
+}
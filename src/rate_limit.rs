@@ -0,0 +1,374 @@
+use crate::auth::pseudonym;
+use crate::client_ip::ClientIp;
+use axum::http::{HeaderName, HeaderValue, Request, Response, StatusCode};
+use std::collections::{BTreeMap, HashMap};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::Instant;
+use tower::{Layer, Service};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+// Beyond this many clients, the least recently seen ones are forgotten
+const MAX_TRACKED_CLIENTS: usize = 10_000;
+
+/// What requests are counted against, each value getting its own bucket.
+#[derive(Clone, Debug, PartialEq)]
+pub enum RateLimitKey {
+    /// The [`ClientIp`] resolved by [`crate::ClientIpLayer`], only known when the server is started
+    /// with `into_make_service_with_connect_info::<SocketAddr>()`.
+    ClientIp,
+    /// The value of a header carrying an API key, e.g. `x-api-key`; requests without it are keyed by
+    /// client IP. The keys aren't checked, so the first request with a key not being tracked is
+    /// also counted against its client IP, for a client making up keys not to get a fresh bucket
+    /// with each.
+    Header(HeaderName),
+}
+
+/// Token bucket allowing each client `burst` requests at once, refilled at `per_second`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RateLimit {
+    pub burst: u32,
+    pub per_second: f64,
+    pub key: RateLimitKey,
+    /// Key of the HMAC-SHA256 identifying API keys as `rate_limit.client`, so the keys can't be
+    /// recovered from the spans. Without one, a random one is drawn at startup, so a key's
+    /// `rate_limit.client` changes when the service restarts.
+    pub salt: Option<String>,
+}
+
+impl RateLimit {
+    pub fn per_client_ip(burst: u32, per_second: f64) -> Self {
+        Self {
+            burst,
+            per_second,
+            key: RateLimitKey::ClientIp,
+            salt: None,
+        }
+    }
+
+    /// Limit keyed by the API key in the header `name`, identified on the spans by its HMAC keyed
+    /// with `salt`.
+    pub fn per_api_key(
+        burst: u32,
+        per_second: f64,
+        name: HeaderName,
+        salt: impl Into<String>,
+    ) -> Self {
+        Self {
+            burst,
+            per_second,
+            key: RateLimitKey::Header(name),
+            salt: Some(salt.into()),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+    // Position in `Buckets::recency`
+    used: u64,
+}
+
+// The buckets of the clients seen most recently, at most `MAX_TRACKED_CLIENTS` of them
+#[derive(Debug, Default)]
+struct Buckets {
+    buckets: HashMap<String, Bucket>,
+    recency: BTreeMap<u64, String>,
+    sequence: u64,
+}
+
+impl Buckets {
+    fn take(&mut self, limit: &RateLimit, client: &str, now: Instant) -> Quota {
+        let burst = f64::from(limit.burst);
+        let used = self.sequence;
+        self.sequence += 1;
+        match self.buckets.get_mut(client) {
+            Some(bucket) => {
+                self.recency.remove(&bucket.used);
+                bucket.used = used;
+            }
+            None => {
+                if self.buckets.len() >= MAX_TRACKED_CLIENTS {
+                    if let Some((_, oldest)) = self.recency.pop_first() {
+                        self.buckets.remove(&oldest);
+                    }
+                }
+                self.buckets.insert(
+                    client.to_string(),
+                    Bucket {
+                        tokens: burst,
+                        updated: now,
+                        used,
+                    },
+                );
+            }
+        }
+        self.recency.insert(used, client.to_string());
+
+        let bucket = self
+            .buckets
+            .get_mut(client)
+            .expect("the bucket was just inserted");
+        let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * limit.per_second).min(burst);
+        bucket.updated = now;
+        let allowed = bucket.tokens >= 1.0;
+        if allowed {
+            bucket.tokens -= 1.0;
+        }
+
+        Quota {
+            allowed,
+            remaining: bucket.tokens as u64,
+            reset: ((burst - bucket.tokens) / limit.per_second).ceil() as u64,
+            retry_after: ((1.0 - bucket.tokens).max(0.0) / limit.per_second).ceil() as u64,
+        }
+    }
+}
+
+// What a request left of its client's bucket
+struct Quota {
+    allowed: bool,
+    remaining: u64,
+    // Seconds until the bucket is full again, and until the next request is allowed
+    reset: u64,
+    retry_after: u64,
+}
+
+// Who a request is counted against: its API key or client IP, and for API keys the client IP too
+struct Client {
+    id: String,
+    ip: Option<String>,
+}
+
+/// Layer rate limiting requests with a [`RateLimit`] token bucket per client, answering the ones
+/// over it with 429 Too Many Requests and a `retry-after` header; without a limit every request
+/// runs.
+///
+/// Every limited response carries the `ratelimit-limit`, `ratelimit-remaining` and
+/// `ratelimit-reset` headers, and the current span gets `rate_limit.exceeded`,
+/// `rate_limit.remaining` and `rate_limit.client` attributes, the latter being the client IP or a
+/// keyed hash of its API key. Requests whose client address isn't known share one bucket.
+#[derive(Clone, Debug, Default)]
+pub struct RateLimitLayer {
+    limit: Option<Arc<RateLimit>>,
+    salt: Arc<str>,
+    buckets: Arc<Mutex<Buckets>>,
+}
+
+impl RateLimitLayer {
+    pub fn new(limit: Option<RateLimit>) -> Self {
+        if let Some(limit) = &limit {
+            assert!(
+                limit.burst > 0 && limit.per_second > 0.0,
+                "the rate limit burst and refill rate must be positive"
+            );
+        }
+        let salt = match limit.as_ref().and_then(|limit| limit.salt.clone()) {
+            Some(salt) => salt,
+            None => rand::random::<[u8; 32]>()
+                .iter()
+                .map(|byte| format!("{byte:02x}"))
+                .collect(),
+        };
+        Self {
+            limit: limit.map(Arc::new),
+            salt: salt.into(),
+            buckets: Arc::default(),
+        }
+    }
+
+    fn take(&self, limit: &RateLimit, client: &Client) -> Quota {
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap();
+        if let Some(ip) = &client.ip {
+            if !buckets.buckets.contains_key(&client.id) {
+                let quota = buckets.take(limit, ip, now);
+                if !quota.allowed {
+                    return quota;
+                }
+            }
+        }
+        buckets.take(limit, &client.id, now)
+    }
+
+    // API keys are secrets, so only a keyed hash of them identifies the client on the span
+    fn client<B>(&self, key: &RateLimitKey, request: &Request<B>) -> Client {
+        let ip = request
+            .extensions()
+            .get::<ClientIp>()
+            .map_or_else(|| "unknown".to_string(), |ClientIp(ip)| ip.to_string());
+        if let RateLimitKey::Header(name) = key {
+            if let Some(value) = request.headers().get(name) {
+                return Client {
+                    id: format!("key:{}", pseudonym(&self.salt, value.as_bytes())),
+                    ip: Some(ip),
+                };
+            }
+        }
+        Client { id: ip, ip: None }
+    }
+}
+
+impl<S> Layer<S> for RateLimitLayer {
+    type Service = RateLimitService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RateLimitService {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+/// Service created by [`RateLimitLayer`].
+#[derive(Clone, Debug)]
+pub struct RateLimitService<S> {
+    inner: S,
+    layer: RateLimitLayer,
+}
+
+impl<S, B, ResBody> Service<Request<B>> for RateLimitService<S>
+where
+    S: Service<Request<B>, Response = Response<ResBody>>,
+    S::Future: Send + 'static,
+    ResBody: Default,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<B>) -> Self::Future {
+        let Some(limit) = self.layer.limit.clone() else {
+            return Box::pin(self.inner.call(request));
+        };
+
+        let client = self.layer.client(&limit.key, &request);
+        let span = tracing::Span::current();
+        span.set_attribute("rate_limit.client", client.id.clone());
+        let quota = self.layer.take(&limit, &client);
+        span.set_attribute("rate_limit.exceeded", !quota.allowed);
+        span.set_attribute("rate_limit.remaining", quota.remaining as i64);
+
+        let headers = [
+            ("ratelimit-limit", u64::from(limit.burst)),
+            ("ratelimit-remaining", quota.remaining),
+            ("ratelimit-reset", quota.reset),
+        ];
+        let future = quota.allowed.then(|| self.inner.call(request));
+        Box::pin(async move {
+            let mut response = match future {
+                Some(future) => future.await?,
+                None => {
+                    let mut response = Response::new(ResBody::default());
+                    *response.status_mut() = StatusCode::TOO_MANY_REQUESTS;
+                    response
+                        .headers_mut()
+                        .insert("retry-after", HeaderValue::from(quota.retry_after));
+                    response
+                }
+            };
+            for (name, value) in headers {
+                response
+                    .headers_mut()
+                    .insert(name, HeaderValue::from(value));
+            }
+            Ok(response)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::Infallible;
+    use tower::ServiceExt;
+
+    const API_KEY: HeaderName = HeaderName::from_static("x-api-key");
+
+    async fn send(layer: &RateLimitLayer, api_key: Option<&str>) -> Response<()> {
+        let service = layer.layer(tower::service_fn(|_| async {
+            Ok::<_, Infallible>(Response::new(()))
+        }));
+        let mut request = Request::new(());
+        request
+            .extensions_mut()
+            .insert(ClientIp("192.0.2.1".parse().unwrap()));
+        if let Some(api_key) = api_key {
+            request
+                .headers_mut()
+                .insert(API_KEY, HeaderValue::from_str(api_key).unwrap());
+        }
+        service.oneshot(request).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn counts_made_up_keys_against_the_client_ip() {
+        let layer = RateLimitLayer::new(Some(RateLimit::per_api_key(2, 0.001, API_KEY, "salt")));
+
+        // A known key keeps its own bucket
+        assert_eq!(send(&layer, Some("a")).await.status(), StatusCode::OK);
+        assert_eq!(send(&layer, Some("a")).await.status(), StatusCode::OK);
+        assert_eq!(
+            send(&layer, Some("a")).await.status(),
+            StatusCode::TOO_MANY_REQUESTS
+        );
+
+        // The first request of key `a` took one of the client IP's two requests
+        assert_eq!(send(&layer, Some("b")).await.status(), StatusCode::OK);
+        assert_eq!(
+            send(&layer, Some("c")).await.status(),
+            StatusCode::TOO_MANY_REQUESTS
+        );
+    }
+
+    #[test]
+    fn identifies_api_keys_by_a_keyed_hash() {
+        let request = |api_key: &str| {
+            let mut request = Request::new(());
+            request
+                .headers_mut()
+                .insert(API_KEY, HeaderValue::from_str(api_key).unwrap());
+            request
+        };
+        let key = RateLimitKey::Header(API_KEY);
+        let layer = RateLimitLayer::new(Some(RateLimit::per_api_key(1, 1.0, API_KEY, "salt")));
+        let other_salt =
+            RateLimitLayer::new(Some(RateLimit::per_api_key(1, 1.0, API_KEY, "pepper")));
+
+        let client = layer.client(&key, &request("secret"));
+        assert_eq!(client.id, format!("key:{}", pseudonym("salt", "secret")));
+        assert!(!client.id.contains("secret"));
+        assert_ne!(client.id, other_salt.client(&key, &request("secret")).id);
+        assert_eq!(client.id, layer.client(&key, &request("secret")).id);
+    }
+
+    #[test]
+    fn forgets_the_least_recently_seen_clients() {
+        let limit = RateLimit::per_client_ip(1, 0.001);
+        let mut buckets = Buckets::default();
+        let now = Instant::now();
+
+        assert!(buckets.take(&limit, "first", now).allowed);
+        for client in 0..MAX_TRACKED_CLIENTS {
+            // Kept in use, so it outlives the others
+            if client % 1000 == 0 {
+                buckets.take(&limit, "first", now);
+            }
+            buckets.take(&limit, &client.to_string(), now);
+        }
+
+        assert_eq!(buckets.buckets.len(), MAX_TRACKED_CLIENTS);
+        assert_eq!(buckets.recency.len(), MAX_TRACKED_CLIENTS);
+        assert!(!buckets.take(&limit, "first", now).allowed);
+        assert!(!buckets.buckets.contains_key("0"));
+    }
+}
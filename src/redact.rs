@@ -0,0 +1,199 @@
+use opentelemetry::trace::Status;
+use opentelemetry::{Array, Context, KeyValue, StringValue, Value};
+use opentelemetry_sdk::error::OTelSdkResult;
+use opentelemetry_sdk::trace::{Span, SpanData, SpanProcessor};
+use opentelemetry_sdk::Resource;
+use regex::Regex;
+use std::borrow::Cow;
+use std::sync::{Arc, OnceLock, RwLock};
+use std::time::Duration;
+
+// Set by `Telemetry::init` and shared by its redacting processors, for the rules to be changed while
+// the service runs
+static INSTALLED: OnceLock<Arc<RwLock<Vec<RedactionRule>>>> = OnceLock::new();
+
+/// What happens to an attribute whose value matches a [`RedactionRule`].
+#[derive(Clone, Debug)]
+pub enum RedactionAction {
+    /// Replaces each match with `[REDACTED]`, keeping the rest of the value.
+    Mask,
+    /// Removes the whole attribute, or replaces the whole name or description, which can't be
+    /// removed, with `[REDACTED]`.
+    Drop,
+}
+
+/// Pattern checked against the names and error descriptions of spans, the names of their events,
+/// and the string and string array values of the attributes of spans, events and links.
+#[derive(Clone, Debug)]
+pub struct RedactionRule {
+    pub pattern: Regex,
+    pub action: RedactionAction,
+}
+
+impl RedactionRule {
+    pub fn new(pattern: Regex, action: RedactionAction) -> Self {
+        Self { pattern, action }
+    }
+
+    /// Masks email addresses.
+    pub fn emails() -> Self {
+        Self::new(
+            Regex::new(r"[A-Za-z0-9._%+-]+@[A-Za-z0-9.-]+\.[A-Za-z]{2,}").unwrap(),
+            RedactionAction::Mask,
+        )
+    }
+
+    /// Masks the credentials of bearer tokens, keeping the scheme.
+    pub fn bearer_tokens() -> Self {
+        Self::new(
+            Regex::new(r"(?i)\bbearer\s+[A-Za-z0-9\-._~+/]+=*").unwrap(),
+            RedactionAction::Mask,
+        )
+    }
+
+    /// Masks runs of 13 to 19 digits, optionally separated by spaces or dashes, as card numbers are.
+    pub fn credit_cards() -> Self {
+        Self::new(
+            Regex::new(r"\b(?:\d[ -]?){12,18}\d\b").unwrap(),
+            RedactionAction::Mask,
+        )
+    }
+
+    /// The email, bearer token and card number rules.
+    pub fn defaults() -> Vec<Self> {
+        vec![Self::emails(), Self::bearer_tokens(), Self::credit_cards()]
+    }
+}
+
+/// [`SpanProcessor`] scrubbing the text matching its rules from finished spans, their events and
+/// links before passing them on to `inner`, so PII never leaves the process, even when recorded in
+/// a span name or an `otel.status_description`.
+#[derive(Debug)]
+pub struct RedactingSpanProcessor<P> {
+    inner: P,
+    rules: Arc<RwLock<Vec<RedactionRule>>>,
+}
+
+impl<P: SpanProcessor> RedactingSpanProcessor<P> {
+    pub fn new(inner: P, rules: Vec<RedactionRule>) -> Self {
+        Self {
+            inner,
+            rules: Arc::new(RwLock::new(rules)),
+        }
+    }
+
+    /// Processor sharing the rules that [`reconfigure`] updates, starting out with `rules`.
+    pub(crate) fn installed(inner: P, rules: &[RedactionRule]) -> Self {
+        let rules = INSTALLED.get_or_init(|| Arc::new(RwLock::new(rules.to_vec())));
+        Self {
+            inner,
+            rules: rules.clone(),
+        }
+    }
+}
+
+/// Replaces the rules of the processors installed by [`crate::Telemetry::init`], if any, for the
+/// spans ending from now on.
+pub(crate) fn reconfigure(rules: Vec<RedactionRule>) {
+    if let Some(installed) = INSTALLED.get() {
+        *installed.write().unwrap() = rules;
+    }
+}
+
+// `None` when a `Drop` rule matches
+fn scrub<'a>(rules: &[RedactionRule], text: &'a str) -> Option<Cow<'a, str>> {
+    let mut text = Cow::Borrowed(text);
+    for rule in rules {
+        if !rule.pattern.is_match(&text) {
+            continue;
+        }
+        match rule.action {
+            RedactionAction::Mask => {
+                text = Cow::Owned(rule.pattern.replace_all(&text, "[REDACTED]").into_owned());
+            }
+            RedactionAction::Drop => return None,
+        }
+    }
+    Some(text)
+}
+
+// Names and descriptions can't be dropped, so they are replaced whole instead
+fn scrub_text(rules: &[RedactionRule], text: &mut Cow<'static, str>) {
+    let scrubbed = match scrub(rules, text) {
+        Some(Cow::Borrowed(_)) => return,
+        Some(Cow::Owned(masked)) => masked,
+        None => "[REDACTED]".to_string(),
+    };
+    *text = Cow::Owned(scrubbed);
+}
+
+// `None` drops the attribute, as for a string array with any element matching a `Drop` rule
+fn redact(rules: &[RedactionRule], attribute: KeyValue) -> Option<KeyValue> {
+    let value = match &attribute.value {
+        Value::String(value) => match scrub(rules, value.as_str())? {
+            Cow::Borrowed(_) => return Some(attribute),
+            Cow::Owned(masked) => Value::from(masked),
+        },
+        Value::Array(Array::String(values)) => {
+            let mut masked = Vec::with_capacity(values.len());
+            for value in values {
+                masked.push(match scrub(rules, value.as_str())? {
+                    Cow::Borrowed(_) => value.clone(),
+                    Cow::Owned(masked) => StringValue::from(masked),
+                });
+            }
+            Value::Array(Array::String(masked))
+        }
+        _ => return Some(attribute),
+    };
+    Some(KeyValue::new(attribute.key, value))
+}
+
+fn redact_all(rules: &[RedactionRule], attributes: &mut Vec<KeyValue>) {
+    *attributes = std::mem::take(attributes)
+        .into_iter()
+        .filter_map(|attribute| redact(rules, attribute))
+        .collect();
+}
+
+impl<P: SpanProcessor> SpanProcessor for RedactingSpanProcessor<P> {
+    fn on_start(&self, span: &mut Span, cx: &Context) {
+        self.inner.on_start(span, cx);
+    }
+
+    fn on_end(&self, mut span: SpanData) {
+        let rules = self.rules.read().unwrap();
+        if rules.is_empty() {
+            drop(rules);
+            return self.inner.on_end(span);
+        }
+
+        scrub_text(&rules, &mut span.name);
+        if let Status::Error { description } = &mut span.status {
+            scrub_text(&rules, description);
+        }
+        redact_all(&rules, &mut span.attributes);
+        for event in &mut span.events.events {
+            scrub_text(&rules, &mut event.name);
+            redact_all(&rules, &mut event.attributes);
+        }
+        for link in &mut span.links.links {
+            redact_all(&rules, &mut link.attributes);
+        }
+
+        drop(rules);
+        self.inner.on_end(span);
+    }
+
+    fn force_flush(&self) -> OTelSdkResult {
+        self.inner.force_flush()
+    }
+
+    fn shutdown_with_timeout(&self, timeout: Duration) -> OTelSdkResult {
+        self.inner.shutdown_with_timeout(timeout)
+    }
+
+    fn set_resource(&mut self, resource: &Resource) {
+        self.inner.set_resource(resource);
+    }
+}
@@ -0,0 +1,104 @@
+use redis::aio::ConnectionLike;
+use redis::{Arg, Cmd, Pipeline, RedisFuture, Value};
+use tracing::field::Empty;
+use tracing::{Instrument, Span};
+
+/// Async [`redis`](redis) connection wrapper running every command sent through it in a client
+/// span following the OpenTelemetry database conventions, with `db.system` `redis`, the command as
+/// `db.operation` and the number of keys it names as `db.redis.key_count`; arguments and values
+/// are never recorded.
+///
+/// It works wherever the connection does, e.g. `redis::cmd("GET").arg(key).query_async(&mut conn)`
+/// with a `TracedRedis<MultiplexedConnection>`.
+#[derive(Clone, Debug)]
+pub struct TracedRedis<C> {
+    inner: C,
+}
+
+impl<C: ConnectionLike> TracedRedis<C> {
+    pub fn new(inner: C) -> Self {
+        Self { inner }
+    }
+
+    pub fn into_inner(self) -> C {
+        self.inner
+    }
+}
+
+impl<C: ConnectionLike + Send> ConnectionLike for TracedRedis<C> {
+    fn req_packed_command<'a>(&'a mut self, cmd: &'a Cmd) -> RedisFuture<'a, Value> {
+        let (operation, key_count) = describe(cmd);
+        let span = command_span(&operation, key_count, self.inner.get_db());
+        let reply = self.inner.req_packed_command(cmd);
+        Box::pin(record_result(reply).instrument(span))
+    }
+
+    fn req_packed_commands<'a>(
+        &'a mut self,
+        cmd: &'a Pipeline,
+        offset: usize,
+        count: usize,
+    ) -> RedisFuture<'a, Vec<Value>> {
+        let key_count = cmd.cmd_iter().map(|cmd| describe(cmd).1).sum();
+        let span = command_span("PIPELINE", key_count, self.inner.get_db());
+        let replies = self.inner.req_packed_commands(cmd, offset, count);
+        Box::pin(record_result(replies).instrument(span))
+    }
+
+    fn get_db(&self) -> i64 {
+        self.inner.get_db()
+    }
+}
+
+fn command_span(operation: &str, key_count: usize, database: i64) -> Span {
+    tracing::info_span!(
+        "Redis command",
+        otel.name = operation,
+        otel.kind = "client",
+        otel.status_code = Empty,
+        db.system = "redis",
+        db.operation = operation,
+        db.redis.database_index = database,
+        // As an `i64`, which `tracing_opentelemetry` records as an integer rather than a string
+        db.redis.key_count = key_count as i64,
+    )
+}
+
+async fn record_result<T>(reply: RedisFuture<'_, T>) -> redis::RedisResult<T> {
+    let result = reply.await;
+    if let Err(error) = &result {
+        Span::current().record("otel.status_code", "ERROR");
+        tracing::error!(exception.message = %error, "exception");
+    }
+    result
+}
+
+// The command name, and how many of its arguments are keys. Redis doesn't say which arguments are
+// keys, so this knows the common multi-key and keyless commands, and takes the first argument of any
+// other command as its one key.
+fn describe(cmd: &Cmd) -> (String, usize) {
+    let mut args = cmd.args_iter().filter_map(|arg| match arg {
+        Arg::Simple(arg) => Some(arg),
+        Arg::Cursor => None,
+    });
+    let operation = args
+        .next()
+        .map(|name| String::from_utf8_lossy(name).to_ascii_uppercase())
+        .unwrap_or_default();
+    let args: Vec<&[u8]> = args.collect();
+
+    let key_count = match operation.as_str() {
+        "DEL" | "UNLINK" | "EXISTS" | "TOUCH" | "MGET" | "WATCH" | "SDIFF" | "SINTER"
+        | "SUNION" | "PFCOUNT" => args.len(),
+        "MSET" | "MSETNX" => args.len() / 2,
+        "EVAL" | "EVALSHA" | "EVAL_RO" | "EVALSHA_RO" | "FCALL" | "FCALL_RO" => args
+            .get(1)
+            .and_then(|count| std::str::from_utf8(count).ok()?.parse().ok())
+            .unwrap_or(0),
+        "PING" | "ECHO" | "AUTH" | "HELLO" | "SELECT" | "INFO" | "DBSIZE" | "TIME" | "FLUSHDB"
+        | "FLUSHALL" | "SCAN" | "KEYS" | "MULTI" | "EXEC" | "DISCARD" | "UNWATCH" | "CLIENT"
+        | "CONFIG" | "SCRIPT" | "FUNCTION" | "PUBLISH" | "SUBSCRIBE" | "PSUBSCRIBE" => 0,
+        _ => usize::from(!args.is_empty()),
+    };
+    (operation, key_count)
+}
@@ -0,0 +1,132 @@
+use crate::TelemetryConfig;
+use opentelemetry::KeyValue;
+use opentelemetry_sdk::resource::EnvResourceDetector;
+use opentelemetry_sdk::Resource;
+use opentelemetry_semantic_conventions::resource::{
+    CONTAINER_ID, HOST_NAME, K8S_NAMESPACE_NAME, K8S_NODE_NAME, K8S_POD_NAME, K8S_POD_UID, OS_TYPE,
+    PROCESS_EXECUTABLE_NAME, PROCESS_PID, SERVICE_INSTANCE_ID, SERVICE_NAME, SERVICE_VERSION,
+};
+use std::sync::OnceLock;
+
+// Generated on first use so the traces, metrics and logs of a process all carry the same one
+static INSTANCE_ID: OnceLock<String> = OnceLock::new();
+
+// Kubernetes doesn't expose these by itself; the pod spec maps them from the downward API, e.g.
+// `env: [{name: K8S_POD_NAME, valueFrom: {fieldRef: {fieldPath: metadata.name}}}]`
+const K8S_POD_NAME_ENV: &str = "K8S_POD_NAME";
+const K8S_POD_UID_ENV: &str = "K8S_POD_UID";
+const K8S_NAMESPACE_NAME_ENV: &str = "K8S_NAMESPACE_NAME";
+const K8S_NODE_NAME_ENV: &str = "K8S_NODE_NAME";
+// Renamed `deployment.environment.name` by newer semantic conventions, but kept so existing queries
+// and dashboards still match
+const DEPLOYMENT_ENVIRONMENT: &str = "deployment.environment";
+const SERVICE_ACCOUNT_NAMESPACE: &str = "/var/run/secrets/kubernetes.io/serviceaccount/namespace";
+
+/// Resource shared by the tracer, meter and logger providers: the detected attributes, then the
+/// configured ones, then those given in `OTEL_RESOURCE_ATTRIBUTES`, then the service name and
+/// environment, each overriding the ones before.
+pub(crate) fn build(config: &TelemetryConfig) -> Resource {
+    let mut configured = vec![KeyValue::new(SERVICE_NAME, config.service_name.clone())];
+    if let Some(environment) = &config.environment {
+        configured.push(KeyValue::new(DEPLOYMENT_ENVIRONMENT, environment.clone()));
+    }
+
+    let attributes = config
+        .resource_attributes
+        .iter()
+        .map(|(key, value)| KeyValue::new(key.clone(), value.clone()));
+
+    Resource::builder_empty()
+        .with_attributes(detected_attributes())
+        .with_attributes(attributes)
+        .with_detector(Box::new(EnvResourceDetector::new()))
+        .with_attributes(configured)
+        .build()
+}
+
+/// Random UUID identifying this process among the instances of the service, the same for as long as
+/// it runs.
+pub fn instance_id() -> &'static str {
+    INSTANCE_ID.get_or_init(|| uuid::Uuid::new_v4().to_string())
+}
+
+/// Describes where the service runs: `service.version`, `service.instance.id`, `host.name`,
+/// `os.type`, `process.pid`, `process.executable.name`, and when available `container.id` and the
+/// Kubernetes pod, namespace and node. Attributes that can't be found are left out.
+pub fn detect() -> Resource {
+    Resource::builder_empty()
+        .with_attributes(detected_attributes())
+        .build()
+}
+
+fn detected_attributes() -> Vec<KeyValue> {
+    let mut attributes = vec![
+        KeyValue::new(SERVICE_VERSION, env!("CARGO_PKG_VERSION")),
+        KeyValue::new(SERVICE_INSTANCE_ID, instance_id()),
+        KeyValue::new(OS_TYPE, std::env::consts::OS),
+        KeyValue::new(PROCESS_PID, i64::from(std::process::id())),
+    ];
+    if let Some(name) = std::env::current_exe()
+        .ok()
+        .and_then(|path| Some(path.file_name()?.to_string_lossy().into_owned()))
+    {
+        attributes.push(KeyValue::new(PROCESS_EXECUTABLE_NAME, name));
+    }
+    if let Some(host) = host_name() {
+        attributes.push(KeyValue::new(HOST_NAME, host));
+    }
+    if let Some(id) = container_id() {
+        attributes.push(KeyValue::new(CONTAINER_ID, id));
+    }
+
+    for (key, variable) in [
+        (K8S_POD_NAME, K8S_POD_NAME_ENV),
+        (K8S_POD_UID, K8S_POD_UID_ENV),
+        (K8S_NODE_NAME, K8S_NODE_NAME_ENV),
+    ] {
+        if let Some(value) = env_var(variable) {
+            attributes.push(KeyValue::new(key, value));
+        }
+    }
+    // Every pod with a service account can read its namespace, so it needs no downward API mapping
+    if let Some(namespace) = env_var(K8S_NAMESPACE_NAME_ENV).or_else(|| {
+        let namespace = std::fs::read_to_string(SERVICE_ACCOUNT_NAMESPACE).ok()?;
+        Some(namespace.trim().to_string()).filter(|namespace| !namespace.is_empty())
+    }) {
+        attributes.push(KeyValue::new(K8S_NAMESPACE_NAME, namespace));
+    }
+    attributes
+}
+
+fn host_name() -> Option<String> {
+    std::fs::read_to_string("/proc/sys/kernel/hostname")
+        .ok()
+        .map(|host| host.trim().to_string())
+        .filter(|host| !host.is_empty())
+        .or_else(|| env_var("HOSTNAME"))
+        .or_else(|| env_var("COMPUTERNAME"))
+}
+
+// Container runtimes name the cgroup after the 64 hex digit container ID, e.g.
+// `0::/system.slice/docker-<id>.scope`. With cgroup v2 the cgroup is usually just `/`, but the
+// runtime's per-container files are mounted from `.../containers/<id>/`.
+fn container_id() -> Option<String> {
+    let cgroup = std::fs::read_to_string("/proc/self/cgroup").unwrap_or_default();
+    let mountinfo = std::fs::read_to_string("/proc/self/mountinfo").unwrap_or_default();
+    hex_id(&cgroup).or_else(|| {
+        mountinfo
+            .split("/containers/")
+            .skip(1)
+            .find_map(|rest| hex_id(rest.split('/').next()?))
+    })
+}
+
+fn hex_id(text: &str) -> Option<String> {
+    text.split(|c: char| !c.is_ascii_hexdigit())
+        .find(|part| part.len() == 64)
+        .map(str::to_string)
+}
+
+fn env_var(name: &str) -> Option<String> {
+    std::env::var(name).ok().filter(|value| !value.is_empty())
+}
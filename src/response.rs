@@ -0,0 +1,186 @@
+use axum::body::{Body, Bytes, HttpBody};
+use axum::http::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use axum::http::{HeaderName, HeaderValue, Request, Response};
+use axum::BoxError;
+use opentelemetry::trace::{SpanContext, TraceContextExt};
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tower::{Layer, Service};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+/// Header written by default, from the W3C Trace Context Level 2 draft.
+pub const TRACERESPONSE_HEADER: &str = "traceresponse";
+
+/// Layer writing the ID of the request's trace into a response header, so whoever reports a failing
+/// request can quote the trace to look up.
+///
+/// By default it writes `traceresponse: 00-<trace-id>-<span-id>-<flags>`; with
+/// [`with_header`](Self::with_header) it writes the bare trace ID, e.g. `X-Trace-Id: <trace-id>`.
+#[derive(Clone, Debug, Default)]
+pub struct TraceResponseLayer {
+    header: Option<HeaderName>,
+}
+
+impl TraceResponseLayer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_header(header: HeaderName) -> Self {
+        Self {
+            header: Some(header),
+        }
+    }
+}
+
+impl<S> Layer<S> for TraceResponseLayer {
+    type Service = TraceResponse<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        TraceResponse {
+            inner,
+            header: self.header.clone(),
+        }
+    }
+}
+
+/// Service created by [`TraceResponseLayer`].
+#[derive(Clone, Debug)]
+pub struct TraceResponse<S> {
+    inner: S,
+    header: Option<HeaderName>,
+}
+
+impl<S, B, ResBody> Service<Request<B>> for TraceResponse<S>
+where
+    S: Service<Request<B>, Response = Response<ResBody>>,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<B>) -> Self::Future {
+        let span_context = current_span_context();
+        let header = span_context.is_valid().then(|| match &self.header {
+            Some(name) => (name.clone(), span_context.trace_id().to_string()),
+            None => (
+                HeaderName::from_static(TRACERESPONSE_HEADER),
+                format!(
+                    "00-{}-{}-{:02x}",
+                    span_context.trace_id(),
+                    span_context.span_id(),
+                    span_context.trace_flags().to_u8()
+                ),
+            ),
+        });
+        let future = self.inner.call(request);
+
+        Box::pin(async move {
+            let mut response = future.await?;
+            if let Some((name, value)) = header {
+                // The IDs are hex, so the value is always a valid header
+                response
+                    .headers_mut()
+                    .insert(name, HeaderValue::from_str(&value).unwrap());
+            }
+            Ok(response)
+        })
+    }
+}
+
+/// Layer replacing the body of 5xx responses with a JSON object holding a generic message and the
+/// request's trace ID, e.g. `{"error":"Internal Server Error","trace_id":"..."}`, so users can quote
+/// the trace without the response exposing internals.
+///
+/// Apply it to the router before [`crate::TelemetryHandle::instrument`] so the request span is
+/// current.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ErrorBodyLayer;
+
+impl ErrorBodyLayer {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl<S> Layer<S> for ErrorBodyLayer {
+    type Service = ErrorBody<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ErrorBody { inner }
+    }
+}
+
+/// Service created by [`ErrorBodyLayer`].
+#[derive(Clone, Debug)]
+pub struct ErrorBody<S> {
+    inner: S,
+}
+
+impl<S, B, ResBody> Service<Request<B>> for ErrorBody<S>
+where
+    S: Service<Request<B>, Response = Response<ResBody>>,
+    S::Future: Send + 'static,
+    ResBody: HttpBody<Data = Bytes> + Send + 'static,
+    ResBody::Error: Into<BoxError>,
+{
+    type Response = Response<Body>;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<B>) -> Self::Future {
+        let span_context = current_span_context();
+        let future = self.inner.call(request);
+
+        Box::pin(async move {
+            let response = future.await?;
+            if !response.status().is_server_error() {
+                return Ok(response.map(Body::new));
+            }
+
+            let mut body = serde_json::Map::new();
+            body.insert(
+                "error".to_string(),
+                response
+                    .status()
+                    .canonical_reason()
+                    .unwrap_or("Server Error")
+                    .into(),
+            );
+            if span_context.is_valid() {
+                body.insert(
+                    "trace_id".to_string(),
+                    span_context.trace_id().to_string().into(),
+                );
+            }
+
+            // The status and other headers are kept, but the length and type describe the old body
+            let (mut parts, _) = response.into_parts();
+            parts.headers.remove(CONTENT_LENGTH);
+            parts
+                .headers
+                .insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+            let body = serde_json::Value::Object(body).to_string();
+            Ok(Response::from_parts(parts, Body::from(body)))
+        })
+    }
+}
+
+// Invalid when there is no current span, such as for requests the trace layer skipped
+pub(crate) fn current_span_context() -> SpanContext {
+    tracing::Span::current()
+        .context()
+        .span()
+        .span_context()
+        .clone()
+}
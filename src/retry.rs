@@ -0,0 +1,294 @@
+use crate::spill::{SpillConfig, SpillQueue};
+use opentelemetry::metrics::Counter;
+use opentelemetry_sdk::error::{OTelSdkError, OTelSdkResult};
+use opentelemetry_sdk::trace::{SpanData, SpanExporter};
+use opentelemetry_sdk::Resource;
+use rand::Rng;
+use std::cell::Cell;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+tokio::task_local! {
+    // The HTTP status of the last response to the export attempt in progress
+    static RESPONSE_STATUS: Cell<Option<u16>>;
+}
+
+/// Records the HTTP status an OTLP/HTTP export attempt was answered with, which the exporters only
+/// report as part of their error messages. Outside of a [`RetryingExporter`] it does nothing.
+pub(crate) fn record_response_status(status: u16) {
+    // Not in the scope of an export attempt, e.g. for the metrics and logs exporters
+    let _ = RESPONSE_STATUS.try_with(|response_status| response_status.set(Some(status)));
+}
+
+/// How [`RetryingExporter`] retries failed exports.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RetryPolicy {
+    /// Attempts per batch, including the first; `1` turns retries off.
+    pub max_attempts: u32,
+    /// Upper bound of the delay before the first retry, doubled for each one after that.
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    /// Time after which a batch is dropped rather than retried again, counted from its first attempt.
+    pub budget: Duration,
+    /// Once a batch runs out of retries, later batches skip the backend for this long rather than
+    /// each waiting out its own retries.
+    pub circuit_open_for: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(5),
+            budget: Duration::from_secs(30),
+            circuit_open_for: Duration::from_secs(30),
+        }
+    }
+}
+
+/// [`SpanExporter`] retrying batches that failed for transient reasons, such as rate limits, 5xx
+/// responses or connection failures, with exponential backoff and full jitter.
+///
+/// A batch still failing once the attempts or budget run out opens the circuit: batches exported
+/// while it is open aren't sent, and the first one after [`RetryPolicy::circuit_open_for`] probes the
+/// backend again. Failed and skipped batches are spilled to disk when
+/// [`with_spill`](Self::with_spill) is set, and replayed once an export succeeds; otherwise, or
+/// when the spill directory is full, they are dropped and counted in the
+/// `otel.exporter.spans.dropped` metric.
+#[derive(Debug)]
+pub struct RetryingExporter<E> {
+    inner: E,
+    policy: RetryPolicy,
+    // When the circuit closes again, if open
+    open_until: Mutex<Option<Instant>>,
+    spill: Option<SpillQueue>,
+    dropped: Counter<u64>,
+}
+
+impl<E: SpanExporter + 'static> RetryingExporter<E> {
+    pub fn new(inner: E, policy: RetryPolicy) -> Self {
+        let dropped = opentelemetry::global::meter(env!("CARGO_PKG_NAME"))
+            .u64_counter("otel.exporter.spans.dropped")
+            .with_description("Spans the exporter gave up on without spilling them to disk")
+            .build();
+        Self {
+            inner,
+            policy,
+            open_until: Mutex::new(None),
+            spill: None,
+            dropped,
+        }
+    }
+
+    /// Spills batches that can't be exported to disk instead of dropping them.
+    pub fn with_spill(mut self, spill: SpillConfig) -> Self {
+        self.spill = Some(SpillQueue::new(spill));
+        self
+    }
+}
+
+impl<E: SpanExporter + 'static> SpanExporter for RetryingExporter<E> {
+    async fn export(&self, batch: Vec<SpanData>) -> OTelSdkResult {
+        let is_open = self
+            .open_until
+            .lock()
+            .unwrap()
+            .is_some_and(|until| Instant::now() < until);
+        let error = if is_open {
+            OTelSdkError::InternalFailure("exporter circuit is open".to_string())
+        } else {
+            match export_with_retries(&self.inner, &self.policy, &batch).await {
+                Ok(()) => {
+                    *self.open_until.lock().unwrap() = None;
+                    if let Some(spill) = &self.spill {
+                        // The new batch is already exported, so a failed replay is only reported
+                        if let Err(err) = spill.replay(&self.inner).await {
+                            tracing::warn!(error = %err, "failed to replay spilled spans");
+                        }
+                    }
+                    return Ok(());
+                }
+                // Permanent failures would be rejected again on replay
+                Err(Failure {
+                    error,
+                    transient: false,
+                }) => {
+                    self.dropped.add(batch.len() as u64, &[]);
+                    return Err(error);
+                }
+                Err(Failure { error, .. }) => {
+                    *self.open_until.lock().unwrap() =
+                        Some(Instant::now() + self.policy.circuit_open_for);
+                    error
+                }
+            }
+        };
+
+        if let Some(spill) = &self.spill {
+            match spill.push(&batch).await {
+                Ok(()) => return Ok(()),
+                Err(err) => tracing::warn!(error = %err, "failed to spill spans"),
+            }
+        }
+        self.dropped.add(batch.len() as u64, &[]);
+        Err(error)
+    }
+
+    fn shutdown_with_timeout(&self, timeout: Duration) -> OTelSdkResult {
+        self.inner.shutdown_with_timeout(timeout)
+    }
+
+    fn force_flush(&self) -> OTelSdkResult {
+        self.inner.force_flush()
+    }
+
+    fn set_resource(&mut self, resource: &Resource) {
+        self.inner.set_resource(resource);
+    }
+}
+
+// A batch the exporter gave up on, and whether retrying it later could succeed
+#[derive(Debug)]
+struct Failure {
+    error: OTelSdkError,
+    transient: bool,
+}
+
+async fn export_with_retries<E: SpanExporter>(
+    inner: &E,
+    policy: &RetryPolicy,
+    batch: &[SpanData],
+) -> Result<(), Failure> {
+    let start = Instant::now();
+    let mut backoff = policy.initial_backoff;
+    let mut attempt = 1;
+    loop {
+        let (result, status) = RESPONSE_STATUS
+            .scope(Cell::new(None), async {
+                let result = inner.export(batch.to_vec()).await;
+                (result, RESPONSE_STATUS.with(Cell::get))
+            })
+            .await;
+        let error = match result {
+            Ok(()) => return Ok(()),
+            Err(error) => error,
+        };
+
+        let transient = is_transient(&error, status);
+        let delay = rand::thread_rng().gen_range(Duration::ZERO..=backoff);
+        let out_of_budget = start.elapsed() + delay > policy.budget;
+        if attempt >= policy.max_attempts || out_of_budget || !transient {
+            return Err(Failure { error, transient });
+        }
+
+        tokio::time::sleep(delay).await;
+        backoff = (backoff * 2).min(policy.max_backoff);
+        attempt += 1;
+    }
+}
+
+// Only rejections that would fail again the same way are permanent: 4xx responses other than 429,
+// and their gRPC equivalents. The HTTP status comes from `record_response_status`; the gRPC
+// exporter keeps the status to itself and only reports its code in the message, as in
+// "TonicTracesClient export failed with gRPC code: Unauthenticated".
+fn is_transient(error: &OTelSdkError, status: Option<u16>) -> bool {
+    const PERMANENT_GRPC_CODES: [&str; 5] = [
+        "InvalidArgument",
+        "NotFound",
+        "PermissionDenied",
+        "Unauthenticated",
+        "Unimplemented",
+    ];
+
+    if let Some(status) = status {
+        return !(400..500).contains(&status) || status == 429;
+    }
+    match error.to_string().split("gRPC code: ").nth(1) {
+        Some(code) => !PERMANENT_GRPC_CODES
+            .iter()
+            .any(|permanent| code.starts_with(permanent)),
+        None => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ExporterBackend, TelemetryConfig};
+    use axum::http::StatusCode;
+    use axum::routing::post;
+    use axum::Router;
+    use std::collections::HashMap;
+
+    // Serves `app` on a local port, returning its address
+    async fn serve(app: Router) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        format!("http://{address}")
+    }
+
+    async fn classify(backend: ExporterBackend) -> bool {
+        let config = TelemetryConfig::new(backend);
+        let target = config.exporter.otlp_target(&config, "traces").unwrap();
+        let exporter = target.span_exporter();
+        let policy = RetryPolicy {
+            max_attempts: 1,
+            ..RetryPolicy::default()
+        };
+        let failure = export_with_retries(&exporter, &policy, &[])
+            .await
+            .unwrap_err();
+        failure.transient
+    }
+
+    #[tokio::test]
+    async fn retries_http_exports_unless_rejected_for_good() {
+        for (status, transient) in [(400, false), (401, false), (429, true), (503, true)] {
+            let status = StatusCode::from_u16(status).unwrap();
+            let endpoint =
+                serve(Router::new().route("/v1/traces", post(move || async move { status }))).await;
+            let backend = ExporterBackend::OtlpHttp {
+                endpoint: format!("{endpoint}/v1/traces"),
+                headers: HashMap::new(),
+            };
+            assert_eq!(classify(backend).await, transient, "{status}");
+        }
+
+        // Nothing listening
+        let backend = ExporterBackend::OtlpHttp {
+            endpoint: "http://127.0.0.1:9/v1/traces".to_string(),
+            headers: HashMap::new(),
+        };
+        assert!(classify(backend).await);
+    }
+
+    #[cfg(feature = "grpc")]
+    #[tokio::test]
+    async fn retries_grpc_exports_unless_rejected_for_good() {
+        // Unauthenticated and Unavailable
+        for (code, transient) in [("16", false), ("14", true)] {
+            let respond = move || async move {
+                [("content-type", "application/grpc"), ("grpc-status", code)]
+            };
+            let endpoint = serve(Router::new().fallback(post(respond))).await;
+            let backend = ExporterBackend::OtlpGrpc {
+                endpoint,
+                headers: HashMap::new(),
+            };
+            assert_eq!(classify(backend).await, transient, "gRPC code {code}");
+        }
+    }
+
+    #[test]
+    fn classifies_by_the_http_status_before_the_message() {
+        let error =
+            OTelSdkError::InternalFailure("export failed with gRPC code: Unauthenticated".into());
+        assert!(is_transient(&error, Some(503)));
+        assert!(!is_transient(&error, None));
+        let error = OTelSdkError::InternalFailure("HTTP export failed: network error".into());
+        assert!(is_transient(&error, None));
+        assert!(!is_transient(&error, Some(404)));
+    }
+}
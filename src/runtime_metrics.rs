@@ -0,0 +1,178 @@
+use opentelemetry::metrics::{Counter, Meter, UpDownCounter};
+use std::time::Duration;
+use tokio::runtime::{Handle, RuntimeMetrics};
+use tokio::task::JoinHandle;
+
+// Gauges are recorded as the change since the last sample, as there is no synchronous gauge
+struct Gauge {
+    instrument: UpDownCounter<i64>,
+    last: i64,
+}
+
+impl Gauge {
+    fn new(meter: &Meter, name: &'static str, description: &'static str) -> Self {
+        Self {
+            instrument: meter
+                .i64_up_down_counter(name)
+                .with_description(description)
+                .build(),
+            last: 0,
+        }
+    }
+
+    fn set(&mut self, value: usize) {
+        let value = value as i64;
+        self.instrument.add(value - self.last, &[]);
+        self.last = value;
+    }
+}
+
+// Likewise, the runtime's counters are totals, recorded as the increase since the last sample
+struct Total {
+    instrument: Counter<u64>,
+    last: u64,
+}
+
+impl Total {
+    fn new(meter: &Meter, name: &'static str, description: &'static str) -> Self {
+        Self {
+            instrument: meter
+                .u64_counter(name)
+                .with_description(description)
+                .build(),
+            last: 0,
+        }
+    }
+
+    fn set(&mut self, value: u64) {
+        self.instrument.add(value.saturating_sub(self.last), &[]);
+        self.last = value;
+    }
+}
+
+struct Instruments {
+    workers: Gauge,
+    alive_tasks: Gauge,
+    global_queue_depth: Gauge,
+    busy_duration: Counter<f64>,
+    last_busy: Duration,
+    parks: Total,
+    #[cfg(tokio_unstable)]
+    blocking_threads: Gauge,
+    #[cfg(tokio_unstable)]
+    blocking_queue_depth: Gauge,
+    #[cfg(tokio_unstable)]
+    polls: Total,
+    #[cfg(tokio_unstable)]
+    budget_forced_yields: Total,
+}
+
+impl Instruments {
+    fn new() -> Self {
+        let meter = opentelemetry::global::meter(env!("CARGO_PKG_NAME"));
+        Self {
+            workers: Gauge::new(&meter, "tokio.workers", "Number of runtime worker threads"),
+            alive_tasks: Gauge::new(&meter, "tokio.tasks.alive", "Number of tasks not finished"),
+            global_queue_depth: Gauge::new(
+                &meter,
+                "tokio.global_queue.depth",
+                "Number of tasks waiting in the runtime's global queue",
+            ),
+            busy_duration: meter
+                .f64_counter("tokio.workers.busy_duration")
+                .with_unit("s")
+                .with_description("Time the worker threads spent running tasks")
+                .build(),
+            last_busy: Duration::ZERO,
+            parks: Total::new(
+                &meter,
+                "tokio.workers.park_count",
+                "Number of times the worker threads parked for lack of work",
+            ),
+            #[cfg(tokio_unstable)]
+            blocking_threads: Gauge::new(
+                &meter,
+                "tokio.blocking_threads",
+                "Number of threads running or waiting for blocking tasks",
+            ),
+            #[cfg(tokio_unstable)]
+            blocking_queue_depth: Gauge::new(
+                &meter,
+                "tokio.blocking_queue.depth",
+                "Number of blocking tasks waiting for a thread",
+            ),
+            #[cfg(tokio_unstable)]
+            polls: Total::new(
+                &meter,
+                "tokio.tasks.poll_count",
+                "Number of times the worker threads polled tasks",
+            ),
+            #[cfg(tokio_unstable)]
+            budget_forced_yields: Total::new(
+                &meter,
+                "tokio.tasks.budget_forced_yield_count",
+                "Number of times tasks were made to yield after exhausting their budget",
+            ),
+        }
+    }
+
+    fn sample(&mut self, metrics: &RuntimeMetrics) {
+        let workers = metrics.num_workers();
+        self.workers.set(workers);
+        self.alive_tasks.set(metrics.num_alive_tasks());
+        self.global_queue_depth.set(metrics.global_queue_depth());
+
+        let busy = (0..workers).map(|worker| metrics.worker_total_busy_duration(worker));
+        let busy = busy.sum::<Duration>();
+        self.busy_duration
+            .add(busy.saturating_sub(self.last_busy).as_secs_f64(), &[]);
+        self.last_busy = busy;
+        self.parks.set(
+            (0..workers)
+                .map(|worker| metrics.worker_park_count(worker))
+                .sum(),
+        );
+
+        #[cfg(tokio_unstable)]
+        {
+            self.blocking_threads.set(metrics.num_blocking_threads());
+            self.blocking_queue_depth
+                .set(metrics.blocking_queue_depth());
+            self.polls.set(
+                (0..workers)
+                    .map(|worker| metrics.worker_poll_count(worker))
+                    .sum(),
+            );
+            self.budget_forced_yields
+                .set(metrics.budget_forced_yield_count());
+        }
+    }
+}
+
+/// Spawns a task sampling the metrics of the current tokio runtime every `interval` into the
+/// metrics pipeline, to tell when latency comes from the executor being saturated rather than from
+/// the handlers.
+///
+/// The worker count, alive tasks, global queue depth, busy time and parks are always recorded.
+/// Built with `RUSTFLAGS="--cfg tokio_unstable"`, the blocking thread count, blocking queue depth,
+/// task polls and budget forced yields are too. Nothing is recorded when called outside a tokio
+/// runtime.
+///
+/// Call this after [`crate::Telemetry::init`], as the metric instruments are created from the global
+/// meter provider, or have it call this by setting [`crate::Telemetry::runtime_metrics`].
+pub fn spawn_runtime_metrics(interval: Duration) -> Option<JoinHandle<()>> {
+    let Ok(handle) = Handle::try_current() else {
+        tracing::warn!("runtime metrics aren't recorded outside a tokio runtime");
+        return None;
+    };
+
+    let mut instruments = Instruments::new();
+    Some(handle.spawn(async move {
+        let metrics = Handle::current().metrics();
+        let mut interval = tokio::time::interval(interval);
+        loop {
+            interval.tick().await;
+            instruments.sample(&metrics);
+        }
+    }))
+}
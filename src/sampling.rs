@@ -0,0 +1,635 @@
+use opentelemetry::trace::{Link, Span as _, SpanKind, TraceContextExt, TraceId};
+use opentelemetry::{Context, Key, KeyValue, Value};
+use opentelemetry_sdk::error::OTelSdkResult;
+use opentelemetry_sdk::trace::{
+    Sampler, SamplingDecision, SamplingResult, ShouldSample, Span, SpanData, SpanProcessor,
+};
+use opentelemetry_sdk::Resource;
+use std::any::TypeId;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
+use std::time::{Duration, Instant};
+use tracing::span::{Attributes, Record};
+use tracing::subscriber::Interest;
+use tracing::{Dispatch, Event, Metadata, Subscriber};
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::layer;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Registry;
+
+/// Which traces to keep, decided when the root span starts.
+#[derive(Clone, Debug, PartialEq)]
+pub enum SamplingStrategy {
+    AlwaysOn,
+    AlwaysOff,
+    /// Keeps this fraction of traces, between `0.0` and `1.0`, chosen by trace ID.
+    TraceIdRatioBased(f64),
+    /// Follows the caller's decision when the request continues a trace, and the inner strategy otherwise.
+    ParentBased(Box<SamplingStrategy>),
+}
+
+impl Default for SamplingStrategy {
+    fn default() -> Self {
+        SamplingStrategy::ParentBased(Box::new(SamplingStrategy::AlwaysOn))
+    }
+}
+
+impl SamplingStrategy {
+    /// Parses the values of `OTEL_TRACES_SAMPLER` and `OTEL_TRACES_SAMPLER_ARG`.
+    ///
+    /// A missing or malformed ratio means `1.0`, as the OpenTelemetry spec asks.
+    pub fn from_otel_names(name: &str, arg: Option<&str>) -> Option<Self> {
+        let ratio = arg
+            .and_then(|arg| arg.trim().parse::<f64>().ok())
+            .filter(|ratio| (0.0..=1.0).contains(ratio))
+            .unwrap_or(1.0);
+        let strategy = match name {
+            "always_on" => SamplingStrategy::AlwaysOn,
+            "always_off" => SamplingStrategy::AlwaysOff,
+            "traceidratio" => SamplingStrategy::TraceIdRatioBased(ratio),
+            "parentbased_always_on" => {
+                SamplingStrategy::ParentBased(Box::new(SamplingStrategy::AlwaysOn))
+            }
+            "parentbased_always_off" => {
+                SamplingStrategy::ParentBased(Box::new(SamplingStrategy::AlwaysOff))
+            }
+            "parentbased_traceidratio" => {
+                SamplingStrategy::ParentBased(Box::new(SamplingStrategy::TraceIdRatioBased(ratio)))
+            }
+            _ => return None,
+        };
+        Some(strategy)
+    }
+
+    pub(crate) fn to_sampler(&self) -> Sampler {
+        match self {
+            SamplingStrategy::AlwaysOn => Sampler::AlwaysOn,
+            SamplingStrategy::AlwaysOff => Sampler::AlwaysOff,
+            SamplingStrategy::TraceIdRatioBased(ratio) => Sampler::TraceIdRatioBased(*ratio),
+            SamplingStrategy::ParentBased(root) => {
+                Sampler::ParentBased(Box::new(root.to_sampler()))
+            }
+        }
+    }
+}
+
+/// Sample rate for requests whose route starts with `prefix`, used instead of the [`SamplingStrategy`].
+#[derive(Clone, Debug, PartialEq, serde::Deserialize)]
+pub struct RouteRule {
+    pub prefix: String,
+    /// Fraction of matching requests to keep, between `0.0` and `1.0`, chosen by trace ID.
+    pub ratio: f64,
+}
+
+// Set by `Telemetry::init`, for the rules to be changed while the service runs
+static INSTALLED: OnceLock<RouteSampler> = OnceLock::new();
+
+/// Sampler applying the longest matching [`RouteRule`] to server spans, and `fallback` to every
+/// other span and to routes without a rule.
+///
+/// Rules take precedence over the caller's sampling decision, so a sampled trace continued by a
+/// health check can still be dropped.
+#[derive(Clone, Debug)]
+pub(crate) struct RouteSampler {
+    // Shared between clones, so the one given to the tracer provider can be updated
+    state: Arc<RwLock<(Vec<RouteRule>, Sampler)>>,
+}
+
+impl RouteSampler {
+    /// Sampler that [`reconfigure`] updates.
+    pub(crate) fn install(rules: Vec<RouteRule>, fallback: Sampler) -> Self {
+        let sampler = Self {
+            state: Arc::new(RwLock::new((rules, fallback))),
+        };
+        let _ = INSTALLED.set(sampler.clone());
+        sampler
+    }
+
+    // The route template is preferred, but isn't known for requests that matched no route
+    fn ratio(rules: &[RouteRule], attributes: &[KeyValue]) -> Option<f64> {
+        let attribute = |key: &str| {
+            attributes
+                .iter()
+                .find(|attribute| attribute.key.as_str() == key)
+        };
+        let path = attribute("http.route")
+            .or_else(|| attribute("http.target"))?
+            .value
+            .as_str();
+        rules
+            .iter()
+            .filter(|rule| path.starts_with(rule.prefix.as_str()))
+            .max_by_key(|rule| rule.prefix.len())
+            .map(|rule| rule.ratio)
+    }
+}
+
+/// Replaces the route rules and fallback strategy of the sampler installed by
+/// [`crate::Telemetry::init`], if any, for the spans started from now on.
+pub(crate) fn reconfigure(rules: Vec<RouteRule>, fallback: &SamplingStrategy) {
+    if let Some(sampler) = INSTALLED.get() {
+        *sampler.state.write().unwrap() = (rules, fallback.to_sampler());
+    }
+}
+
+impl ShouldSample for RouteSampler {
+    fn should_sample(
+        &self,
+        parent_context: Option<&Context>,
+        trace_id: TraceId,
+        name: &str,
+        span_kind: &SpanKind,
+        attributes: &[KeyValue],
+        links: &[Link],
+    ) -> SamplingResult {
+        let (rules, fallback) = &*self.state.read().unwrap();
+        // Client spans carry the route or target of the request they send, which the rules aren't
+        // about
+        let ratio = match span_kind {
+            SpanKind::Server => Self::ratio(rules, attributes),
+            _ => None,
+        };
+        let Some(ratio) = ratio else {
+            return fallback.should_sample(
+                parent_context,
+                trace_id,
+                name,
+                span_kind,
+                attributes,
+                links,
+            );
+        };
+
+        SamplingResult {
+            decision: if in_ratio(trace_id, ratio) {
+                SamplingDecision::RecordAndSample
+            } else {
+                SamplingDecision::Drop
+            },
+            attributes: Vec::new(),
+            trace_state: parent_context
+                .map(|cx| cx.span().span_context().trace_state().clone())
+                .unwrap_or_default(),
+        }
+    }
+}
+
+/// Which finished traces to export when tail sampling is enabled.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TailSampling {
+    /// Traces whose spans span at least this long are always kept.
+    pub latency_threshold: Duration,
+    /// Fraction of the remaining traces to keep anyway, between `0.0` and `1.0`, chosen by trace ID.
+    pub ratio: f64,
+    /// Most traces buffered at once; spans of traces started beyond this are exported undecided.
+    pub max_traces: usize,
+    /// Longest a trace is buffered for. Traces still open by then, such as those of WebSocket or
+    /// server-sent events requests, are judged on the spans that have ended so far, and the spans
+    /// ending after that follow the same decision.
+    pub max_age: Duration,
+}
+
+impl Default for TailSampling {
+    fn default() -> Self {
+        Self {
+            latency_threshold: Duration::from_secs(1),
+            ratio: 0.01,
+            max_traces: 10_000,
+            max_age: Duration::from_secs(60),
+        }
+    }
+}
+
+// How often at most the buffered traces are checked for ones older than `TailSampling::max_age`
+const SWEEP_INTERVAL: Duration = Duration::from_secs(1);
+
+/// [`SpanProcessor`] holding back spans until every span of their trace started in this process has
+/// ended, then passing the trace on to `inner` only if [`TailSampling`] keeps it.
+///
+/// A trace is kept when any span recorded an `http.status_code` of 500 or more, when it ran for at
+/// least the latency threshold, or when its trace ID falls in the sampled ratio.
+#[derive(Debug)]
+pub struct TailSamplingProcessor<P> {
+    inner: P,
+    policy: TailSampling,
+    buffer: Mutex<Buffer>,
+}
+
+#[derive(Debug)]
+struct Buffer {
+    traces: HashMap<TraceId, BufferedTrace>,
+    // When the traces were last checked for stale ones
+    swept: Instant,
+}
+
+#[derive(Debug)]
+struct BufferedTrace {
+    started: Instant,
+    open_spans: usize,
+    spans: Vec<SpanData>,
+    // Whether the trace is kept, once decided before all its spans ended
+    decision: Option<bool>,
+}
+
+impl BufferedTrace {
+    fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            open_spans: 0,
+            spans: Vec::new(),
+            decision: None,
+        }
+    }
+}
+
+impl<P: SpanProcessor> TailSamplingProcessor<P> {
+    pub fn new(inner: P, policy: TailSampling) -> Self {
+        Self {
+            inner,
+            policy,
+            buffer: Mutex::new(Buffer {
+                traces: HashMap::new(),
+                swept: Instant::now(),
+            }),
+        }
+    }
+
+    // Decides the traces buffered for longer than `max_age` that have ended spans to judge them
+    // on, returning the spans to pass on
+    fn sweep(&self, buffer: &mut Buffer) -> Vec<SpanData> {
+        let now = Instant::now();
+        if now.duration_since(buffer.swept) < SWEEP_INTERVAL.min(self.policy.max_age) {
+            return Vec::new();
+        }
+        buffer.swept = now;
+
+        let mut kept = Vec::new();
+        for trace in buffer.traces.values_mut() {
+            let stale = now.duration_since(trace.started) >= self.policy.max_age;
+            if trace.decision.is_none() && stale && !trace.spans.is_empty() {
+                let spans = std::mem::take(&mut trace.spans);
+                let keep = self.keep(&spans);
+                trace.decision = Some(keep);
+                if keep {
+                    kept.extend(spans);
+                }
+            }
+        }
+        kept
+    }
+
+    fn keep(&self, spans: &[SpanData]) -> bool {
+        let Some(first) = spans.first() else {
+            return false;
+        };
+
+        let status_code = Key::from_static_str("http.status_code");
+        let server_error = spans
+            .iter()
+            .flat_map(|span| &span.attributes)
+            .any(|attribute| {
+                attribute.key == status_code
+                    && matches!(attribute.value, Value::I64(code) if code >= 500)
+            });
+
+        let start = spans.iter().map(|span| span.start_time).min();
+        let end = spans.iter().map(|span| span.end_time).max();
+        let slow = match (start, end) {
+            (Some(start), Some(end)) => end
+                .duration_since(start)
+                .is_ok_and(|latency| latency >= self.policy.latency_threshold),
+            _ => false,
+        };
+
+        server_error || slow || in_ratio(first.span_context.trace_id(), self.policy.ratio)
+    }
+
+    fn export(&self, spans: Vec<SpanData>) {
+        if self.keep(&spans) {
+            for span in spans {
+                self.inner.on_end(span);
+            }
+        }
+    }
+}
+
+impl<P: SpanProcessor> SpanProcessor for TailSamplingProcessor<P> {
+    fn on_start(&self, span: &mut Span, cx: &Context) {
+        let trace_id = span.span_context().trace_id();
+        {
+            let traces = &mut self.buffer.lock().unwrap().traces;
+            if traces.len() < self.policy.max_traces || traces.contains_key(&trace_id) {
+                traces
+                    .entry(trace_id)
+                    .or_insert_with(BufferedTrace::new)
+                    .open_spans += 1;
+            }
+        }
+        self.inner.on_start(span, cx);
+    }
+
+    fn on_end(&self, span: SpanData) {
+        let trace_id = span.span_context.trace_id();
+        let mut buffer = self.buffer.lock().unwrap();
+        let mut spans = self.sweep(&mut buffer);
+        let mut finished = None;
+        match buffer.traces.get_mut(&trace_id) {
+            None => spans.push(span),
+            Some(trace) => {
+                trace.open_spans = trace.open_spans.saturating_sub(1);
+                match trace.decision {
+                    Some(true) => spans.push(span),
+                    Some(false) => {}
+                    None => trace.spans.push(span),
+                }
+                if trace.open_spans == 0 {
+                    finished = buffer.traces.remove(&trace_id);
+                }
+            }
+        }
+        drop(buffer);
+
+        for span in spans {
+            self.inner.on_end(span);
+        }
+        if let Some(trace) = finished.filter(|trace| trace.decision.is_none()) {
+            self.export(trace.spans);
+        }
+    }
+
+    fn force_flush(&self) -> OTelSdkResult {
+        self.inner.force_flush()
+    }
+
+    // Traces still in flight are judged on the spans that have ended so far
+    fn shutdown_with_timeout(&self, timeout: Duration) -> OTelSdkResult {
+        let traces = std::mem::take(&mut self.buffer.lock().unwrap().traces);
+        for trace in traces.into_values() {
+            self.export(trace.spans);
+        }
+        self.inner.shutdown_with_timeout(timeout)
+    }
+
+    fn set_resource(&mut self, resource: &Resource) {
+        self.inner.set_resource(resource);
+    }
+}
+
+// Same scheme as the SDK's `TraceIdRatioBased`, so a trace kept at a ratio is also kept at any higher one
+fn in_ratio(trace_id: TraceId, ratio: f64) -> bool {
+    if ratio >= 1.0 {
+        return true;
+    }
+    let bytes = trace_id.to_bytes();
+    let low = u64::from_be_bytes(bytes[8..16].try_into().unwrap()) >> 1;
+    let bound = (ratio.max(0.0) * (1u64 << 63) as f64) as u64;
+    low < bound
+}
+
+// In the extensions of request spans the sampler dropped, and of the spans below them that
+// `SkipSampledOut` kept from its layer
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum SampledOut {
+    Request,
+    Skipped,
+}
+
+/// Marks `span`, a request span the sampler dropped, so [`SkipSampledOut`] keeps the spans and
+/// events below it from OpenTelemetry.
+pub(crate) fn mark_sampled_out(span: &tracing::Span) {
+    let Some(id) = span.id() else {
+        return;
+    };
+    tracing::dispatcher::get_default(|dispatch| {
+        let span = dispatch
+            .downcast_ref::<Registry>()
+            .and_then(|registry| registry.span(&id));
+        if let Some(span) = span {
+            let mut extensions = span.extensions_mut();
+            if extensions.get_mut::<SampledOut>().is_none() {
+                extensions.insert(SampledOut::Request);
+            }
+        }
+    });
+}
+
+/// Layer wrapping the OpenTelemetry one, so the spans and events below a request span the sampler
+/// dropped never reach it, rather than each being recorded by OpenTelemetry only to be dropped too.
+///
+/// Request spans are known to be dropped once [`crate::OtelOnRequest`] has seen them, which
+/// [`crate::Telemetry::init`] installs along with this layer, so the only cost left for the
+/// handler's spans is that of the other layers, e.g. the JSON logs. Root spans, e.g. those of
+/// [`crate::spawn_traced`] tasks, are still sampled on their own. The spans skipped have no
+/// OpenTelemetry context of their own, so the crate's clients and log layers propagate and record
+/// the request span's, which is the one the sampler dropped; `OpenTelemetrySpanExt::context` on them
+/// returns an empty one.
+#[derive(Clone, Debug)]
+pub struct SkipSampledOut<L> {
+    inner: L,
+}
+
+impl<L> SkipSampledOut<L> {
+    pub fn new(inner: L) -> Self {
+        Self { inner }
+    }
+}
+
+fn skipped<S: Subscriber + for<'a> LookupSpan<'a>>(
+    id: &tracing::span::Id,
+    ctx: &layer::Context<'_, S>,
+) -> bool {
+    ctx.span(id)
+        .is_some_and(|span| span.extensions().get::<SampledOut>() == Some(&SampledOut::Skipped))
+}
+
+impl<S, L> tracing_subscriber::Layer<S> for SkipSampledOut<L>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    L: tracing_subscriber::Layer<S>,
+{
+    fn on_register_dispatch(&self, subscriber: &Dispatch) {
+        self.inner.on_register_dispatch(subscriber);
+    }
+
+    fn on_layer(&mut self, subscriber: &mut S) {
+        self.inner.on_layer(subscriber);
+    }
+
+    fn register_callsite(&self, metadata: &'static Metadata<'static>) -> Interest {
+        self.inner.register_callsite(metadata)
+    }
+
+    fn enabled(&self, metadata: &Metadata<'_>, ctx: layer::Context<'_, S>) -> bool {
+        self.inner.enabled(metadata, ctx)
+    }
+
+    fn on_new_span(
+        &self,
+        attrs: &Attributes<'_>,
+        id: &tracing::span::Id,
+        ctx: layer::Context<'_, S>,
+    ) {
+        let parent = if let Some(parent) = attrs.parent() {
+            ctx.span(parent)
+        } else if attrs.is_contextual() {
+            ctx.lookup_current()
+        } else {
+            None
+        };
+        if parent.is_some_and(|parent| parent.extensions().get::<SampledOut>().is_some()) {
+            if let Some(span) = ctx.span(id) {
+                span.extensions_mut().insert(SampledOut::Skipped);
+            }
+            return;
+        }
+        self.inner.on_new_span(attrs, id, ctx);
+    }
+
+    fn on_record(&self, id: &tracing::span::Id, values: &Record<'_>, ctx: layer::Context<'_, S>) {
+        if !skipped(id, &ctx) {
+            self.inner.on_record(id, values, ctx);
+        }
+    }
+
+    fn on_follows_from(
+        &self,
+        id: &tracing::span::Id,
+        follows: &tracing::span::Id,
+        ctx: layer::Context<'_, S>,
+    ) {
+        if !skipped(id, &ctx) {
+            self.inner.on_follows_from(id, follows, ctx);
+        }
+    }
+
+    fn event_enabled(&self, event: &Event<'_>, ctx: layer::Context<'_, S>) -> bool {
+        self.inner.event_enabled(event, ctx)
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: layer::Context<'_, S>) {
+        let sampled_out = ctx
+            .event_span(event)
+            .is_some_and(|span| span.extensions().get::<SampledOut>().is_some());
+        if !sampled_out {
+            self.inner.on_event(event, ctx);
+        }
+    }
+
+    fn on_enter(&self, id: &tracing::span::Id, ctx: layer::Context<'_, S>) {
+        if !skipped(id, &ctx) {
+            self.inner.on_enter(id, ctx);
+        }
+    }
+
+    fn on_exit(&self, id: &tracing::span::Id, ctx: layer::Context<'_, S>) {
+        if !skipped(id, &ctx) {
+            self.inner.on_exit(id, ctx);
+        }
+    }
+
+    fn on_close(&self, id: tracing::span::Id, ctx: layer::Context<'_, S>) {
+        if !skipped(&id, &ctx) {
+            self.inner.on_close(id, ctx);
+        }
+    }
+
+    fn on_id_change(
+        &self,
+        old: &tracing::span::Id,
+        new: &tracing::span::Id,
+        ctx: layer::Context<'_, S>,
+    ) {
+        self.inner.on_id_change(old, new, ctx);
+    }
+
+    fn max_level_hint(&self) -> Option<LevelFilter> {
+        self.inner.max_level_hint()
+    }
+
+    // The OpenTelemetry layer is looked up through the subscriber for the spans' contexts
+    unsafe fn downcast_raw(&self, id: TypeId) -> Option<*const ()> {
+        if id == TypeId::of::<Self>() {
+            return Some(self as *const Self as *const ());
+        }
+        unsafe { self.inner.downcast_raw(id) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sampler(rules: Vec<RouteRule>) -> RouteSampler {
+        RouteSampler {
+            state: Arc::new(RwLock::new((rules, Sampler::AlwaysOn))),
+        }
+    }
+
+    fn rule(prefix: &str, ratio: f64) -> RouteRule {
+        RouteRule {
+            prefix: prefix.to_string(),
+            ratio,
+        }
+    }
+
+    fn decision(
+        sampler: &RouteSampler,
+        kind: SpanKind,
+        attributes: &[KeyValue],
+    ) -> SamplingDecision {
+        sampler
+            .should_sample(None, TraceId::from(1), "span", &kind, attributes, &[])
+            .decision
+    }
+
+    #[test]
+    fn applies_the_longest_matching_prefix() {
+        let sampler = sampler(vec![rule("/api", 1.0), rule("/api/health", 0.0)]);
+
+        let health = [KeyValue::new("http.route", "/api/health")];
+        assert_eq!(
+            decision(&sampler, SpanKind::Server, &health),
+            SamplingDecision::Drop
+        );
+        let users = [KeyValue::new("http.route", "/api/users/{id}")];
+        assert_eq!(
+            decision(&sampler, SpanKind::Server, &users),
+            SamplingDecision::RecordAndSample
+        );
+    }
+
+    #[test]
+    fn falls_back_to_the_target_then_the_strategy() {
+        let sampler = sampler(vec![rule("/health", 0.0)]);
+
+        let unmatched = [KeyValue::new("http.target", "/health?verbose")];
+        assert_eq!(
+            decision(&sampler, SpanKind::Server, &unmatched),
+            SamplingDecision::Drop
+        );
+        let other = [KeyValue::new("http.route", "/users")];
+        assert_eq!(
+            decision(&sampler, SpanKind::Server, &other),
+            SamplingDecision::RecordAndSample
+        );
+    }
+
+    #[test]
+    fn leaves_client_spans_to_the_fallback() {
+        let sampler = sampler(vec![rule("/health", 0.0)]);
+
+        let health = [KeyValue::new("http.route", "/health")];
+        assert_eq!(
+            decision(&sampler, SpanKind::Client, &health),
+            SamplingDecision::RecordAndSample
+        );
+    }
+
+    #[test]
+    fn keeps_traces_within_the_ratio() {
+        assert!(in_ratio(TraceId::from(u128::from(u64::MAX)), 1.0));
+        assert!(!in_ratio(TraceId::from(u128::from(u64::MAX)), 0.5));
+        assert!(in_ratio(TraceId::from(1), 0.5));
+        assert!(!in_ratio(TraceId::from(1), 0.0));
+    }
+}
@@ -0,0 +1,76 @@
+use opentelemetry::metrics::Counter;
+use opentelemetry::KeyValue;
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::Layer;
+
+// An unreachable backend fails every export, so each kind of error is only let through this often
+const REPORT_INTERVAL: Duration = Duration::from_secs(10);
+
+// Created on first use, so from the meter provider `Telemetry::init` installs before the subscriber
+static ERRORS: OnceLock<Counter<u64>> = OnceLock::new();
+
+fn errors() -> &'static Counter<u64> {
+    ERRORS.get_or_init(|| {
+        opentelemetry::global::meter(env!("CARGO_PKG_NAME"))
+            .u64_counter("otel.sdk.errors")
+            .with_description("Warnings and errors reported by the OpenTelemetry SDK and exporters")
+            .build()
+    })
+}
+
+/// Layer counting the warnings and errors the OpenTelemetry SDK and exporters report through
+/// `tracing`, such as failed exports or full queues, in the `otel.sdk.errors` metric, and letting
+/// each kind through to the other layers at most once every 10 seconds.
+///
+/// OpenTelemetry no longer has a global error handler, these events are what replaced it.
+pub(crate) struct SdkErrorLayer {
+    // When each kind of event, by its name, was last let through
+    reported: Mutex<HashMap<&'static str, Instant>>,
+}
+
+impl SdkErrorLayer {
+    pub(crate) fn new() -> Self {
+        Self {
+            reported: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl<S: Subscriber> Layer<S> for SdkErrorLayer {
+    fn event_enabled(&self, event: &Event<'_>, _ctx: Context<'_, S>) -> bool {
+        let metadata = event.metadata();
+        if !is_sdk_target(metadata.target()) || *metadata.level() > Level::WARN {
+            return true;
+        }
+
+        // The SDK names its events after what failed, e.g. `BatchSpanProcessor.ExportError`
+        let name = metadata.name();
+        errors().add(
+            1,
+            &[
+                KeyValue::new("name", name),
+                KeyValue::new("level", metadata.level().as_str()),
+            ],
+        );
+
+        let now = Instant::now();
+        let mut reported = self.reported.lock().unwrap();
+        match reported.get(name) {
+            Some(last) if now.duration_since(*last) < REPORT_INTERVAL => false,
+            _ => {
+                reported.insert(name, now);
+                true
+            }
+        }
+    }
+}
+
+/// Whether `target` is one of the OpenTelemetry crates', e.g. `opentelemetry_sdk` or
+/// `opentelemetry-otlp`, which log under their package names.
+pub(crate) fn is_sdk_target(target: &str) -> bool {
+    target.starts_with("opentelemetry")
+}
@@ -0,0 +1,216 @@
+use opentelemetry::metrics::{Counter, Histogram, ObservableGauge};
+use opentelemetry::trace::Span as _;
+use opentelemetry::{Context, KeyValue};
+use opentelemetry_sdk::error::OTelSdkResult;
+use opentelemetry_sdk::trace::{Span, SpanData, SpanExporter, SpanProcessor};
+use opentelemetry_sdk::Resource;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+// Spans handed to the batch processors, and the ones their exporters accepted or failed to export,
+// for the queue depth and for the shutdown to report how many were still pending
+static QUEUED: AtomicU64 = AtomicU64::new(0);
+static EXPORTED: AtomicU64 = AtomicU64::new(0);
+static FAILED: AtomicU64 = AtomicU64::new(0);
+// Sampled spans started and ended, for how many are active
+static STARTED: AtomicU64 = AtomicU64::new(0);
+static ENDED: AtomicU64 = AtomicU64::new(0);
+
+// Created on first use, so from the meter provider `Telemetry::init` installs before the tracer
+static INSTRUMENTS: OnceLock<Instruments> = OnceLock::new();
+
+struct Instruments {
+    started: Counter<u64>,
+    queued: Counter<u64>,
+    exported: Counter<u64>,
+    failed: Counter<u64>,
+    export_duration: Histogram<f64>,
+    attributes_limited: Counter<u64>,
+    _queue_depth: ObservableGauge<u64>,
+}
+
+fn instruments() -> &'static Instruments {
+    INSTRUMENTS.get_or_init(|| {
+        let meter = opentelemetry::global::meter(env!("CARGO_PKG_NAME"));
+        Instruments {
+            started: meter
+                .u64_counter("otel.spans.started")
+                .with_description("Sampled spans started")
+                .build(),
+            queued: meter
+                .u64_counter("otel.processor.spans.queued")
+                .with_description("Sampled spans handed to the batch processors once ended")
+                .build(),
+            exported: meter
+                .u64_counter("otel.exporter.spans.exported")
+                .with_description("Spans the exporters accepted, including ones spilled to disk")
+                .build(),
+            failed: meter
+                .u64_counter("otel.exporter.spans.failed")
+                .with_description("Spans lost to failed exports")
+                .build(),
+            export_duration: meter
+                .f64_histogram("otel.exporter.duration")
+                .with_unit("s")
+                .with_description("Duration of span exports, including retries")
+                .build(),
+            attributes_limited: meter
+                .u64_counter("otel.processor.attributes.limited")
+                .with_description(
+                    "Span attributes dropped, cut or hashed to stay within the limits",
+                )
+                .build(),
+            _queue_depth: meter
+                .u64_observable_gauge("otel.processor.queue.depth")
+                .with_description("Spans waiting in the batch processors or being exported")
+                .with_callback(|gauge| gauge.observe(pending(), &[]))
+                .build(),
+        }
+    })
+}
+
+/// Spans handed to the batch processors and neither exported nor failed yet.
+pub(crate) fn pending() -> u64 {
+    QUEUED
+        .load(Ordering::Relaxed)
+        .saturating_sub(EXPORTED.load(Ordering::Relaxed) + FAILED.load(Ordering::Relaxed))
+}
+
+/// Spans the exporters accepted so far.
+pub(crate) fn exported() -> u64 {
+    EXPORTED.load(Ordering::Relaxed)
+}
+
+/// Spans lost to failed exports so far.
+pub(crate) fn failed() -> u64 {
+    FAILED.load(Ordering::Relaxed)
+}
+
+/// Counts `count` span attributes dropped, cut or hashed, as the `reason` is, by the attribute limits.
+pub(crate) fn attributes_limited(reason: &'static str, count: u64) {
+    if count > 0 {
+        instruments()
+            .attributes_limited
+            .add(count, &[KeyValue::new("reason", reason)]);
+    }
+}
+
+/// Sampled spans started and not ended yet.
+pub(crate) fn active() -> u64 {
+    STARTED
+        .load(Ordering::Relaxed)
+        .saturating_sub(ENDED.load(Ordering::Relaxed))
+}
+
+/// Counts the sampled spans started and ended, once whatever the number of exporters.
+#[derive(Debug)]
+pub(crate) struct StartCountingProcessor;
+
+impl SpanProcessor for StartCountingProcessor {
+    fn on_start(&self, span: &mut Span, _cx: &Context) {
+        if span.span_context().is_sampled() {
+            STARTED.fetch_add(1, Ordering::Relaxed);
+            instruments().started.add(1, &[]);
+        }
+    }
+
+    fn on_end(&self, span: SpanData) {
+        if span.span_context.is_sampled() {
+            ENDED.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    fn force_flush(&self) -> OTelSdkResult {
+        Ok(())
+    }
+
+    fn shutdown_with_timeout(&self, _timeout: Duration) -> OTelSdkResult {
+        Ok(())
+    }
+}
+
+/// Counts the spans reaching the batch processor it wraps.
+#[derive(Debug)]
+pub(crate) struct CountingProcessor<P> {
+    inner: P,
+}
+
+impl<P: SpanProcessor> CountingProcessor<P> {
+    pub(crate) fn new(inner: P) -> Self {
+        Self { inner }
+    }
+}
+
+impl<P: SpanProcessor> SpanProcessor for CountingProcessor<P> {
+    fn on_start(&self, span: &mut Span, cx: &Context) {
+        self.inner.on_start(span, cx);
+    }
+
+    fn on_end(&self, span: SpanData) {
+        if span.span_context.is_sampled() {
+            QUEUED.fetch_add(1, Ordering::Relaxed);
+            instruments().queued.add(1, &[]);
+        }
+        self.inner.on_end(span);
+    }
+
+    fn force_flush(&self) -> OTelSdkResult {
+        self.inner.force_flush()
+    }
+
+    fn shutdown_with_timeout(&self, timeout: Duration) -> OTelSdkResult {
+        self.inner.shutdown_with_timeout(timeout)
+    }
+
+    fn set_resource(&mut self, resource: &Resource) {
+        self.inner.set_resource(resource);
+    }
+}
+
+/// Counts and times the spans exported by the exporter it wraps.
+#[derive(Debug)]
+pub(crate) struct CountingExporter<E> {
+    inner: E,
+}
+
+impl<E: SpanExporter> CountingExporter<E> {
+    pub(crate) fn new(inner: E) -> Self {
+        Self { inner }
+    }
+}
+
+impl<E: SpanExporter> SpanExporter for CountingExporter<E> {
+    async fn export(&self, batch: Vec<SpanData>) -> OTelSdkResult {
+        let spans = batch.len() as u64;
+        let start = Instant::now();
+        let result = self.inner.export(batch).await;
+        let instruments = instruments();
+        let outcome = if result.is_ok() {
+            EXPORTED.fetch_add(spans, Ordering::Relaxed);
+            instruments.exported.add(spans, &[]);
+            "success"
+        } else {
+            FAILED.fetch_add(spans, Ordering::Relaxed);
+            instruments.failed.add(spans, &[]);
+            "failure"
+        };
+        instruments.export_duration.record(
+            start.elapsed().as_secs_f64(),
+            &[KeyValue::new("outcome", outcome)],
+        );
+        result
+    }
+
+    fn shutdown_with_timeout(&self, timeout: Duration) -> OTelSdkResult {
+        self.inner.shutdown_with_timeout(timeout)
+    }
+
+    fn force_flush(&self) -> OTelSdkResult {
+        self.inner.force_flush()
+    }
+
+    fn set_resource(&mut self, resource: &Resource) {
+        self.inner.set_resource(resource);
+    }
+}
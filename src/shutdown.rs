@@ -0,0 +1,351 @@
+use axum::http::Request;
+use opentelemetry::KeyValue;
+use opentelemetry_sdk::error::{OTelSdkError, OTelSdkResult};
+use opentelemetry_sdk::trace::SdkTracerProvider;
+use std::future::{Future, IntoFuture};
+use std::pin::pin;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+use tower::{Layer, Service};
+use tracing::field::Empty;
+use tracing::Instrument;
+
+// Set by `Telemetry::init` for `shutdown_signal` and `ShutdownCoordinator` to pick up
+static TIMEOUT: OnceLock<Duration> = OnceLock::new();
+static DRAIN_TIMEOUT: OnceLock<Duration> = OnceLock::new();
+// The global provider can't be shut down through `opentelemetry::global`, so a handle is kept here
+static TRACER_PROVIDER: OnceLock<SdkTracerProvider> = OnceLock::new();
+
+pub(crate) const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+pub(crate) const DEFAULT_DRAIN_TIMEOUT: Duration = Duration::from_secs(20);
+
+pub(crate) fn set_timeout(timeout: Duration) {
+    let _ = TIMEOUT.set(timeout);
+}
+
+pub(crate) fn set_drain_timeout(timeout: Duration) {
+    let _ = DRAIN_TIMEOUT.set(timeout);
+}
+
+pub(crate) fn set_tracer_provider(provider: SdkTracerProvider) {
+    let _ = TRACER_PROVIDER.set(provider);
+}
+
+fn timeout() -> Duration {
+    *TIMEOUT.get().unwrap_or(&DEFAULT_TIMEOUT)
+}
+
+/// Exports the spans waiting in the tracer provider installed by [`crate::Telemetry::init`] without
+/// shutting it down, giving up after the [`crate::Telemetry::shutdown_timeout`].
+pub async fn force_flush_spans() -> OTelSdkResult {
+    let Some(provider) = TRACER_PROVIDER.get().cloned() else {
+        return Err(OTelSdkError::InternalFailure(
+            "no tracer provider is installed".to_string(),
+        ));
+    };
+    let timeout = timeout();
+    match run_until(Instant::now() + timeout, move || provider.force_flush()).await {
+        Some(result) => result,
+        None => Err(OTelSdkError::Timeout(timeout)),
+    }
+}
+
+/// Flushes and shuts down the global tracer, meter and logger providers, giving up once `timeout`
+/// has passed, e.g. because the collector is unreachable, so shutdown never outlasts the grace period
+/// it was given.
+///
+/// Logs how many of the spans pending when it started were flushed, and how many were dropped.
+pub async fn shutdown_providers(timeout: Duration) {
+    let deadline = Instant::now() + timeout;
+    let exported = crate::self_metrics::exported();
+    let pending = crate::self_metrics::pending();
+
+    let finished = match TRACER_PROVIDER.get().cloned() {
+        Some(provider) => run_until(deadline, move || provider.shutdown())
+            .await
+            .is_some(),
+        None => true,
+    };
+    let flushed = crate::self_metrics::exported() - exported;
+    let dropped = pending.saturating_sub(flushed);
+    if finished {
+        tracing::info!(flushed, dropped, "flushed pending spans");
+    } else {
+        tracing::warn!(
+            flushed,
+            dropped,
+            ?timeout,
+            "timed out flushing pending spans"
+        );
+    }
+
+    #[cfg(feature = "metrics")]
+    if run_until(deadline, crate::metrics::shutdown_meter_provider)
+        .await
+        .is_none()
+    {
+        tracing::warn!(?timeout, "timed out shutting down the meter provider");
+    }
+    #[cfg(feature = "logs")]
+    if run_until(deadline, crate::logs::shutdown_logger_provider)
+        .await
+        .is_none()
+    {
+        tracing::warn!(?timeout, "timed out shutting down the logger provider");
+    }
+}
+
+/// [`shutdown_providers`] with the timeout given to [`crate::Telemetry::shutdown_timeout`].
+pub(crate) async fn shutdown_configured_providers() {
+    shutdown_providers(timeout()).await;
+}
+
+// The providers' flushes and shutdowns block, so they run on their own thread, which is left behind
+// if it doesn't finish in time. Unlike `spawn_blocking`, that doesn't keep the runtime from exiting.
+async fn run_until<T: Send + 'static>(
+    deadline: Instant,
+    f: impl FnOnce() -> T + Send + 'static,
+) -> Option<T> {
+    let (done, finished) = tokio::sync::oneshot::channel();
+    std::thread::spawn(move || {
+        let _ = done.send(f());
+    });
+    let remaining = deadline.saturating_duration_since(Instant::now());
+    tokio::time::timeout(remaining, finished).await.ok()?.ok()
+}
+
+/// Resolves on Ctrl+C or SIGTERM.
+pub(crate) async fn wait_for_signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("failed to install Ctrl+C handler");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("failed to install signal handler")
+            .recv()
+            .await;
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+}
+
+#[derive(Debug)]
+struct DrainState {
+    in_flight: AtomicU64,
+    drained: AtomicU64,
+    // Set once the drain deadline has passed, after which finishing requests are no longer counted
+    closed: AtomicBool,
+    shutting_down: watch::Sender<bool>,
+}
+
+/// Shuts a server down in order: on Ctrl+C or SIGTERM it stops accepting connections, waits for the
+/// requests in flight for at most the [`crate::Telemetry::drain_timeout`], and only then flushes the
+/// telemetry, so the spans of the requests that finished draining are exported too.
+///
+/// The drain gets a `graceful shutdown` span recording the requests in flight when it started as
+/// `http.server.in_flight`, and how many finished or were still running at the deadline as
+/// `shutdown.drained` and `shutdown.aborted`, which are also counted in the
+/// `http.server.shutdown.requests` metric by `outcome`:
+///
+/// ```ignore
+/// let shutdown = ShutdownCoordinator::new();
+/// let app = telemetry.instrument(router).layer(shutdown.layer());
+/// let server = axum::serve(listener, app).with_graceful_shutdown(shutdown.signal());
+/// shutdown.drain(server).await?;
+/// ```
+#[derive(Clone, Debug)]
+pub struct ShutdownCoordinator {
+    state: Arc<DrainState>,
+    drain_timeout: Duration,
+}
+
+impl Default for ShutdownCoordinator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ShutdownCoordinator {
+    pub fn new() -> Self {
+        Self {
+            state: Arc::new(DrainState {
+                in_flight: AtomicU64::new(0),
+                drained: AtomicU64::new(0),
+                closed: AtomicBool::new(false),
+                shutting_down: watch::Sender::new(false),
+            }),
+            drain_timeout: *DRAIN_TIMEOUT.get().unwrap_or(&DEFAULT_DRAIN_TIMEOUT),
+        }
+    }
+
+    /// Layer keeping count of the requests in flight, which must wrap every route of the server.
+    pub fn layer(&self) -> DrainLayer {
+        DrainLayer {
+            state: self.state.clone(),
+        }
+    }
+
+    /// Resolves on Ctrl+C or SIGTERM, starting the shutdown, or once [`trigger`](Self::trigger)ed,
+    /// for the server's `with_graceful_shutdown` to stop accepting connections.
+    pub async fn signal(self) {
+        let mut shutting_down = self.state.shutting_down.subscribe();
+        tokio::select! {
+            () = wait_for_signal() => self.trigger(),
+            _ = shutting_down.wait_for(|shutting_down| *shutting_down) => {}
+        }
+    }
+
+    /// Starts the shutdown without a signal, e.g. from a Kubernetes `preStop` endpoint.
+    pub fn trigger(&self) {
+        if !self.state.shutting_down.send_replace(true) {
+            tracing::warn!("starting graceful shutdown");
+        }
+    }
+
+    /// Runs `server` until it stops, or until the drain timeout after the shutdown started, then
+    /// flushes and shuts down the telemetry providers as [`shutdown_providers`] does, waiting at most
+    /// the [`crate::Telemetry::shutdown_timeout`].
+    ///
+    /// Requests still running at the deadline are left behind, to be cut off as the runtime exits.
+    pub async fn drain<F>(&self, server: F) -> std::io::Result<()>
+    where
+        F: IntoFuture<Output = std::io::Result<()>>,
+    {
+        let mut server = pin!(server.into_future());
+        let mut shutting_down = self.state.shutting_down.subscribe();
+        let result = tokio::select! {
+            result = &mut server => result,
+            _ = shutting_down.wait_for(|shutting_down| *shutting_down) => {
+                self.wait_for_drain(server).await
+            }
+        };
+        shutdown_configured_providers().await;
+        result
+    }
+
+    async fn wait_for_drain<F>(&self, server: F) -> std::io::Result<()>
+    where
+        F: Future<Output = std::io::Result<()>>,
+    {
+        let span = tracing::info_span!(
+            "graceful shutdown",
+            http.server.in_flight = self.state.in_flight.load(Ordering::Relaxed) as i64,
+            shutdown.drained = Empty,
+            shutdown.aborted = Empty,
+            otel.status_code = Empty,
+        );
+        let result = tokio::time::timeout(self.drain_timeout, server)
+            .instrument(span.clone())
+            .await;
+        self.state.closed.store(true, Ordering::Relaxed);
+
+        let drained = self.state.drained.load(Ordering::Relaxed);
+        let aborted = self.state.in_flight.load(Ordering::Relaxed);
+        // As signed integers, which `tracing_opentelemetry` records as integer attributes
+        span.record("shutdown.drained", drained as i64);
+        span.record("shutdown.aborted", aborted as i64);
+        let requests = opentelemetry::global::meter(env!("CARGO_PKG_NAME"))
+            .u64_counter("http.server.shutdown.requests")
+            .with_description("HTTP server requests drained or aborted by the graceful shutdown")
+            .build();
+        requests.add(drained, &[KeyValue::new("outcome", "drained")]);
+        requests.add(aborted, &[KeyValue::new("outcome", "aborted")]);
+        if aborted > 0 {
+            span.record("otel.status_code", "ERROR");
+            tracing::warn!(
+                parent: &span,
+                drained,
+                aborted,
+                timeout = ?self.drain_timeout,
+                "requests still in flight at the drain deadline"
+            );
+        } else {
+            tracing::info!(parent: &span, drained, "drained the requests in flight");
+        }
+        // Ended before the flush, to be exported with it
+        drop(span);
+        result.unwrap_or(Ok(()))
+    }
+}
+
+/// Layer created by [`ShutdownCoordinator::layer`].
+#[derive(Clone, Debug)]
+pub struct DrainLayer {
+    state: Arc<DrainState>,
+}
+
+impl<S> Layer<S> for DrainLayer {
+    type Service = Drain<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Drain {
+            inner,
+            state: self.state.clone(),
+        }
+    }
+}
+
+/// Service created by [`DrainLayer`].
+#[derive(Clone, Debug)]
+pub struct Drain<S> {
+    inner: S,
+    state: Arc<DrainState>,
+}
+
+// Counts a request out when its handler finishes or is dropped
+struct InFlight {
+    state: Arc<DrainState>,
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        if self.state.closed.load(Ordering::Relaxed) {
+            return;
+        }
+        self.state.in_flight.fetch_sub(1, Ordering::Relaxed);
+        if *self.state.shutting_down.borrow() {
+            self.state.drained.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+impl<S, B> Service<Request<B>> for Drain<S>
+where
+    S: Service<Request<B>>,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future =
+        std::pin::Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<B>) -> Self::Future {
+        self.state.in_flight.fetch_add(1, Ordering::Relaxed);
+        let in_flight = InFlight {
+            state: self.state.clone(),
+        };
+        let future = self.inner.call(request);
+        Box::pin(async move {
+            let result = future.await;
+            drop(in_flight);
+            result
+        })
+    }
+}
@@ -0,0 +1,66 @@
+use tokio::runtime::Handle;
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::task::JoinHandle;
+
+/// Spawns a task handling the signals that inspect a running service without stopping it:
+///
+/// - `SIGUSR1` logs the telemetry pipeline's state: the spans active, waiting to be exported,
+///   exported and lost to failed exports so far.
+/// - `SIGUSR2` exports the spans waiting in the tracer provider straight away, as
+///   [`crate::force_flush_spans`] does.
+/// - `SIGQUIT` logs the state of the tokio runtime, its workers, alive tasks and global queue depth,
+///   rather than quitting, as a JVM's thread dump does.
+///
+/// `SIGTERM` and Ctrl+C are left to [`crate::shutdown_signal`] or a [`crate::ShutdownCoordinator`].
+/// Nothing is handled when called outside a tokio runtime. Call this after
+/// [`crate::Telemetry::init`], or have it call this by setting
+/// [`crate::Telemetry::signal_handlers`].
+pub fn spawn_signal_handlers() -> Option<JoinHandle<()>> {
+    let Ok(handle) = Handle::try_current() else {
+        tracing::warn!("signals aren't handled outside a tokio runtime");
+        return None;
+    };
+
+    Some(handle.spawn(async move {
+        let (Ok(mut stats), Ok(mut flush), Ok(mut dump)) = (
+            signal(SignalKind::user_defined1()),
+            signal(SignalKind::user_defined2()),
+            signal(SignalKind::quit()),
+        ) else {
+            tracing::warn!("failed to install the signal handlers");
+            return;
+        };
+        loop {
+            tokio::select! {
+                _ = stats.recv() => log_telemetry_stats(),
+                _ = flush.recv() => match crate::force_flush_spans().await {
+                    Ok(()) => tracing::info!("flushed pending spans on SIGUSR2"),
+                    Err(err) => {
+                        tracing::warn!(error = %err, "failed to flush pending spans on SIGUSR2")
+                    }
+                },
+                _ = dump.recv() => log_runtime_dump(),
+            }
+        }
+    }))
+}
+
+fn log_telemetry_stats() {
+    tracing::info!(
+        active_spans = crate::self_metrics::active(),
+        queue_depth = crate::self_metrics::pending(),
+        exported_spans = crate::self_metrics::exported(),
+        dropped_spans = crate::self_metrics::failed(),
+        "telemetry stats"
+    );
+}
+
+fn log_runtime_dump() {
+    let metrics = Handle::current().metrics();
+    tracing::info!(
+        workers = metrics.num_workers(),
+        alive_tasks = metrics.num_alive_tasks(),
+        global_queue_depth = metrics.global_queue_depth(),
+        "runtime dump"
+    );
+}
@@ -0,0 +1,219 @@
+use crate::client_ip::ClientIp;
+use axum::extract::{ConnectInfo, MatchedPath};
+use axum::http::header::USER_AGENT;
+use axum::http::{Request, Response, Version};
+#[cfg(any(feature = "fmt", feature = "logs"))]
+use opentelemetry::trace::SpanContext;
+use opentelemetry::trace::TraceContextExt;
+use opentelemetry::Context;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tower_http::classify::ServerErrorsFailureClass;
+use tower_http::request_id::RequestId;
+use tower_http::trace::{MakeSpan, OnFailure, OnRequest, OnResponse};
+use tracing::field::Empty;
+use tracing::{Dispatch, Span};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Registry;
+
+/// [`MakeSpan`] recording the request using the OpenTelemetry HTTP semantic conventions.
+///
+/// Spans are named after the matched route template, e.g. `GET /users/{id}`, so the name stays low
+/// cardinality; requests that match no route are named after the method alone.
+///
+/// The request ID set by tower-http's `SetRequestIdLayer`, as
+/// [`crate::TelemetryHandle::instrument`] does, is recorded as `request.id`, so logs carrying the
+/// request ID can be matched to the trace. The HTTP version is recorded as
+/// `network.protocol.version`, `2` for the HTTP/2 requests `axum::serve` accepts over TLS or in
+/// cleartext with prior knowledge (h2c), to tell their latency from that of HTTP/1.1 requests
+/// queued behind one another on a connection.
+///
+/// The client and connection attributes are left to [`OtelOnRequest`], for sampled spans only.
+/// `net.peer.ip` is only known when the server is started with
+/// `into_make_service_with_connect_info::<SocketAddr>()`, and `client.address`, the client behind
+/// any trusted proxies, when [`crate::ClientIpLayer`] runs before too. Requests served over a
+/// `TlsListener`, with the `tls` feature, also get the negotiated TLS version, cipher and ALPN
+/// protocol, and those served over a Unix socket with `UdsMakeService` the credentials of the peer
+/// process.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct OtelMakeSpan;
+
+impl<B> MakeSpan<B> for OtelMakeSpan {
+    fn make_span(&mut self, request: &Request<B>) -> Span {
+        let route = request
+            .extensions()
+            .get::<MatchedPath>()
+            .map(MatchedPath::as_str);
+        let target = request
+            .uri()
+            .path_and_query()
+            .map_or(request.uri().path(), |path_and_query| {
+                path_and_query.as_str()
+            });
+        let request_id = request
+            .extensions()
+            .get::<RequestId>()
+            .and_then(|id| id.header_value().to_str().ok());
+        let protocol_version = match request.version() {
+            Version::HTTP_09 => "0.9",
+            Version::HTTP_10 => "1.0",
+            Version::HTTP_2 => "2",
+            Version::HTTP_3 => "3",
+            _ => "1.1",
+        };
+
+        let name = match route {
+            Some(route) => format!("{} {route}", request.method()),
+            None => request.method().to_string(),
+        };
+
+        tracing::info_span!(
+            "HTTP request",
+            otel.name = name,
+            otel.kind = "server",
+            http.method = %request.method(),
+            http.route = route,
+            http.target = target,
+            http.status_code = Empty,
+            otel.status_code = Empty,
+            user_agent.original = Empty,
+            request.id = request_id,
+            net.peer.ip = Empty,
+            client.address = Empty,
+            network.protocol.name = "http",
+            network.protocol.version = protocol_version,
+        )
+    }
+}
+
+/// [`OnRequest`] recording the attributes of the request that [`OtelMakeSpan`] leaves for once the
+/// span has started: the client and connection ones, which the sampler doesn't read.
+///
+/// They are only recorded when the span is sampled, or not known to OpenTelemetry at all, e.g. when
+/// only logged. A span the sampler dropped is rather marked for [`crate::SkipSampledOut`] to keep
+/// the spans below it from OpenTelemetry, so unsampled requests cost little more than their span.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct OtelOnRequest;
+
+impl<B> OnRequest<B> for OtelOnRequest {
+    fn on_request(&mut self, request: &Request<B>, span: &Span) {
+        let span_context = span.context().span().span_context().clone();
+        if span_context.is_valid() && !span_context.is_sampled() {
+            crate::sampling::mark_sampled_out(span);
+            return;
+        }
+
+        let extensions = request.extensions();
+        if let Some(user_agent) = request
+            .headers()
+            .get(USER_AGENT)
+            .and_then(|value| value.to_str().ok())
+        {
+            span.record("user_agent.original", user_agent);
+        }
+        if let Some(ConnectInfo(addr)) = extensions.get::<ConnectInfo<SocketAddr>>() {
+            span.record("net.peer.ip", tracing::field::display(addr.ip()));
+        }
+        if let Some(ClientIp(ip)) = extensions.get::<ClientIp>() {
+            span.record("client.address", tracing::field::display(ip));
+        }
+        #[cfg(unix)]
+        if let Some(peer) = extensions.get::<crate::uds::UnixPeer>() {
+            peer.record(span);
+        }
+        #[cfg(feature = "tls")]
+        if let Some(session) = extensions.get::<crate::tls::TlsSession>() {
+            session.record(span);
+        }
+    }
+}
+
+/// [`OnResponse`] filling in the `http.status_code` field declared by [`OtelMakeSpan`], and marking
+/// the span as an error for 5xx responses, or also for 4xx ones when built
+/// [`with_client_errors`](Self::with_client_errors).
+#[derive(Clone, Copy, Debug, Default)]
+pub struct OtelOnResponse {
+    client_errors: bool,
+}
+
+impl OtelOnResponse {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Also marks the span as an error when the response is a 4xx.
+    pub fn with_client_errors(mut self, client_errors: bool) -> Self {
+        self.client_errors = client_errors;
+        self
+    }
+}
+
+impl<B> OnResponse<B> for OtelOnResponse {
+    fn on_response(self, response: &Response<B>, _latency: Duration, span: &Span) {
+        let status = response.status();
+        // Widened so `tracing_opentelemetry` records an integer attribute rather than a string
+        span.record("http.status_code", i64::from(status.as_u16()));
+        if status.is_server_error() || (self.client_errors && status.is_client_error()) {
+            span.record("otel.status_code", "ERROR");
+        }
+    }
+}
+
+/// [`OnFailure`] marking the span as an error when the service or the response body fails, and
+/// recording the failure as an `exception` event.
+///
+/// 5xx responses are already marked by [`OtelOnResponse`].
+#[derive(Clone, Copy, Debug, Default)]
+pub struct OtelOnFailure;
+
+impl OnFailure<ServerErrorsFailureClass> for OtelOnFailure {
+    fn on_failure(&mut self, failure: ServerErrorsFailureClass, _latency: Duration, span: &Span) {
+        span.record("otel.status_code", "ERROR");
+        if let ServerErrorsFailureClass::Error(message) = failure {
+            tracing::error!(parent: span, exception.message = message, "exception");
+        }
+    }
+}
+
+/// IDs of a `tracing` span as seen by OpenTelemetry, or of its closest ancestor OpenTelemetry
+/// records when it doesn't record the span itself, starting its OpenTelemetry span if that hasn't
+/// happened yet. Must not be called while holding the span's extensions.
+#[cfg(any(feature = "fmt", feature = "logs"))]
+pub(crate) fn otel_span_context(id: &tracing::span::Id) -> Option<SpanContext> {
+    tracing::dispatcher::get_default(|dispatch| {
+        let cx = known_otel_context(id, dispatch)?;
+        let span_context = cx.span().span_context().clone();
+        span_context.is_valid().then_some(span_context)
+    })
+}
+
+/// The OpenTelemetry context of `span`, or of its closest ancestor OpenTelemetry records when it
+/// doesn't record the span itself, e.g. below a request [`crate::SkipSampledOut`] skipped, for the
+/// trace and its sampling decision to still be propagated from there.
+pub(crate) fn otel_context(span: &Span) -> Context {
+    span.id()
+        .and_then(|id| {
+            tracing::dispatcher::get_default(|dispatch| known_otel_context(&id, dispatch))
+        })
+        .unwrap_or_default()
+}
+
+fn known_otel_context(id: &tracing::span::Id, dispatch: &Dispatch) -> Option<Context> {
+    let known = |id: &tracing::span::Id| {
+        tracing_opentelemetry::get_otel_context(id, dispatch)
+            .filter(|cx| cx.span().span_context().is_valid())
+    };
+    if let Some(cx) = known(id) {
+        return Some(cx);
+    }
+    // Collected first, as starting the OpenTelemetry span takes its extensions
+    let ancestors: Vec<_> = dispatch
+        .downcast_ref::<Registry>()?
+        .span(id)?
+        .scope()
+        .skip(1)
+        .map(|span| span.id())
+        .collect();
+    ancestors.iter().find_map(known)
+}
@@ -0,0 +1,150 @@
+use opentelemetry::trace::Status;
+use opentelemetry::{Array, Key, StringValue, Value};
+use std::borrow::Cow;
+use std::time::SystemTime;
+use tracing::Span;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+/// The span of the code running now, inside a handler the request span.
+pub fn current_span() -> Span {
+    Span::current()
+}
+
+/// Shorthands for recording on a `tracing` span what OpenTelemetry exports with it, without
+/// declaring fields up front or building OpenTelemetry keys and values, e.g.
+/// `current_span().set_attr("cart.items", 3)`.
+///
+/// Nothing is recorded on disabled spans, such as those of requests to the health probes.
+pub trait SpanExt {
+    /// Sets the attribute `key`, replacing any value it already has.
+    fn set_attr(&self, key: impl Into<Key>, value: impl AttributeValue) -> &Self;
+
+    /// Adds an event named `name` with `attributes`, timestamped now.
+    fn add_event<K: Into<Key>, V: AttributeValue>(
+        &self,
+        name: impl Into<Cow<'static, str>>,
+        attributes: impl IntoIterator<Item = (K, V)>,
+    ) -> &Self;
+
+    /// Marks the span as an error with `error` as its message, and adds it as an `exception` event
+    /// with `exception.type` and `exception.message`.
+    fn record_error<E: std::error::Error + ?Sized>(&self, error: &E) -> &Self;
+}
+
+impl SpanExt for Span {
+    fn set_attr(&self, key: impl Into<Key>, value: impl AttributeValue) -> &Self {
+        self.set_attribute(key, value.into_value());
+        self
+    }
+
+    fn add_event<K: Into<Key>, V: AttributeValue>(
+        &self,
+        name: impl Into<Cow<'static, str>>,
+        attributes: impl IntoIterator<Item = (K, V)>,
+    ) -> &Self {
+        let attributes = attributes
+            .into_iter()
+            .map(|(key, value)| opentelemetry::KeyValue::new(key, value.into_value()))
+            .collect();
+        OpenTelemetrySpanExt::add_event_with_timestamp(self, name, SystemTime::now(), attributes);
+        self
+    }
+
+    fn record_error<E: std::error::Error + ?Sized>(&self, error: &E) -> &Self {
+        let message = error.to_string();
+        let attributes = vec![
+            opentelemetry::KeyValue::new("exception.type", std::any::type_name::<E>()),
+            opentelemetry::KeyValue::new("exception.message", message.clone()),
+        ];
+        OpenTelemetrySpanExt::add_event_with_timestamp(
+            self,
+            "exception",
+            SystemTime::now(),
+            attributes,
+        );
+        self.set_status(Status::error(message));
+        self
+    }
+}
+
+/// Values [`SpanExt`] records as attributes: numbers, booleans, strings and vectors of them.
+pub trait AttributeValue {
+    fn into_value(self) -> Value;
+}
+
+macro_rules! integer_attribute_values {
+    ($($integer:ty),*) => {
+        $(
+            impl AttributeValue for $integer {
+                fn into_value(self) -> Value {
+                    Value::I64(self as i64)
+                }
+            }
+        )*
+    };
+}
+
+// Unsigned values past `i64::MAX` wrap, as OpenTelemetry has no unsigned integers
+integer_attribute_values!(i8, i16, i32, i64, isize, u8, u16, u32, u64, usize);
+
+impl AttributeValue for f32 {
+    fn into_value(self) -> Value {
+        Value::F64(f64::from(self))
+    }
+}
+
+impl AttributeValue for f64 {
+    fn into_value(self) -> Value {
+        Value::F64(self)
+    }
+}
+
+impl AttributeValue for bool {
+    fn into_value(self) -> Value {
+        Value::Bool(self)
+    }
+}
+
+impl AttributeValue for &'static str {
+    fn into_value(self) -> Value {
+        Value::from(self)
+    }
+}
+
+impl AttributeValue for String {
+    fn into_value(self) -> Value {
+        Value::from(self)
+    }
+}
+
+impl AttributeValue for Vec<i64> {
+    fn into_value(self) -> Value {
+        Value::Array(Array::I64(self))
+    }
+}
+
+impl AttributeValue for Vec<f64> {
+    fn into_value(self) -> Value {
+        Value::Array(Array::F64(self))
+    }
+}
+
+impl AttributeValue for Vec<bool> {
+    fn into_value(self) -> Value {
+        Value::Array(Array::Bool(self))
+    }
+}
+
+impl AttributeValue for Vec<String> {
+    fn into_value(self) -> Value {
+        Value::Array(Array::String(
+            self.into_iter().map(StringValue::from).collect(),
+        ))
+    }
+}
+
+impl AttributeValue for Value {
+    fn into_value(self) -> Value {
+        self
+    }
+}
@@ -0,0 +1,595 @@
+use crate::cardinality::fnv1a;
+use opentelemetry::trace::{
+    Event, Link, SpanContext, SpanId, SpanKind, Status, TraceFlags, TraceId, TraceState,
+};
+use opentelemetry::{Array, InstrumentationScope, KeyValue, Value};
+use opentelemetry_sdk::error::{OTelSdkError, OTelSdkResult};
+use opentelemetry_sdk::trace::{SpanData, SpanEvents, SpanExporter, SpanLinks};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::Mutex;
+
+// Replaying everything at once could hold up the batch processor long enough for its queue to
+// overflow, so each successful export replays at most this many spilled batches
+const REPLAYED_PER_EXPORT: usize = 8;
+
+/// Where [`crate::RetryingExporter`] keeps the batches it couldn't export while the backend is
+/// unreachable, until they can be replayed.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SpillConfig {
+    /// Directory holding one JSON file per batch, created when missing. Batches left over from a
+    /// previous run are replayed too.
+    ///
+    /// [`crate::Telemetry::init`] gives each exporter a subdirectory of its own, named after the
+    /// service, the exporter's position and its endpoint, so batches are only ever replayed to the
+    /// backend they were meant for. The default, in the temporary directory, is also specific to
+    /// the process, so services on the same host never replay each other's batches; set one to
+    /// replay them after a restart.
+    pub directory: PathBuf,
+    /// Batches that would take the directory over this size are dropped instead of spilled.
+    pub max_bytes: u64,
+}
+
+impl Default for SpillConfig {
+    fn default() -> Self {
+        Self {
+            directory: std::env::temp_dir().join(format!(
+                "{}-spans-{}",
+                env!("CARGO_PKG_NAME"),
+                std::process::id()
+            )),
+            max_bytes: 64 * 1024 * 1024,
+        }
+    }
+}
+
+impl SpillConfig {
+    /// The configuration of the exporter at `index` among those of `service_name`, sending to
+    /// `endpoint`, spilling to a subdirectory of [`directory`](Self::directory) of its own.
+    pub(crate) fn for_exporter(&self, service_name: &str, index: usize, endpoint: &str) -> Self {
+        let service: String = service_name
+            .chars()
+            .map(|c| match c {
+                'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_' | '.' => c,
+                _ => '_',
+            })
+            .collect();
+        let name = format!("{service}-{index}-{:016x}", fnv1a(endpoint.as_bytes()));
+        Self {
+            directory: self.directory.join(name),
+            max_bytes: self.max_bytes,
+        }
+    }
+}
+
+/// Bounded on-disk queue of span batches, oldest first.
+#[derive(Debug)]
+pub(crate) struct SpillQueue {
+    config: SpillConfig,
+    // Keeps file names unique when two batches are spilled in the same nanosecond
+    sequence: AtomicU64,
+    // Bytes of the spilled batches, scanned once and then kept up to date, rather than summed on
+    // every push
+    size: AtomicU64,
+    // Held while replaying so concurrent exports don't replay the same file twice
+    replaying: Mutex<()>,
+}
+
+impl SpillQueue {
+    pub(crate) fn new(config: SpillConfig) -> Self {
+        let size = left_over_size(&config.directory);
+        Self {
+            config,
+            sequence: AtomicU64::new(0),
+            size: AtomicU64::new(size),
+            replaying: Mutex::new(()),
+        }
+    }
+
+    /// Writes `batch` to disk, failing when the directory is full.
+    pub(crate) async fn push(&self, batch: &[SpanData]) -> OTelSdkResult {
+        let stored: Vec<StoredSpan> = batch.iter().map(StoredSpan::from).collect();
+        let json = serde_json::to_vec(&stored)
+            .map_err(|err| OTelSdkError::InternalFailure(err.to_string()))?;
+
+        // Reserved up front, so concurrent pushes can't take the directory over its size together
+        let len = json.len() as u64;
+        let max_bytes = self.config.max_bytes;
+        self.size
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |size| {
+                (size + len <= max_bytes).then_some(size + len)
+            })
+            .map_err(|_| {
+                OTelSdkError::InternalFailure("span spill directory is full".to_string())
+            })?;
+
+        let written = self.write(json).await;
+        if written.is_err() {
+            self.size.fetch_sub(len, Ordering::Relaxed);
+        }
+        written
+    }
+
+    async fn write(&self, json: Vec<u8>) -> OTelSdkResult {
+        tokio::fs::create_dir_all(&self.config.directory)
+            .await
+            .map_err(io_error)?;
+        // Written under a temporary name and renamed, so replays never read a partial batch
+        let name = format!(
+            "{:020}-{:06}",
+            nanos(SystemTime::now()),
+            self.sequence.fetch_add(1, Ordering::Relaxed)
+        );
+        let temporary = self.config.directory.join(format!("{name}.tmp"));
+        tokio::fs::write(&temporary, json).await.map_err(io_error)?;
+        tokio::fs::rename(
+            &temporary,
+            self.config.directory.join(format!("{name}.json")),
+        )
+        .await
+        .map_err(io_error)
+    }
+
+    async fn remove(&self, path: &Path, len: usize) -> OTelSdkResult {
+        tokio::fs::remove_file(path).await.map_err(io_error)?;
+        self.size.fetch_sub(len as u64, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Exports the oldest spilled batches through `exporter`, deleting each once it is accepted and
+    /// stopping at the first failure so the rest wait for the next attempt.
+    pub(crate) async fn replay<E: SpanExporter>(&self, exporter: &E) -> OTelSdkResult {
+        let Ok(_replaying) = self.replaying.try_lock() else {
+            return Ok(());
+        };
+
+        for path in self.files().await?.into_iter().take(REPLAYED_PER_EXPORT) {
+            let json = tokio::fs::read(&path).await.map_err(io_error)?;
+            // A batch that can't be read back would fail the same way forever
+            let Ok(stored) = serde_json::from_slice::<Vec<StoredSpan>>(&json) else {
+                tracing::warn!(path = %path.display(), "discarding unreadable spilled spans");
+                self.remove(&path, json.len()).await?;
+                continue;
+            };
+
+            let batch = stored.into_iter().map(SpanData::from).collect();
+            exporter.export(batch).await?;
+            self.remove(&path, json.len()).await?;
+        }
+        Ok(())
+    }
+
+    // Spilled batches sorted oldest first, as their names start with the time they were written
+    async fn files(&self) -> Result<Vec<PathBuf>, OTelSdkError> {
+        let mut files = Vec::new();
+        let mut entries = match tokio::fs::read_dir(&self.config.directory).await {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(files),
+            Err(err) => return Err(io_error(err)),
+        };
+        while let Some(entry) = entries.next_entry().await.map_err(io_error)? {
+            let path = entry.path();
+            if path
+                .extension()
+                .is_some_and(|extension| extension == "json")
+            {
+                files.push(path);
+            }
+        }
+        files.sort();
+        Ok(files)
+    }
+}
+
+// Bytes of the batches a previous run left in `directory`, counted against the limit until replayed
+fn left_over_size(directory: &Path) -> u64 {
+    let Ok(entries) = std::fs::read_dir(directory) else {
+        return 0;
+    };
+    entries
+        .filter_map(Result::ok)
+        .filter(|entry| {
+            entry
+                .path()
+                .extension()
+                .is_some_and(|extension| extension == "json")
+        })
+        .filter_map(|entry| entry.metadata().ok())
+        .map(|metadata| metadata.len())
+        .sum()
+}
+
+fn io_error(err: std::io::Error) -> OTelSdkError {
+    OTelSdkError::InternalFailure(err.to_string())
+}
+
+fn nanos(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos() as u64
+}
+
+fn from_nanos(nanos: u64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_nanos(nanos)
+}
+
+// `SpanData` isn't serializable, so spilled spans are stored as these and rebuilt on replay. Dropped
+// attribute, event and link counts aren't kept, and the resource is the exporter's own.
+#[derive(Serialize, Deserialize)]
+struct StoredSpan {
+    context: StoredContext,
+    parent_span_id: String,
+    #[serde(default)]
+    parent_span_is_remote: bool,
+    kind: StoredKind,
+    name: String,
+    start_time: u64,
+    end_time: u64,
+    attributes: Vec<StoredKeyValue>,
+    events: Vec<StoredEvent>,
+    links: Vec<StoredLink>,
+    status: StoredStatus,
+    scope_name: String,
+    scope_version: Option<String>,
+    scope_schema_url: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct StoredContext {
+    trace_id: String,
+    span_id: String,
+    flags: u8,
+    remote: bool,
+    state: String,
+}
+
+#[derive(Serialize, Deserialize)]
+enum StoredKind {
+    Client,
+    Server,
+    Producer,
+    Consumer,
+    Internal,
+}
+
+#[derive(Serialize, Deserialize)]
+struct StoredEvent {
+    name: String,
+    timestamp: u64,
+    attributes: Vec<StoredKeyValue>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct StoredLink {
+    context: StoredContext,
+    attributes: Vec<StoredKeyValue>,
+}
+
+#[derive(Serialize, Deserialize)]
+enum StoredStatus {
+    Unset,
+    Ok,
+    Error(String),
+}
+
+#[derive(Serialize, Deserialize)]
+struct StoredKeyValue {
+    key: String,
+    value: StoredValue,
+}
+
+#[derive(Serialize, Deserialize)]
+enum StoredValue {
+    Bool(bool),
+    I64(i64),
+    F64(f64),
+    String(String),
+    BoolArray(Vec<bool>),
+    I64Array(Vec<i64>),
+    F64Array(Vec<f64>),
+    StringArray(Vec<String>),
+}
+
+impl From<&SpanData> for StoredSpan {
+    fn from(span: &SpanData) -> Self {
+        Self {
+            context: StoredContext::from(&span.span_context),
+            parent_span_id: span.parent_span_id.to_string(),
+            parent_span_is_remote: span.parent_span_is_remote,
+            kind: match span.span_kind {
+                SpanKind::Client => StoredKind::Client,
+                SpanKind::Server => StoredKind::Server,
+                SpanKind::Producer => StoredKind::Producer,
+                SpanKind::Consumer => StoredKind::Consumer,
+                SpanKind::Internal => StoredKind::Internal,
+            },
+            name: span.name.to_string(),
+            start_time: nanos(span.start_time),
+            end_time: nanos(span.end_time),
+            attributes: span.attributes.iter().map(StoredKeyValue::from).collect(),
+            events: span
+                .events
+                .iter()
+                .map(|event| StoredEvent {
+                    name: event.name.to_string(),
+                    timestamp: nanos(event.timestamp),
+                    attributes: event.attributes.iter().map(StoredKeyValue::from).collect(),
+                })
+                .collect(),
+            links: span
+                .links
+                .iter()
+                .map(|link| StoredLink {
+                    context: StoredContext::from(&link.span_context),
+                    attributes: link.attributes.iter().map(StoredKeyValue::from).collect(),
+                })
+                .collect(),
+            status: match &span.status {
+                Status::Unset => StoredStatus::Unset,
+                Status::Ok => StoredStatus::Ok,
+                Status::Error { description } => StoredStatus::Error(description.to_string()),
+            },
+            scope_name: span.instrumentation_scope.name().to_string(),
+            scope_version: span.instrumentation_scope.version().map(str::to_string),
+            scope_schema_url: span.instrumentation_scope.schema_url().map(str::to_string),
+        }
+    }
+}
+
+impl From<StoredSpan> for SpanData {
+    fn from(span: StoredSpan) -> Self {
+        let mut events = SpanEvents::default();
+        events.events = span
+            .events
+            .into_iter()
+            .map(|event| {
+                Event::new(
+                    event.name,
+                    from_nanos(event.timestamp),
+                    event.attributes.into_iter().map(KeyValue::from).collect(),
+                    0,
+                )
+            })
+            .collect();
+
+        let mut links = SpanLinks::default();
+        links.links = span
+            .links
+            .into_iter()
+            .map(|link| {
+                Link::new(
+                    SpanContext::from(link.context),
+                    link.attributes.into_iter().map(KeyValue::from).collect(),
+                    0,
+                )
+            })
+            .collect();
+
+        let mut scope = InstrumentationScope::builder(span.scope_name);
+        if let Some(version) = span.scope_version {
+            scope = scope.with_version(version);
+        }
+        if let Some(schema_url) = span.scope_schema_url {
+            scope = scope.with_schema_url(schema_url);
+        }
+
+        SpanData {
+            span_context: SpanContext::from(span.context),
+            parent_span_id: SpanId::from_hex(&span.parent_span_id).unwrap_or(SpanId::INVALID),
+            parent_span_is_remote: span.parent_span_is_remote,
+            span_kind: match span.kind {
+                StoredKind::Client => SpanKind::Client,
+                StoredKind::Server => SpanKind::Server,
+                StoredKind::Producer => SpanKind::Producer,
+                StoredKind::Consumer => SpanKind::Consumer,
+                StoredKind::Internal => SpanKind::Internal,
+            },
+            name: Cow::Owned(span.name),
+            start_time: from_nanos(span.start_time),
+            end_time: from_nanos(span.end_time),
+            attributes: span.attributes.into_iter().map(KeyValue::from).collect(),
+            dropped_attributes_count: 0,
+            events,
+            links,
+            status: match span.status {
+                StoredStatus::Unset => Status::Unset,
+                StoredStatus::Ok => Status::Ok,
+                StoredStatus::Error(description) => Status::error(description),
+            },
+            instrumentation_scope: scope.build(),
+        }
+    }
+}
+
+impl From<&SpanContext> for StoredContext {
+    fn from(context: &SpanContext) -> Self {
+        Self {
+            trace_id: context.trace_id().to_string(),
+            span_id: context.span_id().to_string(),
+            flags: context.trace_flags().to_u8(),
+            remote: context.is_remote(),
+            state: context.trace_state().header(),
+        }
+    }
+}
+
+impl From<StoredContext> for SpanContext {
+    fn from(context: StoredContext) -> Self {
+        SpanContext::new(
+            TraceId::from_hex(&context.trace_id).unwrap_or(TraceId::INVALID),
+            SpanId::from_hex(&context.span_id).unwrap_or(SpanId::INVALID),
+            TraceFlags::new(context.flags),
+            context.remote,
+            context
+                .state
+                .parse()
+                .unwrap_or_else(|_| TraceState::default()),
+        )
+    }
+}
+
+impl From<&KeyValue> for StoredKeyValue {
+    fn from(attribute: &KeyValue) -> Self {
+        let value = match &attribute.value {
+            Value::Bool(value) => StoredValue::Bool(*value),
+            Value::I64(value) => StoredValue::I64(*value),
+            Value::F64(value) => StoredValue::F64(*value),
+            Value::String(value) => StoredValue::String(value.to_string()),
+            Value::Array(Array::Bool(values)) => StoredValue::BoolArray(values.clone()),
+            Value::Array(Array::I64(values)) => StoredValue::I64Array(values.clone()),
+            Value::Array(Array::F64(values)) => StoredValue::F64Array(values.clone()),
+            Value::Array(Array::String(values)) => {
+                StoredValue::StringArray(values.iter().map(|value| value.to_string()).collect())
+            }
+            // Kinds added to the SDK since are kept in their display form
+            value => StoredValue::String(value.to_string()),
+        };
+        Self {
+            key: attribute.key.to_string(),
+            value,
+        }
+    }
+}
+
+impl From<StoredKeyValue> for KeyValue {
+    fn from(attribute: StoredKeyValue) -> Self {
+        let value = match attribute.value {
+            StoredValue::Bool(value) => Value::Bool(value),
+            StoredValue::I64(value) => Value::I64(value),
+            StoredValue::F64(value) => Value::F64(value),
+            StoredValue::String(value) => Value::from(value),
+            StoredValue::BoolArray(values) => Value::Array(Array::Bool(values)),
+            StoredValue::I64Array(values) => Value::Array(Array::I64(values)),
+            StoredValue::F64Array(values) => Value::Array(Array::F64(values)),
+            StoredValue::StringArray(values) => {
+                Value::Array(Array::String(values.into_iter().map(Into::into).collect()))
+            }
+        };
+        KeyValue::new(attribute.key, value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opentelemetry::trace::{Span as _, Tracer as _, TracerProvider as _};
+    use opentelemetry_sdk::trace::SdkTracerProvider;
+    use std::sync::atomic::AtomicBool;
+    use std::sync::Arc;
+
+    #[test]
+    fn gives_each_exporter_its_own_directory() {
+        let spill = SpillConfig {
+            directory: PathBuf::from("/var/spill"),
+            max_bytes: 1024,
+        };
+        let honeycomb = spill.for_exporter("pick list", 0, "https://api.honeycomb.io/v1/traces");
+        let collector = spill.for_exporter("pick list", 1, "http://localhost:4318/v1/traces");
+        let other_service = spill.for_exporter("billing", 0, "https://api.honeycomb.io/v1/traces");
+
+        assert_eq!(
+            honeycomb.directory.parent(),
+            Some(spill.directory.as_path())
+        );
+        assert!(honeycomb
+            .directory
+            .file_name()
+            .unwrap()
+            .to_string_lossy()
+            .starts_with("pick_list-0-"));
+        assert_ne!(honeycomb.directory, collector.directory);
+        assert_ne!(honeycomb.directory, other_service.directory);
+        assert_eq!(honeycomb.max_bytes, 1024);
+    }
+
+    #[test]
+    fn defaults_to_a_directory_of_the_process() {
+        let directory = SpillConfig::default().directory;
+        let name = directory
+            .file_name()
+            .unwrap()
+            .to_string_lossy()
+            .into_owned();
+        assert!(name.ends_with(&format!("-{}", std::process::id())));
+    }
+    // Exporter keeping what it is given, or failing while `failing` is set
+    #[derive(Clone, Debug, Default)]
+    struct Recording {
+        spans: Arc<std::sync::Mutex<Vec<SpanData>>>,
+        failing: Arc<AtomicBool>,
+    }
+
+    impl SpanExporter for Recording {
+        async fn export(&self, batch: Vec<SpanData>) -> OTelSdkResult {
+            if self.failing.load(Ordering::Relaxed) {
+                return Err(OTelSdkError::InternalFailure("unavailable".to_string()));
+            }
+            self.spans.lock().unwrap().extend(batch);
+            Ok(())
+        }
+    }
+
+    fn spans(names: &[&'static str]) -> Vec<SpanData> {
+        let recording = Recording::default();
+        let provider = SdkTracerProvider::builder()
+            .with_simple_exporter(recording.clone())
+            .build();
+        let tracer = provider.tracer("test");
+        for name in names {
+            let mut span = tracer.start(*name);
+            span.set_attribute(KeyValue::new("http.status_code", 503));
+            span.add_event("retrying", vec![KeyValue::new("attempt", 2)]);
+            span.end();
+        }
+        let spans = recording.spans.lock().unwrap().clone();
+        spans
+    }
+
+    #[tokio::test]
+    async fn replays_spilled_batches_oldest_first_within_the_size_limit() {
+        let directory = std::env::temp_dir().join(format!(
+            "{}-spill-test-{}",
+            env!("CARGO_PKG_NAME"),
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&directory);
+        let config = SpillConfig {
+            directory: directory.clone(),
+            max_bytes: 4096,
+        };
+        let queue = SpillQueue::new(config.clone());
+
+        let first = spans(&["first"]);
+        queue.push(&first).await.unwrap();
+        queue.push(&spans(&["second", "third"])).await.unwrap();
+        let size = queue.size.load(Ordering::Relaxed);
+        assert!(size > 0);
+        // Counted again from the files by a queue starting over
+        assert_eq!(SpillQueue::new(config).size.load(Ordering::Relaxed), size);
+        let huge = spans(&["span"; 16]);
+        assert!(queue.push(&huge).await.is_err());
+
+        let exporter = Recording::default();
+        exporter.failing.store(true, Ordering::Relaxed);
+        assert!(queue.replay(&exporter).await.is_err());
+        assert_eq!(queue.size.load(Ordering::Relaxed), size);
+
+        exporter.failing.store(false, Ordering::Relaxed);
+        queue.replay(&exporter).await.unwrap();
+        let replayed = exporter.spans.lock().unwrap().clone();
+        let names: Vec<_> = replayed.iter().map(|span| span.name.as_ref()).collect();
+        assert_eq!(names, ["first", "second", "third"]);
+        let span = &replayed[0];
+        assert_eq!(span.span_context, first[0].span_context);
+        assert_eq!(span.start_time, first[0].start_time);
+        assert_eq!(span.attributes, first[0].attributes);
+        assert_eq!(span.events.events[0].name, "retrying");
+        assert_eq!(queue.size.load(Ordering::Relaxed), 0);
+        assert!(queue.files().await.unwrap().is_empty());
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
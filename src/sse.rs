@@ -0,0 +1,93 @@
+use crate::body::MeteredBody;
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::response::{IntoResponse, Response};
+use futures_util::Stream;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tracing::Span;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+/// Server-Sent Events response holding the request span open until the event stream ends or the
+/// client disconnects, then recording `sse.events_sent` and `sse.bytes_sent` on it, e.g.
+/// `traced_sse(events).keep_alive(KeepAlive::default())` as the handler's response.
+///
+/// Keep-alive comments count towards the bytes but not the events. Create it in the handler, while
+/// the request span is current.
+pub fn traced_sse<S, E>(stream: S) -> TracedSse<S>
+where
+    S: Stream<Item = Result<Event, E>> + Send + 'static,
+{
+    TracedSse {
+        span: Span::current(),
+        stream,
+        keep_alive: None,
+    }
+}
+
+/// Response returned by [`traced_sse`].
+#[derive(Debug)]
+pub struct TracedSse<S> {
+    span: Span,
+    stream: S,
+    keep_alive: Option<KeepAlive>,
+}
+
+impl<S> TracedSse<S> {
+    /// Sends keep-alive comments like [`Sse::keep_alive`].
+    pub fn keep_alive(mut self, keep_alive: KeepAlive) -> Self {
+        self.keep_alive = Some(keep_alive);
+        self
+    }
+}
+
+impl<S, E> IntoResponse for TracedSse<S>
+where
+    S: Stream<Item = Result<Event, E>> + Send + 'static,
+    E: Into<axum::BoxError>,
+{
+    fn into_response(self) -> Response {
+        let events = CountedEvents {
+            inner: Box::pin(self.stream),
+            span: self.span.clone(),
+            events: 0,
+        };
+        let sse = Sse::new(events);
+        let response = match self.keep_alive {
+            Some(keep_alive) => sse.keep_alive(keep_alive).into_response(),
+            None => sse.into_response(),
+        };
+
+        let span = self.span;
+        response.map(|body| {
+            axum::body::Body::new(MeteredBody::new(body, move |bytes| {
+                span.set_attribute("sse.bytes_sent", bytes as i64);
+            }))
+        })
+    }
+}
+
+// Records the events sent so far when the stream is dropped, which is when the response ends
+struct CountedEvents<S> {
+    inner: Pin<Box<S>>,
+    span: Span,
+    events: i64,
+}
+
+impl<S: Stream> Stream for CountedEvents<S> {
+    type Item = S::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        let poll = this.inner.as_mut().poll_next(cx);
+        if let Poll::Ready(Some(_)) = &poll {
+            this.events += 1;
+        }
+        poll
+    }
+}
+
+impl<S> Drop for CountedEvents<S> {
+    fn drop(&mut self) {
+        self.span.set_attribute("sse.events_sent", self.events);
+    }
+}
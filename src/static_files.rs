@@ -0,0 +1,113 @@
+use crate::{OtelMakeSpan, OtelOnFailure, OtelOnRequest, OtelOnResponse, PropagatingMakeSpan};
+use axum::extract::OriginalUri;
+use axum::http::header::CONTENT_LENGTH;
+use axum::http::{Request, Response, StatusCode};
+use std::sync::Arc;
+use std::time::Duration;
+use tower::Layer;
+use tower_http::classify::{ServerErrorsAsFailures, SharedClassifier};
+use tower_http::services::ServeDir;
+use tower_http::trace::{
+    DefaultOnBodyChunk, DefaultOnEos, MakeSpan, OnResponse, Trace, TraceLayer,
+};
+use tracing::Span;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+/// The service returned by [`traced_serve_dir`].
+pub type TracedServeDir = Trace<
+    ServeDir,
+    SharedClassifier<ServerErrorsAsFailures>,
+    AssetMakeSpan,
+    OtelOnRequest,
+    AssetOnResponse,
+    DefaultOnBodyChunk,
+    DefaultOnEos,
+    OtelOnFailure,
+>;
+
+/// Serves the files of `serve_dir` with a span per request, named after the class of the asset
+/// rather than its file name, e.g. `GET /assets/*.js`, so fingerprinted bundles don't make every
+/// deploy a new span name. The span records `static.cache` as `hit` when the client's copy was
+/// still fresh and got a 304, `miss` otherwise, and the bytes sent as `http.response.body.size`.
+///
+/// Files with one of the `untraced_extensions`, e.g. `["map", "woff2"]`, get no span at all. The
+/// service makes its own spans, so it's mounted beside the [`crate::TelemetryHandle::instrument`]ed
+/// routes rather than within them, e.g.
+/// `telemetry.instrument(api).nest_service("/assets", traced_serve_dir(dir, &[]))`.
+pub fn traced_serve_dir(serve_dir: ServeDir, untraced_extensions: &[&str]) -> TracedServeDir {
+    TraceLayer::new_for_http()
+        .make_span_with(AssetMakeSpan {
+            untraced: untraced_extensions
+                .iter()
+                .map(|extension| extension.trim_start_matches('.').to_ascii_lowercase())
+                .collect(),
+        })
+        .on_request(OtelOnRequest)
+        .on_response(AssetOnResponse)
+        .on_failure(OtelOnFailure)
+        .layer(serve_dir)
+}
+
+// Classes of `/assets/app.3f2a.js` and `/` are `/assets/*.js` and `/`
+fn asset_class(path: &str) -> String {
+    let (directory, file) = path.rsplit_once('/').unwrap_or(("", path));
+    match file.rsplit_once('.') {
+        Some((_, extension)) if !extension.is_empty() => format!("{directory}/*.{extension}"),
+        _ if file.is_empty() => format!("{directory}/"),
+        _ => format!("{directory}/*"),
+    }
+}
+
+/// [`MakeSpan`] of [`traced_serve_dir`], making [`OtelMakeSpan`] spans named after the asset class,
+/// and disabled ones for the untraced extensions.
+#[derive(Clone, Debug)]
+pub struct AssetMakeSpan {
+    untraced: Arc<[String]>,
+}
+
+impl<B> MakeSpan<B> for AssetMakeSpan {
+    fn make_span(&mut self, request: &Request<B>) -> Span {
+        // Nesting strips the mount point from the URI the files are looked up by
+        let path = request
+            .extensions()
+            .get::<OriginalUri>()
+            .map_or(request.uri().path(), |OriginalUri(uri)| uri.path());
+        let extension = path
+            .rsplit('/')
+            .next()
+            .and_then(|file| file.rsplit_once('.'))
+            .map(|(_, extension)| extension.to_ascii_lowercase());
+        if extension.is_some_and(|extension| self.untraced.contains(&extension)) {
+            return Span::none();
+        }
+
+        let class = asset_class(path);
+        let span = PropagatingMakeSpan::new(OtelMakeSpan).make_span(request);
+        span.record("otel.name", format!("{} {class}", request.method()));
+        span.record("http.route", class);
+        span
+    }
+}
+
+/// [`OnResponse`] of [`traced_serve_dir`], recording what [`OtelOnResponse`] does, whether the
+/// client's cached copy was reused, and the bytes sent.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct AssetOnResponse;
+
+impl<B> OnResponse<B> for AssetOnResponse {
+    fn on_response(self, response: &Response<B>, latency: Duration, span: &Span) {
+        OtelOnResponse::new().on_response(response, latency, span);
+        let cache = match response.status() {
+            StatusCode::NOT_MODIFIED => "hit",
+            _ => "miss",
+        };
+        span.set_attribute("static.cache", cache);
+        if let Some(bytes) = response
+            .headers()
+            .get(CONTENT_LENGTH)
+            .and_then(|value| value.to_str().ok()?.parse::<i64>().ok())
+        {
+            span.set_attribute("http.response.body.size", bytes);
+        }
+    }
+}
@@ -0,0 +1,35 @@
+use opentelemetry::trace::TraceContextExt;
+use std::future::Future;
+use tokio::task::JoinHandle;
+use tracing::{Instrument, Span};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+/// Spawns `future` on the tokio runtime in a new trace, whose root span named `name` links to the
+/// span current when it was spawned, e.g. the request span.
+///
+/// The task gets its own trace rather than a child span, so fire-and-forget work outliving the
+/// request doesn't stretch the request's trace, while still leading back to what started it.
+pub fn spawn_traced<F>(name: &'static str, future: F) -> JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    tokio::spawn(future.instrument(linked_root_span(name)))
+}
+
+/// Root span named `name` linking to the span current now, as [`spawn_traced`] runs its task in.
+pub fn linked_root_span(name: &'static str) -> Span {
+    let initiator = crate::span::otel_context(&Span::current())
+        .span()
+        .span_context()
+        .clone();
+    let span = tracing::info_span!(
+        parent: None,
+        "background task",
+        otel.name = name,
+        otel.kind = "internal",
+        code.function = name,
+    );
+    span.add_link(initiator);
+    span
+}
@@ -0,0 +1,250 @@
+use crate::span_ext::AttributeValue;
+use crate::{
+    ExporterBackend, PropagationFormat, SequentialIdGenerator, TelemetryConfig, TelemetryHandle,
+};
+use axum::body::{Body, Bytes};
+use axum::http::{Request, Response};
+use axum::Router;
+use opentelemetry::trace::{Status, TracerProvider as _};
+use opentelemetry::{Key, Value};
+use opentelemetry_sdk::trace::{InMemorySpanExporter, SdkTracerProvider, SpanData};
+use std::cell::RefCell;
+use std::fmt::Write as _;
+use tower::ServiceExt;
+use tracing::subscriber::DefaultGuard;
+use tracing_subscriber::layer::SubscriberExt;
+
+pub use opentelemetry::trace::SpanKind;
+
+thread_local! {
+    // The exporter of the `TestTelemetry` installed on this thread, for `assert_span!` to look into
+    static CURRENT: RefCell<Option<InMemorySpanExporter>> = const { RefCell::new(None) };
+}
+
+/// Records the spans of the current thread in memory rather than exporting them, for tests to check
+/// the instrumentation of a service without a collector, until dropped:
+///
+/// ```
+/// # use axum::body::Body;
+/// # use axum::http::Request;
+/// # use axum::routing::get;
+/// # use axum::Router;
+/// # use axum_picklist::assert_span;
+/// # use axum_picklist::testing::{send, TestTelemetry};
+/// # async fn list_users() -> &'static str {
+/// #     "[]"
+/// # }
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() {
+/// let telemetry = TestTelemetry::new();
+/// let app = telemetry.router(Router::new().route("/users", get(list_users)));
+/// send(&app, Request::get("/users").body(Body::empty()).unwrap()).await;
+/// assert_span!("GET /users", status = Ok, kind = Server, "http.status_code" => 200);
+/// # }
+/// ```
+///
+/// Spans are recorded when they end, by the tracing subscriber of the thread that creates them, so
+/// the tests must run on a current-thread runtime, as `#[tokio::test]`'s is by default, and the
+/// requests be handled within the test rather than by a spawned server. Spans go through neither the
+/// sampler nor the redaction rules, and get the IDs of a [`SequentialIdGenerator`], so the same in
+/// every run of the test.
+#[derive(Debug)]
+pub struct TestTelemetry {
+    exporter: InMemorySpanExporter,
+    provider: SdkTracerProvider,
+    telemetry: TelemetryHandle,
+    _guard: DefaultGuard,
+}
+
+impl TestTelemetry {
+    pub fn new() -> Self {
+        Self::with_config(TelemetryConfig::new(ExporterBackend::None))
+    }
+
+    /// Test telemetry whose [`router`](Self::router) gets the middleware of `config`, e.g. to test
+    /// its rate limit or client address settings; its exporter and pipeline settings are ignored.
+    pub fn with_config(config: TelemetryConfig) -> Self {
+        crate::propagation::install_propagators(&[
+            PropagationFormat::W3C,
+            PropagationFormat::Baggage,
+        ]);
+        let exporter = InMemorySpanExporter::default();
+        let provider = SdkTracerProvider::builder()
+            .with_simple_exporter(exporter.clone())
+            .with_id_generator(SequentialIdGenerator::new())
+            .build();
+        let tracer = provider.tracer(env!("CARGO_PKG_NAME"));
+        let subscriber =
+            tracing_subscriber::registry().with(tracing_opentelemetry::layer().with_tracer(tracer));
+        let guard = tracing::subscriber::set_default(subscriber);
+        CURRENT.with(|current| *current.borrow_mut() = Some(exporter.clone()));
+        Self {
+            exporter,
+            provider,
+            telemetry: TelemetryHandle::from_config(config),
+            _guard: guard,
+        }
+    }
+
+    /// `router` wrapped in the middleware [`TelemetryHandle::instrument`] adds, as the service
+    /// would serve it, configured as given to [`with_config`](Self::with_config) whatever
+    /// [`crate::Telemetry::init`] installed.
+    pub fn router(&self, router: Router) -> Router {
+        self.telemetry.instrument(router)
+    }
+
+    /// The spans ended so far, in the order they ended, so children before their parents.
+    pub fn spans(&self) -> Vec<SpanData> {
+        self.exporter.get_finished_spans().unwrap_or_default()
+    }
+
+    /// Forgets the spans recorded so far, e.g. those of a request setting up the test.
+    pub fn reset(&self) {
+        self.exporter.reset();
+    }
+}
+
+impl Default for TestTelemetry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for TestTelemetry {
+    fn drop(&mut self) {
+        CURRENT.with(|current| current.borrow_mut().take());
+        let _ = self.provider.shutdown();
+    }
+}
+
+/// Sends `request` to `router`, as a client of the service would, and reads the whole response, so
+/// the request span has ended by the time it returns. Panics if the body fails.
+pub async fn send(router: &Router, request: Request<Body>) -> Response<Bytes> {
+    let response = match router.clone().oneshot(request).await {
+        Ok(response) => response,
+        Err(infallible) => match infallible {},
+    };
+    let (parts, body) = response.into_parts();
+    let body = axum::body::to_bytes(body, usize::MAX)
+        .await
+        .expect("failed to read the response body");
+    Response::from_parts(parts, body)
+}
+
+/// The status [`assert_span!`](crate::assert_span) expects of a span.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SpanStatus {
+    /// Not marked as an error, whether the status was left unset, as that of successful HTTP
+    /// requests is, or set to Ok.
+    Ok,
+    Error,
+}
+
+/// The span [`assert_span!`](crate::assert_span) looks for among those the [`TestTelemetry`] of
+/// the thread recorded.
+#[derive(Clone, Debug)]
+pub struct ExpectedSpan {
+    name: String,
+    status: Option<SpanStatus>,
+    kind: Option<SpanKind>,
+    attributes: Vec<(Key, Value)>,
+}
+
+impl ExpectedSpan {
+    pub fn named(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            status: None,
+            kind: None,
+            attributes: Vec::new(),
+        }
+    }
+
+    pub fn status(mut self, status: SpanStatus) -> Self {
+        self.status = Some(status);
+        self
+    }
+
+    pub fn kind(mut self, kind: SpanKind) -> Self {
+        self.kind = Some(kind);
+        self
+    }
+
+    pub fn attribute(mut self, key: impl Into<Key>, value: impl AttributeValue) -> Self {
+        self.attributes.push((key.into(), value.into_value()));
+        self
+    }
+
+    fn matches(&self, span: &SpanData) -> bool {
+        let status = match &span.status {
+            Status::Error { .. } => SpanStatus::Error,
+            Status::Ok | Status::Unset => SpanStatus::Ok,
+        };
+        span.name == self.name
+            && self.status.is_none_or(|expected| expected == status)
+            && self
+                .kind
+                .as_ref()
+                .is_none_or(|kind| *kind == span.span_kind)
+            && self.attributes.iter().all(|(key, value)| {
+                span.attributes
+                    .iter()
+                    .any(|attribute| attribute.key == *key && attribute.value == *value)
+            })
+    }
+
+    /// Returns the first recorded span matching, panicking with the recorded ones if none does.
+    #[track_caller]
+    pub fn assert(&self) -> SpanData {
+        let spans = CURRENT.with(|current| {
+            current
+                .borrow()
+                .as_ref()
+                .expect("assert_span! needs a TestTelemetry installed on this thread")
+                .get_finished_spans()
+                .unwrap_or_default()
+        });
+        if let Some(span) = spans.iter().find(|span| self.matches(span)) {
+            return span.clone();
+        }
+
+        let mut recorded = String::new();
+        for span in &spans {
+            let _ = write!(
+                recorded,
+                "\n  {} ({:?}, {:?})",
+                span.name, span.span_kind, span.status
+            );
+            for attribute in &span.attributes {
+                let _ = write!(recorded, "\n    {} = {}", attribute.key, attribute.value);
+            }
+        }
+        if spans.is_empty() {
+            recorded.push_str(" none");
+        }
+        panic!("no recorded span matches {self:?}, recorded:{recorded}");
+    }
+}
+
+/// Asserts that the [`TestTelemetry`](crate::testing::TestTelemetry) of the thread recorded a span
+/// with the given name, and optionally status, kind and attributes, returning its
+/// [`SpanData`](opentelemetry_sdk::trace::SpanData):
+/// `assert_span!("GET /users/{id}", status = Error, kind = Server, "http.status_code" => 500)`.
+///
+/// The statuses are those of [`SpanStatus`](crate::testing::SpanStatus) and the kinds those of
+/// [`SpanKind`](crate::testing::SpanKind). On failure the recorded spans are printed.
+#[macro_export]
+macro_rules! assert_span {
+    (@set $expected:ident, status, $value:ident) => {
+        $expected.status($crate::testing::SpanStatus::$value)
+    };
+    (@set $expected:ident, kind, $value:ident) => {
+        $expected.kind($crate::testing::SpanKind::$value)
+    };
+    ($name:expr $(, $setting:ident = $value:ident)* $(, $key:literal => $attribute:expr)* $(,)?) => {{
+        let expected = $crate::testing::ExpectedSpan::named($name);
+        $(let expected = $crate::assert_span!(@set expected, $setting, $value);)*
+        $(let expected = expected.attribute($key, $attribute);)*
+        expected.assert()
+    }};
+}
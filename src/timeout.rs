@@ -0,0 +1,132 @@
+use crate::TelemetryConfig;
+use axum::http::{Request, Response, StatusCode};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tower::{Layer, Service};
+
+/// Timeout for the requests whose path starts with `prefix`, taking precedence over the global one.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RouteTimeout {
+    pub prefix: String,
+    pub timeout: Duration,
+}
+
+/// Layer answering requests still running after their timeout with an empty response, 408 Request
+/// Timeout unless [`with_status`](Self::with_status) says otherwise, dropping the handler's future;
+/// without a timeout requests run for as long as they take.
+///
+/// Timed out requests get a `request.timeout` event and an error status on the current span.
+#[derive(Clone, Debug)]
+pub struct RequestTimeoutLayer {
+    timeout: Option<Duration>,
+    routes: Arc<Vec<RouteTimeout>>,
+    status: StatusCode,
+}
+
+impl Default for RequestTimeoutLayer {
+    fn default() -> Self {
+        Self::new(None)
+    }
+}
+
+impl RequestTimeoutLayer {
+    pub fn new(timeout: Option<Duration>) -> Self {
+        Self {
+            timeout,
+            routes: Arc::new(Vec::new()),
+            status: StatusCode::REQUEST_TIMEOUT,
+        }
+    }
+
+    /// Gives the requests whose path starts with `prefix` `timeout` instead, the longest matching
+    /// prefix winning, even when there is no global timeout.
+    pub fn with_route(mut self, prefix: impl Into<String>, timeout: Duration) -> Self {
+        Arc::make_mut(&mut self.routes).push(RouteTimeout {
+            prefix: prefix.into(),
+            timeout,
+        });
+        self
+    }
+
+    /// Answers timed out requests with `status`, e.g. 504 Gateway Timeout for a proxy whose
+    /// upstreams are the ones running late.
+    pub fn with_status(mut self, status: StatusCode) -> Self {
+        self.status = status;
+        self
+    }
+
+    pub(crate) fn from_config(config: &TelemetryConfig) -> Self {
+        Self {
+            timeout: config.request_timeout,
+            routes: Arc::new(config.route_timeouts.clone()),
+            status: config.timeout_status,
+        }
+    }
+}
+
+impl<S> Layer<S> for RequestTimeoutLayer {
+    type Service = RequestTimeout<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RequestTimeout {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+/// Service created by [`RequestTimeoutLayer`].
+#[derive(Clone, Debug)]
+pub struct RequestTimeout<S> {
+    inner: S,
+    layer: RequestTimeoutLayer,
+}
+
+impl<S, B, ResBody> Service<Request<B>> for RequestTimeout<S>
+where
+    S: Service<Request<B>, Response = Response<ResBody>>,
+    S::Future: Send + 'static,
+    ResBody: Default,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<B>) -> Self::Future {
+        let path = request.uri().path();
+        let timeout = self
+            .layer
+            .routes
+            .iter()
+            .filter(|route| path.starts_with(route.prefix.as_str()))
+            .max_by_key(|route| route.prefix.len())
+            .map(|route| route.timeout)
+            .or(self.layer.timeout);
+        let status = self.layer.status;
+
+        let future = self.inner.call(request);
+        let Some(timeout) = timeout else {
+            return Box::pin(future);
+        };
+
+        Box::pin(async move {
+            match tokio::time::timeout(timeout, future).await {
+                Ok(result) => result,
+                Err(_) => {
+                    tracing::Span::current().record("otel.status_code", "ERROR");
+                    tracing::warn!(timeout_ms = timeout.as_millis() as u64, "request.timeout");
+                    let mut response = Response::new(ResBody::default());
+                    *response.status_mut() = status;
+                    Ok(response)
+                }
+            }
+        })
+    }
+}
@@ -0,0 +1,346 @@
+use axum::extract::connect_info::ConnectInfo;
+use axum::extract::Request;
+use axum::response::Response;
+use axum::serve::{IncomingStream, Listener};
+use rustls::crypto::CryptoProvider;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::server::{ClientHello, ResolvesServerCert, ServerConnection};
+use rustls::sign::CertifiedKey;
+use rustls::{ProtocolVersion, ServerConfig};
+use std::convert::Infallible;
+use std::future::Future;
+use std::io;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::{Arc, RwLock};
+use std::task::{Context, Poll};
+use std::time::{Duration, SystemTime};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio_rustls::server::TlsStream;
+use tokio_rustls::TlsAcceptor;
+use tower::Service;
+use tracing::Span;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+// Clients that haven't finished their handshake by then are disconnected, so they can't hold a
+// connection open without ever sending a request
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Connections whose handshake is done but that the server hasn't picked up yet
+const ACCEPT_BACKLOG: usize = 128;
+
+fn invalid_data(err: impl std::error::Error + Send + Sync + 'static) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}
+
+/// The certificate chain and private key a [`TlsListener`] presents, read from PEM files.
+///
+/// The files are re-read by [`reload`](Self::reload), or whenever they change while a
+/// [`TlsListener`] serves them, so rotated certificates, e.g. renewed by cert-manager or certbot,
+/// are presented to new connections without a restart. A reload failing, e.g. when only one of the
+/// files has been replaced yet, keeps the previous certificate.
+#[derive(Clone, Debug)]
+pub struct TlsCertificates {
+    cert_path: PathBuf,
+    key_path: PathBuf,
+    current: Arc<RwLock<Arc<CertifiedKey>>>,
+}
+
+impl TlsCertificates {
+    /// Reads the certificate chain at `cert_path`, leaf first, and the PKCS#8, PKCS#1 or SEC1 key at
+    /// `key_path`, failing if they are missing, invalid or don't match.
+    pub fn load(cert_path: impl Into<PathBuf>, key_path: impl Into<PathBuf>) -> io::Result<Self> {
+        let cert_path = cert_path.into();
+        let key_path = key_path.into();
+        let key = certified_key(&cert_path, &key_path)?;
+        Ok(Self {
+            cert_path,
+            key_path,
+            current: Arc::new(RwLock::new(Arc::new(key))),
+        })
+    }
+
+    /// Re-reads the files, new handshakes presenting the new certificate if they are valid.
+    pub fn reload(&self) -> io::Result<()> {
+        let key = certified_key(&self.cert_path, &self.key_path)?;
+        *self
+            .current
+            .write()
+            .expect("the certificate lock is poisoned") = Arc::new(key);
+        Ok(())
+    }
+
+    fn modified(&self) -> [Option<SystemTime>; 2] {
+        [&self.cert_path, &self.key_path].map(|path| {
+            std::fs::metadata(path)
+                .and_then(|metadata| metadata.modified())
+                .ok()
+        })
+    }
+
+    // Polled rather than watched, like the configuration file, for the symlink swaps of Kubernetes
+    // mounted secrets to be seen too
+    fn watch(self) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(POLL_INTERVAL);
+            let mut last_modified = self.modified();
+            loop {
+                interval.tick().await;
+                let current = self.modified();
+                if current == last_modified {
+                    continue;
+                }
+                last_modified = current;
+
+                match self.reload() {
+                    Ok(()) => tracing::info!(
+                        path = %self.cert_path.display(),
+                        "reloaded the TLS certificate"
+                    ),
+                    Err(err) => tracing::warn!(
+                        error = %err,
+                        path = %self.cert_path.display(),
+                        "failed to reload the TLS certificate"
+                    ),
+                }
+            }
+        })
+    }
+
+    fn server_config(&self) -> io::Result<ServerConfig> {
+        let mut config = ServerConfig::builder_with_provider(provider())
+            .with_safe_default_protocol_versions()
+            .map_err(invalid_data)?
+            .with_no_client_auth()
+            .with_cert_resolver(Arc::new(self.clone()));
+        config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+        Ok(config)
+    }
+}
+
+impl ResolvesServerCert for TlsCertificates {
+    fn resolve(&self, _client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        self.current.read().ok().map(|key| Arc::clone(&key))
+    }
+}
+
+// Chosen explicitly, as other dependencies may enable more than one of rustls' providers
+fn provider() -> Arc<CryptoProvider> {
+    Arc::new(rustls::crypto::ring::default_provider())
+}
+
+fn certified_key(cert_path: &Path, key_path: &Path) -> io::Result<CertifiedKey> {
+    let certs = CertificateDer::pem_file_iter(cert_path)
+        .map_err(invalid_data)?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(invalid_data)?;
+    if certs.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("no certificate in {}", cert_path.display()),
+        ));
+    }
+    let key = PrivateKeyDer::from_pem_file(key_path).map_err(invalid_data)?;
+    CertifiedKey::from_der(certs, key, &provider()).map_err(invalid_data)
+}
+
+/// [`Listener`] terminating TLS with rustls, to serve HTTPS without a terminating proxy in front:
+/// `axum::serve(TlsListener::bind(address, certificates).await?, TlsMakeService::new(app))`.
+///
+/// Clients supporting HTTP/2 are offered it through ALPN, the others falling back to HTTP/1.1.
+/// Handshakes happen in tasks of their own, so slow clients don't hold up the others, and the failed
+/// ones are logged at debug level. The [`TlsCertificates`] files are watched for as long as the
+/// listener lives.
+#[derive(Debug)]
+pub struct TlsListener {
+    local_addr: SocketAddr,
+    connections: mpsc::Receiver<(TlsStream<TcpStream>, SocketAddr)>,
+    tasks: [JoinHandle<()>; 2],
+}
+
+impl TlsListener {
+    pub async fn bind(address: SocketAddr, certificates: TlsCertificates) -> io::Result<Self> {
+        let acceptor = TlsAcceptor::from(Arc::new(certificates.server_config()?));
+        let listener = TcpListener::bind(address).await?;
+        let local_addr = listener.local_addr()?;
+        let (sender, connections) = mpsc::channel(ACCEPT_BACKLOG);
+        tracing::info!(address = %local_addr, "serving HTTPS");
+
+        let accepting = tokio::spawn(async move {
+            loop {
+                let (stream, peer) = match listener.accept().await {
+                    Ok(connection) => connection,
+                    Err(err) => {
+                        // Mostly running out of file descriptors, which takes a while to recover from
+                        tracing::warn!(error = %err, "failed to accept a connection");
+                        tokio::time::sleep(Duration::from_secs(1)).await;
+                        continue;
+                    }
+                };
+                let acceptor = acceptor.clone();
+                let sender = sender.clone();
+                tokio::spawn(async move {
+                    match tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                        Ok(Ok(stream)) => {
+                            let _ = sender.send((stream, peer)).await;
+                        }
+                        Ok(Err(err)) => {
+                            tracing::debug!(error = %err, %peer, "TLS handshake failed")
+                        }
+                        Err(_) => tracing::debug!(%peer, "TLS handshake timed out"),
+                    }
+                });
+            }
+        });
+        Ok(Self {
+            local_addr,
+            connections,
+            tasks: [accepting, certificates.watch()],
+        })
+    }
+}
+
+impl Drop for TlsListener {
+    fn drop(&mut self) {
+        for task in &self.tasks {
+            task.abort();
+        }
+    }
+}
+
+impl Listener for TlsListener {
+    type Io = TlsStream<TcpStream>;
+    type Addr = SocketAddr;
+
+    async fn accept(&mut self) -> (Self::Io, Self::Addr) {
+        match self.connections.recv().await {
+            Some(connection) => connection,
+            // The accepting task only stops with the listener
+            None => std::future::pending().await,
+        }
+    }
+
+    fn local_addr(&self) -> io::Result<Self::Addr> {
+        Ok(self.local_addr)
+    }
+}
+
+/// What was negotiated for the TLS connection a request came over, in its extensions when served
+/// with [`TlsMakeService`], and recorded by [`crate::OtelOnRequest`] as the `tls.*` attributes.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TlsSession {
+    /// E.g. `1.3`.
+    pub protocol_version: Option<&'static str>,
+    /// The IANA name of the cipher suite, e.g. `TLS13_AES_128_GCM_SHA256`.
+    pub cipher: Option<&'static str>,
+    /// The ALPN protocol, e.g. `h2`.
+    pub next_protocol: Option<String>,
+    /// The SNI the client asked for.
+    pub server_name: Option<String>,
+}
+
+impl TlsSession {
+    fn new(connection: &ServerConnection) -> Self {
+        let protocol_version = connection
+            .protocol_version()
+            .and_then(|version| match version {
+                ProtocolVersion::TLSv1_2 => Some("1.2"),
+                ProtocolVersion::TLSv1_3 => Some("1.3"),
+                _ => None,
+            });
+        Self {
+            protocol_version,
+            cipher: connection
+                .negotiated_cipher_suite()
+                .and_then(|suite| suite.suite().as_str()),
+            next_protocol: connection
+                .alpn_protocol()
+                .map(|protocol| String::from_utf8_lossy(protocol).into_owned()),
+            server_name: connection.server_name().map(str::to_string),
+        }
+    }
+
+    pub(crate) fn record(&self, span: &Span) {
+        span.set_attribute("tls.protocol.name", "tls");
+        if let Some(version) = self.protocol_version {
+            span.set_attribute("tls.protocol.version", version);
+        }
+        if let Some(cipher) = self.cipher {
+            span.set_attribute("tls.cipher", cipher);
+        }
+        if let Some(protocol) = &self.next_protocol {
+            span.set_attribute("tls.next_protocol", protocol.clone());
+        }
+        if let Some(server_name) = &self.server_name {
+            span.set_attribute("tls.client.server_name", server_name.clone());
+        }
+    }
+}
+
+/// Make service for serving a router on a [`TlsListener`], giving each request the
+/// [`ConnectInfo<SocketAddr>`] of its peer, as `into_make_service_with_connect_info` does on a TCP
+/// listener, and the [`TlsSession`] of its connection.
+#[derive(Clone, Debug)]
+pub struct TlsMakeService<S> {
+    inner: S,
+}
+
+impl<S> TlsMakeService<S> {
+    pub fn new(inner: S) -> Self {
+        Self { inner }
+    }
+}
+
+impl<S: Clone> Service<IncomingStream<'_, TlsListener>> for TlsMakeService<S> {
+    type Response = TlsConnection<S>;
+    type Error = Infallible;
+    type Future = std::future::Ready<Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, stream: IncomingStream<'_, TlsListener>) -> Self::Future {
+        let (_, connection) = stream.io().get_ref();
+        std::future::ready(Ok(TlsConnection {
+            inner: self.inner.clone(),
+            peer: *stream.remote_addr(),
+            session: Arc::new(TlsSession::new(connection)),
+        }))
+    }
+}
+
+/// Service created by [`TlsMakeService`] for each connection.
+#[derive(Clone, Debug)]
+pub struct TlsConnection<S> {
+    inner: S,
+    peer: SocketAddr,
+    session: Arc<TlsSession>,
+}
+
+impl<S> Service<Request> for TlsConnection<S>
+where
+    S: Service<Request, Response = Response, Error = Infallible>,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = Infallible;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut request: Request) -> Self::Future {
+        let extensions = request.extensions_mut();
+        extensions.insert(ConnectInfo(self.peer));
+        extensions.insert(TlsSession::clone(&self.session));
+        Box::pin(self.inner.call(request))
+    }
+}
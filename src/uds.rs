@@ -0,0 +1,138 @@
+use axum::extract::Request;
+use axum::response::Response;
+use axum::serve::IncomingStream;
+use std::convert::Infallible;
+use std::future::Future;
+use std::io;
+use std::os::unix::fs::FileTypeExt;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tokio::net::UnixListener;
+use tower::Service;
+use tracing::Span;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+/// Binds a Unix domain socket at `path`, e.g. `/run/app/http.sock`, to serve behind a local proxy
+/// or sidecar with `axum::serve(bind_unix(path)?, UdsMakeService::new(app))`.
+///
+/// A socket left at `path` by a previous run is removed first, but any other kind of file is an
+/// error rather than being replaced.
+pub fn bind_unix(path: impl AsRef<Path>) -> io::Result<UnixListener> {
+    let path = path.as_ref();
+    match std::fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_socket() => std::fs::remove_file(path)?,
+        Ok(_) => {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} exists and isn't a socket", path.display()),
+            ))
+        }
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+        Err(err) => return Err(err),
+    }
+    let listener = UnixListener::bind(path)?;
+    tracing::info!(path = %path.display(), "serving on a Unix socket");
+    Ok(listener)
+}
+
+/// The process on the other end of the Unix socket a request came over, in its extensions when
+/// served with [`UdsMakeService`], and recorded by [`crate::OtelOnRequest`] as `unix.peer.pid`,
+/// `unix.peer.uid` and `unix.peer.gid`, along with the socket as `network.local.address`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UnixPeer {
+    /// Not known on every platform, e.g. on the BSDs.
+    pub pid: Option<i32>,
+    pub uid: u32,
+    pub gid: u32,
+    pub socket: Option<PathBuf>,
+}
+
+impl UnixPeer {
+    pub(crate) fn record(&self, span: &Span) {
+        span.set_attribute("network.transport", "unix");
+        if let Some(socket) = &self.socket {
+            span.set_attribute("network.local.address", socket.display().to_string());
+        }
+        if let Some(pid) = self.pid {
+            span.set_attribute("unix.peer.pid", i64::from(pid));
+        }
+        span.set_attribute("unix.peer.uid", i64::from(self.uid));
+        span.set_attribute("unix.peer.gid", i64::from(self.gid));
+    }
+}
+
+/// Make service for serving a router on a [`UnixListener`], giving each request the [`UnixPeer`]
+/// of its connection, read from the socket's peer credentials.
+#[derive(Clone, Debug)]
+pub struct UdsMakeService<S> {
+    inner: S,
+}
+
+impl<S> UdsMakeService<S> {
+    pub fn new(inner: S) -> Self {
+        Self { inner }
+    }
+}
+
+impl<S: Clone> Service<IncomingStream<'_, UnixListener>> for UdsMakeService<S> {
+    type Response = UdsConnection<S>;
+    type Error = Infallible;
+    type Future = std::future::Ready<Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, stream: IncomingStream<'_, UnixListener>) -> Self::Future {
+        let peer = match stream.io().peer_cred() {
+            Ok(credentials) => Some(Arc::new(UnixPeer {
+                pid: credentials.pid(),
+                uid: credentials.uid(),
+                gid: credentials.gid(),
+                socket: stream
+                    .io()
+                    .local_addr()
+                    .ok()
+                    .and_then(|address| address.as_pathname().map(Path::to_path_buf)),
+            })),
+            Err(err) => {
+                tracing::debug!(error = %err, "failed to read the Unix socket peer credentials");
+                None
+            }
+        };
+        std::future::ready(Ok(UdsConnection {
+            inner: self.inner.clone(),
+            peer,
+        }))
+    }
+}
+
+/// Service created by [`UdsMakeService`] for each connection.
+#[derive(Clone, Debug)]
+pub struct UdsConnection<S> {
+    inner: S,
+    peer: Option<Arc<UnixPeer>>,
+}
+
+impl<S> Service<Request> for UdsConnection<S>
+where
+    S: Service<Request, Response = Response, Error = Infallible>,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = Infallible;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut request: Request) -> Self::Future {
+        if let Some(peer) = &self.peer {
+            request.extensions_mut().insert(UnixPeer::clone(peer));
+        }
+        Box::pin(self.inner.call(request))
+    }
+}
@@ -0,0 +1,48 @@
+use crate::RequestEnricher;
+use axum::http::header::USER_AGENT;
+use axum::http::Request;
+use opentelemetry::KeyValue;
+use woothee::parser::Parser;
+use woothee::woothee::VALUE_UNKNOWN;
+
+/// [`RequestEnricher`] parsing the `User-Agent` header of each request, recording the client's
+/// name and version as `user_agent.name` and `user_agent.version`, e.g. `Chrome` and `120.0.0.0`,
+/// its operating system as `user_agent.os.name`, its kind of device as `user_agent.device`, one of
+/// `pc`, `smartphone`, `mobilephone` or `appliance`, and `user_agent.bot` for crawlers.
+///
+/// Nothing the parser doesn't recognise is recorded. Like every enrichment the attributes are
+/// propagated as baggage, e.g.
+/// `telemetry.instrument(router.layer(EnrichLayer::new(UserAgentEnricher)))`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct UserAgentEnricher;
+
+impl RequestEnricher for UserAgentEnricher {
+    fn enrich<B>(&self, request: &Request<B>) -> Vec<KeyValue> {
+        let Some(result) = request
+            .headers()
+            .get(USER_AGENT)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| Parser::new().parse(value))
+        else {
+            return Vec::new();
+        };
+
+        let bot = result.category == "crawler";
+        let mut attributes = vec![KeyValue::new("user_agent.bot", bot)];
+        let known = [
+            ("user_agent.name", result.name),
+            ("user_agent.version", result.version),
+            ("user_agent.os.name", result.os),
+        ];
+        let device = (!bot && result.category != "misc").then_some(result.category);
+        for (key, value) in known
+            .into_iter()
+            .chain(device.map(|device| ("user_agent.device", device)))
+        {
+            if !value.is_empty() && value != VALUE_UNKNOWN {
+                attributes.push(KeyValue::new(key, value.to_string()));
+            }
+        }
+        attributes
+    }
+}
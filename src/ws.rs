@@ -0,0 +1,163 @@
+use axum::extract::ws::{Message, OnFailedUpgrade, WebSocket, WebSocketUpgrade};
+use axum::response::Response;
+use futures_util::SinkExt;
+use std::future::Future;
+use tracing::field::Empty;
+use tracing::{Instrument, Span};
+
+/// Upgrades the connection like [`WebSocketUpgrade::on_upgrade`], running `callback` in a span
+/// covering the whole connection, child of the request span, with a [`TracedWebSocket`] counting
+/// the messages and bytes sent and received as `websocket.messages_sent`,
+/// `websocket.messages_received`, `websocket.bytes_sent` and `websocket.bytes_received`.
+///
+/// Call it from the handler, while the request span is current, e.g.
+/// `traced_upgrade(ws, |mut socket| async move { while let Some(Ok(message)) = socket.recv().await {
+/// ... } })`.
+pub fn traced_upgrade<F, C, Fut>(ws: WebSocketUpgrade<F>, callback: C) -> Response
+where
+    F: OnFailedUpgrade,
+    C: FnOnce(TracedWebSocket) -> Fut + Send + 'static,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    let span = tracing::info_span!(
+        "WebSocket connection",
+        otel.name = "WebSocket connection",
+        otel.kind = "server",
+        otel.status_code = Empty,
+        websocket.messages_sent = 0,
+        websocket.messages_received = 0,
+        websocket.bytes_sent = 0,
+        websocket.bytes_received = 0,
+        websocket.close_code = Empty,
+    );
+    ws.on_upgrade(move |socket| {
+        let socket = TracedWebSocket::new(socket, span.clone());
+        callback(socket).instrument(span)
+    })
+}
+
+/// [`WebSocket`] recording its traffic on the connection span made by [`traced_upgrade`], and with
+/// [`with_message_spans`](Self::with_message_spans) a child span per message.
+#[derive(Debug)]
+pub struct TracedWebSocket {
+    socket: WebSocket,
+    span: Span,
+    message_spans: bool,
+    messages_sent: i64,
+    messages_received: i64,
+    bytes_sent: i64,
+    bytes_received: i64,
+}
+
+impl TracedWebSocket {
+    fn new(socket: WebSocket, span: Span) -> Self {
+        Self {
+            socket,
+            span,
+            message_spans: false,
+            messages_sent: 0,
+            messages_received: 0,
+            bytes_sent: 0,
+            bytes_received: 0,
+        }
+    }
+
+    /// Also records each message in its own span with `websocket.message.direction`,
+    /// `websocket.message.type` and `websocket.message.size`; busy sockets make many spans.
+    pub fn with_message_spans(mut self, enabled: bool) -> Self {
+        self.message_spans = enabled;
+        self
+    }
+
+    /// Receives a message like [`WebSocket::recv`].
+    pub async fn recv(&mut self) -> Option<Result<Message, axum::Error>> {
+        let message = self.socket.recv().await;
+        match &message {
+            Some(Ok(message)) => {
+                let size = size(message);
+                self.messages_received += 1;
+                self.bytes_received += size;
+                self.span
+                    .record("websocket.messages_received", self.messages_received);
+                self.span
+                    .record("websocket.bytes_received", self.bytes_received);
+                if let Message::Close(Some(frame)) = message {
+                    self.span
+                        .record("websocket.close_code", i64::from(frame.code));
+                }
+                if self.message_spans {
+                    // Received messages are only seen once they have arrived, so their span is empty
+                    self.message_span("received", message, size);
+                }
+            }
+            Some(Err(error)) => {
+                self.span.record("otel.status_code", "ERROR");
+                tracing::error!(parent: &self.span, exception.message = %error, "exception");
+            }
+            None => {}
+        }
+        message
+    }
+
+    /// Sends a message like [`WebSocket::send`].
+    pub async fn send(&mut self, message: Message) -> Result<(), axum::Error> {
+        let size = size(&message);
+        let span = if self.message_spans {
+            self.message_span("sent", &message, size)
+        } else {
+            Span::none()
+        };
+        let result = self.socket.send(message).instrument(span).await;
+        match &result {
+            Ok(()) => {
+                self.messages_sent += 1;
+                self.bytes_sent += size;
+                self.span
+                    .record("websocket.messages_sent", self.messages_sent);
+                self.span.record("websocket.bytes_sent", self.bytes_sent);
+            }
+            Err(error) => {
+                self.span.record("otel.status_code", "ERROR");
+                tracing::error!(parent: &self.span, exception.message = %error, "exception");
+            }
+        }
+        result
+    }
+
+    /// Gracefully closes the connection like [`WebSocket::close`].
+    pub async fn close(mut self) -> Result<(), axum::Error> {
+        self.socket.close().await
+    }
+
+    /// The underlying socket, whose traffic is no longer recorded.
+    pub fn into_inner(self) -> WebSocket {
+        self.socket
+    }
+
+    fn message_span(&self, direction: &'static str, message: &Message, size: i64) -> Span {
+        let kind = match message {
+            Message::Text(_) => "text",
+            Message::Binary(_) => "binary",
+            Message::Ping(_) => "ping",
+            Message::Pong(_) => "pong",
+            Message::Close(_) => "close",
+        };
+        tracing::info_span!(
+            parent: &self.span,
+            "WebSocket message",
+            otel.name = format!("WebSocket {direction}"),
+            websocket.message.direction = direction,
+            websocket.message.type = kind,
+            websocket.message.size = size,
+        )
+    }
+}
+
+fn size(message: &Message) -> i64 {
+    let size = match message {
+        Message::Text(text) => text.len(),
+        Message::Binary(data) | Message::Ping(data) | Message::Pong(data) => data.len(),
+        Message::Close(frame) => frame.as_ref().map_or(0, |frame| frame.reason.len()),
+    };
+    size as i64
+}
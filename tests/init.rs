@@ -0,0 +1,22 @@
+use axum_picklist::config::ConfigError;
+use axum_picklist::connectivity::ConnectivityCheck;
+use axum_picklist::{ExporterBackend, Telemetry};
+use std::collections::HashMap;
+
+// In one test, as the second case needs the global subscriber the first must leave unset
+#[test]
+fn fails_rather_than_panicking() {
+    // Nothing listens on the discard port
+    let unreachable = ExporterBackend::OtlpHttp {
+        endpoint: "http://127.0.0.1:9/v1/traces".to_string(),
+        headers: HashMap::new(),
+    };
+    let result = Telemetry::new(unreachable)
+        .connectivity_check(ConnectivityCheck::FailFast)
+        .init();
+    assert!(matches!(result, Err(ConfigError::Unreachable(_))));
+
+    tracing::subscriber::set_global_default(tracing::subscriber::NoSubscriber::default()).unwrap();
+    let result = Telemetry::new(ExporterBackend::None).init();
+    assert!(matches!(result, Err(ConfigError::SubscriberInstalled)));
+}
@@ -0,0 +1,188 @@
+use axum::body::Body;
+use axum::extract::ConnectInfo;
+use axum::http::{Request, StatusCode};
+use axum::routing::get;
+use axum::Router;
+use axum_picklist::testing::{send, TestTelemetry};
+use axum_picklist::{
+    assert_span, ClientIpConfig, ExporterBackend, HeaderCaptureConfig, RateLimit, TelemetryConfig,
+    TracedClient,
+};
+use std::net::SocketAddr;
+
+async fn get_user() -> &'static str {
+    "{}"
+}
+
+async fn fail() -> StatusCode {
+    StatusCode::INTERNAL_SERVER_ERROR
+}
+
+fn app() -> Router {
+    Router::new()
+        .route("/users/{id}", get(get_user))
+        .route("/fail", get(fail))
+}
+
+fn get_from(uri: &str, peer: &str) -> Request<Body> {
+    let mut request = Request::get(uri).body(Body::empty()).unwrap();
+    let peer: SocketAddr = peer.parse().unwrap();
+    request.extensions_mut().insert(ConnectInfo(peer));
+    request
+}
+
+#[tokio::test]
+async fn names_spans_after_the_route_template() {
+    let telemetry = TestTelemetry::new();
+    let app = telemetry.router(app());
+
+    send(&app, Request::get("/users/42").body(Body::empty()).unwrap()).await;
+    assert_span!(
+        "GET /users/{id}",
+        kind = Server,
+        "http.method" => "GET",
+        "http.route" => "/users/{id}",
+        "http.target" => "/users/42",
+        "network.protocol.version" => "1.1",
+    );
+
+    send(&app, Request::get("/missing").body(Body::empty()).unwrap()).await;
+    assert_span!("GET", kind = Server, "http.target" => "/missing");
+}
+
+#[tokio::test]
+async fn marks_server_errors_only() {
+    let telemetry = TestTelemetry::new();
+    let app = telemetry.router(app());
+
+    send(&app, Request::get("/fail").body(Body::empty()).unwrap()).await;
+    assert_span!("GET /fail", status = Error, "http.status_code" => 500);
+
+    send(&app, Request::get("/users/42").body(Body::empty()).unwrap()).await;
+    assert_span!("GET /users/{id}", status = Ok, "http.status_code" => 200);
+
+    send(&app, Request::get("/missing").body(Body::empty()).unwrap()).await;
+    assert_span!("GET", status = Ok, "http.status_code" => 404);
+}
+
+#[tokio::test]
+async fn rate_limits_requests_over_the_burst() {
+    let mut config = TelemetryConfig::new(ExporterBackend::None);
+    config.rate_limit = Some(RateLimit::per_client_ip(1, 0.001));
+    let telemetry = TestTelemetry::with_config(config);
+    let app = telemetry.router(app());
+
+    let response = send(&app, get_from("/users/1", "192.0.2.1:5000")).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_span!(
+        "GET /users/{id}",
+        "rate_limit.exceeded" => false,
+        "rate_limit.remaining" => 0,
+        "rate_limit.client" => "192.0.2.1",
+    );
+
+    telemetry.reset();
+    let response = send(&app, get_from("/users/1", "192.0.2.1:5000")).await;
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert!(response.headers().contains_key("retry-after"));
+    assert_span!("GET /users/{id}", "http.status_code" => 429, "rate_limit.exceeded" => true);
+
+    // Each client has its own bucket
+    let response = send(&app, get_from("/users/1", "192.0.2.2:5000")).await;
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn resolves_the_client_behind_trusted_proxies() {
+    let mut config = TelemetryConfig::new(ExporterBackend::None);
+    config.client_ip = ClientIpConfig {
+        trusted_proxies: vec!["10.0.0.0/8".parse().unwrap()],
+        ..ClientIpConfig::default()
+    };
+    let telemetry = TestTelemetry::with_config(config);
+    let app = telemetry.router(app());
+
+    let mut request = get_from("/users/1", "10.0.0.2:5000");
+    request.headers_mut().insert(
+        "x-forwarded-for",
+        "198.51.100.1, 203.0.113.7, 10.0.0.1".parse().unwrap(),
+    );
+    send(&app, request).await;
+    assert_span!(
+        "GET /users/{id}",
+        "client.address" => "203.0.113.7",
+        "net.peer.ip" => "10.0.0.2",
+    );
+
+    // Untrusted peers can't claim to forward for anyone else
+    telemetry.reset();
+    let mut request = get_from("/users/1", "192.0.2.1:5000");
+    request
+        .headers_mut()
+        .insert("x-forwarded-for", "203.0.113.7".parse().unwrap());
+    send(&app, request).await;
+    assert_span!("GET /users/{id}", "client.address" => "192.0.2.1");
+}
+
+#[tokio::test]
+async fn records_the_configured_headers_but_never_credentials() {
+    let mut config = TelemetryConfig::new(ExporterBackend::None);
+    config.headers = HeaderCaptureConfig {
+        request: vec![
+            "Accept".to_string(),
+            "Authorization".to_string(),
+            "x-api-key".to_string(),
+        ],
+        response: vec!["content-type".to_string(), "set-cookie".to_string()],
+        deny: vec!["X-API-Key".to_string()],
+    };
+    let telemetry = TestTelemetry::with_config(config);
+    let app = telemetry.router(app());
+
+    let request = Request::get("/users/1")
+        .header("accept", "text/plain")
+        .header("accept", "application/json")
+        .header("authorization", "Bearer secret")
+        .header("x-api-key", "secret")
+        .body(Body::empty())
+        .unwrap();
+    send(&app, request).await;
+    let span = assert_span!(
+        "GET /users/{id}",
+        "http.request.header.accept" => "text/plain, application/json",
+        "http.response.header.content-type" => "text/plain; charset=utf-8",
+    );
+    for key in [
+        "http.request.header.authorization",
+        "http.request.header.x-api-key",
+    ] {
+        assert!(
+            span.attributes
+                .iter()
+                .all(|attribute| attribute.key.as_str() != key),
+            "{key} was recorded"
+        );
+    }
+}
+
+#[tokio::test]
+async fn records_why_client_requests_failed() {
+    let _telemetry = TestTelemetry::new();
+
+    // Nothing listens on the discard port
+    let client = TracedClient::default();
+    let request = client.get("http://127.0.0.1:9/");
+    let error = client.send(request).await.unwrap_err();
+    let span = assert_span!("GET", kind = Client, status = Error, "error.type" => "connect");
+    // Among the events the connection pool logs
+    let event = span
+        .events
+        .iter()
+        .find(|event| event.name == "exception")
+        .unwrap();
+    assert!(event
+        .attributes
+        .iter()
+        .any(|attribute| attribute.key.as_str() == "exception.message"
+            && attribute.value.as_str() == error.to_string()));
+}
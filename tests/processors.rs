@@ -0,0 +1,228 @@
+use axum_picklist::{
+    AttributeLimitProcessor, AttributeLimits, RedactingSpanProcessor, RedactionAction,
+    RedactionRule, TailSampling, TailSamplingProcessor,
+};
+use opentelemetry::trace::{
+    Link, Span as _, SpanContext, SpanId, Status, TraceContextExt as _, TraceFlags, TraceId,
+    TraceState, Tracer as _, TracerProvider as _,
+};
+use opentelemetry::{Array, KeyValue, StringValue, Value};
+use opentelemetry_sdk::trace::{
+    InMemorySpanExporter, SdkTracerProvider, SimpleSpanProcessor, SpanData, SpanProcessor,
+};
+use regex::Regex;
+use std::time::Duration;
+
+// A provider exporting through `processor`, which is handed the exporter to pass spans on to
+fn provider<P: SpanProcessor + 'static>(
+    processor: impl FnOnce(SimpleSpanProcessor<InMemorySpanExporter>) -> P,
+) -> (SdkTracerProvider, InMemorySpanExporter) {
+    let exporter = InMemorySpanExporter::default();
+    let provider = SdkTracerProvider::builder()
+        .with_span_processor(processor(SimpleSpanProcessor::new(exporter.clone())))
+        .build();
+    (provider, exporter)
+}
+
+fn attribute<'a>(span: &'a SpanData, key: &str) -> Option<&'a Value> {
+    span.attributes
+        .iter()
+        .find(|attribute| attribute.key.as_str() == key)
+        .map(|attribute| &attribute.value)
+}
+
+#[test]
+fn redacts_names_statuses_attributes_events_and_links() {
+    let rules = vec![
+        RedactionRule::emails(),
+        RedactionRule::new(Regex::new("secret").unwrap(), RedactionAction::Drop),
+    ];
+    let (provider, exporter) = provider(|inner| RedactingSpanProcessor::new(inner, rules));
+    let tracer = provider.tracer("test");
+
+    let linked = SpanContext::new(
+        TraceId::from(1),
+        SpanId::from(1),
+        TraceFlags::SAMPLED,
+        false,
+        TraceState::default(),
+    );
+    let mut span = tracer
+        .span_builder("lookup jane@example.com")
+        .with_links(vec![Link::new(
+            linked,
+            vec![KeyValue::new("by", "jane@example.com")],
+            0,
+        )])
+        .start(&tracer);
+    span.set_attribute(KeyValue::new("user", "jane@example.com"));
+    span.set_attribute(KeyValue::new("token", "secret value"));
+    span.set_attribute(KeyValue::new("id", 7));
+    span.set_attribute(KeyValue::new(
+        "recipients",
+        Value::Array(Array::String(vec![
+            StringValue::from("jane@example.com"),
+            StringValue::from("everyone"),
+        ])),
+    ));
+    span.set_attribute(KeyValue::new(
+        "keys",
+        Value::Array(Array::String(vec![
+            StringValue::from("public"),
+            StringValue::from("secret"),
+        ])),
+    ));
+    span.add_event("mailed jane@example.com", Vec::new());
+    span.add_event("read secret", vec![KeyValue::new("to", "jane@example.com")]);
+    span.set_status(Status::error("no user jane@example.com"));
+    span.end();
+
+    let spans = exporter.get_finished_spans().unwrap();
+    let span = &spans[0];
+    assert_eq!(span.name, "lookup [REDACTED]");
+    assert_eq!(span.status, Status::error("no user [REDACTED]"));
+    assert_eq!(attribute(span, "user"), Some(&Value::from("[REDACTED]")));
+    assert_eq!(attribute(span, "token"), None);
+    assert_eq!(attribute(span, "id"), Some(&Value::I64(7)));
+    assert_eq!(
+        attribute(span, "recipients"),
+        Some(&Value::Array(Array::String(vec![
+            StringValue::from("[REDACTED]"),
+            StringValue::from("everyone"),
+        ])))
+    );
+    assert_eq!(attribute(span, "keys"), None);
+
+    let events = &span.events.events;
+    assert_eq!(events[0].name, "mailed [REDACTED]");
+    assert_eq!(events[1].name, "[REDACTED]");
+    assert_eq!(
+        events[1].attributes,
+        vec![KeyValue::new("to", "[REDACTED]")]
+    );
+    assert_eq!(
+        span.links.links[0].attributes,
+        vec![KeyValue::new("by", "[REDACTED]")]
+    );
+}
+
+#[test]
+fn tail_sampling_keeps_failed_and_slow_traces_only() {
+    let policy = TailSampling {
+        latency_threshold: Duration::from_millis(50),
+        ratio: 0.0,
+        max_traces: 100,
+        max_age: Duration::from_secs(60),
+    };
+    let (provider, exporter) = provider(|inner| TailSamplingProcessor::new(inner, policy));
+    let tracer = provider.tracer("test");
+
+    // A fast, successful trace is dropped
+    tracer.in_span("ok", |cx| {
+        cx.span()
+            .set_attribute(KeyValue::new("http.status_code", 200));
+    });
+    assert!(exporter.get_finished_spans().unwrap().is_empty());
+
+    // A failed trace is held back until its root ends, then kept whole
+    tracer.in_span("failed", |cx| {
+        tracer.in_span("query", |_| {});
+        assert!(exporter.get_finished_spans().unwrap().is_empty());
+        cx.span()
+            .set_attribute(KeyValue::new("http.status_code", 503));
+    });
+    let names: Vec<_> = exporter
+        .get_finished_spans()
+        .unwrap()
+        .into_iter()
+        .map(|span| span.name)
+        .collect();
+    assert_eq!(names, ["query", "failed"]);
+
+    exporter.reset();
+    tracer.in_span("slow", |_| std::thread::sleep(Duration::from_millis(60)));
+    assert_eq!(exporter.get_finished_spans().unwrap()[0].name, "slow");
+}
+
+#[test]
+fn tail_sampling_decides_traces_open_for_too_long() {
+    let policy = TailSampling {
+        latency_threshold: Duration::from_secs(60),
+        ratio: 0.0,
+        max_traces: 100,
+        max_age: Duration::from_millis(50),
+    };
+    let (provider, exporter) = provider(|inner| TailSamplingProcessor::new(inner, policy));
+    let tracer = provider.tracer("test");
+    let names = || -> Vec<_> {
+        exporter
+            .get_finished_spans()
+            .unwrap()
+            .into_iter()
+            .map(|span| span.name)
+            .collect()
+    };
+
+    // Long-lived requests, one of which failed a message
+    let failing = tracer.start("failing socket");
+    let cx = opentelemetry::Context::current_with_span(failing);
+    let mut message = tracer.start_with_context("failed message", &cx);
+    message.set_attribute(KeyValue::new("http.status_code", 500));
+    message.end();
+    let quiet = tracer.start("quiet socket");
+    let quiet_cx = opentelemetry::Context::current_with_span(quiet);
+    tracer.start_with_context("message", &quiet_cx).end();
+    assert!(names().is_empty());
+
+    // Any span ending once they are stale has them decided
+    std::thread::sleep(Duration::from_millis(60));
+    tracer.start("other").end();
+    assert_eq!(names(), ["failed message"]);
+
+    // Their remaining spans follow the decision
+    cx.span().end();
+    quiet_cx.span().end();
+    assert_eq!(names(), ["failed message", "failing socket"]);
+}
+
+#[test]
+fn limits_the_count_and_length_of_attributes() {
+    let limits = AttributeLimits {
+        max_attributes: 3,
+        max_value_length: 2,
+        hashed_keys: vec!["session.id".to_string()],
+    };
+    let (provider, exporter) = provider(|inner| AttributeLimitProcessor::new(inner, limits));
+    let tracer = provider.tracer("test");
+
+    let mut span = tracer.start("limited");
+    span.set_attribute(KeyValue::new("name", "héllo world"));
+    span.set_attribute(KeyValue::new("session.id", "abc"));
+    span.set_attribute(KeyValue::new("count", 12_345_678));
+    span.set_attribute(KeyValue::new("extra", "dropped"));
+    span.add_event(
+        "event",
+        vec![
+            KeyValue::new("a", 1),
+            KeyValue::new("b", 2),
+            KeyValue::new("c", 3),
+            KeyValue::new("d", 4),
+        ],
+    );
+    span.end();
+
+    let spans = exporter.get_finished_spans().unwrap();
+    let span = &spans[0];
+    // Cut on a character boundary, as `é` takes two bytes
+    assert_eq!(attribute(span, "name"), Some(&Value::from("h")));
+    let hashed = attribute(span, "session.id").unwrap().as_str();
+    assert_ne!(hashed, "abc");
+    assert_eq!(hashed.len(), 16);
+    assert_eq!(attribute(span, "count"), Some(&Value::I64(12_345_678)));
+    assert_eq!(attribute(span, "extra"), None);
+    assert_eq!(span.dropped_attributes_count, 1);
+
+    let event = &span.events.events[0];
+    assert_eq!(event.attributes.len(), 3);
+    assert_eq!(event.dropped_attributes_count, 1);
+}